@@ -0,0 +1,126 @@
+//! An asynchronous connection to gpsd, using tokio.
+//!
+//! Only available with the `tokio` feature enabled. The API mirrors that of
+//! `GpsdConnection`, but every method is an `async fn`.
+//!
+//! By default the connection is over TCP, but any `AsyncRead + AsyncWrite`
+//! transport can be used with `AsyncGpsdConnection::from_transport`.
+//!
+//! Reading is cancellation safe: if a `get_response` future is dropped (say,
+//! because another branch of a `select!` won), whatever part of a line it had
+//! read is kept for the next call.
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::time::{self, Duration, Instant};
+use crate::errors::GpsdError;
+use crate::types::{Command, Envelope, ParseMode, RawLevel, Response};
+use crate::{decode_line, parse_envelope, watch_command, GpsdResult};
+
+/// An asynchronous connection to gpsd, over the transport `S`.
+pub struct AsyncGpsdConnection<S = TcpStream> {
+    raw_data: bool,
+    parse_mode: ParseMode,
+    inner: BufReader<S>,
+    /// The line being read, kept across cancelled reads.
+    line: Vec<u8>
+}
+impl AsyncGpsdConnection<TcpStream> {
+    /// Make a new connection to a given address.
+    pub async fn new<A: ToSocketAddrs>(addr: A) -> GpsdResult<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        Ok(Self::from_transport(stream))
+    }
+}
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncGpsdConnection<S> {
+    /// Speak the gpsd protocol over an already-open `transport`.
+    pub fn from_transport(transport: S) -> Self {
+        Self { inner: BufReader::new(transport), raw_data: false, parse_mode: ParseMode::default(), line: Vec::new() }
+    }
+    /// Get back the underlying transport, discarding any buffered data.
+    pub fn into_inner(self) -> S {
+        self.inner.into_inner()
+    }
+    /// Disable watcher mode, then shut the connection down. See
+    /// `GpsdConnection::close`.
+    pub async fn close(mut self) -> GpsdResult<()> {
+        self.send("?WATCH={\"enable\":false};\n").await?;
+        let stream = self.inner.get_mut();
+        stream.flush().await?;
+        stream.shutdown().await?;
+        Ok(())
+    }
+    /// See `GpsdConnection::set_parse_mode`.
+    pub fn set_parse_mode(&mut self, mode: ParseMode) {
+        self.parse_mode = mode;
+    }
+    async fn send(&mut self, msg: &str) -> GpsdResult<()> {
+        self.inner.get_mut().write_all(msg.as_bytes()).await?;
+        Ok(())
+    }
+    /// Send `cmd` to gpsd.
+    pub async fn send_command(&mut self, cmd: &Command) -> GpsdResult<()> {
+        if let Command::Watch(ref w) = *cmd {
+            self.raw_data = w.sends_raw();
+        }
+        self.send(&cmd.to_line()).await
+    }
+    /// Enable or disable watcher mode.
+    pub async fn watch(&mut self, watch: bool) -> GpsdResult<()> {
+        self.watch_raw(watch, true, RawLevel::Off).await
+    }
+    /// Enable RAW mode. See `GpsdConnection::watch_raw` for the meaning of `raw`.
+    pub async fn watch_raw(&mut self, watch: bool, json: bool, raw: RawLevel) -> GpsdResult<()> {
+        self.raw_data = raw.is_on();
+        self.send(&watch_command(watch, json, raw)).await
+    }
+    /// Request data from the last-seen fixes on all active GPS devices.
+    pub async fn poll(&mut self) -> GpsdResult<()> {
+        self.send("?POLL;\n").await
+    }
+    /// Ask for the server's version (triggers a Response::Version).
+    pub async fn version(&mut self) -> GpsdResult<()> {
+        self.send("?VERSION;\n").await
+    }
+    /// Ask for the server's devices (triggers a Response::Devices)
+    pub async fn devices(&mut self) -> GpsdResult<()> {
+        self.send("?DEVICES;\n").await
+    }
+    /// Wait for the next response from gpsd.
+    pub async fn get_response(&mut self) -> GpsdResult<Response> {
+        self.get_envelope().await.map(|e| e.parsed)
+    }
+    /// Like `get_response`, but also returning the line gpsd sent. See
+    /// `GpsdConnection::get_envelope`.
+    pub async fn get_envelope(&mut self) -> GpsdResult<Envelope> {
+        loop {
+            if self.inner.read_until(b'\n', &mut self.line).await? == 0 {
+                bail!(GpsdError::closed());
+            }
+            let buf = decode_line(::std::mem::take(&mut self.line));
+            if buf.trim().is_empty() {
+                debug!("empty line received from GPSD");
+                continue;
+            }
+            debug!("raw GPSD data: {}", buf);
+            return parse_envelope(buf, self.raw_data, self.parse_mode);
+        }
+    }
+    /// Wait up to `timeout` for the next response from gpsd, failing with
+    /// `GpsdError::Timeout` if none arrives in time.
+    ///
+    /// Any partial line read before the timeout is kept for the next call.
+    pub async fn next_response_timeout(&mut self, timeout: Duration) -> GpsdResult<Response> {
+        self.next_response_before(Instant::now() + timeout).await
+    }
+    /// Wait until `deadline` for the next response from gpsd, failing with
+    /// `GpsdError::Timeout` if none arrives in time.
+    ///
+    /// Any partial line read before the deadline is kept for the next call.
+    pub async fn next_response_before(&mut self, deadline: Instant) -> GpsdResult<Response> {
+        match time::timeout_at(deadline, self.get_response()).await {
+            Ok(r) => r,
+            Err(_) => bail!(GpsdError::Timeout(String::from("no response from gpsd before the deadline")))
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Direct connections to Bluetooth serial (RFCOMM) GPS receivers.
+//!
+//! Only available on Linux, with the `bluetooth` feature enabled. This lets
+//! handheld setups talk to a classic Bluetooth GPS puck without running gpsd.
+//! The receiver must already be paired.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::mem;
+use std::os::unix::io::FromRawFd;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use crate::nmea::NmeaDecoder;
+use crate::types::Response;
+use crate::{decode_line, parse_line, GpsdResult};
+
+/// Bluetooth socket protocol number for RFCOMM, from `<bluetooth/bluetooth.h>`.
+const BTPROTO_RFCOMM: libc::c_int = 3;
+
+/// `struct sockaddr_rc`, from `<bluetooth/rfcomm.h>`.
+#[repr(C)]
+struct SockaddrRc {
+    rc_family: libc::sa_family_t,
+    rc_bdaddr: [u8; 6],
+    rc_channel: u8
+}
+
+/// A Bluetooth device address, like `00:11:22:33:44:55`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BdAddr(pub [u8; 6]);
+impl FromStr for BdAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut ret = [0; 6];
+        let mut parts = s.split(':');
+        for b in ret.iter_mut() {
+            let part = parts.next()
+                .ok_or_else(|| format!("too few octets in address '{}'", s))?;
+            *b = u8::from_str_radix(part, 16)
+                .map_err(|e| format!("invalid octet '{}' in address '{}': {}", part, s, e))?;
+        }
+        if parts.next().is_some() {
+            return Err(format!("too many octets in address '{}'", s));
+        }
+        Ok(BdAddr(ret))
+    }
+}
+impl fmt::Display for BdAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let b = self.0;
+        write!(f, "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}", b[0], b[1], b[2], b[3], b[4], b[5])
+    }
+}
+
+/// Open an RFCOMM stream socket to the given device and channel.
+fn rfcomm_connect(addr: BdAddr, channel: u8) -> io::Result<File> {
+    // bdaddr_t is stored with the octets in reverse order.
+    let mut bdaddr = addr.0;
+    bdaddr.reverse();
+    let sa = SockaddrRc {
+        rc_family: libc::AF_BLUETOOTH as libc::sa_family_t,
+        rc_bdaddr: bdaddr,
+        rc_channel: channel
+    };
+    unsafe {
+        let fd = libc::socket(libc::AF_BLUETOOTH, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, BTPROTO_RFCOMM);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // Take ownership straight away, so the fd is closed on error.
+        let file = File::from_raw_fd(fd);
+        let ret = libc::connect(fd,
+                                &sa as *const SockaddrRc as *const libc::sockaddr,
+                                mem::size_of::<SockaddrRc>() as libc::socklen_t);
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(file)
+    }
+}
+
+/// A connection to a Bluetooth GPS receiver, speaking NMEA over RFCOMM.
+///
+/// Lines from the receiver are returned as `Response::Raw`, unless
+/// `set_decode_nmea` asks for NMEA sentences to be decoded. If a reconnect
+/// delay is set (the default is one second), the source transparently
+/// reconnects whenever the link drops, retrying until it succeeds.
+pub struct RfcommSource {
+    addr: BdAddr,
+    channel: u8,
+    reconnect_delay: Option<Duration>,
+    nmea: Option<NmeaDecoder>,
+    inner: Option<BufReader<File>>
+}
+impl RfcommSource {
+    /// Connect to the receiver with address `addr`, on RFCOMM channel `channel`
+    /// (usually 1 for GPS pucks).
+    pub fn connect(addr: BdAddr, channel: u8) -> GpsdResult<Self> {
+        let file = rfcomm_connect(addr, channel)?;
+        Ok(Self {
+            addr,
+            channel,
+            reconnect_delay: Some(Duration::from_secs(1)),
+            nmea: None,
+            inner: Some(BufReader::new(file))
+        })
+    }
+    /// Set how long to wait between reconnection attempts, or `None` to
+    /// disable reconnecting and return link errors instead.
+    pub fn set_reconnect_delay(&mut self, delay: Option<Duration>) {
+        self.reconnect_delay = delay;
+    }
+    /// Whether NMEA sentences are decoded into the TPV and SKY reports they
+    /// make up, skipping those that can't be read, rather than returned as
+    /// `Response::Raw`. They aren't by default.
+    pub fn set_decode_nmea(&mut self, decode: bool) {
+        self.nmea = if decode { Some(NmeaDecoder::new()) } else { None };
+    }
+    /// Whether the link is currently up.
+    pub fn is_connected(&self) -> bool {
+        self.inner.is_some()
+    }
+    fn reconnect(&mut self, delay: Duration) {
+        loop {
+            thread::sleep(delay);
+            match rfcomm_connect(self.addr, self.channel) {
+                Ok(file) => {
+                    info!("reconnected to {} channel {}", self.addr, self.channel);
+                    self.inner = Some(BufReader::new(file));
+                    return;
+                },
+                Err(e) => debug!("reconnecting to {} failed: {}", self.addr, e)
+            }
+        }
+    }
+    /// Wait for the next line from the receiver, blocking if necessary.
+    pub fn get_response(&mut self) -> GpsdResult<Response> {
+        loop {
+            if self.inner.is_none() {
+                match self.reconnect_delay {
+                    Some(d) => self.reconnect(d),
+                    None => bail!(io::Error::from(io::ErrorKind::NotConnected))
+                }
+            }
+            let mut buf = Vec::new();
+            let res = self.inner.as_mut().unwrap().read_until(b'\n', &mut buf);
+            let err = match res {
+                Ok(0) => io::Error::from(io::ErrorKind::UnexpectedEof),
+                Ok(_) => {
+                    let buf = decode_line(buf);
+                    let line = buf.trim();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    if let (Some(dec), true) = (self.nmea.as_mut(), line.starts_with('$')) {
+                        match dec.decode_or_skip(line) {
+                            Some(resp) => return Ok(resp),
+                            None => continue
+                        }
+                    }
+                    return parse_line(line.into(), true);
+                },
+                Err(e) => e
+            };
+            warn!("lost link to {}: {}", self.addr, err);
+            self.inner = None;
+            if self.reconnect_delay.is_none() {
+                bail!(err);
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Parsing the most frequent reports without copying their strings.
+//!
+//! At 10 Hz, parsing every TPV and SKY report into `Response` allocates its
+//! device name (and, for other classes, paths and driver strings) each time.
+//! `parse` reads TPV and SKY reports into types that borrow their strings from
+//! the line instead, and only says which class anything else was, so you can
+//! fall back to `Response` for the rare classes you care about:
+//!
+//! ```rust
+//! use unbounded_gpsd::borrowed::{self, ResponseRef};
+//!
+//! let line = r#"{"class":"TPV","device":"/dev/ttyUSB0","mode":3,"lat":59.345,"lon":18.054}"#;
+//! match borrowed::parse(line).unwrap() {
+//!     ResponseRef::Tpv(tpv) => assert_eq!(tpv.device.as_deref(), Some("/dev/ttyUSB0")),
+//!     _ => unreachable!()
+//! }
+//! ```
+//!
+//! Strings containing JSON escapes can't be borrowed, and are copied as usual.
+
+use std::borrow::Cow;
+use serde::{Deserialize, Deserializer};
+use crate::errors::GpsdError;
+use crate::timestamp::Timestamp;
+use crate::types::{FixMode, SatelliteObject};
+use crate::GpsdResult;
+
+/// A TPV report, with every field optional. See `TpvResponse` for what the
+/// fields mean.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct TpvRef<'a> {
+    #[serde(borrow, default, deserialize_with = "borrow_str")]
+    pub device: Option<Cow<'a, str>>,
+    #[serde(default, with = "crate::timestamp::rfc3339::option")]
+    pub time: Option<Timestamp>,
+    pub mode: Option<FixMode>,
+    #[serde(rename = "ept")]
+    pub time_err: Option<f64>,
+    pub lat: Option<f64>,
+    #[serde(rename = "epy")]
+    pub lat_err: Option<f64>,
+    pub lon: Option<f64>,
+    #[serde(rename = "epx")]
+    pub lon_err: Option<f64>,
+    pub alt: Option<f64>,
+    #[serde(rename = "epv")]
+    pub alt_err: Option<f64>,
+    pub track: Option<f64>,
+    pub speed: Option<f64>,
+    pub climb: Option<f64>
+}
+
+/// A SKY report. See `SkyResponse` for what the fields mean.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct SkyRef<'a> {
+    #[serde(borrow, default, deserialize_with = "borrow_str")]
+    pub device: Option<Cow<'a, str>>,
+    #[serde(default, with = "crate::timestamp::rfc3339::option")]
+    pub time: Option<Timestamp>,
+    pub hdop: Option<f64>,
+    pub vdop: Option<f64>,
+    pub pdop: Option<f64>,
+    #[serde(default)]
+    pub satellites: Vec<SatelliteObject>
+}
+
+/// A report parsed by `parse`.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "class")]
+#[non_exhaustive]
+pub enum ResponseRef<'a> {
+    #[serde(rename = "TPV", borrow)]
+    Tpv(TpvRef<'a>),
+    #[serde(rename = "SKY", borrow)]
+    Sky(SkyRef<'a>),
+    /// Any other class. Parse the line as a `Response` to see what it says.
+    #[serde(other)]
+    Other
+}
+
+/// Deserialize an optional string, borrowing it if it has no escapes. (serde
+/// only borrows bare `Cow`s by itself.)
+fn borrow_str<'de: 'a, 'a, D: Deserializer<'de>>(de: D) -> Result<Option<Cow<'a, str>>, D::Error> {
+    #[derive(Deserialize)]
+    struct Wrapped<'a>(#[serde(borrow)] Cow<'a, str>);
+    Ok(Option::<Wrapped>::deserialize(de)?.map(|w| w.0))
+}
+
+/// Parse `line` (one line of gpsd output), borrowing strings from it where
+/// possible.
+pub fn parse(line: &str) -> GpsdResult<ResponseRef<'_>> {
+    serde_json::from_str(line).map_err(|e| GpsdError::Json { line: line.to_owned(), source: e })
+}
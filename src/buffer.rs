@@ -0,0 +1,196 @@
+//! Bounded buffering between a reader and a slow consumer.
+//!
+//! `GpsdConnection::spawn_channel` blocks the reader when its buffer is full,
+//! which is usually what you want: gpsd's socket buffer fills up, and gpsd
+//! eventually drops the client. For a UI that only cares about the latest fix,
+//! it's better to throw old reports away, and for a logger it may be better to
+//! throw new ones away than stall. A buffer from `bounded` (or
+//! `GpsdConnection::spawn_buffered`) holds a fixed number of items and applies
+//! an explicit `OverflowPolicy` when it's full, counting what it dropped.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// What to do with a new item when the buffer is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest buffered item to make room.
+    DropOldest,
+    /// Discard the new item.
+    DropNewest,
+    /// Wait for the consumer to make room.
+    Block
+}
+
+/// Counters describing how a buffer has coped.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BufferStats {
+    /// Items currently buffered.
+    pub len: usize,
+    /// The most items that have been buffered at once.
+    pub high_water: usize,
+    /// Items accepted into the buffer.
+    pub accepted: u64,
+    /// Items discarded because the buffer was full.
+    pub dropped: u64,
+    /// Times the sender had to wait for room.
+    pub blocked: u64
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    stats: BufferStats,
+    senders_gone: bool,
+    receiver_gone: bool
+}
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    cond: Condvar,
+    capacity: usize,
+    policy: OverflowPolicy
+}
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Make a buffer holding up to `capacity` items, handling overflow with
+/// `policy`.
+///
+/// # Panics
+///
+/// Panics if `capacity` is 0.
+pub fn bounded<T>(capacity: usize, policy: OverflowPolicy) -> (BufferSender<T>, BufferReceiver<T>) {
+    assert!(capacity > 0, "buffer capacity must be positive");
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            items: VecDeque::with_capacity(capacity),
+            stats: BufferStats::default(),
+            senders_gone: false,
+            receiver_gone: false
+        }),
+        cond: Condvar::new(),
+        capacity,
+        policy
+    });
+    (BufferSender(shared.clone()), BufferReceiver(shared))
+}
+
+/// The sending half of a buffer.
+pub struct BufferSender<T>(Arc<Shared<T>>);
+impl<T> BufferSender<T> {
+    /// Add `item` to the buffer, applying the overflow policy if it's full.
+    ///
+    /// Fails, giving the item back, if the receiver has been dropped.
+    pub fn send(&self, item: T) -> Result<(), T> {
+        let shared = &*self.0;
+        let mut st = shared.lock();
+        if st.receiver_gone {
+            return Err(item);
+        }
+        if st.items.len() >= shared.capacity {
+            match shared.policy {
+                OverflowPolicy::DropOldest => {
+                    st.items.pop_front();
+                    st.stats.dropped += 1;
+                },
+                OverflowPolicy::DropNewest => {
+                    st.stats.dropped += 1;
+                    return Ok(());
+                },
+                OverflowPolicy::Block => {
+                    st.stats.blocked += 1;
+                    while st.items.len() >= shared.capacity && !st.receiver_gone {
+                        st = shared.cond.wait(st).unwrap_or_else(|e| e.into_inner());
+                    }
+                    if st.receiver_gone {
+                        return Err(item);
+                    }
+                }
+            }
+        }
+        st.items.push_back(item);
+        st.stats.accepted += 1;
+        st.stats.len = st.items.len();
+        st.stats.high_water = st.stats.high_water.max(st.stats.len);
+        shared.cond.notify_all();
+        Ok(())
+    }
+    /// The buffer's counters.
+    pub fn stats(&self) -> BufferStats {
+        self.0.lock().stats
+    }
+}
+impl<T> Drop for BufferSender<T> {
+    fn drop(&mut self) {
+        self.0.lock().senders_gone = true;
+        self.0.cond.notify_all();
+    }
+}
+
+/// The receiving half of a buffer.
+pub struct BufferReceiver<T>(Arc<Shared<T>>);
+impl<T> BufferReceiver<T> {
+    fn take(&self, st: &mut State<T>) -> Option<T> {
+        let item = st.items.pop_front()?;
+        st.stats.len = st.items.len();
+        self.0.cond.notify_all();
+        Some(item)
+    }
+    /// Wait for the next item. Returns `None` once the sender has gone and
+    /// the buffer is empty.
+    pub fn recv(&self) -> Option<T> {
+        let mut st = self.0.lock();
+        loop {
+            if let Some(item) = self.take(&mut st) {
+                return Some(item);
+            }
+            if st.senders_gone {
+                return None;
+            }
+            st = self.0.cond.wait(st).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+    /// Wait up to `timeout` for the next item. Returns `None` if nothing
+    /// arrived in time, or the sender has gone and the buffer is empty.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+        let mut st = self.0.lock();
+        loop {
+            if let Some(item) = self.take(&mut st) {
+                return Some(item);
+            }
+            let now = Instant::now();
+            if st.senders_gone || now >= deadline {
+                return None;
+            }
+            st = self.0.cond.wait_timeout(st, deadline - now).unwrap_or_else(|e| e.into_inner()).0;
+        }
+    }
+    /// Take the next item, if there is one, without waiting.
+    pub fn try_recv(&self) -> Option<T> {
+        let mut st = self.0.lock();
+        self.take(&mut st)
+    }
+    /// Whether the sender has gone. Items may still be buffered.
+    pub fn is_disconnected(&self) -> bool {
+        self.0.lock().senders_gone
+    }
+    /// The buffer's counters.
+    pub fn stats(&self) -> BufferStats {
+        self.0.lock().stats
+    }
+    /// A blocking iterator over items, ending once the sender has gone and
+    /// the buffer is empty.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        ::std::iter::from_fn(move || self.recv())
+    }
+}
+impl<T> Drop for BufferReceiver<T> {
+    fn drop(&mut self) {
+        self.0.lock().receiver_gone = true;
+        self.0.cond.notify_all();
+    }
+}
@@ -0,0 +1,223 @@
+//! A builder for `GpsdConnection`s with non-default options.
+
+use std::io;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+use std::time::Duration;
+use crate::reconnect::ReconnectPolicy;
+use crate::socks::Socks5Proxy;
+use crate::types::{ParseMode, RawLevel, WatchObject};
+use crate::{connect_addrs, GpsdConnection, GpsdResult, Stream};
+
+/// The default port gpsd listens on.
+pub const DEFAULT_PORT: u16 = 2947;
+
+/// Collects options for a `GpsdConnection`, then connects.
+///
+/// Get one with `GpsdConnection::builder()`.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use std::time::Duration;
+/// # use unbounded_gpsd::GpsdConnection;
+/// # use unbounded_gpsd::reconnect::ReconnectPolicy;
+/// # use unbounded_gpsd::types::WatchObject;
+/// let conn = GpsdConnection::builder()
+///     .host("gpsd.local")
+///     .port(2947)
+///     .timeout(Duration::from_secs(5))
+///     .reconnect(ReconnectPolicy::default())
+///     .watch(WatchObject::default().with_json())
+///     .connect()
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct GpsdConnectionBuilder {
+    host: String,
+    port: u16,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    inactivity_timeout: Option<Duration>,
+    watchdog: Option<Duration>,
+    reconnect: Option<ReconnectPolicy>,
+    watch: Option<WatchObject>,
+    proxy: Option<Socks5Proxy>,
+    parse_mode: ParseMode,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<rustls::ClientConfig>>
+}
+impl Default for GpsdConnectionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl GpsdConnectionBuilder {
+    /// A builder for connecting to gpsd on `localhost`, port 2947, with no
+    /// timeouts, reconnection or watch.
+    pub fn new() -> Self {
+        Self {
+            host: "localhost".into(),
+            port: DEFAULT_PORT,
+            connect_timeout: None,
+            read_timeout: None,
+            inactivity_timeout: None,
+            watchdog: None,
+            reconnect: None,
+            watch: None,
+            proxy: None,
+            parse_mode: ParseMode::default(),
+            #[cfg(feature = "tls")]
+            tls: None
+        }
+    }
+    /// The hostname or IP address to connect to.
+    pub fn host(mut self, host: &str) -> Self {
+        self.host = host.into();
+        self
+    }
+    /// Take the host, port and device from a URL like
+    /// `gpsd://host:2947/dev/ttyACM0`, as other gpsd clients accept.
+    ///
+    /// The port defaults to 2947, and the host to `localhost`; IPv6 addresses
+    /// go in brackets (`gpsd://[::1]`). If there's a device path, a JSON watch
+    /// of just that device is sent once connected, replacing any set with
+    /// `watch`.
+    pub fn url(mut self, url: &str) -> GpsdResult<Self> {
+        let (host, port, device) = parse_url(url)?;
+        self.host = host;
+        self.port = port;
+        if let Some(device) = device {
+            self.watch = Some(WatchObject::default().with_json().with_device(&device));
+        }
+        Ok(self)
+    }
+    /// The port to connect to.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+    /// Give up on connecting to each address after `timeout`. Through a
+    /// proxy, this bounds connecting to the proxy and each step of the
+    /// handshake instead.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+    /// See `GpsdConnection::set_read_timeout`.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+    /// See `GpsdConnection::set_inactivity_timeout`.
+    pub fn inactivity_timeout(mut self, timeout: Duration) -> Self {
+        self.inactivity_timeout = Some(timeout);
+        self
+    }
+    /// See `GpsdConnection::set_watchdog`.
+    pub fn watchdog(mut self, period: Duration) -> Self {
+        self.watchdog = Some(period);
+        self
+    }
+    /// See `GpsdConnection::set_reconnect`.
+    pub fn reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = Some(policy);
+        self
+    }
+    /// Send these watch parameters once connected.
+    pub fn watch(mut self, watch: WatchObject) -> Self {
+        self.watch = Some(watch);
+        self
+    }
+    /// Ask for raw data at `level` in the watch sent once connected, which
+    /// is a JSON watch if `watch` hasn't set one.
+    pub fn raw(mut self, level: RawLevel) -> Self {
+        let watch = self.watch.take().unwrap_or_else(|| WatchObject::default().with_json());
+        self.watch = Some(watch.with_raw(level));
+        self
+    }
+    /// See `GpsdConnection::set_parse_mode`.
+    pub fn parse_mode(mut self, mode: ParseMode) -> Self {
+        self.parse_mode = mode;
+        self
+    }
+    /// Connect through a SOCKS5 proxy. The host is resolved by the proxy.
+    pub fn proxy(mut self, proxy: Socks5Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+    /// Connect using TLS, configured by `config`. See `tls` for details.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, config: Arc<rustls::ClientConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+    /// Open a TCP connection as configured, without any TLS.
+    fn connect_tcp(&self) -> io::Result<TcpStream> {
+        if let Some(ref proxy) = self.proxy {
+            return Ok(proxy.connect_timeout(&self.host, self.port, self.connect_timeout)?);
+        }
+        let addrs = (&self.host[..], self.port).to_socket_addrs()?.collect::<Vec<SocketAddr>>();
+        connect_addrs(&addrs, self.connect_timeout)
+    }
+    fn connect_stream(&self) -> io::Result<Stream> {
+        let sock = self.connect_tcp()?;
+        #[cfg(feature = "tls")]
+        {
+            if let Some(ref config) = self.tls {
+                return crate::tls::wrap(sock, &self.host, config.clone());
+            }
+        }
+        Ok(Box::new(sock))
+    }
+    /// Connect to gpsd, apply the configured options, and send the watch
+    /// parameters (if any).
+    pub fn connect(self) -> GpsdResult<GpsdConnection> {
+        let opts = self.clone();
+        let mut conn = GpsdConnection::with_connector(Box::new(move || opts.connect_stream()))?;
+        conn.set_read_timeout(self.read_timeout)?;
+        conn.set_inactivity_timeout(self.inactivity_timeout);
+        conn.set_watchdog(self.watchdog);
+        conn.set_reconnect(self.reconnect);
+        conn.set_parse_mode(self.parse_mode);
+        if let Some(ref w) = self.watch {
+            conn.watch_with(w)?;
+        }
+        Ok(conn)
+    }
+}
+
+/// Split a `gpsd://` URL into host, port and device path.
+pub(crate) fn parse_url(url: &str) -> GpsdResult<(String, u16, Option<String>)> {
+    let bad = |why: &str| io::Error::new(io::ErrorKind::InvalidInput, format!("bad gpsd URL '{}': {}", url, why));
+    let rest = match url.find("://") {
+        Some(i) if url[..i].eq_ignore_ascii_case("gpsd") => &url[i + 3..],
+        _ => return Err(bad("expected a gpsd:// URL").into())
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "")
+    };
+    let (host, port) = if let Some(v6) = authority.strip_prefix('[') {
+        let end = v6.find(']').ok_or_else(|| bad("unclosed '['"))?;
+        let port = v6[end + 1..].strip_prefix(':');
+        if port.is_none() && end + 1 != v6.len() {
+            return Err(bad("junk after IPv6 address").into());
+        }
+        (&v6[..end], port)
+    } else {
+        match authority.split_once(':') {
+            Some((_, p)) if p.contains(':') => return Err(bad("IPv6 addresses must be in brackets").into()),
+            Some((h, p)) => (h, Some(p)),
+            None => (authority, None)
+        }
+    };
+    let port = match port {
+        Some(p) => p.parse().map_err(|_| bad("invalid port"))?,
+        None => DEFAULT_PORT
+    };
+    let host = if host.is_empty() { "localhost" } else { host };
+    let device = if path.len() > 1 { Some(path.to_owned()) } else { None };
+    Ok((host.to_owned(), port, device))
+}
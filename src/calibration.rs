@@ -0,0 +1,126 @@
+//! Calibrating GPS speed against an external reference.
+//!
+//! Feed a `SpeedCalibrator` pairs of GPS speed and reference speed (e.g. from
+//! wheel sensors) collected over a session, and it fits
+//! `reference = scale * gps + bias` by least squares. The resulting
+//! `SpeedCalibration` records the fit along with its uncertainty in a form
+//! that can be serialized and stored per device.
+
+use crate::types::{DevicePath, TpvResponse};
+
+/// A linear fit of reference speed against GPS speed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpeedCalibration {
+    /// The device the calibration applies to, if known.
+    pub device: Option<DevicePath>,
+    /// Multiplicative factor: how many reference m/s per GPS m/s.
+    pub scale: f64,
+    /// Additive offset in meters per second.
+    pub bias: f64,
+    /// Standard error of `scale`.
+    pub scale_stderr: f64,
+    /// Standard error of `bias`, in meters per second.
+    pub bias_stderr: f64,
+    /// Standard deviation of the residuals, in meters per second.
+    pub residual_stddev: f64,
+    /// Number of samples the fit is based on.
+    pub samples: usize
+}
+impl SpeedCalibration {
+    /// Convert a GPS speed to the reference's scale.
+    pub fn apply(&self, gps_speed: f64) -> f64 {
+        self.scale * gps_speed + self.bias
+    }
+    /// 95% confidence interval for `scale`.
+    pub fn scale_interval(&self) -> (f64, f64) {
+        (self.scale - 1.96 * self.scale_stderr, self.scale + 1.96 * self.scale_stderr)
+    }
+    /// 95% confidence interval for `bias`.
+    pub fn bias_interval(&self) -> (f64, f64) {
+        (self.bias - 1.96 * self.bias_stderr, self.bias + 1.96 * self.bias_stderr)
+    }
+    /// Whether the GPS agrees with the reference within 95% confidence, to
+    /// within `scale_tol` on scale and `bias_tol` m/s on bias.
+    pub fn is_consistent(&self, scale_tol: f64, bias_tol: f64) -> bool {
+        let (slo, shi) = self.scale_interval();
+        let (blo, bhi) = self.bias_interval();
+        slo <= 1.0 + scale_tol && shi >= 1.0 - scale_tol && blo <= bias_tol && bhi >= -bias_tol
+    }
+}
+
+/// Accumulates speed samples over a session.
+#[derive(Debug, Clone, Default)]
+pub struct SpeedCalibrator {
+    device: Option<DevicePath>,
+    n: usize,
+    sum_x: f64,
+    sum_y: f64,
+    sum_xx: f64,
+    sum_xy: f64,
+    sum_yy: f64
+}
+impl SpeedCalibrator {
+    /// Start a calibration session for `device` (if known).
+    pub fn new(device: Option<DevicePath>) -> Self {
+        Self { device, ..Default::default() }
+    }
+    /// Add a pair of simultaneous GPS and reference speeds, in meters per second.
+    pub fn add(&mut self, gps_speed: f64, reference_speed: f64) {
+        self.n += 1;
+        self.sum_x += gps_speed;
+        self.sum_y += reference_speed;
+        self.sum_xx += gps_speed * gps_speed;
+        self.sum_xy += gps_speed * reference_speed;
+        self.sum_yy += reference_speed * reference_speed;
+    }
+    /// Add a TPV report along with the reference speed at the time of the
+    /// report. Returns whether the report had a speed to use; reports from
+    /// a device other than the session's aren't used.
+    pub fn add_tpv(&mut self, tpv: &TpvResponse, reference_speed: f64) -> bool {
+        if let (Some(ours), Some(theirs)) = (self.device.as_ref(), tpv.device()) {
+            if !ours.matches(theirs) {
+                return false;
+            }
+        }
+        match tpv.speed() {
+            Some(s) => {
+                self.add(s, reference_speed);
+                true
+            },
+            None => false
+        }
+    }
+    /// Number of samples collected so far.
+    pub fn samples(&self) -> usize {
+        self.n
+    }
+    /// Compute the fit. Needs at least three samples, with some variation in
+    /// GPS speed.
+    pub fn estimate(&self) -> Option<SpeedCalibration> {
+        if self.n < 3 {
+            return None;
+        }
+        let n = self.n as f64;
+        let sxx = self.sum_xx - self.sum_x * self.sum_x / n;
+        let sxy = self.sum_xy - self.sum_x * self.sum_y / n;
+        let syy = self.sum_yy - self.sum_y * self.sum_y / n;
+        if sxx <= f64::EPSILON {
+            return None;
+        }
+        let scale = sxy / sxx;
+        let bias = (self.sum_y - scale * self.sum_x) / n;
+        let sse = (syy - scale * sxy).max(0.0);
+        let residual_var = sse / (n - 2.0);
+        let scale_stderr = (residual_var / sxx).sqrt();
+        let bias_stderr = (residual_var * self.sum_xx / (n * sxx)).sqrt();
+        Some(SpeedCalibration {
+            device: self.device.clone(),
+            scale,
+            bias,
+            scale_stderr,
+            bias_stderr,
+            residual_stddev: residual_var.sqrt(),
+            samples: self.n
+        })
+    }
+}
@@ -0,0 +1,181 @@
+//! Feeding TOFF and PPS reports to chrony, through its SOCK refclock.
+//!
+//! Watching with `WatchObject::with_pps`, gpsd sends a TOFF report each
+//! cycle (when the receiver said the fix was, and when the system clock said
+//! it arrived) and a PPS report for each pulse.
+//! gpsd can feed these to chrony itself; this module does the same from the
+//! client side, so the feed can come from anything this crate can read from,
+//! like a remote gpsd or a replayed session.
+//!
+//! chrony reads samples from a Unix datagram socket named in its config:
+//!
+//! ```text
+//! refclock SOCK /run/chrony.ttyUSB0.sock refid PPS
+//! ```
+//!
+//! Each sample is a `struct sock_sample`, in chrony's `refclock_sock.c`: the
+//! system time of the measurement, the offset of true time from it, and a
+//! leap second indicator, in the machine's native layout. chrony creates the
+//! socket, so it has to be running before `ChronySock::connect`. Sockets are
+//! only available on Unix; `TimeReport` and `encode_sample` work anywhere.
+//!
+//! TOFF and PPS aren't classes `Response` has variants for, so they arrive as
+//! `Response::Unknown` (with the default `ParseMode::Lenient`);
+//! `TimeReport::from_response` picks them out.
+
+use std::mem::size_of;
+use std::os::raw::{c_int, c_long};
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::{io, os::unix::net::UnixDatagram};
+use serde_json::Value;
+use crate::types::Response;
+
+/// `SOCK_MAGIC`, which chrony checks every sample for.
+const SOCK_MAGIC: c_int = 0x534f_434b;
+
+/// Size of a `struct sock_sample` on this machine.
+pub const SAMPLE_SIZE: usize = 2 * size_of::<c_long>() + size_of::<f64>() + 4 * size_of::<c_int>();
+
+/// Which kind of time report a `TimeReport` came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimeClass {
+    /// TOFF: the receiver's in-band time, good to a few milliseconds at best.
+    Toff,
+    /// PPS: a pulse-per-second edge, good to microseconds or better.
+    Pps
+}
+
+/// A TOFF or PPS report: a time from the receiver, and the system clock's
+/// time when it arrived.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeReport {
+    pub class: TimeClass,
+    /// The device the report came from.
+    pub device: Option<String>,
+    /// The receiver's time: seconds and nanoseconds since the epoch.
+    pub real_sec: i64,
+    pub real_nsec: i64,
+    /// The system clock's time, as for `real_sec` and `real_nsec`.
+    pub clock_sec: i64,
+    pub clock_nsec: i64,
+    /// The source's precision as a power of two seconds, e.g. -20 for about
+    /// a microsecond, if reported.
+    pub precision: Option<i32>
+}
+impl TimeReport {
+    /// The report in `resp`, if it's a TOFF or PPS report with all its times.
+    pub fn from_response(resp: &Response) -> Option<Self> {
+        let v = match *resp {
+            Response::Unknown(ref v) => v,
+            _ => return None
+        };
+        let class = match v.get("class").and_then(Value::as_str)? {
+            "TOFF" => TimeClass::Toff,
+            "PPS" => TimeClass::Pps,
+            _ => return None
+        };
+        let int = |k: &str| v.get(k).and_then(Value::as_i64);
+        Some(TimeReport {
+            class,
+            device: v.get("device").and_then(Value::as_str).map(str::to_owned),
+            real_sec: int("real_sec")?,
+            real_nsec: int("real_nsec")?,
+            clock_sec: int("clock_sec")?,
+            clock_nsec: int("clock_nsec")?,
+            precision: int("precision").map(|p| p as i32)
+        })
+    }
+    /// How far ahead of the system clock the receiver's time is, in seconds.
+    pub fn offset(&self) -> f64 {
+        // In whole nanoseconds first, so small offsets come out exact.
+        let nanos = i128::from(self.real_sec - self.clock_sec) * 1_000_000_000 + i128::from(self.real_nsec - self.clock_nsec);
+        nanos as f64 / 1e9
+    }
+}
+
+/// A leap second warning, for chrony to apply at the end of the day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Leap {
+    /// No leap second pending.
+    #[default]
+    Normal,
+    /// The last minute of the day has 61 seconds.
+    Insert,
+    /// The last minute of the day has 59 seconds.
+    Delete
+}
+
+/// `report` as a `struct sock_sample`, in this machine's layout, with the
+/// leap indicator `leap`.
+pub fn encode_sample(report: &TimeReport, leap: Leap) -> Vec<u8> {
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+    // struct timeval: the measurement is of the system clock.
+    sample.extend_from_slice(&(report.clock_sec as c_long).to_ne_bytes());
+    sample.extend_from_slice(&((report.clock_nsec / 1000) as c_long).to_ne_bytes());
+    sample.extend_from_slice(&report.offset().to_ne_bytes());
+    // pulse is 0: the offset is the whole offset, not just the fraction of a
+    // second. gpsd sends PPS samples the same way, having matched each pulse
+    // to its second.
+    let leap: c_int = match leap {
+        Leap::Normal => 0,
+        Leap::Insert => 1,
+        Leap::Delete => 2
+    };
+    for v in &[0, leap, 0, SOCK_MAGIC] {
+        sample.extend_from_slice(&v.to_ne_bytes());
+    }
+    sample
+}
+
+/// The socket path gpsd uses for `device`: `/run/chrony.<name>.sock`, where
+/// `name` is the last part of the device's path.
+pub fn socket_path(device: &str) -> PathBuf {
+    let name = Path::new(device).file_name().map_or(device.into(), |n| n.to_string_lossy());
+    PathBuf::from(format!("/run/chrony.{}.sock", name))
+}
+
+/// A connection to one of chrony's SOCK refclocks.
+#[cfg(unix)]
+pub struct ChronySock {
+    sock: UnixDatagram,
+    leap: Leap,
+    samples: u64
+}
+#[cfg(unix)]
+impl ChronySock {
+    /// Connect to the refclock socket at `path`.
+    pub fn connect<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let sock = UnixDatagram::unbound()?;
+        sock.connect(path)?;
+        Ok(ChronySock { sock, leap: Leap::Normal, samples: 0 })
+    }
+    /// Connect to the socket for `device`, wherever gpsd would; see
+    /// `socket_path`.
+    pub fn for_device(device: &str) -> io::Result<Self> {
+        Self::connect(socket_path(device))
+    }
+    /// Mark the samples sent from now on with `leap`, e.g. on the day of a
+    /// leap second announced in the receiver's almanac.
+    pub fn set_leap(&mut self, leap: Leap) {
+        self.leap = leap;
+    }
+    /// Send `report` as a sample.
+    pub fn send(&mut self, report: &TimeReport) -> io::Result<()> {
+        self.sock.send(&encode_sample(report, self.leap))?;
+        self.samples += 1;
+        Ok(())
+    }
+    /// Send a sample if the response is a TOFF or PPS report. Returns
+    /// whether it was.
+    pub fn send_response(&mut self, resp: &Response) -> io::Result<bool> {
+        match TimeReport::from_response(resp) {
+            Some(report) => self.send(&report).map(|_| true),
+            None => Ok(false)
+        }
+    }
+    /// Number of samples sent so far.
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+}
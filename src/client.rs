@@ -0,0 +1,233 @@
+//! A callback-based client that reads from gpsd on a background thread.
+//!
+//! Instead of writing a big `match` over every `Response`, register a handler
+//! for each report class you care about and let the client dispatch to it.
+
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use crate::decimate::Decimation;
+use crate::errors::GpsdError;
+use crate::events::ConnectionEvent;
+use crate::latest::LatestState;
+use crate::types::*;
+use crate::{CloseHandle, GpsdConnection};
+
+type Handler<T> = Box<dyn FnMut(&T) + Send>;
+type StrHandler = Box<dyn FnMut(&str) + Send>;
+
+#[derive(Default)]
+struct Handlers {
+    tpv: Vec<Handler<TpvResponse>>,
+    sky: Vec<Handler<SkyResponse>>,
+    device: Vec<Handler<DeviceObject>>,
+    error: Vec<StrHandler>,
+    response: Vec<Handler<Response>>,
+    read_error: Vec<Handler<GpsdError>>,
+    connection: Vec<Handler<ConnectionEvent>>
+}
+impl Handlers {
+    fn dispatch(&mut self, resp: &Response) {
+        match *resp {
+            Response::Tpv(ref tpv) => self.tpv.iter_mut().for_each(|f| f(tpv)),
+            Response::Sky(ref sky) => self.sky.iter_mut().for_each(|f| f(sky)),
+            Response::Poll { ref tpv, ref sky, .. } => {
+                for t in tpv {
+                    self.tpv.iter_mut().for_each(|f| f(t));
+                }
+                for s in sky {
+                    self.sky.iter_mut().for_each(|f| f(s));
+                }
+            },
+            Response::Device(ref dev) => self.device.iter_mut().for_each(|f| f(dev)),
+            Response::Devices { ref devices, .. } => {
+                for d in devices {
+                    self.device.iter_mut().for_each(|f| f(d));
+                }
+            },
+            Response::Error { ref message } => self.error.iter_mut().for_each(|f| f(message)),
+            _ => {}
+        }
+        self.response.iter_mut().for_each(|f| f(resp));
+    }
+}
+
+/// A gpsd client that runs the read loop on a background thread, dispatching
+/// parsed reports to registered handlers.
+///
+/// Handlers can be registered before or after calling `start`. They are run on
+/// the reader thread, so they should avoid blocking for long.
+///
+/// Dropping a running client stops the read loop and waits for it to finish,
+/// as `close` does.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use unbounded_gpsd::GpsdConnection;
+/// # use unbounded_gpsd::client::GpsdClient;
+/// let mut conn = GpsdConnection::new("127.0.0.1:2947").unwrap();
+/// conn.watch(true).unwrap();
+/// let mut client = GpsdClient::new(conn);
+/// client.on_tpv(|tpv| println!("{:?}", tpv));
+/// client.start();
+/// client.join().unwrap();
+/// ```
+pub struct GpsdClient {
+    handlers: Arc<Mutex<Handlers>>,
+    conn: Option<GpsdConnection>,
+    thread: Option<JoinHandle<()>>,
+    close: Option<CloseHandle>,
+    latest: LatestState
+}
+impl GpsdClient {
+    /// Wrap a connection. The read loop doesn't run until `start` is called.
+    pub fn new(conn: GpsdConnection) -> Self {
+        Self {
+            handlers: Default::default(),
+            conn: Some(conn),
+            thread: None,
+            close: None,
+            latest: LatestState::new()
+        }
+    }
+    /// Register a handler for TPV reports, including those inside POLL responses.
+    pub fn on_tpv<F>(&mut self, f: F) -> &mut Self where F: FnMut(&TpvResponse) + Send + 'static {
+        self.handlers.lock().unwrap().tpv.push(Box::new(f));
+        self
+    }
+    /// Register a handler for SKY reports, including those inside POLL responses.
+    pub fn on_sky<F>(&mut self, f: F) -> &mut Self where F: FnMut(&SkyResponse) + Send + 'static {
+        self.handlers.lock().unwrap().sky.push(Box::new(f));
+        self
+    }
+    /// Register a handler for devices, from both DEVICE and DEVICES reports.
+    pub fn on_device<F>(&mut self, f: F) -> &mut Self where F: FnMut(&DeviceObject) + Send + 'static {
+        self.handlers.lock().unwrap().device.push(Box::new(f));
+        self
+    }
+    /// Register a handler for ERROR reports sent by gpsd, receiving the error message.
+    pub fn on_error<F>(&mut self, f: F) -> &mut Self where F: FnMut(&str) + Send + 'static {
+        self.handlers.lock().unwrap().error.push(Box::new(f));
+        self
+    }
+    /// Register a handler that sees every response, after any class-specific handlers.
+    pub fn on_response<F>(&mut self, f: F) -> &mut Self where F: FnMut(&Response) + Send + 'static {
+        self.handlers.lock().unwrap().response.push(Box::new(f));
+        self
+    }
+    /// Register a handler for errors encountered while reading from gpsd.
+    ///
+    /// Responses that fail to deserialize are reported here and skipped; any
+    /// other error stops the read loop after the handlers have run.
+    pub fn on_read_error<F>(&mut self, f: F) -> &mut Self where F: FnMut(&GpsdError) + Send + 'static {
+        self.handlers.lock().unwrap().read_error.push(Box::new(f));
+        self
+    }
+    /// Register a handler for the connection coming up and going down; see
+    /// `events`.
+    ///
+    /// This replaces any handler set with `GpsdConnection::on_connection_event`
+    /// on the wrapped connection.
+    pub fn on_connection_event<F>(&mut self, f: F) -> &mut Self where F: FnMut(&ConnectionEvent) + Send + 'static {
+        self.handlers.lock().unwrap().connection.push(Box::new(f));
+        self
+    }
+    /// Drop excess TPV reports before they reach the handlers; see
+    /// `GpsdConnection::set_decimation`. Has no effect once started.
+    pub fn decimate(&mut self, rule: Decimation) -> &mut Self {
+        if let Some(ref mut conn) = self.conn {
+            conn.set_decimation(Some(rule));
+        }
+        self
+    }
+    /// Start the read loop on a background thread. Does nothing if it's already started.
+    pub fn start(&mut self) {
+        let mut conn = match self.conn.take() {
+            Some(c) => c,
+            None => return
+        };
+        let handlers = self.handlers.clone();
+        let latest = self.latest.clone();
+        let h = self.handlers.clone();
+        conn.on_connection_event(move |ev| h.lock().unwrap().connection.iter_mut().for_each(|f| f(ev)));
+        let close = conn.close_handle();
+        self.close = Some(close.clone());
+        self.thread = Some(thread::spawn(move || {
+            loop {
+                match conn.get_response() {
+                    Ok(resp) => {
+                        latest.update(&resp);
+                        handlers.lock().unwrap().dispatch(&resp);
+                    },
+                    // Being closed isn't an error worth reporting.
+                    Err(_) if close.is_closed() => break,
+                    Err(e) => {
+                        let fatal = !matches!(e, GpsdError::Json { .. });
+                        debug!("client read error (fatal: {}): {}", fatal, e);
+                        handlers.lock().unwrap().read_error.iter_mut().for_each(|f| f(&e));
+                        if fatal {
+                            break;
+                        }
+                    }
+                }
+            }
+            conn.finish();
+        }));
+    }
+    /// The most recent reports the read loop has seen, kept up to date as it
+    /// runs. See `latest`.
+    pub fn latest(&self) -> LatestState {
+        self.latest.clone()
+    }
+    /// The most recent TPV report, from any device.
+    pub fn latest_fix(&self) -> Option<TpvResponse> {
+        self.latest.latest_fix()
+    }
+    /// The most recent SKY report, from any device.
+    pub fn latest_sky(&self) -> Option<SkyResponse> {
+        self.latest.latest_sky()
+    }
+    /// Whether the read loop is currently running.
+    pub fn is_running(&self) -> bool {
+        self.thread.as_ref().map(|t| !t.is_finished()).unwrap_or(false)
+    }
+    /// Wait for the read loop to finish (i.e. the connection to be closed, or
+    /// fail with a fatal error).
+    ///
+    /// Returns immediately if the client was never started.
+    pub fn join(mut self) -> thread::Result<()> {
+        match self.thread.take() {
+            Some(t) => t.join(),
+            None => Ok(())
+        }
+    }
+    /// Stop the read loop, close the connection (see `GpsdConnection::close`)
+    /// and wait for the reader thread to finish.
+    ///
+    /// Returns an error if a handler panicked.
+    pub fn close(mut self) -> thread::Result<()> {
+        self.stop()
+    }
+    fn stop(&mut self) -> thread::Result<()> {
+        if let Some(conn) = self.conn.take() {
+            if let Err(e) = conn.close() {
+                debug!("closing connection failed: {}", e);
+            }
+        }
+        if let Some(ref c) = self.close {
+            c.close();
+        }
+        match self.thread.take() {
+            Some(t) => t.join(),
+            None => Ok(())
+        }
+    }
+}
+impl Drop for GpsdClient {
+    fn drop(&mut self) {
+        // Only stop a running loop; an unstarted connection is just dropped.
+        if self.thread.is_some() {
+            let _ = self.stop();
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! A tokio-util codec for gpsd's wire protocol.
+//!
+//! Only available with the `codec` feature enabled. `GpsdCodec` turns lines
+//! from gpsd into `Response`s, and `Command`s into lines, so you can build
+//! your own framed transports and proxies out of tokio-util's `Framed`,
+//! `FramedRead` and `FramedWrite`:
+//!
+//! ```rust
+//! # use bytes::BytesMut;
+//! # use tokio_util::codec::{Decoder, Encoder};
+//! # use unbounded_gpsd::codec::GpsdCodec;
+//! # use unbounded_gpsd::types::{Command, Response};
+//! let mut codec = GpsdCodec::new();
+//! let mut buf = BytesMut::new();
+//! codec.encode(Command::Version, &mut buf).unwrap();
+//! assert_eq!(&buf[..], b"?VERSION;\n");
+//!
+//! let mut buf = BytesMut::from(&br#"{"class":"VERSION","release":"3.17","rev":"3.17","proto_major":3,"proto_minor":12}"#[..]);
+//! assert!(codec.decode(&mut buf).unwrap().is_none());
+//! buf.extend_from_slice(b"\n");
+//! match codec.decode(&mut buf).unwrap() {
+//!     Some(Ok(Response::Version { release, .. })) => assert_eq!(release, "3.17"),
+//!     x => panic!("{:?}", x)
+//! }
+//! ```
+//!
+//! Decoding errors that only affect one line (it isn't a response we
+//! recognise, or is too long) are yielded as items, so a stream of them
+//! carries on past a bad line; only I/O errors end it.
+
+use std::io;
+use bytes::{BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+use crate::types::{Command, Response};
+use crate::{decode_line, parse_line, GpsdResult};
+
+/// The longest line accepted by default. gpsd's own limit is much smaller.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// Decodes lines into `Response`s, and encodes `Command`s into lines.
+#[derive(Debug, Clone)]
+pub struct GpsdCodec {
+    raw: bool,
+    max_line_length: usize,
+    /// Whether we're throwing away the rest of an overlong line.
+    discarding: bool,
+    /// How far into the buffer we've already looked for a newline.
+    searched: usize
+}
+impl Default for GpsdCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl GpsdCodec {
+    /// A codec expecting JSON responses.
+    pub fn new() -> Self {
+        Self { raw: false, max_line_length: DEFAULT_MAX_LINE_LENGTH, discarding: false, searched: 0 }
+    }
+    /// Whether to return lines that aren't JSON as `Response::Raw`, as with
+    /// RAW mode. Encoding a `Command::Watch` sets this according to its `raw`
+    /// field.
+    pub fn set_raw(&mut self, raw: bool) {
+        self.raw = raw;
+    }
+    /// Reject lines longer than `max` bytes, rather than buffering them.
+    pub fn with_max_line_length(mut self, max: usize) -> Self {
+        self.max_line_length = max;
+        self
+    }
+    fn too_long() -> GpsdResult<Response> {
+        Err(io::Error::new(io::ErrorKind::InvalidData, "line from gpsd too long").into())
+    }
+}
+impl Decoder for GpsdCodec {
+    type Item = GpsdResult<Response>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+        loop {
+            let newline = src[self.searched..].iter().position(|&b| b == b'\n').map(|i| self.searched + i);
+            let end = match newline {
+                Some(i) => i + 1,
+                None => {
+                    if self.discarding {
+                        src.clear();
+                        self.searched = 0;
+                    } else if src.len() > self.max_line_length {
+                        src.clear();
+                        self.searched = 0;
+                        self.discarding = true;
+                        return Ok(Some(Self::too_long()));
+                    } else {
+                        self.searched = src.len();
+                    }
+                    return Ok(None);
+                }
+            };
+            let line = src.split_to(end);
+            self.searched = 0;
+            if self.discarding {
+                // The rest of the overlong line, which has been reported.
+                self.discarding = false;
+                continue;
+            }
+            if line.len() > self.max_line_length {
+                return Ok(Some(Self::too_long()));
+            }
+            let line = decode_line(line.to_vec());
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Ok(Some(parse_line(line, self.raw)));
+        }
+    }
+    fn decode_eof(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+        if let Some(item) = self.decode(src)? {
+            return Ok(Some(item));
+        }
+        // Treat whatever's left as a final line.
+        let rest = src.split_to(src.len());
+        self.searched = 0;
+        if self.discarding || rest.is_empty() {
+            self.discarding = false;
+            return Ok(None);
+        }
+        let line = decode_line(rest.to_vec());
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(parse_line(line, self.raw)))
+    }
+}
+impl Encoder<Command> for GpsdCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, cmd: Command, dst: &mut BytesMut) -> io::Result<()> {
+        self.encode(&cmd, dst)
+    }
+}
+impl<'a> Encoder<&'a Command> for GpsdCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, cmd: &'a Command, dst: &mut BytesMut) -> io::Result<()> {
+        if let Command::Watch(ref w) = *cmd {
+            self.raw = w.sends_raw();
+        }
+        dst.put_slice(cmd.to_line().as_bytes());
+        Ok(())
+    }
+}
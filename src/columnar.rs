@@ -0,0 +1,194 @@
+//! Writing fixes to Parquet files, for analysis in Polars, DuckDB and the
+//! like.
+//!
+//! Only available with the `parquet` feature enabled. A `TpvBatcher`
+//! collects TPV reports into Apache Arrow record batches, one row each, with
+//! the columns in `schema`; a `ParquetWriter` feeds those batches to a
+//! Snappy-compressed Parquet file. Values a report doesn't have are nulls,
+//! and times are UTC timestamps to the nanosecond, so
+//! `duckdb -c "SELECT * FROM 'track.parquet'"` or `pl.read_parquet` gives
+//! properly typed columns straight away.
+//!
+//! Columnar storage is what makes this worthwhile for big recordings: a day
+//! of 10 Hz fixes is under a million rows, compresses well, and a query
+//! only reads the columns it uses.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use arrow_array::builder::{Float64Builder, StringBuilder, TimestampNanosecondBuilder, UInt8Builder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use crate::errors::GpsdResult;
+use crate::timestamp::to_epoch_nanos;
+use crate::types::{Response, TpvResponse};
+use crate::units::ErrorEstimate;
+
+/// Rows a `ParquetWriter` batches up by default before writing them out.
+pub const DEFAULT_BATCH_SIZE: usize = 8192;
+
+/// The columns of a `TpvBatcher`'s record batches, all nullable: `time`
+/// (as a UTC timestamp), `device`, `mode` (0 to 3, as gpsd sends it), then
+/// `lat`, `lon`, `alt`, `speed`, `track`, `climb`, and the error estimates
+/// `ept`, `epx`, `epy`, `epv`, `epd`, `eps` and `epc`, all as gpsd names and
+/// measures them.
+pub fn schema() -> SchemaRef {
+    static SCHEMA: OnceLock<SchemaRef> = OnceLock::new();
+    SCHEMA.get_or_init(|| {
+        let mut fields = vec![
+            Field::new("time", DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())), true),
+            Field::new("device", DataType::Utf8, true),
+            Field::new("mode", DataType::UInt8, true)
+        ];
+        fields.extend(FLOAT_COLUMNS.iter().map(|name| Field::new(*name, DataType::Float64, true)));
+        Arc::new(Schema::new(fields))
+    }).clone()
+}
+/// The `Float64` columns, in order.
+const FLOAT_COLUMNS: [&str; 13] = [
+    "lat", "lon", "alt", "speed", "track", "climb", "ept", "epx", "epy", "epv", "epd", "eps", "epc"
+];
+
+/// Collects TPV reports into Arrow record batches.
+pub struct TpvBatcher {
+    time: TimestampNanosecondBuilder,
+    device: StringBuilder,
+    mode: UInt8Builder,
+    floats: Vec<Float64Builder>,
+    rows: usize
+}
+impl Default for TpvBatcher {
+    fn default() -> Self {
+        TpvBatcher {
+            time: TimestampNanosecondBuilder::new().with_timezone("UTC"),
+            device: StringBuilder::new(),
+            mode: UInt8Builder::new(),
+            floats: FLOAT_COLUMNS.iter().map(|_| Float64Builder::new()).collect(),
+            rows: 0
+        }
+    }
+}
+impl TpvBatcher {
+    /// An empty batcher.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add a row for the report.
+    pub fn push(&mut self, tpv: &TpvResponse) {
+        let e = tpv.errors();
+        let value = |est: Option<ErrorEstimate>| est.map(|est| est.value);
+        let floats = [
+            tpv.lat(), tpv.lon(), tpv.alt(), tpv.speed(), tpv.track(), tpv.climb(),
+            value(e.time), value(e.lon), value(e.lat), value(e.alt), value(e.track), value(e.speed), value(e.climb)
+        ];
+        self.time.append_option(tpv.time().as_ref().and_then(to_epoch_nanos));
+        self.device.append_option(tpv.device());
+        self.mode.append_option(tpv.mode().map(u8::from));
+        for (builder, v) in self.floats.iter_mut().zip(floats.iter()) {
+            builder.append_option(*v);
+        }
+        self.rows += 1;
+    }
+    /// Number of rows added since the last batch was taken.
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+    /// Whether no rows have been added since the last batch was taken.
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+    /// Take the rows added so far as a record batch, leaving the batcher
+    /// empty.
+    pub fn finish(&mut self) -> RecordBatch {
+        let mut columns: Vec<ArrayRef> = vec![
+            Arc::new(self.time.finish()),
+            Arc::new(self.device.finish()),
+            Arc::new(self.mode.finish())
+        ];
+        columns.extend(self.floats.iter_mut().map(|b| Arc::new(b.finish()) as ArrayRef));
+        self.rows = 0;
+        RecordBatch::try_new(schema(), columns).expect("batch columns match the schema")
+    }
+}
+
+/// Writes TPV reports to a Parquet file, a batch at a time.
+pub struct ParquetWriter<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    batcher: TpvBatcher,
+    batch_size: usize,
+    rows: u64
+}
+impl ParquetWriter<File> {
+    /// Write to a new file at `path`, replacing any existing file.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, ParquetError> {
+        Self::new(File::create(path)?)
+    }
+}
+impl<W: Write + Send> ParquetWriter<W> {
+    /// Write to `out`.
+    pub fn new(out: W) -> Result<Self, ParquetError> {
+        let props = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+        Ok(Self {
+            writer: ArrowWriter::try_new(out, schema(), Some(props))?,
+            batcher: TpvBatcher::new(),
+            batch_size: DEFAULT_BATCH_SIZE,
+            rows: 0
+        })
+    }
+    /// Batch up `rows` rows (at least one) before writing them out, rather
+    /// than `DEFAULT_BATCH_SIZE`.
+    pub fn with_batch_size(mut self, rows: usize) -> Self {
+        self.batch_size = rows.max(1);
+        self
+    }
+    /// Add a row for the report, writing out the batch if it's full.
+    pub fn write_tpv(&mut self, tpv: &TpvResponse) -> Result<(), ParquetError> {
+        self.batcher.push(tpv);
+        self.rows += 1;
+        if self.batcher.len() >= self.batch_size {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+    /// Add a row if the response is a TPV report. Returns whether it was.
+    pub fn write_response(&mut self, resp: &Response) -> Result<bool, ParquetError> {
+        match *resp {
+            Response::Tpv(ref tpv) => self.write_tpv(tpv).map(|_| true),
+            _ => Ok(false)
+        }
+    }
+    /// Add a row for each TPV report from `responses` until it ends,
+    /// returning how many were added, then write out the batch. As with
+    /// `CsvWriter::consume`, unparseable responses are skipped and other
+    /// errors stop it, with the rows added before still written out.
+    /// Parquet errors come back as I/O errors.
+    pub fn consume<I: IntoIterator<Item = GpsdResult<Response>>>(&mut self, responses: I) -> GpsdResult<u64> {
+        crate::consume(self, responses,
+                       |w, resp| Ok(w.write_response(resp).map_err(io::Error::other)?),
+                       |w| Ok(w.flush_batch().map_err(io::Error::other)?))
+    }
+    /// Number of rows added so far.
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+    /// Write out the rows batched up so far, even if there aren't a full
+    /// batch's worth.
+    pub fn flush_batch(&mut self) -> Result<(), ParquetError> {
+        if !self.batcher.is_empty() {
+            let batch = self.batcher.finish();
+            self.writer.write(&batch)?;
+        }
+        Ok(())
+    }
+    /// Write out any remaining rows and the file footer, giving back the
+    /// writer. A file isn't readable until this is done.
+    pub fn finish(mut self) -> Result<W, ParquetError> {
+        self.flush_batch()?;
+        self.writer.into_inner()
+    }
+}
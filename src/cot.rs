@@ -0,0 +1,131 @@
+//! TPV reports as Cursor-on-Target position events, for ATAK and TAK
+//! servers.
+//!
+//! A `CotGenerator` turns each TPV report with a position into a CoT
+//! `event`, as one line of XML, for one unit identified by its `uid`. Laid
+//! out, and without the XML declaration, one looks like this:
+//!
+//! ```xml
+//! <event version="2.0" uid="gpsd-1" type="a-f-G-U-C" how="m-g" time="2017-07-01T12:00:00.000Z"
+//!        start="2017-07-01T12:00:00.000Z" stale="2017-07-01T12:02:00.000Z">
+//!   <point lat="59.345" lon="18.054" hae="100.7" ce="3.6" le="5.1"/>
+//!   <detail>...</detail>
+//! </event>
+//! ```
+//!
+//! CoT wants heights above the WGS84 ellipsoid, so `hae` is `altHAE` if
+//! gpsd sent it, or `altMSL` (or `alt`) plus `geoidSep`. `ce` and `le` are
+//! one-sigma errors in meters: `le` from `epv`, and `ce` the horizontal RMS
+//! error, from `epx` and `epy` combined or failing those from `eph`. gpsd's
+//! estimates are at 95% confidence, so they're scaled down; `eph` is the
+//! radius of a 95% circle, which takes more scaling than the errors along
+//! one axis. Anything unknown is CoT's 9999999.0. The `detail` has the unit's
+//! callsign, if it was given one, and its course and speed.
+//!
+//! Events are stamped with the report's time, and go stale a while after
+//! (two minutes unless `with_stale` says otherwise), after which TAK clients
+//! show the unit as out of date. Receivers drop events that are stale by
+//! their own clock, so replayed sessions may need a long stale time.
+//!
+//! Sending is up to the caller: a TAK server takes events over TCP, and
+//! ATAK devices on a LAN listen for them on the SA multicast group,
+//! 239.2.3.1 port 6969.
+
+use std::time::Duration;
+use crate::gpx::escape;
+use crate::timestamp::{format_millis, from_epoch_nanos, now, to_epoch_nanos};
+use crate::types::TpvResponse;
+use crate::units::{ErrorEstimate, ErrorUnit};
+
+/// What CoT uses for an unknown height or error.
+pub const UNKNOWN: f64 = 9_999_999.0;
+/// The event type `CotGenerator::new` uses: a friendly ground unit, as ATAK
+/// reports its own position.
+pub const DEFAULT_TYPE: &str = "a-f-G-U-C";
+/// How long events stay current by default.
+pub const DEFAULT_STALE: Duration = Duration::from_secs(120);
+/// The horizontal RMS error for a 95% radius: with the same normal error σ
+/// along each axis, 95% of fixes are within √(-2 ln 0.05)σ, and the RMS
+/// error is √2σ.
+const EPH_TO_RMS: f64 = std::f64::consts::SQRT_2 / 2.447_746_830_680_816;
+
+/// Makes CoT position events for a unit from its TPV reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CotGenerator {
+    uid: String,
+    event_type: String,
+    callsign: Option<String>,
+    stale: Duration
+}
+impl CotGenerator {
+    /// Events for the unit `uid`, which should be unique to it and stay the
+    /// same between runs.
+    pub fn new(uid: &str) -> Self {
+        CotGenerator { uid: uid.to_owned(), event_type: DEFAULT_TYPE.to_owned(), callsign: None, stale: DEFAULT_STALE }
+    }
+    /// Use the CoT type `event_type` (e.g. `a-f-G-E-V-C` for a vehicle)
+    /// rather than `DEFAULT_TYPE`.
+    pub fn with_type(mut self, event_type: &str) -> Self {
+        self.event_type = event_type.to_owned();
+        self
+    }
+    /// Name the unit `callsign` on maps.
+    pub fn with_callsign(mut self, callsign: &str) -> Self {
+        self.callsign = Some(callsign.to_owned());
+        self
+    }
+    /// Have events go stale `stale` after their time, rather than after
+    /// `DEFAULT_STALE`.
+    pub fn with_stale(mut self, stale: Duration) -> Self {
+        self.stale = stale;
+        self
+    }
+    /// The event for the report, or `None` if it hasn't got a valid
+    /// position. Reports without a time are stamped with the current time.
+    pub fn event(&self, tpv: &TpvResponse) -> Option<String> {
+        let pos = tpv.position()?;
+        let extra = |k: &str| tpv.extra().get(k).and_then(|v| v.as_f64());
+        let hae = pos.height_above_ellipsoid();
+        let sigma = |e: Option<ErrorEstimate>| e.filter(|e| e.unit == ErrorUnit::Meters).map(ErrorEstimate::one_sigma);
+        let e = tpv.errors();
+        let ce = match (sigma(e.lon), sigma(e.lat)) {
+            (Some(x), Some(y)) => Some(x.hypot(y)),
+            _ => extra("eph").map(|eph| eph * EPH_TO_RMS)
+        };
+        let time = tpv.time().unwrap_or_else(now);
+        let stale = to_epoch_nanos(&time)
+            .and_then(|n| from_epoch_nanos(i128::from(n) + self.stale.as_nanos() as i128))
+            .unwrap_or(time);
+        let (time, stale) = (format_millis(&time), format_millis(&stale));
+
+        let mut xml = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\
+             <event version=\"2.0\" uid=\"{}\" type=\"{}\" how=\"m-g\" time=\"{}\" start=\"{}\" stale=\"{}\">",
+            escape(&self.uid), escape(&self.event_type), time, time, stale
+        );
+        xml += &format!(
+            "<point lat=\"{}\" lon=\"{}\" hae=\"{}\" ce=\"{}\" le=\"{}\"/><detail>",
+            pos.lat.degrees(), pos.lon.degrees(), hae.unwrap_or(UNKNOWN),
+            ce.unwrap_or(UNKNOWN), sigma(e.alt).unwrap_or(UNKNOWN)
+        );
+        if let Some(ref callsign) = self.callsign {
+            xml += &format!("<contact callsign=\"{}\"/>", escape(callsign));
+        }
+        if tpv.track().is_some() || tpv.speed().is_some() {
+            // Course in degrees from true north, speed in meters per second.
+            xml += "<track";
+            if let Some(course) = tpv.track() {
+                xml += &format!(" course=\"{}\"", course);
+            }
+            if let Some(speed) = tpv.speed() {
+                xml += &format!(" speed=\"{}\"", speed);
+            }
+            xml += "/>";
+        }
+        xml += &format!(
+            "<precisionlocation geopointsrc=\"GPS\" altsrc=\"{}\"/></detail></event>",
+            if hae.is_some() { "GPS" } else { "???" }
+        );
+        Some(xml)
+    }
+}
@@ -0,0 +1,138 @@
+//! Writing fixes out as CSV, for spreadsheets and data frames.
+//!
+//! A `CsvWriter` writes a header row, then one row for each TPV report, with
+//! the columns it was given. Columns are named as gpsd names the fields, and
+//! anything a report doesn't have is left empty, so the output loads straight
+//! into pandas (`pd.read_csv(path, parse_dates=["time"])`) or a spreadsheet.
+//!
+//! `CsvWriter::consume` takes a whole response stream, like a
+//! `GpsdConnection`, skipping everything that isn't a TPV report.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::errors::GpsdResult;
+use crate::timestamp::format_millis;
+use crate::types::{Response, TpvResponse};
+
+/// A column (or, for `Errors`, a group of columns) in the CSV output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CsvColumn {
+    /// `time`, as RFC 3339 to the millisecond.
+    Time,
+    /// `lat`, in degrees.
+    Lat,
+    /// `lon`, in degrees.
+    Lon,
+    /// `alt`, in meters.
+    Alt,
+    /// `speed` over ground, in meters per second.
+    Speed,
+    /// `track`, the course over ground in degrees from true north.
+    Track,
+    /// `mode`, the fix type: 0 to 3, as gpsd sends it.
+    Mode,
+    /// The error estimates `ept`, `epx`, `epy`, `epv`, `epd`, `eps` and
+    /// `epc`, as seven columns.
+    Errors
+}
+impl CsvColumn {
+    /// Every column, in the order `CsvWriter::new` writes them.
+    pub const ALL: &'static [CsvColumn] = &[
+        CsvColumn::Time, CsvColumn::Lat, CsvColumn::Lon, CsvColumn::Alt,
+        CsvColumn::Speed, CsvColumn::Track, CsvColumn::Mode, CsvColumn::Errors
+    ];
+    /// The names of the columns this makes.
+    fn names(self) -> &'static [&'static str] {
+        match self {
+            CsvColumn::Time => &["time"],
+            CsvColumn::Lat => &["lat"],
+            CsvColumn::Lon => &["lon"],
+            CsvColumn::Alt => &["alt"],
+            CsvColumn::Speed => &["speed"],
+            CsvColumn::Track => &["track"],
+            CsvColumn::Mode => &["mode"],
+            CsvColumn::Errors => &["ept", "epx", "epy", "epv", "epd", "eps", "epc"]
+        }
+    }
+}
+
+/// `v` as a CSV field, or an empty one.
+fn field<T: ToString>(v: Option<T>) -> String {
+    v.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// Writes TPV reports to a CSV file, one row each.
+pub struct CsvWriter<W: Write> {
+    out: W,
+    columns: Vec<CsvColumn>,
+    rows: u64
+}
+impl CsvWriter<BufWriter<File>> {
+    /// Write every column to a new file at `path`, replacing any existing
+    /// file.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+impl<W: Write> CsvWriter<W> {
+    /// Write every column to `out`, starting with the header row.
+    pub fn new(out: W) -> io::Result<Self> {
+        Self::with_columns(out, CsvColumn::ALL)
+    }
+    /// Write `columns`, in that order, to `out`, starting with the header
+    /// row.
+    pub fn with_columns(mut out: W, columns: &[CsvColumn]) -> io::Result<Self> {
+        let header = columns.iter().flat_map(|c| c.names().iter().copied()).collect::<Vec<_>>();
+        writeln!(out, "{}", header.join(","))?;
+        Ok(Self { out, columns: columns.to_vec(), rows: 0 })
+    }
+    /// Write a row for the report.
+    pub fn write_tpv(&mut self, tpv: &TpvResponse) -> io::Result<()> {
+        let mut fields = Vec::new();
+        for column in &self.columns {
+            match *column {
+                CsvColumn::Time => fields.push(field(tpv.time().map(|t| format_millis(&t)))),
+                CsvColumn::Lat => fields.push(field(tpv.lat())),
+                CsvColumn::Lon => fields.push(field(tpv.lon())),
+                CsvColumn::Alt => fields.push(field(tpv.alt())),
+                CsvColumn::Speed => fields.push(field(tpv.speed())),
+                CsvColumn::Track => fields.push(field(tpv.track())),
+                CsvColumn::Mode => fields.push(field(tpv.mode().map(u8::from))),
+                CsvColumn::Errors => {
+                    let e = tpv.errors();
+                    for est in &[e.time, e.lon, e.lat, e.alt, e.track, e.speed, e.climb] {
+                        fields.push(field(est.map(|est| est.value)));
+                    }
+                }
+            }
+        }
+        writeln!(self.out, "{}", fields.join(","))?;
+        self.rows += 1;
+        Ok(())
+    }
+    /// Write a row if the response is a TPV report. Returns whether it was.
+    pub fn write_response(&mut self, resp: &Response) -> io::Result<bool> {
+        match *resp {
+            Response::Tpv(ref tpv) => self.write_tpv(tpv).map(|_| true),
+            _ => Ok(false)
+        }
+    }
+    /// Write a row for each TPV report from `responses` until it ends,
+    /// returning how many were written, and flush the output. Responses
+    /// that couldn't be parsed are logged and skipped. Any other error, from
+    /// the stream or from writing, stops it, though what was written before
+    /// is still flushed.
+    pub fn consume<I: IntoIterator<Item = GpsdResult<Response>>>(&mut self, responses: I) -> GpsdResult<u64> {
+        crate::consume(self, responses, |w, resp| Ok(w.write_response(resp)?), |w| Ok(w.out.flush()?))
+    }
+    /// Number of rows written so far, not counting the header.
+    pub fn rows(&self) -> u64 {
+        self.rows
+    }
+    /// Flush the output, giving back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
@@ -0,0 +1,74 @@
+//! Thinning out TPV reports from fast receivers.
+//!
+//! A receiver running at 10 Hz produces ten TPVs a second; plenty of
+//! applications only want one. With a `Decimation` set on a `GpsdConnection`,
+//! excess TPVs are dropped as they're read, before they reach iterators,
+//! channels or handlers. Other responses are never dropped.
+//!
+//! Each device is decimated separately, so reports from one receiver don't
+//! crowd out another's.
+
+use std::collections::HashMap;
+use std::time::Duration;
+use crate::timestamp::{self, Timestamp};
+use crate::types::TpvResponse;
+
+/// Which TPV reports to keep.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decimation {
+    /// Keep the first report, then every `n`th after it.
+    EveryNth(u32),
+    /// Keep a report only if at least this long has passed (going by the
+    /// reports' own timestamps) since the last one kept. Reports without a
+    /// timestamp are always kept.
+    MinInterval(Duration)
+}
+
+#[derive(Default)]
+struct DeviceState {
+    seen: u32,
+    last_kept: Option<Timestamp>
+}
+
+/// Applies a `Decimation` to a stream of TPV reports.
+pub struct Decimator {
+    rule: Decimation,
+    devices: HashMap<Option<String>, DeviceState>
+}
+impl Decimator {
+    /// A decimator applying `rule`.
+    pub fn new(rule: Decimation) -> Self {
+        Self { rule, devices: HashMap::new() }
+    }
+    /// The rule being applied.
+    pub fn rule(&self) -> &Decimation {
+        &self.rule
+    }
+    /// Whether to keep `tpv`.
+    pub fn accept(&mut self, tpv: &TpvResponse) -> bool {
+        let state = self.devices.entry(tpv.device().map(String::from)).or_default();
+        match self.rule {
+            Decimation::EveryNth(n) => {
+                let keep = state.seen == 0;
+                state.seen = (state.seen + 1) % n.max(1);
+                keep
+            },
+            Decimation::MinInterval(min) => {
+                let time = match tpv.time() {
+                    Some(t) => t,
+                    None => return true
+                };
+                let keep = match state.last_kept {
+                    // A timestamp going backwards means the receiver was reset,
+                    // or the clock was stepped; start again from here.
+                    Some(last) if time >= last => timestamp::elapsed(last, time).unwrap_or_default() >= min,
+                    _ => true
+                };
+                if keep {
+                    state.last_kept = Some(time);
+                }
+                keep
+            }
+        }
+    }
+}
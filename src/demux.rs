@@ -0,0 +1,121 @@
+//! Splitting responses from several receivers into per-device streams.
+//!
+//! When gpsd manages more than one device, TPV, SKY and DEVICE reports are
+//! tagged with the device they came from. A `Demux` routes each response to the
+//! stream registered for its device, and everything else (untagged responses,
+//! and reports from devices nobody asked for) to the default stream. Device
+//! names are compared as `DevicePath::matches` compares them.
+
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use crate::types::{normalize_path, Response};
+use crate::GpsdConnection;
+
+struct Routes {
+    devices: HashMap<String, SyncSender<Response>>,
+    default: Option<SyncSender<Response>>
+}
+
+/// Routes responses into per-device channels.
+///
+/// Clones share the same routes, so streams can be added from anywhere, even
+/// while a reader thread started with `GpsdConnection::spawn_demux` is running.
+///
+/// Each stream buffers up to `capacity` responses. When a stream is full,
+/// routing blocks until its receiver catches up, so one slow consumer holds up
+/// the rest. Streams whose receivers have been dropped are forgotten.
+#[derive(Clone)]
+pub struct Demux {
+    routes: Arc<Mutex<Routes>>,
+    capacity: usize
+}
+impl Demux {
+    /// A demux with no streams, buffering up to `capacity` responses per stream.
+    pub fn new(capacity: usize) -> Self {
+        let routes = Routes { devices: HashMap::new(), default: None };
+        Self { routes: Arc::new(Mutex::new(routes)), capacity }
+    }
+    /// A stream of responses from `device` (e.g. `/dev/ttyUSB0`).
+    ///
+    /// Asking for the same device again replaces the earlier stream.
+    pub fn device(&self, device: &str) -> Receiver<Response> {
+        let (tx, rx) = mpsc::sync_channel(self.capacity);
+        self.routes.lock().unwrap().devices.insert(normalize_path(device).into_owned(), tx);
+        rx
+    }
+    /// The stream of responses not routed to any device stream.
+    ///
+    /// Asking again replaces the earlier stream. If there is no default
+    /// stream, those responses are dropped.
+    pub fn default_stream(&self) -> Receiver<Response> {
+        let (tx, rx) = mpsc::sync_channel(self.capacity);
+        self.routes.lock().unwrap().default = Some(tx);
+        rx
+    }
+    /// Devices that currently have a stream, normalized as by
+    /// `DevicePath::normalized`.
+    pub fn devices(&self) -> Vec<String> {
+        self.routes.lock().unwrap().devices.keys().cloned().collect()
+    }
+    /// Route one response to its stream.
+    pub fn route(&self, resp: Response) {
+        let key = resp.device().map(|d| normalize_path(d).into_owned());
+        let tx = {
+            let routes = self.routes.lock().unwrap();
+            key.as_ref()
+                .and_then(|k| routes.devices.get(k))
+                .or(routes.default.as_ref())
+                .cloned()
+        };
+        let tx = match tx {
+            Some(tx) => tx,
+            None => {
+                debug!("no stream for response from {:?}, dropping", key);
+                return;
+            }
+        };
+        // Send without holding the lock, so a full stream doesn't stop others
+        // from being added.
+        let res = match tx.try_send(resp) {
+            Err(TrySendError::Full(resp)) => tx.send(resp).map_err(|_| ()),
+            Err(TrySendError::Disconnected(_)) => Err(()),
+            Ok(()) => Ok(())
+        };
+        if res.is_err() {
+            let mut routes = self.routes.lock().unwrap();
+            match key {
+                Some(ref k) if routes.devices.contains_key(k) => {
+                    debug!("stream for {} dropped", k);
+                    routes.devices.remove(k);
+                },
+                _ => routes.default = None
+            }
+        }
+    }
+    /// Whether nothing can receive responses any more: no streams are left,
+    /// and there are no other clones of this demux to add them.
+    fn is_orphaned(&self) -> bool {
+        let routes = self.routes.lock().unwrap();
+        routes.devices.is_empty() && routes.default.is_none() && Arc::strong_count(&self.routes) == 1
+    }
+}
+
+impl GpsdConnection {
+    /// Move the connection onto a background reader thread, routing its
+    /// responses through `demux`.
+    ///
+    /// As with `spawn_channel`, responses that fail to deserialize are logged
+    /// and skipped. The thread exits when the connection fails, or once every
+    /// stream and every clone of `demux` has been dropped.
+    pub fn spawn_demux(self, demux: Demux) {
+        self.spawn_reader(move |resp| {
+            demux.route(resp);
+            let orphaned = demux.is_orphaned();
+            if orphaned {
+                debug!("all demux streams dropped, stopping reader");
+            }
+            !orphaned
+        });
+    }
+}
@@ -0,0 +1,209 @@
+//! Finding gpsd instances on the local network with mDNS.
+//!
+//! Only available with the `mdns` feature enabled. gpsd can be advertised
+//! (e.g. by Avahi, or gpsd's own zeroconf support) as the DNS-SD service
+//! `_gpsd._tcp`; `discover` sends a one-shot mDNS query for it and collects
+//! the answers.
+//!
+//! Only IPv4 multicast is used for the query, but responders may still report
+//! IPv6 addresses for the hosts they know about.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// The DNS-SD service type gpsd is advertised as.
+pub const SERVICE: &str = "_gpsd._tcp.local";
+
+const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+
+/// A gpsd instance that answered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredGpsd {
+    /// The advertised instance name, e.g. `gpsd on boat._gpsd._tcp.local`.
+    pub instance: String,
+    /// The host it's running on, e.g. `boat.local`.
+    pub host: String,
+    /// The port it's listening on.
+    pub port: u16,
+    /// Addresses of the host, as reported by the responder. May be empty if
+    /// the responder didn't include them, in which case resolve `host`.
+    pub addrs: Vec<IpAddr>
+}
+impl DiscoveredGpsd {
+    /// Socket addresses to connect to, suitable for `GpsdConnection::new`.
+    pub fn socket_addrs(&self) -> Vec<SocketAddr> {
+        self.addrs.iter().map(|&ip| SocketAddr::new(ip, self.port)).collect()
+    }
+}
+
+/// Query for gpsd instances, waiting up to `timeout` for answers.
+///
+/// Instances that answer more than once are only returned once.
+pub fn discover(timeout: Duration) -> io::Result<Vec<DiscoveredGpsd>> {
+    // Querying from a port other than 5353 makes this a "one-shot" query
+    // (RFC 6762 section 5.1), answered by unicast straight back to us.
+    let sock = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    sock.set_multicast_ttl_v4(255)?;
+    sock.send_to(&query(SERVICE), (MDNS_ADDR, MDNS_PORT))?;
+    let deadline = Instant::now() + timeout;
+    let mut found: Vec<DiscoveredGpsd> = Vec::new();
+    let mut buf = [0; 9000];
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        sock.set_read_timeout(Some(deadline - now))?;
+        let n = match sock.recv_from(&mut buf) {
+            Ok((n, _)) => n,
+            Err(ref e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+            Err(e) => return Err(e)
+        };
+        match parse_response(&buf[..n], SERVICE) {
+            Some(instances) => {
+                for inst in instances {
+                    if !found.iter().any(|f| f.instance == inst.instance) {
+                        found.push(inst);
+                    }
+                }
+            },
+            None => debug!("ignoring malformed mDNS packet")
+        }
+    }
+    Ok(found)
+}
+
+/// Encode `name` as DNS labels.
+fn push_name(out: &mut Vec<u8>, name: &str) {
+    for label in name.trim_end_matches('.').split('.') {
+        out.push(label.len() as u8);
+        out.extend(label.as_bytes());
+    }
+    out.push(0);
+}
+/// Build a PTR query for `service`.
+pub(crate) fn query(service: &str) -> Vec<u8> {
+    // ID 0, no flags, one question.
+    let mut out = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    push_name(&mut out, service);
+    out.extend(&TYPE_PTR.to_be_bytes());
+    out.extend(&CLASS_IN.to_be_bytes());
+    out
+}
+
+/// A cursor over a DNS message.
+struct Reader<'a> {
+    msg: &'a [u8],
+    pos: usize
+}
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> Option<u8> {
+        let b = *self.msg.get(self.pos)?;
+        self.pos += 1;
+        Some(b)
+    }
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from(self.u8()?) << 8 | u16::from(self.u8()?))
+    }
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        let b = self.msg.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(b)
+    }
+    /// Read a possibly-compressed name.
+    fn name(&mut self) -> Option<String> {
+        let mut labels = Vec::new();
+        let mut pos = self.pos;
+        let mut jumped = false;
+        // Bound the number of pointers followed, in case they loop.
+        for _ in 0..128 {
+            let len = *self.msg.get(pos)? as usize;
+            match len {
+                0 => {
+                    if !jumped {
+                        self.pos = pos + 1;
+                    }
+                    return Some(labels.join("."));
+                },
+                l if l & 0xC0 == 0xC0 => {
+                    let target = (l & 0x3F) << 8 | *self.msg.get(pos + 1)? as usize;
+                    if !jumped {
+                        self.pos = pos + 2;
+                    }
+                    jumped = true;
+                    pos = target;
+                },
+                l => {
+                    let label = self.msg.get(pos + 1..pos + 1 + l)?;
+                    labels.push(String::from_utf8_lossy(label).into_owned());
+                    pos += 1 + l;
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Pull the instances of `service` out of an mDNS response. Returns `None` if
+/// the packet is malformed or isn't a response.
+pub(crate) fn parse_response(msg: &[u8], service: &str) -> Option<Vec<DiscoveredGpsd>> {
+    let mut r = Reader { msg, pos: 0 };
+    let _id = r.u16()?;
+    let flags = r.u16()?;
+    if flags & 0x8000 == 0 {
+        return None;
+    }
+    let qd = r.u16()?;
+    let records = r.u16()? as usize + r.u16()? as usize + r.u16()? as usize;
+    for _ in 0..qd {
+        r.name()?;
+        r.bytes(4)?;
+    }
+    let mut instances = Vec::new();
+    let mut srv = HashMap::new();
+    let mut addrs: HashMap<String, Vec<IpAddr>> = HashMap::new();
+    for _ in 0..records {
+        let name = r.name()?.to_lowercase();
+        let rtype = r.u16()?;
+        let _class = r.u16()?;
+        let _ttl = r.bytes(4)?;
+        let len = r.u16()? as usize;
+        let end = r.pos.checked_add(len)?;
+        match rtype {
+            TYPE_PTR if name == service.to_lowercase() => instances.push(r.name()?),
+            TYPE_SRV => {
+                let _prio = r.u16()?;
+                let _weight = r.u16()?;
+                let port = r.u16()?;
+                srv.insert(name, (r.name()?, port));
+            },
+            TYPE_A if len == 4 => {
+                let b = r.bytes(4)?;
+                addrs.entry(name).or_default().push(IpAddr::V4(Ipv4Addr::new(b[0], b[1], b[2], b[3])));
+            },
+            TYPE_AAAA if len == 16 => {
+                let mut b = [0; 16];
+                b.copy_from_slice(r.bytes(16)?);
+                addrs.entry(name).or_default().push(IpAddr::V6(Ipv6Addr::from(b)));
+            },
+            _ => {}
+        }
+        if end > msg.len() {
+            return None;
+        }
+        r.pos = end;
+    }
+    Some(instances.into_iter().filter_map(|instance| {
+        let (host, port) = srv.get(&instance.to_lowercase())?.clone();
+        let addrs = addrs.get(&host.to_lowercase()).cloned().unwrap_or_default();
+        Some(DiscoveredGpsd { instance, host, port, addrs })
+    }).collect())
+}
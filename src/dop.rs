@@ -0,0 +1,121 @@
+//! Dilution of precision from the satellite view.
+//!
+//! Some receivers report satellites but no DOPs. gpsd computes DOPs from the
+//! geometry of the satellites used in the fix: each one gives a line of sight
+//! from its azimuth and elevation, and the DOPs come from the inverse of the
+//! covariance matrix of those lines. `SkyResponse::computed_dops` does the
+//! same, and `SkyResponse::fill_missing_dops` uses it to fill in whatever the
+//! report left out.
+//!
+//! Like gpsd's, this needs at least four used satellites, and gives nothing if
+//! their geometry is degenerate (say, all at the same elevation and azimuth).
+
+use crate::types::SkyResponse;
+
+/// Below this, a pivot is taken to be zero and the matrix singular.
+const SINGULAR: f64 = 1e-10;
+
+/// DOPs computed from a satellite view.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Dops {
+    /// Longitudinal d.o.p.
+    pub xdop: f64,
+    /// Latitudinal d.o.p.
+    pub ydop: f64,
+    /// Altitude d.o.p.
+    pub vdop: f64,
+    /// Time d.o.p.
+    pub tdop: f64,
+    /// Horizontal d.o.p.
+    pub hdop: f64,
+    /// Spherical d.o.p.
+    pub pdop: f64,
+    /// Hyperspherical d.o.p.
+    pub gdop: f64
+}
+
+/// Invert a 4x4 matrix by Gauss-Jordan elimination; `None` if it's singular.
+fn invert(mut m: [[f64; 4]; 4]) -> Option<[[f64; 4]; 4]> {
+    let mut inv = [[0.0; 4]; 4];
+    for (i, row) in inv.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    for col in 0..4 {
+        let pivot = (col..4).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+        if m[pivot][col].abs() < SINGULAR {
+            return None;
+        }
+        m.swap(col, pivot);
+        inv.swap(col, pivot);
+        let p = m[col][col];
+        for k in 0..4 {
+            m[col][k] /= p;
+            inv[col][k] /= p;
+        }
+        for row in 0..4 {
+            if row != col {
+                let f = m[row][col];
+                for k in 0..4 {
+                    m[row][k] -= f * m[col][k];
+                    inv[row][k] -= f * inv[col][k];
+                }
+            }
+        }
+    }
+    Some(inv)
+}
+
+impl SkyResponse {
+    /// DOPs computed from the azimuth and elevation of the used satellites,
+    /// whatever the report itself says. `None` with fewer than four of them,
+    /// or if their geometry gives no solution.
+    pub fn computed_dops(&self) -> Option<Dops> {
+        let lines: Vec<[f64; 4]> = self.used_satellites()
+            .filter(|s| s.elevation <= 90)
+            .map(|s| {
+                let (az, el) = (f64::from(s.azimuth).to_radians(), f64::from(s.elevation).to_radians());
+                [az.sin() * el.cos(), az.cos() * el.cos(), el.sin(), 1.0]
+            })
+            .collect();
+        if lines.len() < 4 {
+            return None;
+        }
+        let mut cov = [[0.0; 4]; 4];
+        for (i, row) in cov.iter_mut().enumerate() {
+            for (j, c) in row.iter_mut().enumerate() {
+                *c = lines.iter().map(|l| l[i] * l[j]).sum();
+            }
+        }
+        let inv = invert(cov)?;
+        let (x, y, v, t) = (inv[0][0], inv[1][1], inv[2][2], inv[3][3]);
+        if x < 0.0 || y < 0.0 || v < 0.0 || t < 0.0 {
+            return None;
+        }
+        Some(Dops {
+            xdop: x.sqrt(),
+            ydop: y.sqrt(),
+            vdop: v.sqrt(),
+            tdop: t.sqrt(),
+            hdop: (x + y).sqrt(),
+            pdop: (x + y + v).sqrt(),
+            gdop: (x + y + v + t).sqrt()
+        })
+    }
+    /// Fill in any DOPs the report left out with ones computed from the
+    /// satellite view; DOPs it did report are kept. Returns whether the view
+    /// gave DOPs to fill in from.
+    pub fn fill_missing_dops(&mut self) -> bool {
+        let dops = match self.computed_dops() {
+            Some(d) => d,
+            None => return false
+        };
+        for (field, value) in [
+            (&mut self.xdop, dops.xdop), (&mut self.ydop, dops.ydop), (&mut self.vdop, dops.vdop),
+            (&mut self.tdop, dops.tdop), (&mut self.hdop, dops.hdop), (&mut self.pdop, dops.pdop),
+            (&mut self.gdop, dops.gdop)
+        ] {
+            field.get_or_insert(value);
+        }
+        true
+    }
+}
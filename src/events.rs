@@ -0,0 +1,31 @@
+//! Connection lifecycle events.
+//!
+//! A handler registered with `GpsdConnection::on_connection_event` (or
+//! `GpsdClient::on_connection_event`) is told when the link to gpsd comes up,
+//! goes down, and is being re-established, so a UI can show the link status
+//! rather than inferring it from silence.
+
+use std::time::Duration;
+
+/// Something that happened to a connection.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionEvent {
+    /// The connection is up. Reported before the first response is read, and
+    /// again each time the connection is re-established.
+    Connected,
+    /// gpsd sent its VERSION banner (which it does on connecting).
+    VersionReceived {
+        release: String,
+        proto_major: u32,
+        proto_minor: u32
+    },
+    /// gpsd confirmed that watcher mode is enabled.
+    WatchEstablished,
+    /// The connection was lost (or closed), for the given reason.
+    Disconnected(String),
+    /// About to wait `delay`, then make reconnection attempt `attempt`.
+    Reconnecting {
+        attempt: u32,
+        delay: Duration
+    }
+}
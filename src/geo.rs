@@ -0,0 +1,55 @@
+//! Conversions to [geo-types](https://crates.io/crates/geo-types), for using
+//! fixes with the geo crates.
+//!
+//! Only available with the `geo` feature enabled. Points and coordinates are
+//! in degrees, with longitude as `x` and latitude as `y`, as geo expects;
+//! altitude is dropped.
+//!
+//! A TPV report may not have a position, so it converts to an `Option`:
+//!
+//! ```rust
+//! use geo_types::Point;
+//! use unbounded_gpsd::types::TpvResponse;
+//!
+//! let tpv = TpvResponse::default();
+//! let point: Option<Point<f64>> = (&tpv).into();
+//! assert!(point.is_none());
+//! ```
+
+use geo_types::{Coord, Point};
+use crate::types::{Fix, Position, TpvResponse};
+
+impl From<Position> for Coord<f64> {
+    fn from(pos: Position) -> Self {
+        Coord { x: pos.lon.degrees(), y: pos.lat.degrees() }
+    }
+}
+impl From<Position> for Point<f64> {
+    fn from(pos: Position) -> Self {
+        Point(pos.into())
+    }
+}
+/// The report's position, if it has a valid one; see
+/// `TpvResponse::position`.
+impl From<&TpvResponse> for Option<Coord<f64>> {
+    fn from(tpv: &TpvResponse) -> Self {
+        tpv.position().map(Coord::from)
+    }
+}
+/// The report's position, if it has a valid one; see
+/// `TpvResponse::position`.
+impl From<&TpvResponse> for Option<Point<f64>> {
+    fn from(tpv: &TpvResponse) -> Self {
+        tpv.position().map(Point::from)
+    }
+}
+impl From<&Fix> for Coord<f64> {
+    fn from(fix: &Fix) -> Self {
+        Coord { x: fix.lon, y: fix.lat }
+    }
+}
+impl From<&Fix> for Point<f64> {
+    fn from(fix: &Fix) -> Self {
+        Point(fix.into())
+    }
+}
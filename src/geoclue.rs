@@ -0,0 +1,453 @@
+//! Serving fixes to desktop apps as a GeoClue2 service, over D-Bus.
+//!
+//! Only available on Linux, with the `geoclue` feature enabled. Desktop
+//! apps (and libgeoclue) find out where they are by asking the GeoClue2
+//! service, `org.freedesktop.GeoClue2` on the system bus. A `GeoClueServer`
+//! provides that service itself, from whatever fixes it's given, so a
+//! receiver read through this crate can stand in for the geoclue daemon:
+//!
+//! ```no_run
+//! use unbounded_gpsd::geoclue::GeoClueServer;
+//! use unbounded_gpsd::GpsdConnection;
+//!
+//! let server = GeoClueServer::system().unwrap();
+//! let mut conn = GpsdConnection::new("127.0.0.1:2947").unwrap();
+//! conn.watch(true).unwrap();
+//! for resp in &mut conn {
+//!     server.observe(&resp.unwrap()).unwrap();
+//! }
+//! ```
+//!
+//! It implements the parts of the GeoClue2 API apps use: the `Manager`, with
+//! `GetClient`, `CreateClient` and `DeleteClient`; `Client` objects, which
+//! get a new `Location` (and a `LocationUpdated` signal) for each fix once
+//! they're started, subject to their `DistanceThreshold` and
+//! `TimeThreshold`; and the `Location` objects themselves. Clients go away
+//! with `DeleteClient`, or when the app that made them leaves the bus,
+//! which a background thread watches for. Every fix is
+//! offered at the `Exact` accuracy level, whatever a client asks for, and
+//! agents (which geoclue uses to ask users for permission) are accepted but
+//! never consulted, so any app on the bus can see the position.
+//!
+//! The geoclue daemon mustn't be running, and the system bus only lets a
+//! process own the service's name if its policy says so; geoclue's own
+//! policy file (`/usr/share/dbus-1/system.d/org.freedesktop.GeoClue2.conf`)
+//! allows the `geoclue` user, so run as that, or as root.
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use zbus::blocking::Connection;
+use zbus::message::Header;
+use zbus::names::OwnedUniqueName;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath};
+use zbus::{fdo, interface, ObjectServer};
+use crate::timestamp::to_epoch_nanos;
+use crate::types::{Position, Response, TpvResponse};
+
+/// The service's well-known name.
+pub const BUS_NAME: &str = "org.freedesktop.GeoClue2";
+/// Where the `Manager` lives.
+pub const MANAGER_PATH: &str = "/org/freedesktop/GeoClue2/Manager";
+/// `GCLUE_ACCURACY_LEVEL_EXACT`.
+const ACCURACY_EXACT: u32 = 8;
+/// What GeoClue2 reports for an altitude it doesn't know.
+const ALTITUDE_UNKNOWN: f64 = -f64::MAX;
+/// What GeoClue2 reports for a speed or heading it doesn't know.
+const UNKNOWN: f64 = -1.0;
+
+fn client_path(id: u32) -> String {
+    format!("/org/freedesktop/GeoClue2/Client/{}", id)
+}
+fn location_path(client: u32, n: u32) -> String {
+    format!("{}/Location/{}", client_path(client), n)
+}
+/// `path` as an object path. The paths made here are always valid ones.
+fn owned(path: String) -> OwnedObjectPath {
+    ObjectPath::from_string_unchecked(path).into()
+}
+/// The client number in `path`, if it's a client's path.
+fn client_id(path: &str) -> Option<u32> {
+    path.strip_prefix("/org/freedesktop/GeoClue2/Client/")?.parse().ok()
+}
+
+/// What the service knows about one client.
+#[derive(Debug, Default)]
+struct ClientState {
+    /// The bus name that made the client, for `GetClient`.
+    owner: Option<OwnedUniqueName>,
+    active: bool,
+    /// Meters a fix must be from the last one to be sent.
+    distance_threshold: u32,
+    /// Seconds since the last fix before another is sent.
+    time_threshold: u32,
+    desktop_id: String,
+    requested_accuracy: u32,
+    /// Numbers of the client's current and previous `Location` objects.
+    location: Option<u32>,
+    previous: Option<u32>,
+    next_location: u32,
+    /// The last fix sent, and when.
+    last: Option<(Position, Instant)>
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    clients: BTreeMap<u32, ClientState>,
+    next_client: u32
+}
+
+/// Tell clients of the `Manager` its `InUse` may have changed.
+async fn in_use_changed(server: &ObjectServer) -> zbus::Result<()> {
+    let manager = server.interface::<_, Manager>(MANAGER_PATH).await?;
+    let iface = manager.get().await;
+    iface.in_use_changed(manager.signal_emitter()).await
+}
+
+fn lock(shared: &Mutex<Shared>) -> MutexGuard<'_, Shared> {
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Take a client's objects off the bus, once it's out of the shared state.
+async fn remove_client(server: &ObjectServer, id: u32, state: ClientState) -> zbus::Result<()> {
+    for n in state.location.into_iter().chain(state.previous) {
+        server.remove::<Location, _>(&location_path(id, n)[..]).await?;
+    }
+    server.remove::<Client, _>(&client_path(id)[..]).await?;
+    Ok(())
+}
+
+/// Delete the clients of apps as they leave the bus, until the connection
+/// closes.
+fn watch_owners(conn: &Connection, shared: Arc<Mutex<Shared>>) -> zbus::Result<()> {
+    let changes = zbus::blocking::fdo::DBusProxy::new(conn)?.receive_name_owner_changed()?;
+    let conn = conn.clone();
+    thread::spawn(move || {
+        for signal in changes {
+            let args = match signal.args() {
+                Ok(args) => args,
+                Err(e) => {
+                    warn!("bad NameOwnerChanged signal: {}", e);
+                    continue;
+                }
+            };
+            let name = args.name().as_str();
+            if args.new_owner().is_some() || !name.starts_with(':') {
+                continue;
+            }
+            let gone: Vec<(u32, ClientState)> = {
+                let mut shared = lock(&shared);
+                let ids: Vec<u32> = shared.clients.iter()
+                    .filter(|(_, c)| c.owner.as_ref().is_some_and(|o| o.as_str() == name))
+                    .map(|(&id, _)| id)
+                    .collect();
+                ids.into_iter().filter_map(|id| shared.clients.remove(&id).map(|c| (id, c))).collect()
+            };
+            if gone.is_empty() {
+                continue;
+            }
+            debug!("{} left the bus, deleting its {} client(s)", name, gone.len());
+            let server = conn.object_server();
+            let res = zbus::block_on(async {
+                for (id, state) in gone {
+                    remove_client(server.inner(), id, state).await?;
+                }
+                in_use_changed(server.inner()).await
+            });
+            if let Err(e) = res {
+                warn!("deleting the clients of {}: {}", name, e);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// `org.freedesktop.GeoClue2.Manager`.
+struct Manager {
+    shared: Arc<Mutex<Shared>>
+}
+impl Manager {
+    async fn add_client(&self, server: &ObjectServer, owner: Option<OwnedUniqueName>) -> fdo::Result<OwnedObjectPath> {
+        let id = {
+            let mut shared = lock(&self.shared);
+            let id = shared.next_client;
+            shared.next_client += 1;
+            shared.clients.insert(id, ClientState { owner, ..ClientState::default() });
+            id
+        };
+        let path = client_path(id);
+        server.at(&path[..], Client { id, shared: self.shared.clone() }).await?;
+        Ok(owned(path))
+    }
+}
+#[interface(name = "org.freedesktop.GeoClue2.Manager")]
+impl Manager {
+    /// The caller's client, made the first time it asks.
+    async fn get_client(&self, #[zbus(header)] hdr: Header<'_>,
+                        #[zbus(object_server)] server: &ObjectServer) -> fdo::Result<OwnedObjectPath> {
+        let owner = hdr.sender().map(|s| OwnedUniqueName::from(s.to_owned()));
+        let existing = lock(&self.shared).clients.iter()
+            .find(|(_, c)| owner.is_some() && c.owner == owner)
+            .map(|(&id, _)| id);
+        match existing {
+            Some(id) => Ok(owned(client_path(id))),
+            None => self.add_client(server, owner).await
+        }
+    }
+    /// A new client, even if the caller already has one.
+    async fn create_client(&self, #[zbus(header)] hdr: Header<'_>,
+                           #[zbus(object_server)] server: &ObjectServer) -> fdo::Result<OwnedObjectPath> {
+        let owner = hdr.sender().map(|s| OwnedUniqueName::from(s.to_owned()));
+        self.add_client(server, owner).await
+    }
+    async fn delete_client(&self, client: ObjectPath<'_>,
+                           #[zbus(object_server)] server: &ObjectServer) -> fdo::Result<()> {
+        let state = client_id(client.as_str()).and_then(|id| lock(&self.shared).clients.remove(&id).map(|s| (id, s)));
+        let (id, state) = state.ok_or_else(|| fdo::Error::InvalidArgs(format!("no client at {}", client)))?;
+        remove_client(server, id, state).await?;
+        in_use_changed(server).await?;
+        Ok(())
+    }
+    /// Agents would be asked before apps get a position; they're accepted,
+    /// but never asked.
+    fn add_agent(&self, _id: &str) {}
+    #[zbus(property)]
+    fn in_use(&self) -> bool {
+        lock(&self.shared).clients.values().any(|c| c.active)
+    }
+    #[zbus(property)]
+    fn available_accuracy_level(&self) -> u32 {
+        ACCURACY_EXACT
+    }
+}
+
+/// `org.freedesktop.GeoClue2.Client`.
+struct Client {
+    id: u32,
+    shared: Arc<Mutex<Shared>>
+}
+impl Client {
+    fn with<T>(&self, f: impl FnOnce(&mut ClientState) -> T) -> fdo::Result<T> {
+        lock(&self.shared).clients.get_mut(&self.id).map(f)
+            .ok_or_else(|| fdo::Error::UnknownObject(client_path(self.id)))
+    }
+}
+#[interface(name = "org.freedesktop.GeoClue2.Client")]
+impl Client {
+    async fn start(&self, #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+                   #[zbus(object_server)] server: &ObjectServer) -> fdo::Result<()> {
+        self.with(|c| c.active = true)?;
+        self.active_changed(&emitter).await?;
+        in_use_changed(server).await?;
+        Ok(())
+    }
+    async fn stop(&self, #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+                  #[zbus(object_server)] server: &ObjectServer) -> fdo::Result<()> {
+        self.with(|c| c.active = false)?;
+        self.active_changed(&emitter).await?;
+        in_use_changed(server).await?;
+        Ok(())
+    }
+    /// The current `Location`, or `/` before the first fix.
+    #[zbus(property)]
+    fn location(&self) -> fdo::Result<OwnedObjectPath> {
+        let path = match self.with(|c| c.location)? {
+            Some(n) => location_path(self.id, n),
+            None => "/".to_owned()
+        };
+        Ok(owned(path))
+    }
+    #[zbus(property)]
+    fn distance_threshold(&self) -> fdo::Result<u32> {
+        self.with(|c| c.distance_threshold)
+    }
+    #[zbus(property)]
+    fn set_distance_threshold(&mut self, meters: u32) -> fdo::Result<()> {
+        self.with(|c| c.distance_threshold = meters)
+    }
+    #[zbus(property)]
+    fn time_threshold(&self) -> fdo::Result<u32> {
+        self.with(|c| c.time_threshold)
+    }
+    #[zbus(property)]
+    fn set_time_threshold(&mut self, seconds: u32) -> fdo::Result<()> {
+        self.with(|c| c.time_threshold = seconds)
+    }
+    #[zbus(property)]
+    fn desktop_id(&self) -> fdo::Result<String> {
+        self.with(|c| c.desktop_id.clone())
+    }
+    #[zbus(property)]
+    fn set_desktop_id(&mut self, id: String) -> fdo::Result<()> {
+        self.with(|c| c.desktop_id = id)
+    }
+    #[zbus(property)]
+    fn requested_accuracy_level(&self) -> fdo::Result<u32> {
+        self.with(|c| c.requested_accuracy)
+    }
+    #[zbus(property)]
+    fn set_requested_accuracy_level(&mut self, level: u32) -> fdo::Result<()> {
+        self.with(|c| c.requested_accuracy = level)
+    }
+    #[zbus(property)]
+    fn active(&self) -> fdo::Result<bool> {
+        self.with(|c| c.active)
+    }
+    #[zbus(signal)]
+    async fn location_updated(emitter: &SignalEmitter<'_>, old: ObjectPath<'_>, new: ObjectPath<'_>) -> zbus::Result<()>;
+}
+
+/// `org.freedesktop.GeoClue2.Location`: one fix, which never changes.
+#[derive(Debug, Clone, PartialEq)]
+struct Location {
+    latitude: f64,
+    longitude: f64,
+    accuracy: f64,
+    altitude: f64,
+    speed: f64,
+    heading: f64,
+    description: String,
+    /// Seconds and microseconds since the epoch.
+    timestamp: (u64, u64)
+}
+impl Location {
+    fn from_tpv(tpv: &TpvResponse, pos: &Position) -> Self {
+        let e = tpv.errors();
+        // gpsd's horizontal error, or failing that the error in each
+        // direction combined; 0 is GeoClue2's "unknown".
+        let eph = tpv.extra().get("eph").and_then(|v| v.as_f64());
+        let accuracy = eph.or_else(|| match (e.lat, e.lon) {
+            (Some(y), Some(x)) => Some(y.value.hypot(x.value)),
+            _ => None
+        });
+        let nanos = tpv.time().as_ref().and_then(to_epoch_nanos).and_then(|n| u64::try_from(n).ok())
+            .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64));
+        Location {
+            latitude: pos.lat.degrees(),
+            longitude: pos.lon.degrees(),
+            accuracy: accuracy.unwrap_or(0.0),
+            altitude: pos.alt_msl.or(pos.alt_hae).unwrap_or(ALTITUDE_UNKNOWN),
+            speed: tpv.speed().unwrap_or(UNKNOWN),
+            heading: tpv.track().unwrap_or(UNKNOWN),
+            description: tpv.device().map_or_else(String::new, |d| format!("GPS fix from {}", d)),
+            timestamp: (nanos / 1_000_000_000, nanos % 1_000_000_000 / 1000)
+        }
+    }
+}
+#[interface(name = "org.freedesktop.GeoClue2.Location")]
+impl Location {
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn latitude(&self) -> f64 {
+        self.latitude
+    }
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn longitude(&self) -> f64 {
+        self.longitude
+    }
+    /// In meters.
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn accuracy(&self) -> f64 {
+        self.accuracy
+    }
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn altitude(&self) -> f64 {
+        self.altitude
+    }
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn speed(&self) -> f64 {
+        self.speed
+    }
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn heading(&self) -> f64 {
+        self.heading
+    }
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn timestamp(&self) -> (u64, u64) {
+        self.timestamp
+    }
+}
+
+/// A GeoClue2 service, serving the fixes it's given.
+pub struct GeoClueServer {
+    conn: Connection,
+    shared: Arc<Mutex<Shared>>
+}
+impl GeoClueServer {
+    /// Serve on the system bus, as GeoClue2 does.
+    pub fn system() -> zbus::Result<Self> {
+        Self::serve(Connection::system()?)
+    }
+    /// Serve on `conn`, e.g. a private bus for testing. Claims `BUS_NAME`
+    /// on it.
+    pub fn serve(conn: Connection) -> zbus::Result<Self> {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        conn.object_server().at(MANAGER_PATH, Manager { shared: shared.clone() })?;
+        watch_owners(&conn, shared.clone())?;
+        conn.request_name(BUS_NAME)?;
+        Ok(GeoClueServer { conn, shared })
+    }
+    /// The connection being served on.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+    /// Send the report's position to every started client whose thresholds
+    /// it passes. Returns how many that was; none, if the report hasn't got
+    /// a valid position.
+    pub fn update(&self, tpv: &TpvResponse) -> zbus::Result<usize> {
+        let pos = match tpv.position() {
+            Some(p) => p,
+            None => return Ok(0)
+        };
+        let location = Location::from_tpv(tpv, &pos);
+        let now = Instant::now();
+        // Decide who gets the fix first, so the lock isn't held while
+        // talking to the bus.
+        let mut updates = vec![];
+        for (&id, client) in lock(&self.shared).clients.iter_mut().filter(|(_, c)| c.active) {
+            if let Some((ref last, at)) = client.last {
+                let moved = last.distance_to(&pos) >= f64::from(client.distance_threshold);
+                let waited = now.duration_since(at).as_secs() >= u64::from(client.time_threshold);
+                if !(moved && waited) {
+                    continue;
+                }
+            }
+            let n = client.next_location;
+            client.next_location += 1;
+            let stale = client.previous.take();
+            client.previous = client.location.replace(n);
+            client.last = Some((pos, now));
+            updates.push((id, n, client.previous, stale));
+        }
+        let server = self.conn.object_server();
+        for &(id, n, old, stale) in &updates {
+            let new = location_path(id, n);
+            server.at(&new[..], location.clone())?;
+            let old = old.map_or_else(|| "/".to_owned(), |o| location_path(id, o));
+            let iface = server.interface::<_, Client>(&client_path(id)[..])?;
+            zbus::block_on(async {
+                Client::location_updated(iface.signal_emitter(), owned(old).as_ref(), owned(new.clone()).as_ref()).await?;
+                iface.get().location_changed(iface.signal_emitter()).await
+            })?;
+            // Keep the location before this one around, in case a client is
+            // still reading it from the last signal.
+            if let Some(stale) = stale {
+                server.remove::<Location, _>(&location_path(id, stale)[..])?;
+            }
+        }
+        Ok(updates.len())
+    }
+    /// `update` from a response, if it's a TPV report.
+    pub fn observe(&self, resp: &Response) -> zbus::Result<usize> {
+        match *resp {
+            Response::Tpv(ref tpv) => self.update(tpv),
+            _ => Ok(0)
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! Converting positions between geodetic, ECEF and local ENU coordinates,
+//! and distances and bearings between them.
+//!
+//! Fixes are geodetic: latitude and longitude on the WGS84 ellipsoid, plus a
+//! height. `Ecef` is the same point as earth-centered, earth-fixed cartesian
+//! coordinates, and a `LocalFrame` gives `Enu` (east, north, up) offsets
+//! from a reference point, which is what robots and vehicles usually
+//! navigate in.
+//!
+//! ECEF needs the height above the ellipsoid: `altHAE`, or the height above
+//! sea level plus `geoidSep` (see `Position::height_above_ellipsoid`). Where
+//! a report has the height above sea level and no separation, that is used
+//! as it is, which puts the point off by up to about 100 m vertically; with
+//! no height at all, the point is put on the ellipsoid.
+//!
+//! For "how far is it", `Position::distance_to` gives the distance along the
+//! ellipsoid by Vincenty's formulae, to within a millimeter or so, and
+//! `Position::bearing_to` the direction to set off in. `haversine` is the
+//! cheaper spherical version, good to about 0.5%.
+
+use crate::types::{Position, TpvResponse};
+use crate::units::Bearing;
+
+/// WGS84 semi-major axis, in meters.
+pub const WGS84_A: f64 = 6_378_137.0;
+/// WGS84 flattening.
+pub const WGS84_F: f64 = 1.0 / 298.257_223_563;
+/// WGS84 first eccentricity squared.
+const WGS84_E2: f64 = WGS84_F * (2.0 - WGS84_F);
+/// The earth's mean radius (IUGG), in meters, for spherical formulae.
+pub const MEAN_RADIUS: f64 = 6_371_008.8;
+
+/// Earth-centered, earth-fixed coordinates, in meters.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Ecef {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64
+}
+/// East, north and up offsets from a `LocalFrame`'s origin, in meters.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Enu {
+    pub east: f64,
+    pub north: f64,
+    pub up: f64
+}
+impl Ecef {
+    /// The point at `lat` and `lon` degrees, `height` meters above the
+    /// ellipsoid.
+    pub fn from_geodetic(lat: f64, lon: f64, height: f64) -> Self {
+        let (lat, lon) = (lat.to_radians(), lon.to_radians());
+        // Radius of curvature in the prime vertical.
+        let n = WGS84_A / (1.0 - WGS84_E2 * lat.sin().powi(2)).sqrt();
+        Ecef {
+            x: (n + height) * lat.cos() * lon.cos(),
+            y: (n + height) * lat.cos() * lon.sin(),
+            z: (n * (1.0 - WGS84_E2) + height) * lat.sin()
+        }
+    }
+    /// The point as latitude and longitude in degrees, and height above the
+    /// ellipsoid in meters.
+    pub fn to_geodetic(self) -> (f64, f64, f64) {
+        let lon = self.y.atan2(self.x);
+        let p = self.x.hypot(self.y);
+        // Iterate on latitude; this converges to well under a millimeter in a
+        // handful of rounds anywhere near the earth.
+        let mut lat = self.z.atan2(p * (1.0 - WGS84_E2));
+        let mut height = 0.0;
+        for _ in 0..8 {
+            let n = WGS84_A / (1.0 - WGS84_E2 * lat.sin().powi(2)).sqrt();
+            height = if lat.cos().abs() > 1e-9 {
+                p / lat.cos() - n
+            } else {
+                self.z.abs() - n * (1.0 - WGS84_E2)
+            };
+            lat = self.z.atan2(p * (1.0 - WGS84_E2 * n / (n + height)));
+        }
+        (lat.to_degrees(), lon.to_degrees(), height)
+    }
+    /// Straight-line distance to `other`, in meters.
+    pub fn distance(self, other: Ecef) -> f64 {
+        ((self.x - other.x).powi(2) + (self.y - other.y).powi(2) + (self.z - other.z).powi(2)).sqrt()
+    }
+}
+impl From<Position> for Ecef {
+    fn from(pos: Position) -> Self {
+        let height = pos.height_above_ellipsoid().or(pos.alt_msl).unwrap_or(0.0);
+        Ecef::from_geodetic(pos.lat.degrees(), pos.lon.degrees(), height)
+    }
+}
+
+/// A local tangent plane: ENU coordinates relative to an origin.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LocalFrame {
+    origin: Ecef,
+    /// Sines and cosines of the origin's latitude and longitude.
+    sin_lat: f64,
+    cos_lat: f64,
+    sin_lon: f64,
+    cos_lon: f64
+}
+impl LocalFrame {
+    /// A frame with its origin at `lat` and `lon` degrees, `height` meters
+    /// above the ellipsoid.
+    pub fn new(lat: f64, lon: f64, height: f64) -> Self {
+        let (lat_r, lon_r) = (lat.to_radians(), lon.to_radians());
+        LocalFrame {
+            origin: Ecef::from_geodetic(lat, lon, height),
+            sin_lat: lat_r.sin(),
+            cos_lat: lat_r.cos(),
+            sin_lon: lon_r.sin(),
+            cos_lon: lon_r.cos()
+        }
+    }
+    /// A frame with its origin at `pos`.
+    pub fn at(pos: Position) -> Self {
+        let height = pos.height_above_ellipsoid().or(pos.alt_msl).unwrap_or(0.0);
+        LocalFrame::new(pos.lat.degrees(), pos.lon.degrees(), height)
+    }
+    pub fn origin(&self) -> Ecef {
+        self.origin
+    }
+    /// `point`, relative to the origin.
+    pub fn to_enu(&self, point: Ecef) -> Enu {
+        let (dx, dy, dz) = (point.x - self.origin.x, point.y - self.origin.y, point.z - self.origin.z);
+        Enu {
+            east: -self.sin_lon * dx + self.cos_lon * dy,
+            north: -self.sin_lat * self.cos_lon * dx - self.sin_lat * self.sin_lon * dy + self.cos_lat * dz,
+            up: self.cos_lat * self.cos_lon * dx + self.cos_lat * self.sin_lon * dy + self.sin_lat * dz
+        }
+    }
+    /// The point at offset `enu` from the origin.
+    pub fn from_enu(&self, enu: Enu) -> Ecef {
+        let Enu { east, north, up } = enu;
+        Ecef {
+            x: self.origin.x - self.sin_lon * east - self.sin_lat * self.cos_lon * north + self.cos_lat * self.cos_lon * up,
+            y: self.origin.y + self.cos_lon * east - self.sin_lat * self.sin_lon * north + self.cos_lat * self.sin_lon * up,
+            z: self.origin.z + self.cos_lat * north + self.sin_lat * up
+        }
+    }
+    /// The report's position relative to the origin, if it has one.
+    pub fn enu_of(&self, tpv: &TpvResponse) -> Option<Enu> {
+        tpv.ecef().map(|p| self.to_enu(p))
+    }
+}
+
+impl TpvResponse {
+    /// The report's position in ECEF coordinates, if it has one. gpsd 3.20
+    /// and later send these (`ecefx` and so on), and they're used as sent;
+    /// otherwise they're worked out from the geodetic position.
+    pub fn ecef(&self) -> Option<Ecef> {
+        let extra = self.extra();
+        let sent = |k: &str| extra.get(k).and_then(|v| v.as_f64());
+        if let (Some(x), Some(y), Some(z)) = (sent("ecefx"), sent("ecefy"), sent("ecefz")) {
+            return Some(Ecef { x, y, z });
+        }
+        self.position().map(Ecef::from)
+    }
+}
+
+/// The great-circle distance in meters between two points, given in
+/// degrees, on a sphere of the earth's mean radius.
+pub fn haversine(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (phi1, phi2) = (lat1.to_radians(), lat2.to_radians());
+    let (dphi, dlambda) = ((lat2 - lat1).to_radians(), (lon2 - lon1).to_radians());
+    let h = (dphi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (dlambda / 2.0).sin().powi(2);
+    2.0 * MEAN_RADIUS * h.sqrt().min(1.0).asin()
+}
+/// The shortest path between two points on the WGS84 ellipsoid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geodesic {
+    /// Length of the path, in meters.
+    pub distance: f64,
+    /// Direction the path sets off in.
+    pub initial_bearing: Bearing,
+    /// Direction the path arrives in.
+    pub final_bearing: Bearing
+}
+/// The geodesic between two points, given in degrees, by Vincenty's inverse
+/// formula. `None` if it doesn't converge, which happens for nearly
+/// antipodal points.
+pub fn vincenty(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> Option<Geodesic> {
+    let b = WGS84_A * (1.0 - WGS84_F);
+    let l = (lon2 - lon1).to_radians();
+    let (u1, u2) = (((1.0 - WGS84_F) * lat1.to_radians().tan()).atan(), ((1.0 - WGS84_F) * lat2.to_radians().tan()).atan());
+    let (sin_u1, cos_u1, sin_u2, cos_u2) = (u1.sin(), u1.cos(), u2.sin(), u2.cos());
+    let mut lambda = l;
+    for _ in 0..200 {
+        let (sin_l, cos_l) = (lambda.sin(), lambda.cos());
+        let sin_sigma = ((cos_u2 * sin_l).powi(2) + (cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_l).powi(2)).sqrt();
+        if sin_sigma == 0.0 {
+            // The same point.
+            return Some(Geodesic { distance: 0.0, initial_bearing: Bearing::new(0.0), final_bearing: Bearing::new(0.0) });
+        }
+        let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_l;
+        let sigma = sin_sigma.atan2(cos_sigma);
+        let sin_alpha = cos_u1 * cos_u2 * sin_l / sin_sigma;
+        let cos2_alpha = 1.0 - sin_alpha * sin_alpha;
+        // On the equator, cos2_alpha is zero and this term drops out.
+        let cos_2sm = if cos2_alpha == 0.0 { 0.0 } else { cos_sigma - 2.0 * sin_u1 * sin_u2 / cos2_alpha };
+        let c = WGS84_F / 16.0 * cos2_alpha * (4.0 + WGS84_F * (4.0 - 3.0 * cos2_alpha));
+        let prev = lambda;
+        lambda = l + (1.0 - c) * WGS84_F * sin_alpha
+            * (sigma + c * sin_sigma * (cos_2sm + c * cos_sigma * (-1.0 + 2.0 * cos_2sm * cos_2sm)));
+        if (lambda - prev).abs() < 1e-12 {
+            let u_sq = cos2_alpha * (WGS84_A * WGS84_A - b * b) / (b * b);
+            let big_a = 1.0 + u_sq / 16384.0 * (4096.0 + u_sq * (-768.0 + u_sq * (320.0 - 175.0 * u_sq)));
+            let big_b = u_sq / 1024.0 * (256.0 + u_sq * (-128.0 + u_sq * (74.0 - 47.0 * u_sq)));
+            let delta_sigma = big_b * sin_sigma * (cos_2sm + big_b / 4.0
+                * (cos_sigma * (-1.0 + 2.0 * cos_2sm * cos_2sm)
+                   - big_b / 6.0 * cos_2sm * (-3.0 + 4.0 * sin_sigma * sin_sigma) * (-3.0 + 4.0 * cos_2sm * cos_2sm)));
+            let (sin_l, cos_l) = (lambda.sin(), lambda.cos());
+            let alpha1 = (cos_u2 * sin_l).atan2(cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_l);
+            let alpha2 = (cos_u1 * sin_l).atan2(-sin_u1 * cos_u2 + cos_u1 * sin_u2 * cos_l);
+            return Some(Geodesic {
+                distance: b * big_a * (sigma - delta_sigma),
+                initial_bearing: Bearing::new(alpha1.to_degrees()),
+                final_bearing: Bearing::new(alpha2.to_degrees())
+            });
+        }
+    }
+    None
+}
+/// The initial great-circle bearing from one point to another, given in
+/// degrees.
+fn spherical_bearing(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> Bearing {
+    let (phi1, phi2, dlambda) = (lat1.to_radians(), lat2.to_radians(), (lon2 - lon1).to_radians());
+    let y = dlambda.sin() * phi2.cos();
+    let x = phi1.cos() * phi2.sin() - phi1.sin() * phi2.cos() * dlambda.cos();
+    Bearing::new(y.atan2(x).to_degrees())
+}
+
+impl Position {
+    /// The geodesic from here to `other`; see `vincenty`.
+    pub fn geodesic_to(&self, other: &Position) -> Option<Geodesic> {
+        vincenty(self.lat.degrees(), self.lon.degrees(), other.lat.degrees(), other.lon.degrees())
+    }
+    /// Distance to `other` along the ellipsoid, in meters. Altitudes are
+    /// ignored. Falls back to `haversine` if Vincenty's formula doesn't
+    /// converge.
+    pub fn distance_to(&self, other: &Position) -> f64 {
+        match self.geodesic_to(other) {
+            Some(g) => g.distance,
+            None => haversine(self.lat.degrees(), self.lon.degrees(), other.lat.degrees(), other.lon.degrees())
+        }
+    }
+    /// The bearing to set off in to get to `other`, with the same fallback
+    /// as `distance_to`.
+    pub fn bearing_to(&self, other: &Position) -> Bearing {
+        match self.geodesic_to(other) {
+            Some(g) => g.initial_bearing,
+            None => spherical_bearing(self.lat.degrees(), self.lon.degrees(), other.lat.degrees(), other.lon.degrees())
+        }
+    }
+}
+impl TpvResponse {
+    /// Distance from the report's position to `other`, in meters, if it has
+    /// a position; see `Position::distance_to`.
+    pub fn distance_to(&self, other: &Position) -> Option<f64> {
+        self.position().map(|p| p.distance_to(other))
+    }
+    /// Bearing from the report's position to `other`, if it has a position.
+    pub fn bearing_to(&self, other: &Position) -> Option<Bearing> {
+        self.position().map(|p| p.bearing_to(other))
+    }
+}
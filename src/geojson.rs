@@ -0,0 +1,140 @@
+//! GeoJSON output, for putting fixes on web maps.
+//!
+//! Only available with the `geojson` feature enabled. Everything here
+//! produces `serde_json::Value`s in the shape of [RFC
+//! 7946](https://tools.ietf.org/html/rfc7946) objects, ready to serialize:
+//!
+//! - `feature` makes a Point `Feature` from one TPV report, with its time,
+//!   mode, speed, course and climb as properties.
+//! - `feature_collection` makes a `FeatureCollection` of those.
+//! - A `Track` collects reports as they arrive, and gives either a
+//!   LineString `Feature` (per-point properties go in `coordinateProperties`,
+//!   as Mapbox and geojson.io read them) or a collection of points.
+//!
+//! Coordinates are `[lon, lat]`, with the altitude as a third element when
+//! the report has one. RFC 7946 wants the height above the WGS84 ellipsoid,
+//! so that's `altHAE` if gpsd sent it, or `altMSL` (or `alt`) plus
+//! `geoidSep`; failing both, the height above mean sea level stands in.
+//! Reports without a valid position are left out.
+
+use serde_json::{Map, Value};
+use crate::timestamp::format_millis;
+use crate::types::TpvResponse;
+
+/// The properties given to points, in order.
+const PROPERTIES: &[&str] = &["time", "mode", "speed", "track", "climb"];
+
+/// A report's coordinates and properties.
+#[derive(Debug, Clone, PartialEq)]
+struct Point {
+    coordinates: Vec<f64>,
+    properties: Map<String, Value>
+}
+impl Point {
+    fn from_tpv(tpv: &TpvResponse) -> Option<Self> {
+        let pos = tpv.position()?;
+        let mut coordinates = vec![pos.lon.degrees(), pos.lat.degrees()];
+        coordinates.extend(pos.height_above_ellipsoid().or(pos.alt_msl));
+        let mut properties = Map::new();
+        if let Some(dev) = tpv.device() {
+            properties.insert("device".into(), dev.into());
+        }
+        let values = [
+            tpv.time().map(|t| format_millis(&t).into()),
+            tpv.mode().map(|m| u8::from(m).into()),
+            tpv.speed().map(Value::from),
+            tpv.track().map(Value::from),
+            tpv.climb().map(Value::from)
+        ];
+        for (name, value) in PROPERTIES.iter().zip(values) {
+            properties.insert((*name).into(), value.unwrap_or(Value::Null));
+        }
+        Some(Point { coordinates, properties })
+    }
+    fn to_feature(&self) -> Value {
+        json!({
+            "type": "Feature",
+            "geometry": { "type": "Point", "coordinates": self.coordinates },
+            "properties": self.properties
+        })
+    }
+}
+
+/// A Point `Feature` for the report's position, or `None` if it hasn't got
+/// a valid one.
+pub fn feature(tpv: &TpvResponse) -> Option<Value> {
+    Point::from_tpv(tpv).map(|p| p.to_feature())
+}
+/// A `FeatureCollection` with a Point for each report that has a position.
+pub fn feature_collection<'a, I: IntoIterator<Item = &'a TpvResponse>>(tpvs: I) -> Value {
+    let features: Vec<Value> = tpvs.into_iter().filter_map(feature).collect();
+    json!({ "type": "FeatureCollection", "features": features })
+}
+
+/// A track, built up from TPV reports.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Track {
+    points: Vec<Point>
+}
+impl Track {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add the report's position to the end of the track. Returns `false`,
+    /// and leaves the track alone, if the report hasn't got one.
+    pub fn push(&mut self, tpv: &TpvResponse) -> bool {
+        match Point::from_tpv(tpv) {
+            Some(p) => {
+                self.points.push(p);
+                true
+            },
+            None => false
+        }
+    }
+    /// Number of points in the track.
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+    /// The track as a LineString `Feature`. Its `coordinateProperties` has
+    /// an array per point property, one entry per coordinate (`null` where
+    /// the report didn't have it).
+    ///
+    /// A LineString needs two points; with fewer, the geometry is `null`.
+    pub fn to_feature(&self) -> Value {
+        let geometry = if self.points.len() < 2 {
+            Value::Null
+        } else {
+            let coordinates: Vec<&[f64]> = self.points.iter().map(|p| &p.coordinates[..]).collect();
+            json!({ "type": "LineString", "coordinates": coordinates })
+        };
+        let per_point: Map<String, Value> = PROPERTIES.iter()
+            .map(|&name| {
+                let values = self.points.iter().map(|p| p.properties[name].clone()).collect();
+                (name.to_owned(), Value::Array(values))
+            })
+            .collect();
+        json!({
+            "type": "Feature",
+            "geometry": geometry,
+            "properties": { "coordinateProperties": per_point }
+        })
+    }
+    /// The track as a `FeatureCollection` of Points, one per report.
+    pub fn to_feature_collection(&self) -> Value {
+        let features: Vec<Value> = self.points.iter().map(Point::to_feature).collect();
+        json!({ "type": "FeatureCollection", "features": features })
+    }
+}
+impl<'a> Extend<&'a TpvResponse> for Track {
+    fn extend<I: IntoIterator<Item = &'a TpvResponse>>(&mut self, tpvs: I) {
+        for tpv in tpvs {
+            self.push(tpv);
+        }
+    }
+}
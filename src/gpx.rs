@@ -0,0 +1,143 @@
+//! Writing fixes out as GPX 1.1, for mapping tools.
+//!
+//! A `GpxWriter` streams TPV reports into a GPX file as they arrive: each one
+//! with a position becomes a `trkpt`, with its altitude (`ele`), time and fix
+//! type. Speed and course go in Garmin's `TrackPointExtension`, which is what
+//! most tools read them from, since GPX 1.1 itself has nowhere to put them.
+//!
+//! GPX wants waypoints before the track, so `write_waypoint` only works until
+//! the first track point is written. Call `finish` at the end to close the
+//! file off; a file that isn't finished can't be read as XML.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::timestamp::format_millis;
+use crate::types::{FixMode, TpvResponse};
+
+const HEADER: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+    "<gpx version=\"1.1\" creator=\"unbounded-gpsd\" xmlns=\"http://www.topografix.com/GPX/1/1\" ",
+    "xmlns:gpxtpx=\"http://www.garmin.com/xmlschemas/TrackPointExtension/v2\">\n"
+);
+
+/// `s`, escaped for use in XML text.
+pub(crate) fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c)
+        }
+    }
+    out
+}
+
+/// Writes TPV reports to a GPX file as a track.
+pub struct GpxWriter<W: Write> {
+    out: W,
+    /// Whether the `trk` element (and a `trkseg`) is open.
+    in_track: bool,
+    points: u64
+}
+impl GpxWriter<BufWriter<File>> {
+    /// Write to a new file at `path`, replacing any existing file.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+impl<W: Write> GpxWriter<W> {
+    /// Write to `out`, starting with the GPX header.
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(HEADER.as_bytes())?;
+        Ok(Self { out, in_track: false, points: 0 })
+    }
+    /// Write the report's position as a waypoint called `name`. Returns
+    /// `false` if the report hasn't got a valid position.
+    ///
+    /// Fails with `InvalidInput` once track points have been written.
+    pub fn write_waypoint(&mut self, tpv: &TpvResponse, name: &str) -> io::Result<bool> {
+        if self.in_track {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "GPX waypoints must come before the track"));
+        }
+        self.write_point("wpt", tpv, Some(name))
+    }
+    /// Add the report's position to the track. Returns `false`, writing
+    /// nothing, if the report hasn't got a valid position.
+    pub fn write_tpv(&mut self, tpv: &TpvResponse) -> io::Result<bool> {
+        if tpv.position().is_none() {
+            return Ok(false);
+        }
+        if !self.in_track {
+            self.out.write_all(b"<trk>\n<trkseg>\n")?;
+            self.in_track = true;
+        }
+        self.write_point("trkpt", tpv, None)
+    }
+    /// Start a new track segment, e.g. after the fix was lost for a while.
+    /// Does nothing before the first track point.
+    pub fn new_segment(&mut self) -> io::Result<()> {
+        if self.in_track {
+            self.out.write_all(b"</trkseg>\n<trkseg>\n")?;
+        }
+        Ok(())
+    }
+    /// Number of track points written so far.
+    pub fn points(&self) -> u64 {
+        self.points
+    }
+    /// Close off the file and flush it, giving back the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.in_track {
+            self.out.write_all(b"</trkseg>\n</trk>\n")?;
+        }
+        self.out.write_all(b"</gpx>\n")?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+    fn write_point(&mut self, tag: &str, tpv: &TpvResponse, name: Option<&str>) -> io::Result<bool> {
+        let pos = match tpv.position() {
+            Some(p) => p,
+            None => return Ok(false)
+        };
+        // Elements in the order the GPX schema wants them.
+        let mut xml = format!("<{} lat=\"{}\" lon=\"{}\">", tag, pos.lat.degrees(), pos.lon.degrees());
+        if let Some(ele) = pos.alt_msl.or(pos.alt_hae) {
+            xml += &format!("<ele>{}</ele>", ele);
+        }
+        if let Some(t) = tpv.time() {
+            xml += &format!("<time>{}</time>", format_millis(&t));
+        }
+        if let Some(name) = name {
+            xml += &format!("<name>{}</name>", escape(name));
+        }
+        let fix = match tpv.mode() {
+            Some(FixMode::NoFix) => Some("none"),
+            Some(FixMode::Fix2d) => Some("2d"),
+            Some(FixMode::Fix3d) => Some("3d"),
+            _ => None
+        };
+        if let Some(fix) = fix {
+            xml += &format!("<fix>{}</fix>", fix);
+        }
+        if tpv.speed().is_some() || tpv.track().is_some() {
+            xml += "<extensions><gpxtpx:TrackPointExtension>";
+            if let Some(speed) = tpv.speed() {
+                xml += &format!("<gpxtpx:speed>{}</gpxtpx:speed>", speed);
+            }
+            if let Some(course) = tpv.track() {
+                xml += &format!("<gpxtpx:course>{}</gpxtpx:course>", course);
+            }
+            xml += "</gpxtpx:TrackPointExtension></extensions>";
+        }
+        xml += &format!("</{}>\n", tag);
+        self.out.write_all(xml.as_bytes())?;
+        if tag == "trkpt" {
+            self.points += 1;
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,132 @@
+//! Sending commands from more than one place.
+//!
+//! A `GpsdConnection` is usually read from in one place, often on a thread of
+//! its own. `GpsdConnection::command_handle` gives you a cloneable handle that
+//! can send commands (changing the watch, polling, and so on) from anywhere
+//! else at the same time:
+//!
+//! ```rust,no_run
+//! # use unbounded_gpsd::GpsdConnection;
+//! let conn = GpsdConnection::new("127.0.0.1:2947").unwrap();
+//! let commands = conn.command_handle();
+//! let responses = conn.spawn_channel(16);
+//! commands.watch(true).unwrap();
+//! for resp in responses {
+//!     println!("{:?}", resp);
+//! }
+//! ```
+//!
+//! If the transport can hand out a separate writer (see
+//! `Transport::write_handle`), commands are written straight away. Otherwise
+//! they're queued and sent by the connection the next time it goes to read,
+//! so they may wait for the next line from gpsd, or the next read timeout.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use crate::transport::WriteHandle;
+use crate::types::{RawLevel, WatchObject};
+use crate::{watch_command, watch_object, GpsdResult, Stream};
+
+/// State shared between a connection and its `CommandHandle`s.
+#[derive(Default)]
+pub(crate) struct Commands {
+    /// A writer for the current stream, if it has one.
+    writer: Mutex<Option<WriteHandle>>,
+    /// Commands waiting for the connection to send them.
+    queued: Mutex<Vec<u8>>,
+    /// Whether RAW mode was asked for, so non-JSON lines are expected.
+    raw: AtomicBool,
+    /// The last watch sent, as sent and as expected back, to restore after
+    /// reconnecting.
+    last_watch: Mutex<Option<(Vec<u8>, WatchObject)>>,
+    /// Bytes written, by whichever route.
+    pub(crate) bytes_written: AtomicU64
+}
+impl Commands {
+    fn writer(&self) -> MutexGuard<'_, Option<WriteHandle>> {
+        self.writer.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    pub(crate) fn set_stream(&self, stream: &Stream) {
+        *self.writer() = stream.write_handle();
+    }
+    pub(crate) fn raw(&self) -> bool {
+        self.raw.load(Ordering::SeqCst)
+    }
+    pub(crate) fn set_raw(&self, raw: bool) {
+        self.raw.store(raw, Ordering::SeqCst);
+    }
+    /// Write `msg` with the shared writer, returning false if there isn't one.
+    ///
+    /// Everything goes through here when there is a writer, so that commands
+    /// from different places are never interleaved.
+    pub(crate) fn write(&self, msg: &[u8]) -> io::Result<bool> {
+        match *self.writer() {
+            Some(ref mut w) => {
+                w.write_all(msg)?;
+                w.flush()?;
+                self.wrote(msg.len());
+                Ok(true)
+            },
+            None => Ok(false)
+        }
+    }
+    pub(crate) fn wrote(&self, n: usize) {
+        self.bytes_written.fetch_add(n as u64, Ordering::Relaxed);
+    }
+    /// Note that `msg` sets up the watch described by `watch`.
+    pub(crate) fn set_watch(&self, msg: &[u8], watch: WatchObject) {
+        self.set_raw(watch.sends_raw());
+        *self.last_watch.lock().unwrap_or_else(|e| e.into_inner()) = Some((msg.to_vec(), watch));
+    }
+    /// The last watch sent, if any.
+    pub(crate) fn last_watch(&self) -> Option<(Vec<u8>, WatchObject)> {
+        self.last_watch.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+    /// Take the commands waiting to be sent.
+    pub(crate) fn take_queued(&self) -> Vec<u8> {
+        ::std::mem::take(&mut *self.queued.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+    fn send(&self, msg: &[u8]) -> GpsdResult<()> {
+        if !self.write(msg)? {
+            self.queued.lock().unwrap_or_else(|e| e.into_inner()).extend_from_slice(msg);
+        }
+        Ok(())
+    }
+}
+
+/// A cloneable handle for sending commands on a `GpsdConnection`.
+///
+/// Created by `GpsdConnection::command_handle`. See the module documentation.
+#[derive(Clone)]
+pub struct CommandHandle(pub(crate) Arc<Commands>);
+impl CommandHandle {
+    /// Enable or disable watcher mode.
+    pub fn watch(&self, watch: bool) -> GpsdResult<()> {
+        self.watch_raw(watch, true, RawLevel::Off)
+    }
+    /// Enable RAW mode. See `GpsdConnection::watch_raw` for the meaning of `raw`.
+    pub fn watch_raw(&self, watch: bool, json: bool, raw: RawLevel) -> GpsdResult<()> {
+        let msg = watch_command(watch, json, raw);
+        self.0.set_watch(msg.as_bytes(), watch_object(watch, json, raw));
+        self.0.send(msg.as_bytes())
+    }
+    /// Set watcher mode parameters, as described by `watch`.
+    pub fn watch_with(&self, watch: &WatchObject) -> GpsdResult<()> {
+        let msg = format!("?WATCH={}\n", serde_json::to_string(watch)?);
+        self.0.set_watch(msg.as_bytes(), watch.clone());
+        self.0.send(msg.as_bytes())
+    }
+    /// Request data from the last-seen fixes on all active GPS devices.
+    pub fn poll(&self) -> GpsdResult<()> {
+        self.0.send(b"?POLL;\n")
+    }
+    /// Ask for the server's version (triggers a Response::Version).
+    pub fn version(&self) -> GpsdResult<()> {
+        self.0.send(b"?VERSION;\n")
+    }
+    /// Ask for the server's devices (triggers a Response::Devices)
+    pub fn devices(&self) -> GpsdResult<()> {
+        self.0.send(b"?DEVICES;\n")
+    }
+}
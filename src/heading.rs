@@ -0,0 +1,123 @@
+//! Detecting course changes in a stream of TPV reports.
+//!
+//! A `HeadingMonitor` watches the course over ground (`track`) of successive
+//! TPV reports. It emits a `HeadingEvent` when the course has changed by more
+//! than a threshold for longer than a minimum time (so brief swerves and GPS
+//! noise don't count), and it tracks how stable the heading has been recently.
+//! Reports with low speed are ignored, since the track of a stationary
+//! receiver is mostly jitter.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+use crate::timestamp::{self, Timestamp};
+use crate::types::TpvResponse;
+use crate::units::Bearing;
+
+/// Something that happened to the course over ground.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeadingEvent {
+    /// The course changed by more than the threshold, and stayed changed for
+    /// at least the sustain time.
+    CourseChanged {
+        /// The previously established course, degrees from true north.
+        from: f64,
+        /// The new course, degrees from true north.
+        to: f64,
+        /// Signed change in degrees, in (-180, 180]; positive is clockwise.
+        delta: f64,
+        /// Timestamp of the report that confirmed the change.
+        time: Timestamp
+    }
+}
+
+/// Pull the time, track and speed out of a TPV, if it has a time and track.
+fn course_of(tpv: &TpvResponse) -> Option<(Timestamp, f64, Option<f64>)> {
+    Some((tpv.time()?, tpv.track()?, tpv.speed()))
+}
+
+/// Watches TPV reports for sustained course changes.
+pub struct HeadingMonitor {
+    threshold: f64,
+    sustain: Duration,
+    min_speed: f64,
+    window: Duration,
+    reference: Option<f64>,
+    candidate: Option<Timestamp>,
+    history: VecDeque<(Timestamp, f64)>
+}
+impl HeadingMonitor {
+    /// Make a monitor that reports course changes of more than `threshold`
+    /// degrees that last for at least `sustain`.
+    ///
+    /// By default, reports slower than 0.5 m/s are ignored, and stability is
+    /// computed over the last 10 seconds.
+    pub fn new(threshold: f64, sustain: Duration) -> Self {
+        Self {
+            threshold,
+            sustain,
+            min_speed: 0.5,
+            window: Duration::from_secs(10),
+            reference: None,
+            candidate: None,
+            history: VecDeque::new()
+        }
+    }
+    /// Ignore reports with a speed below `speed` (meters per second).
+    pub fn set_min_speed(&mut self, speed: f64) {
+        self.min_speed = speed;
+    }
+    /// Compute `stability` over reports from the last `window`.
+    pub fn set_stability_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+    /// The currently established course, if any.
+    pub fn course(&self) -> Option<f64> {
+        self.reference
+    }
+    /// How stable the heading has been over the stability window, from 0
+    /// (all over the place) to 1 (perfectly steady).
+    ///
+    /// This is the mean resultant length of the recent tracks, treated as unit
+    /// vectors. Returns `None` if there are no recent reports.
+    pub fn stability(&self) -> Option<f64> {
+        if self.history.is_empty() {
+            return None;
+        }
+        let (s, c) = self.history.iter()
+            .fold((0.0, 0.0), |(s, c), &(_, t)| (s + t.to_radians().sin(), c + t.to_radians().cos()));
+        let n = self.history.len() as f64;
+        Some(((s / n).powi(2) + (c / n).powi(2)).sqrt())
+    }
+    /// Feed in a TPV report, returning an event if it confirms a course change.
+    pub fn update(&mut self, tpv: &TpvResponse) -> Option<HeadingEvent> {
+        let (time, track, speed) = course_of(tpv)?;
+        if speed.map(|s| s < self.min_speed).unwrap_or(false) {
+            return None;
+        }
+        self.history.push_back((time, track));
+        let window = self.window;
+        while self.history.front().and_then(|&(t, _)| timestamp::elapsed(t, time)).is_some_and(|d| d > window) {
+            self.history.pop_front();
+        }
+        let reference = match self.reference {
+            Some(r) => r,
+            None => {
+                self.reference = Some(track);
+                return None;
+            }
+        };
+        let delta = Bearing::new(track) - Bearing::new(reference);
+        if delta.abs() <= self.threshold {
+            self.candidate = None;
+            return None;
+        }
+        let since = *self.candidate.get_or_insert(time);
+        let held = timestamp::elapsed(since, time).unwrap_or_default();
+        if held < self.sustain {
+            return None;
+        }
+        self.reference = Some(track);
+        self.candidate = None;
+        Some(HeadingEvent::CourseChanged { from: reference, to: track, delta, time })
+    }
+}
@@ -0,0 +1,91 @@
+//! Fanning out one gpsd connection to many consumers.
+//!
+//! Only available with the `tokio` feature enabled. A `GpsdHub` reads from
+//! gpsd once, on its own task, and broadcasts every response to all of its
+//! subscribers, so that e.g. a logger, a UI and a navigation subsystem can
+//! share a single connection.
+
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use crate::asynchronous::AsyncGpsdConnection;
+use crate::errors::GpsdError;
+use crate::latest::LatestState;
+use crate::types::Response;
+
+/// A task reading from gpsd and broadcasting responses to subscribers.
+///
+/// Responses are shared between subscribers, so they're handed out as
+/// `Arc<Response>`. A subscriber that falls more than `capacity` responses
+/// behind misses the oldest ones, and is told so with `RecvError::Lagged`.
+/// Once the connection fails, subscribers get `RecvError::Closed`.
+///
+/// Dropping the hub stops the reader task.
+pub struct GpsdHub {
+    tx: broadcast::WeakSender<Arc<Response>>,
+    task: JoinHandle<()>,
+    latest: LatestState
+}
+impl GpsdHub {
+    /// Spawn the reader task for `conn` on the current tokio runtime, keeping
+    /// up to `capacity` responses for slow subscribers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if not called from within a tokio runtime, or if `capacity` is 0.
+    pub fn spawn<S>(mut conn: AsyncGpsdConnection<S>, capacity: usize) -> Self
+        where S: AsyncRead + AsyncWrite + Unpin + Send + 'static {
+        let (tx, _) = broadcast::channel(capacity);
+        let weak = tx.downgrade();
+        let latest = LatestState::new();
+        let l = latest.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                match conn.get_response().await {
+                    // Failing to send just means nobody is subscribed right now.
+                    Ok(resp) => {
+                        l.update(&resp);
+                        let _ = tx.send(Arc::new(resp));
+                    },
+                    Err(e) => {
+                        if let GpsdError::Json { line: ref s, source: ref e } = e {
+                            warn!("skipping undeserializable response '{}': {}", s, e);
+                            continue;
+                        }
+                        warn!("hub reader stopping: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+        Self { tx: weak, task, latest }
+    }
+    /// Subscribe to responses received from now on.
+    ///
+    /// If the reader has already stopped, the returned receiver is closed.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Response>> {
+        match self.tx.upgrade() {
+            Some(tx) => tx.subscribe(),
+            None => broadcast::channel(1).1
+        }
+    }
+    /// The most recent reports the hub has seen, kept up to date as it reads.
+    /// See `latest`.
+    pub fn latest(&self) -> LatestState {
+        self.latest.clone()
+    }
+    /// The number of active subscribers.
+    pub fn receiver_count(&self) -> usize {
+        self.tx.upgrade().map(|tx| tx.receiver_count()).unwrap_or(0)
+    }
+    /// Whether the reader task has stopped.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+impl Drop for GpsdHub {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,159 @@
+//! Formatting reports as InfluxDB line protocol.
+//!
+//! A `LineProtocol` turns TPV, SKY and PPS reports into lines ready to write
+//! to InfluxDB (or Telegraf, or anything else that takes line protocol), one
+//! per report:
+//!
+//! ```text
+//! tpv,device=/dev/ttyUSB0,mode=3 lat=59.345,lon=18.054,alt=78.2,track=320.9,speed=0.06,climb=0.1,ept=0.005 1498910400000000000
+//! ```
+//!
+//! Measurements are named after the report's class (`tpv`, `sky`, `pps` and
+//! `toff`), with an optional prefix. The device and fix mode are tags, along
+//! with any added with `with_tag`, like a vehicle ID; everything else is a
+//! field, named as gpsd names it. SKY reports get `satellites` and `used`
+//! counts as well as their DOPs, and PPS and TOFF reports an `offset` in
+//! seconds. Lines carry the report's time to the nanosecond, or no timestamp
+//! (so the server uses its own clock) if the report hasn't got one.
+
+use crate::chrony::{TimeClass, TimeReport};
+use crate::timestamp::{to_epoch_nanos, Timestamp};
+use crate::types::{Response, SkyResponse, TpvResponse};
+
+/// Characters to escape in tag keys, tag values and field keys.
+const KEY_SPECIAL: &[char] = &[',', '=', ' '];
+
+/// The time in nanoseconds since the epoch, as lines are timestamped.
+fn nanos(time: Option<Timestamp>) -> Option<i128> {
+    time.as_ref().and_then(to_epoch_nanos).map(i128::from)
+}
+/// `s` with `special` characters backslash-escaped.
+fn escape(s: &str, special: &[char]) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// A line being built: fields in the order they're added.
+struct Line {
+    fields: Vec<String>
+}
+impl Line {
+    fn new() -> Self {
+        Line { fields: Vec::new() }
+    }
+    fn float(&mut self, key: &str, v: Option<f64>) {
+        // Line protocol has no way to write NaN or infinities.
+        if let Some(v) = v.filter(|v| v.is_finite()) {
+            self.fields.push(format!("{}={}", escape(key, KEY_SPECIAL), v));
+        }
+    }
+    fn int(&mut self, key: &str, v: Option<i64>) {
+        if let Some(v) = v {
+            self.fields.push(format!("{}={}i", escape(key, KEY_SPECIAL), v));
+        }
+    }
+}
+
+/// Formats reports as InfluxDB line protocol.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LineProtocol {
+    prefix: String,
+    tags: Vec<(String, String)>
+}
+impl LineProtocol {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Start measurement names with `prefix`, e.g. `gps_` for `gps_tpv`.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_owned();
+        self
+    }
+    /// Tag every line with `key` set to `value`, as well as the report's own
+    /// tags.
+    pub fn with_tag(mut self, key: &str, value: &str) -> Self {
+        self.tags.push((key.to_owned(), value.to_owned()));
+        self
+    }
+    /// The response as a line, without a trailing newline, if it's a TPV,
+    /// SKY or PPS report with anything to say.
+    pub fn format(&self, resp: &Response) -> Option<String> {
+        match *resp {
+            Response::Tpv(ref tpv) => self.tpv(tpv),
+            Response::Sky(ref sky) => self.sky(sky),
+            _ => TimeReport::from_response(resp).and_then(|r| self.pps(&r))
+        }
+    }
+    /// The report as a line, or `None` if it hasn't got any fields (a TPV
+    /// report with just a mode, say).
+    pub fn tpv(&self, tpv: &TpvResponse) -> Option<String> {
+        let mut line = Line::new();
+        line.float("lat", tpv.lat());
+        line.float("lon", tpv.lon());
+        line.float("alt", tpv.alt());
+        line.float("track", tpv.track());
+        line.float("speed", tpv.speed());
+        line.float("climb", tpv.climb());
+        let e = tpv.errors();
+        for &(key, est) in &[("ept", e.time), ("epx", e.lon), ("epy", e.lat), ("epv", e.alt),
+                             ("epd", e.track), ("eps", e.speed), ("epc", e.climb)] {
+            line.float(key, est.map(|e| e.value));
+        }
+        let mode = tpv.mode().map(|m| u8::from(m).to_string());
+        self.finish("tpv", tpv.device(), mode.as_deref(), line, nanos(tpv.time()))
+    }
+    /// The report as a line. There's always one, as the satellite counts are
+    /// fields.
+    pub fn sky(&self, sky: &SkyResponse) -> Option<String> {
+        let mut line = Line::new();
+        for &(key, dop) in &[("xdop", sky.xdop), ("ydop", sky.ydop), ("vdop", sky.vdop), ("tdop", sky.tdop),
+                             ("hdop", sky.hdop), ("pdop", sky.pdop), ("gdop", sky.gdop)] {
+            line.float(key, dop);
+        }
+        line.int("satellites", Some(sky.satellites.len() as i64));
+        line.int("used", Some(sky.used_satellites().count() as i64));
+        self.finish("sky", sky.device.as_deref(), None, line, nanos(sky.time))
+    }
+    /// A PPS or TOFF report as a line, in the `pps` or `toff` measurement,
+    /// with the receiver's time as its timestamp.
+    pub fn pps(&self, report: &TimeReport) -> Option<String> {
+        let mut line = Line::new();
+        line.float("offset", Some(report.offset()));
+        line.int("precision", report.precision.map(i64::from));
+        let time = i128::from(report.real_sec) * 1_000_000_000 + i128::from(report.real_nsec);
+        let measurement = match report.class {
+            TimeClass::Pps => "pps",
+            TimeClass::Toff => "toff"
+        };
+        self.finish(measurement, report.device.as_deref(), None, line, Some(time))
+    }
+    /// The line, with its tags sorted by key as InfluxDB prefers, or `None`
+    /// if it hasn't got any fields.
+    fn finish(&self, measurement: &str, device: Option<&str>, mode: Option<&str>, line: Line, nanos: Option<i128>) -> Option<String> {
+        if line.fields.is_empty() {
+            return None;
+        }
+        let mut tags: Vec<(&str, &str)> = self.tags.iter().map(|(k, v)| (&k[..], &v[..])).collect();
+        tags.extend(device.map(|d| ("device", d)));
+        tags.extend(mode.map(|m| ("mode", m)));
+        // Line protocol can't write an empty tag value.
+        tags.retain(|&(_, v)| !v.is_empty());
+        tags.sort();
+        let mut s = escape(&format!("{}{}", self.prefix, measurement), &[',', ' ']);
+        for (k, v) in tags {
+            s += &format!(",{}={}", escape(k, KEY_SPECIAL), escape(v, KEY_SPECIAL));
+        }
+        s += " ";
+        s += &line.fields.join(",");
+        if let Some(ns) = nanos {
+            s += &format!(" {}", ns);
+        }
+        Some(s)
+    }
+}
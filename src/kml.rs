@@ -0,0 +1,171 @@
+//! KML and KMZ output, for Google Earth.
+//!
+//! `placemark` makes a document with a single Placemark at a report's
+//! position, for showing where a receiver is now. A `KmlTrack` collects
+//! reports as they arrive and makes a document with a `gx:Track`, which
+//! Google Earth can play back against its time slider.
+//!
+//! `write_kmz` packs any of these into a KMZ file: a zip archive with the
+//! document as `doc.kml`. The document is stored uncompressed, which every
+//! KMZ reader accepts, so no compression library is needed.
+
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use crate::gpx::escape;
+use crate::timestamp::format_millis;
+use crate::types::{Position, TpvResponse};
+
+const HEADER: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+    "<kml xmlns=\"http://www.opengis.net/kml/2.2\" xmlns:gx=\"http://www.google.com/kml/ext/2.2\">\n",
+    "<Document>\n"
+);
+const FOOTER: &str = "</Document>\n</kml>\n";
+
+/// `pos` as a KML coordinate: longitude, latitude and altitude, if known.
+fn coordinate(pos: &Position, sep: char) -> String {
+    match pos.alt_msl.or(pos.alt_hae) {
+        Some(alt) => format!("{}{sep}{}{sep}{}", pos.lon.degrees(), pos.lat.degrees(), alt, sep = sep),
+        None => format!("{}{}{}", pos.lon.degrees(), sep, pos.lat.degrees())
+    }
+}
+/// KML's `altitudeMode` for a position: altitudes are above sea level, and
+/// positions without one are drawn on the ground.
+fn altitude_mode(pos: &Position) -> &'static str {
+    if pos.alt_msl.or(pos.alt_hae).is_some() { "absolute" } else { "clampToGround" }
+}
+
+/// A KML document with a Placemark called `name` at the report's position,
+/// or `None` if it hasn't got a valid one. The report's time, if any, is the
+/// Placemark's `TimeStamp`.
+pub fn placemark(tpv: &TpvResponse, name: &str) -> Option<String> {
+    let pos = tpv.position()?;
+    let mut kml = format!("{}<Placemark>\n<name>{}</name>\n", HEADER, escape(name));
+    if let Some(t) = tpv.time() {
+        kml += &format!("<TimeStamp><when>{}</when></TimeStamp>\n", format_millis(&t));
+    }
+    kml += &format!("<Point><altitudeMode>{}</altitudeMode><coordinates>{}</coordinates></Point>\n",
+                    altitude_mode(&pos), coordinate(&pos, ','));
+    kml += "</Placemark>\n";
+    kml += FOOTER;
+    Some(kml)
+}
+
+/// A recorded track, built up from TPV reports.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KmlTrack {
+    /// `when` and `gx:coord` of each point.
+    points: Vec<(String, String)>,
+    /// Whether every point had an altitude.
+    altitudes: bool
+}
+impl KmlTrack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add the report to the end of the track. Returns `false`, and leaves
+    /// the track alone, if the report hasn't got both a valid position and a
+    /// time; `gx:Track` needs both.
+    pub fn push(&mut self, tpv: &TpvResponse) -> bool {
+        let (pos, time) = match (tpv.position(), tpv.time()) {
+            (Some(p), Some(t)) => (p, t),
+            _ => return false
+        };
+        let has_alt = pos.alt_msl.or(pos.alt_hae).is_some();
+        self.altitudes = has_alt && (self.altitudes || self.points.is_empty());
+        self.points.push((format_millis(&time), coordinate(&pos, ' ')));
+        true
+    }
+    /// Number of points in the track.
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.altitudes = false;
+    }
+    /// A KML document with the track as a Placemark called `name`.
+    ///
+    /// Unless every point had an altitude, the track is drawn on the ground.
+    pub fn to_kml(&self, name: &str) -> String {
+        let mode = if self.altitudes { "absolute" } else { "clampToGround" };
+        let mut kml = format!("{}<Placemark>\n<name>{}</name>\n<gx:Track>\n<altitudeMode>{}</altitudeMode>\n",
+                              HEADER, escape(name), mode);
+        for (when, _) in &self.points {
+            kml += &format!("<when>{}</when>\n", when);
+        }
+        for (_, coord) in &self.points {
+            kml += &format!("<gx:coord>{}</gx:coord>\n", coord);
+        }
+        kml += "</gx:Track>\n</Placemark>\n";
+        kml += FOOTER;
+        kml
+    }
+}
+
+/// The CRC-32 (as zip uses it) of `data`.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= u32::from(b);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Write `kml` (as from `placemark` or `KmlTrack::to_kml`) to `out` as a KMZ
+/// archive.
+pub fn write_kmz<W: Write>(kml: &str, mut out: W) -> io::Result<()> {
+    const NAME: &[u8] = b"doc.kml";
+    // 1980-01-01 00:00, the zip epoch; readers don't care.
+    const DOS_DATE: u16 = (1 << 5) | 1;
+    let data = kml.as_bytes();
+    let size = u32::try_from(data.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "KML too big for a KMZ file"))?;
+    let crc = crc32(data);
+    // The fields local and central headers share: version needed (2.0),
+    // flags, method (stored), time, date, CRC, sizes and name length.
+    let mut common = Vec::with_capacity(24);
+    for v in &[20u16, 0, 0, 0, DOS_DATE] {
+        common.extend_from_slice(&v.to_le_bytes());
+    }
+    for v in &[crc, size, size] {
+        common.extend_from_slice(&v.to_le_bytes());
+    }
+    common.extend_from_slice(&(NAME.len() as u16).to_le_bytes());
+
+    let mut local = 0x0403_4b50u32.to_le_bytes().to_vec();
+    local.extend_from_slice(&common);
+    local.extend_from_slice(&0u16.to_le_bytes());
+    local.extend_from_slice(NAME);
+
+    let mut central = 0x0201_4b50u32.to_le_bytes().to_vec();
+    central.extend_from_slice(&20u16.to_le_bytes());
+    central.extend_from_slice(&common);
+    // Extra and comment lengths, disk number, internal and external
+    // attributes, then where the local header is.
+    central.extend_from_slice(&[0; 12]);
+    central.extend_from_slice(&0u32.to_le_bytes());
+    central.extend_from_slice(NAME);
+
+    let central_offset = u32::try_from(local.len() + data.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "KML too big for a KMZ file"))?;
+    let mut end = 0x0605_4b50u32.to_le_bytes().to_vec();
+    for v in &[0u16, 0, 1, 1] {
+        end.extend_from_slice(&v.to_le_bytes());
+    }
+    end.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    end.extend_from_slice(&central_offset.to_le_bytes());
+    end.extend_from_slice(&0u16.to_le_bytes());
+
+    out.write_all(&local)?;
+    out.write_all(data)?;
+    out.write_all(&central)?;
+    out.write_all(&end)?;
+    out.flush()
+}
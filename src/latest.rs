@@ -0,0 +1,112 @@
+//! Keeping the most recent reports to hand.
+//!
+//! A `LatestState` remembers the last TPV, SKY and DEVICE report for each
+//! device, so code that answers requests (an HTTP handler, say) can ask for
+//! the current position without consuming the response stream. `GpsdClient`
+//! and `GpsdHub` keep one up to date as they read; you can also feed one from
+//! your own read loop with `update`.
+//!
+//! Devices are looked up as `DevicePath::matches` compares them, so
+//! `/dev//ttyUSB0` finds the reports from `/dev/ttyUSB0`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+use crate::types::*;
+use crate::types::normalize_path;
+
+/// The last reports seen from one device.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceState {
+    pub tpv: Option<TpvResponse>,
+    pub sky: Option<SkyResponse>,
+    pub device: Option<DeviceObject>
+}
+
+#[derive(Default)]
+struct Inner {
+    devices: HashMap<String, DeviceState>,
+    /// The last reports from any device, including those that don't say
+    /// which device they're from.
+    any: DeviceState
+}
+
+/// The most recent reports, per device.
+///
+/// Clones share the same state, so hand them out to whoever needs to read it.
+#[derive(Clone, Default)]
+pub struct LatestState {
+    inner: Arc<RwLock<Inner>>
+}
+impl LatestState {
+    /// An empty state.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn read(&self) -> RwLockReadGuard<'_, Inner> {
+        self.inner.read().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Remember whatever reports `resp` contains, including those inside POLL
+    /// and DEVICES responses.
+    pub fn update(&self, resp: &Response) {
+        let mut inner = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        match *resp {
+            Response::Tpv(ref tpv) => inner.tpv(tpv),
+            Response::Sky(ref sky) => inner.sky(sky),
+            Response::Poll { ref tpv, ref sky, .. } => {
+                tpv.iter().for_each(|t| inner.tpv(t));
+                sky.iter().for_each(|s| inner.sky(s));
+            },
+            Response::Device(ref dev) => inner.device(dev),
+            Response::Devices { ref devices, .. } => devices.iter().for_each(|d| inner.device(d)),
+            _ => {}
+        }
+    }
+    /// The most recent TPV report from any device.
+    pub fn latest_fix(&self) -> Option<TpvResponse> {
+        self.read().any.tpv.clone()
+    }
+    /// The most recent TPV report from `device`.
+    pub fn latest_fix_for(&self, device: &str) -> Option<TpvResponse> {
+        self.read().devices.get(&*normalize_path(device)).and_then(|d| d.tpv.clone())
+    }
+    /// The most recent SKY report from any device.
+    pub fn latest_sky(&self) -> Option<SkyResponse> {
+        self.read().any.sky.clone()
+    }
+    /// The most recent SKY report from `device`.
+    pub fn latest_sky_for(&self, device: &str) -> Option<SkyResponse> {
+        self.read().devices.get(&*normalize_path(device)).and_then(|d| d.sky.clone())
+    }
+    /// Everything known about `device`.
+    pub fn device(&self, device: &str) -> Option<DeviceState> {
+        self.read().devices.get(&*normalize_path(device)).cloned()
+    }
+    /// The devices reports have been seen from, normalized as by
+    /// `DevicePath::normalized`.
+    pub fn devices(&self) -> Vec<String> {
+        self.read().devices.keys().cloned().collect()
+    }
+}
+impl Inner {
+    fn entry(&mut self, device: Option<&str>) -> Option<&mut DeviceState> {
+        device.map(move |d| self.devices.entry(normalize_path(d).into_owned()).or_default())
+    }
+    fn tpv(&mut self, tpv: &TpvResponse) {
+        if let Some(d) = self.entry(tpv.device()) {
+            d.tpv = Some(tpv.clone());
+        }
+        self.any.tpv = Some(tpv.clone());
+    }
+    fn sky(&mut self, sky: &SkyResponse) {
+        if let Some(d) = self.entry(sky.device.as_deref()) {
+            d.sky = Some(sky.clone());
+        }
+        self.any.sky = Some(sky.clone());
+    }
+    fn device(&mut self, dev: &DeviceObject) {
+        if let Some(d) = self.entry(dev.path()) {
+            d.device = Some(dev.clone());
+        }
+        self.any.device = Some(dev.clone());
+    }
+}
@@ -8,139 +8,1096 @@
 extern crate serde;
 #[macro_use] extern crate serde_derive;
 #[macro_use] extern crate serde_json;
+#[cfg(feature = "chrono")]
 extern crate chrono;
-#[macro_use] extern crate error_chain;
+#[macro_use] extern crate bitflags;
 #[macro_use] extern crate log;
+#[cfg(all(feature = "bluetooth", target_os = "linux"))]
+extern crate libc;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "tls")]
+extern crate rustls;
 
-use std::net::{ToSocketAddrs, TcpStream};
-use std::io::{BufRead, BufReader, Write};
-use std::time::Duration;
+use std::collections::VecDeque;
+use std::net::{SocketAddr, ToSocketAddrs, TcpStream};
+use std::io::{self, BufRead, BufReader, Write};
+use std::mem;
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Return early with an error, converted into a `GpsdError`.
+macro_rules! bail {
+    ($e:expr) => {
+        return Err(::std::convert::From::from($e))
+    };
+}
 
 pub mod errors {
-    //! Error handling, using error-chain.
+    //! Error handling.
+    //!
+    //! Everything that can fail returns a `GpsdError`, whose variants say what
+    //! kind of failure it was, so callers can decide whether to retry, skip a
+    //! line or give up.
+
+    use std::{error, fmt, io};
 
-    error_chain! {
-        types {
-            Error, ErrorKind, ResultExt, GpsdResult;
+    /// Something that went wrong talking to gpsd.
+    #[derive(Debug)]
+    #[non_exhaustive]
+    pub enum GpsdError {
+        /// An I/O error, e.g. from the socket. Read timeouts set with
+        /// `set_read_timeout` come through as these.
+        Io(io::Error),
+        /// A line that couldn't be deserialized. The line is empty if it was
+        /// a command that couldn't be serialized instead.
+        Json {
+            line: String,
+            source: serde_json::Error
+        },
+        /// The other end broke the protocol: a misbehaving SOCKS5 proxy, say,
+        /// or a garbled NMEA sentence.
+        Protocol(String),
+        /// Nothing arrived in time.
+        Timeout(String),
+        /// The connection was closed, by gpsd or by us.
+        Disconnected(String),
+        /// gpsd stopped answering: the watchdog's keepalive probe went
+        /// unanswered, though the connection is still open.
+        Unhealthy(String),
+        /// gpsd replied to a request with an ERROR.
+        ServerError {
+            message: String
         }
-        foreign_links {
-            Io(::std::io::Error);
-            Serde(::serde_json::Error);
+    }
+    impl GpsdError {
+        /// The error for gpsd hanging up.
+        pub(crate) fn closed() -> Self {
+            GpsdError::Disconnected(String::from("gpsd connection closed"))
+        }
+    }
+    impl fmt::Display for GpsdError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match *self {
+                GpsdError::Io(ref e) => write!(f, "I/O error: {}", e),
+                GpsdError::Json { ref line, ref source } if line.is_empty() => write!(f, "JSON error: {}", source),
+                GpsdError::Json { ref line, ref source } => write!(f, "failed to deserialize text '{}': {}", line, source),
+                GpsdError::Protocol(ref s) => write!(f, "protocol error: {}", s),
+                GpsdError::Timeout(ref s) => write!(f, "timed out: {}", s),
+                GpsdError::Disconnected(ref s) => f.write_str(s),
+                GpsdError::Unhealthy(ref s) => write!(f, "gpsd unresponsive: {}", s),
+                GpsdError::ServerError { ref message } => write!(f, "gpsd error: {}", message)
+            }
         }
-        errors {
-            DeserFailed(s: String, e: ::serde_json::Error) {
-                display("failed to deserialize text '{}': {}", s, e)
+    }
+    impl error::Error for GpsdError {
+        fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+            match *self {
+                GpsdError::Io(ref e) => Some(e),
+                GpsdError::Json { ref source, .. } => Some(source),
+                _ => None
             }
-            GpsdFailed(s: String) {
-                display("gpsd connection closed")
+        }
+    }
+    impl From<io::Error> for GpsdError {
+        fn from(e: io::Error) -> Self {
+            GpsdError::Io(e)
+        }
+    }
+    /// For code that can only return I/O errors, like connectors: I/O
+    /// errors are unwrapped, and anything else is wrapped whole.
+    impl From<GpsdError> for io::Error {
+        fn from(e: GpsdError) -> Self {
+            match e {
+                GpsdError::Io(e) => e,
+                e => io::Error::other(e)
             }
         }
     }
+    impl From<serde_json::Error> for GpsdError {
+        fn from(e: serde_json::Error) -> Self {
+            GpsdError::Json { line: String::new(), source: e }
+        }
+    }
+
+    /// A `Result` whose error is a `GpsdError`.
+    pub type GpsdResult<T> = Result<T, GpsdError>;
 }
-pub use errors::GpsdResult;
+pub use errors::{GpsdError, GpsdResult};
 pub mod types;
+pub mod timestamp;
+pub mod client;
+pub mod udp;
+pub mod heading;
+pub mod socks;
+pub mod calibration;
+pub mod reconnect;
+pub mod builder;
+pub mod transport;
+pub mod mock;
+pub mod nonblocking;
+pub mod demux;
+pub mod decimate;
+pub mod record;
+pub mod replay;
+pub mod multi;
+pub mod events;
+pub mod latest;
+pub mod buffer;
+pub mod handle;
+pub mod stats;
+pub mod borrowed;
+pub mod units;
+pub mod dop;
+pub mod gpx;
+pub mod kml;
+pub mod nmea;
+pub mod geodesy;
+pub mod csv;
+pub mod chrony;
+pub mod influx;
+pub mod metrics;
+pub mod ros;
+pub mod cot;
+#[cfg(all(feature = "bluetooth", target_os = "linux"))]
+pub mod bluetooth;
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+#[cfg(feature = "tokio")]
+pub mod hub;
+#[cfg(feature = "tokio")]
+pub mod task;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+#[cfg(feature = "mdns")]
+pub mod discovery;
+#[cfg(feature = "codec")]
+pub mod codec;
+#[cfg(feature = "parquet")]
+pub mod columnar;
+#[cfg(feature = "geo")]
+pub mod geo;
+#[cfg(all(feature = "geoclue", target_os = "linux"))]
+pub mod geoclue;
+#[cfg(feature = "geojson")]
+pub mod geojson;
+#[cfg(feature = "proj")]
+pub mod proj;
+#[cfg(feature = "prost")]
+pub mod proto;
+#[cfg(feature = "uom")]
+pub mod quantities;
+#[cfg(feature = "sqlite")]
+pub mod storage;
+#[cfg(feature = "utm")]
+pub mod utm;
 #[cfg(test)]
 pub mod tests;
 use types::*;
+use reconnect::{Jitter, ReconnectEvent, ReconnectPolicy};
+use decimate::{Decimation, Decimator};
+use record::Recorder;
+use events::ConnectionEvent;
+use buffer::{BufferReceiver, OverflowPolicy};
+use handle::{CommandHandle, Commands};
+use stats::ConnectionStats;
+pub use builder::GpsdConnectionBuilder;
+pub use transport::Transport;
+use transport::ShutdownHandle;
+
+/// Whichever `Transport` a `GpsdConnection` is reading, boxed: a TCP or
+/// Unix socket, TLS, a WebSocket, a replayed file, or one the caller made.
+type Stream = Box<dyn Transport>;
+
+/// Something that can (re-)establish the stream for a `GpsdConnection`.
+type Connector = Box<dyn FnMut() -> io::Result<Stream> + Send>;
+type ReconnectHandler = Box<dyn FnMut(&ReconnectEvent) + Send>;
+type EventHandler = Box<dyn FnMut(&ConnectionEvent) + Send>;
 
 /// A connection to gpsd.
 pub struct GpsdConnection {
-    raw_data: bool,
-    inner: BufReader<TcpStream>
+    inner: BufReader<Stream>,
+    connector: Connector,
+    read_timeout: Option<Duration>,
+    inactivity_timeout: Option<Duration>,
+    watchdog: Option<Duration>,
+    probe_sent: Option<Instant>,
+    last_data: Instant,
+    line: Vec<u8>,
+    reconnect: Option<ReconnectPolicy>,
+    on_reconnect: Option<ReconnectHandler>,
+    on_event: Option<EventHandler>,
+    /// Whether `Connected` is yet to be reported for the current stream.
+    announce_connected: bool,
+    /// The watch re-sent after reconnecting, until gpsd confirms it.
+    watch_pending: Option<WatchObject>,
+    /// Responses read while waiting for the reply to a `request`.
+    deferred: VecDeque<GpsdResult<Envelope>>,
+    /// Counters, apart from bytes written (which `commands` keeps).
+    stats: ConnectionStats,
+    jitter: Jitter,
+    decimator: Option<Decimator>,
+    recorder: Option<Recorder>,
+    parse_mode: ParseMode,
+    closer: Arc<Closer>,
+    commands: Arc<Commands>
+}
+/// State shared between a connection and its `CloseHandle`s.
+#[derive(Default)]
+struct Closer {
+    closing: AtomicBool,
+    /// Shuts down the current stream's reading side, if it can be.
+    shutdown: Mutex<Option<ShutdownHandle>>
+}
+impl Closer {
+    fn is_closing(&self) -> bool {
+        self.closing.load(Ordering::SeqCst)
+    }
+    fn set_stream(&self, stream: &Stream) {
+        *self.shutdown.lock().unwrap() = stream.shutdown_handle();
+    }
+}
+/// A handle for stopping a `GpsdConnection` from another thread, e.g. one
+/// moved onto a background reader with `spawn_channel`.
+///
+/// Created by `GpsdConnection::close_handle`.
+#[derive(Clone)]
+pub struct CloseHandle(Arc<Closer>);
+impl CloseHandle {
+    /// Stop the connection: a `get_response` in progress (or any later one)
+    /// fails with `GpsdError::Disconnected`, as if gpsd had hung up, and no
+    /// reconnection is attempted.
+    ///
+    /// If the transport can't be shut down from another thread (see
+    /// `Transport::shutdown_handle`), a read in progress isn't interrupted but
+    /// the connection stops once it finishes.
+    pub fn close(&self) {
+        self.0.closing.store(true, Ordering::SeqCst);
+        if let Some(ref f) = *self.0.shutdown.lock().unwrap() {
+            f();
+        }
+    }
+    /// Whether `close` has been called.
+    pub fn is_closed(&self) -> bool {
+        self.0.is_closing()
+    }
 }
 impl GpsdConnection {
     /// Make a new connection to a given address.
+    ///
+    /// If the address resolves to several socket addresses, they're tried
+    /// alternating between IPv6 and IPv4, with a slow attempt overlapping the
+    /// next (here and when reconnecting).
     pub fn new<A: ToSocketAddrs>(addr: A) -> GpsdResult<Self> {
-        let stream = TcpStream::connect(addr)?;
-        let inner = BufReader::new(stream);
-        Ok(Self { inner, raw_data: false })
+        let addrs = addr.to_socket_addrs()?.collect::<Vec<SocketAddr>>();
+        Self::with_connector(Box::new(move || connect_addrs(&addrs, None).map(|s| Box::new(s) as Stream)))
     }
-    /// Enable or disable watcher mode.
-    fn _watch(&mut self, watch: bool, json: bool, raw: u8) -> GpsdResult<()> {
+    /// Connect to the gpsd named by a URL like `gpsd://host:2947/dev/ttyACM0`,
+    /// watching the device if there is one. See
+    /// `GpsdConnectionBuilder::url`.
+    pub fn connect_url(url: &str) -> GpsdResult<Self> {
+        Self::builder().url(url)?.connect()
+    }
+    /// Returns a builder, for making a connection with more options.
+    pub fn builder() -> GpsdConnectionBuilder {
+        GpsdConnectionBuilder::new()
+    }
+    /// Make a new connection to a given address, giving up on each of the
+    /// addresses it resolves to after `timeout`.
+    ///
+    /// The same timeout applies when reconnecting.
+    pub fn connect_timeout<A: ToSocketAddrs>(addr: A, timeout: Duration) -> GpsdResult<Self> {
+        let addrs = addr.to_socket_addrs()?.collect::<Vec<SocketAddr>>();
+        Self::with_connector(Box::new(move || connect_addrs(&addrs, Some(timeout)).map(|s| Box::new(s) as Stream)))
+    }
+    /// Speak the gpsd protocol over an already-open `transport`.
+    ///
+    /// The transport can't be reopened, so reconnecting always fails; use
+    /// `with_transport` if you need that.
+    pub fn from_transport<T: Transport + 'static>(transport: T) -> GpsdResult<Self> {
+        let mut transport = Some(transport);
+        Self::with_transport(move || {
+            transport.take().ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "transport can't be reopened"))
+        })
+    }
+    /// Speak the gpsd protocol over transports opened by `connect`, which is
+    /// called once now and again each time the connection is re-established.
+    pub fn with_transport<F, T>(mut connect: F) -> GpsdResult<Self>
+        where F: FnMut() -> io::Result<T> + Send + 'static, T: Transport + 'static {
+        Self::with_connector(Box::new(move || connect().map(|t| Box::new(t) as Stream)))
+    }
+    fn with_connector(mut connector: Connector) -> GpsdResult<Self> {
+        let stream = connector()?;
+        let closer = Arc::new(Closer::default());
+        closer.set_stream(&stream);
+        let commands = Arc::new(Commands::default());
+        commands.set_stream(&stream);
+        Ok(Self {
+            inner: BufReader::new(stream),
+            connector,
+            read_timeout: None,
+            inactivity_timeout: None,
+            watchdog: None,
+            probe_sent: None,
+            last_data: Instant::now(),
+            line: Vec::new(),
+            reconnect: None,
+            on_reconnect: None,
+            on_event: None,
+            announce_connected: true,
+            watch_pending: None,
+            deferred: VecDeque::new(),
+            stats: ConnectionStats::default(),
+            jitter: Jitter::new(),
+            decimator: None,
+            recorder: None,
+            parse_mode: ParseMode::default(),
+            closer,
+            commands
+        })
+    }
+    /// Disable watcher mode, then shut the connection down.
+    ///
+    /// Dropping a connection closes it too, but just hangs up; this tells gpsd
+    /// first, so it can stop streaming (and, if nobody else is watching, power
+    /// down the device) straight away.
+    pub fn close(mut self) -> GpsdResult<()> {
+        self.send(b"?WATCH={\"enable\":false};\n")?;
         let stream = self.inner.get_mut();
-        let watch_data = json!({
-            "class": "WATCH",
-            "enable": watch,
-            "json": json,
-            "raw": raw,
-        });
-        self.raw_data = raw > 0;
-        let msg = format!("?WATCH={}\n", watch_data.to_string());
-        stream.write_all(msg.as_bytes())?;
+        stream.flush()?;
+        stream.shutdown()?;
+        Ok(())
+    }
+    /// Returns a handle that can stop this connection from another thread.
+    /// See `CloseHandle::close`.
+    pub fn close_handle(&self) -> CloseHandle {
+        CloseHandle(self.closer.clone())
+    }
+    /// Called by background readers as they stop: if they were asked to
+    /// stop with a `CloseHandle`, close the connection properly.
+    fn finish(self) {
+        if self.closer.is_closing() {
+            if let Err(e) = self.close() {
+                debug!("closing connection failed: {}", e);
+            }
+        }
+    }
+    /// A snapshot of the connection's counters; see the `stats` module.
+    pub fn stats(&self) -> ConnectionStats {
+        let mut stats = self.stats.clone();
+        stats.bytes_written = self.commands.bytes_written.load(Ordering::Relaxed);
+        stats
+    }
+    /// Returns a cloneable handle for sending commands on this connection
+    /// from elsewhere, e.g. while it's being read on another thread. See
+    /// `handle`.
+    pub fn command_handle(&self) -> CommandHandle {
+        CommandHandle(self.commands.clone())
+    }
+    /// Write a command to gpsd.
+    fn send(&mut self, msg: &[u8]) -> GpsdResult<()> {
+        if !self.commands.write(msg)? {
+            self.inner.get_mut().write_all(msg)?;
+            self.commands.wrote(msg.len());
+        }
         Ok(())
     }
+    fn closed_error() -> GpsdError {
+        GpsdError::closed()
+    }
+    /// Enable automatic reconnection with the given policy, or disable it
+    /// with `None` (the default).
+    ///
+    /// When enabled, `get_response` responds to the connection being closed or
+    /// failing by reconnecting (using the same address, proxy or TLS settings
+    /// as the original connection), and carries on reading. Read timeouts don't
+    /// trigger a reconnect. If the policy gives up, the error that caused the
+    /// disconnection is returned.
+    ///
+    /// Once reconnected, the last watch sent is sent again, and gpsd's reply is
+    /// checked against it; see `ReconnectEvent::WatchRestored`.
+    pub fn set_reconnect(&mut self, policy: Option<ReconnectPolicy>) {
+        self.reconnect = policy;
+    }
+    /// Register a handler to be told about reconnection progress.
+    ///
+    /// The handler is called from within `get_response`.
+    pub fn on_reconnect<F>(&mut self, f: F) where F: FnMut(&ReconnectEvent) + Send + 'static {
+        self.on_reconnect = Some(Box::new(f));
+    }
+    fn reconnect_event(&mut self, ev: ReconnectEvent) {
+        debug!("reconnect: {:?}", ev);
+        match ev {
+            ReconnectEvent::Attempt { attempt, delay } => {
+                self.connection_event(ConnectionEvent::Reconnecting { attempt, delay });
+            },
+            ReconnectEvent::Reconnected { .. } => self.announce_connected = true,
+            _ => {}
+        }
+        if let Some(ref mut f) = self.on_reconnect {
+            f(&ev);
+        }
+    }
+    /// Register a handler to be told about the connection coming up and going
+    /// down. See `events` for details.
+    ///
+    /// The handler is called from within `get_response`; in particular, the
+    /// initial `Connected` is reported at the start of the first call.
+    pub fn on_connection_event<F>(&mut self, f: F) where F: FnMut(&ConnectionEvent) + Send + 'static {
+        self.on_event = Some(Box::new(f));
+    }
+    fn connection_event(&mut self, ev: ConnectionEvent) {
+        debug!("connection event: {:?}", ev);
+        if let Some(ref mut f) = self.on_event {
+            f(&ev);
+        }
+    }
+    /// Try to re-establish the connection after it failed with `cause`,
+    /// according to the reconnect policy.
+    fn try_reconnect(&mut self, cause: GpsdError) -> GpsdResult<()> {
+        let policy = match self.reconnect {
+            Some(ref p) => p.clone(),
+            None => return Err(cause)
+        };
+        self.reconnect_event(ReconnectEvent::Disconnected(cause.to_string()));
+        let mut attempt = 0;
+        while policy.may_retry(attempt) {
+            if self.closer.is_closing() {
+                return Err(Self::closed_error());
+            }
+            attempt += 1;
+            let delay = policy.delay(attempt, &mut self.jitter);
+            self.reconnect_event(ReconnectEvent::Attempt { attempt, delay });
+            thread::sleep(delay);
+            let res = (self.connector)().and_then(|s| {
+                s.set_read_timeout(self.read_timeout)?;
+                Ok(s)
+            });
+            match res {
+                Ok(stream) => {
+                    // Closing may have raced with us swapping the stream.
+                    self.closer.set_stream(&stream);
+                    self.commands.set_stream(&stream);
+                    if self.closer.is_closing() {
+                        return Err(Self::closed_error());
+                    }
+                    self.inner = BufReader::new(stream);
+                    self.line.clear();
+                    self.last_data = Instant::now();
+                    self.probe_sent = None;
+                    self.stats.reconnects += 1;
+                    self.reconnect_event(ReconnectEvent::Reconnected { attempts: attempt });
+                    if let Some((msg, watch)) = self.commands.last_watch() {
+                        // If this fails, so will the next read, which will
+                        // reconnect again.
+                        match self.send(&msg) {
+                            Ok(()) => self.watch_pending = Some(watch),
+                            Err(e) => warn!("restoring watch failed: {}", e)
+                        }
+                    }
+                    return Ok(());
+                },
+                Err(e) => {
+                    self.reconnect_event(ReconnectEvent::AttemptFailed { attempt, error: e.to_string() });
+                }
+            }
+        }
+        self.reconnect_event(ReconnectEvent::GaveUp { attempts: attempt });
+        Err(cause)
+    }
+    /// Enable or disable watcher mode.
+    fn _watch(&mut self, watch: bool, json: bool, raw: RawLevel) -> GpsdResult<()> {
+        let msg = watch_command(watch, json, raw);
+        self.commands.set_watch(msg.as_bytes(), watch_object(watch, json, raw));
+        self.send(msg.as_bytes())
+    }
+    /// Set watcher mode parameters, as described by `watch`.
+    pub fn watch_with(&mut self, watch: &WatchObject) -> GpsdResult<()> {
+        let msg = format!("?WATCH={}\n", serde_json::to_string(watch)?);
+        self.commands.set_watch(msg.as_bytes(), watch.clone());
+        self.send(msg.as_bytes())
+    }
     /// Enable or disable watcher mode.
     pub fn watch(&mut self, watch: bool) -> GpsdResult<()> {
-        self._watch(watch, true, 0)
+        self._watch(watch, true, RawLevel::Off)
     }
-    /// Enable RAW mode. In RAW mode, gpsd sends raw data from the GPS device, depending on the value of `raw`:
-    ///
-    /// When this attribute is set to 1 for a channel, gpsd reports the unprocessed NMEA or
-    /// AIVDM data stream from whatever device is attached. Binary GPS packets are hex-dumped.
-    /// RTCM2 and RTCM3 packets are not dumped in raw mode. When this attribute is set to 2 for a channel that
-    /// processes binary data, gpsd reports the received data verbatim without hex-dumping.
-    pub fn watch_raw(&mut self, watch: bool, json: bool, raw: u8) -> GpsdResult<()> {
+    /// Enable RAW mode. In RAW mode, gpsd sends raw data from the GPS device,
+    /// as described by `raw` (see `RawLevel`).
+    pub fn watch_raw(&mut self, watch: bool, json: bool, raw: RawLevel) -> GpsdResult<()> {
         self._watch(watch, json, raw)
     }
+    /// Send `cmd` to gpsd.
+    pub fn send_command(&mut self, cmd: &Command) -> GpsdResult<()> {
+        let msg = cmd.to_line();
+        if let Command::Watch(ref w) = *cmd {
+            self.commands.set_watch(msg.as_bytes(), w.clone());
+        }
+        self.send(msg.as_bytes())
+    }
+    /// Send `cmd` to gpsd and wait for its reply (see `Command::is_reply`).
+    /// If gpsd replies with an ERROR, this fails with
+    /// `GpsdError::ServerError`.
+    ///
+    /// Other responses that arrive in the meantime aren't lost: subsequent
+    /// calls to `get_response` return them first. `Raw` commands can't be
+    /// sent this way, as their replies can't be told apart.
+    pub fn request(&mut self, cmd: &Command) -> GpsdResult<Response> {
+        if !cmd.expects_reply() {
+            bail!(io::Error::new(io::ErrorKind::InvalidInput, "can't wait for the reply to a raw command"));
+        }
+        self.send_command(cmd)?;
+        loop {
+            match self.read_envelope() {
+                Ok(Envelope { parsed: Response::Error { message }, .. }) => bail!(GpsdError::ServerError { message }),
+                Ok(env) if cmd.is_reply(&env.parsed) => return Ok(env.parsed),
+                r @ Ok(_) | r @ Err(GpsdError::Json { .. }) => self.deferred.push_back(r),
+                Err(e) => return Err(e)
+            }
+        }
+    }
     /// The POLL command requests data from the last-seen fixes on all active
     /// GPS devices. Devices must previously have been activated by ?WATCH to be
     /// pollable.
     pub fn poll(&mut self) -> GpsdResult<()> {
-        let stream = self.inner.get_mut();
-        stream.write_all("?POLL;\n".as_bytes())?;
-        Ok(())
+        self.send(b"?POLL;\n")
     }
     /// Ask for the server's version (triggers a Response::Version).
     pub fn version(&mut self) -> GpsdResult<()> {
-        let stream = self.inner.get_mut();
-        stream.write_all("?VERSION;\n".as_bytes())?;
-        Ok(())
+        self.send(b"?VERSION;\n")
     }
     /// Ask for the server's devices (triggers a Response::Devices)
     pub fn devices(&mut self) -> GpsdResult<()> {
-        let stream = self.inner.get_mut();
-        stream.write_all("?DEVICES;\n".as_bytes())?;
-        Ok(())
+        self.send(b"?DEVICES;\n")
     }
     /// Sets the read timeout for `get_response`.
     ///
-    /// A value of `None` implies that the read will never block.
+    /// If a single read from gpsd takes longer than this, `get_response`
+    /// returns an `Io` error with kind `WouldBlock` or `TimedOut`. Any partial
+    /// line read so far is kept for the next call. A value of `None` implies
+    /// that the read will block indefinitely.
     pub fn set_read_timeout(&mut self, dur: Option<Duration>) -> GpsdResult<()> {
         self.inner.get_ref().set_read_timeout(dur)?;
+        self.read_timeout = dur;
         Ok(())
     }
+    /// Sets the inactivity timeout for `get_response`.
+    ///
+    /// If nothing at all has been received from gpsd for this long,
+    /// `get_response` fails with `GpsdError::Timeout`, even if there's no read
+    /// timeout set. A value of `None` (the default) disables the check.
+    pub fn set_inactivity_timeout(&mut self, dur: Option<Duration>) {
+        self.inactivity_timeout = dur;
+        self.last_data = Instant::now();
+    }
+    /// Enable or disable (with `None`, the default) the keepalive watchdog.
+    ///
+    /// If nothing is received from gpsd for `period`, a `?POLL;` probe is sent.
+    /// If nothing arrives within another `period` after that, the connection
+    /// is considered dead: `get_response` reconnects if a reconnect policy is
+    /// set, and otherwise fails with `GpsdError::Unhealthy`. The connection
+    /// is left open, and the next call starts probing again.
+    ///
+    /// The reply to the probe is returned from `get_response` as usual.
+    pub fn set_watchdog(&mut self, period: Option<Duration>) {
+        self.watchdog = period;
+        self.probe_sent = None;
+        self.last_data = Instant::now();
+    }
+    /// Drop excess TPV reports according to `rule`, or stop dropping them
+    /// (with `None`, the default). See `decimate` for details.
+    pub fn set_decimation(&mut self, rule: Option<Decimation>) {
+        self.decimator = rule.map(Decimator::new);
+    }
+    /// Record every line received from now on with `recorder`, or stop
+    /// recording (with `None`). See `record` for details.
+    ///
+    /// If writing to the recording fails, a warning is logged and recording
+    /// stops; reading from gpsd carries on regardless.
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) {
+        self.recorder = recorder;
+    }
+    /// Choose how to treat responses this crate doesn't expect (see
+    /// `ParseMode`). The default is lenient; strict is for debugging.
+    pub fn set_parse_mode(&mut self, mode: ParseMode) {
+        self.parse_mode = mode;
+    }
+    /// Read a line from gpsd, honouring the read timeout, inactivity timeout
+    /// and watchdog.
+    ///
+    /// Returns the number of bytes read, like `read_line`; the data is
+    /// accumulated in `self.line`.
+    fn read_line(&mut self) -> GpsdResult<usize> {
+        loop {
+            let queued = self.commands.take_queued();
+            if !queued.is_empty() {
+                self.inner.get_mut().write_all(&queued)?;
+                self.commands.wrote(queued.len());
+            }
+            let mut limit = None;
+            if let Some(max) = self.inactivity_timeout {
+                let elapsed = self.last_data.elapsed();
+                if elapsed >= max {
+                    bail!(GpsdError::Timeout(format!("nothing received from gpsd for {:?}", elapsed)));
+                }
+                limit = Some(max - elapsed);
+            }
+            if let Some(period) = self.watchdog {
+                let since = self.probe_sent.unwrap_or(self.last_data);
+                if since.elapsed() >= period {
+                    if self.probe_sent.take().is_some() {
+                        self.last_data = Instant::now();
+                        bail!(GpsdError::Unhealthy(format!("no reply to ?POLL; within {:?}", period)));
+                    }
+                    debug!("nothing received for {:?}, sending keepalive probe", period);
+                    self.send(b"?POLL;\n")?;
+                    self.probe_sent = Some(Instant::now());
+                }
+                let since = self.probe_sent.unwrap_or(self.last_data);
+                let remaining = period.checked_sub(since.elapsed()).unwrap_or_default();
+                limit = Some(limit.map_or(remaining, |l: Duration| l.min(remaining)));
+            }
+            // Only override the socket's read timeout if a deadline comes first.
+            let deadline = match (limit, self.read_timeout) {
+                (Some(l), Some(rt)) if rt <= l => None,
+                (Some(l), _) => Some(l.max(Duration::from_millis(1))),
+                (None, _) => None
+            };
+            if let Some(d) = deadline {
+                self.inner.get_ref().set_read_timeout(Some(d))?;
+            }
+            let res = self.inner.read_until(b'\n', &mut self.line);
+            if deadline.is_some() {
+                self.inner.get_ref().set_read_timeout(self.read_timeout)?;
+            }
+            match res {
+                Ok(n) => {
+                    self.last_data = Instant::now();
+                    self.probe_sent = None;
+                    self.stats.bytes_read += n as u64;
+                    return Ok(n);
+                },
+                // Go round again to check the deadlines.
+                Err(ref e) if deadline.is_some() && is_timeout(e) => continue,
+                Err(e) => return Err(e.into())
+            }
+        }
+    }
+    /// Move the connection onto a background reader thread, returning a
+    /// channel that receives its responses.
+    ///
+    /// At most `capacity` responses are buffered; once the buffer is full, the
+    /// reader stops reading from gpsd until the receiver catches up. Responses
+    /// that fail to deserialize are logged and skipped. The thread exits,
+    /// closing the channel, when the connection fails or the receiver is
+    /// dropped. To stop it sooner, take a `close_handle` first.
+    pub fn spawn_channel(self, capacity: usize) -> Receiver<Response> {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        self.spawn_reader(move |resp| {
+            let sent = tx.send(resp).is_ok();
+            if !sent {
+                debug!("response receiver dropped, stopping reader");
+            }
+            sent
+        });
+        rx
+    }
+    /// Like `spawn_channel`, but handling a full buffer according to `policy`
+    /// rather than always blocking the reader. See `buffer` for details.
+    ///
+    /// The receiver's `stats` say how many responses were dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn spawn_buffered(self, capacity: usize, policy: OverflowPolicy) -> BufferReceiver<Response> {
+        let (tx, rx) = buffer::bounded(capacity, policy);
+        self.spawn_reader(move |resp| {
+            let sent = tx.send(resp).is_ok();
+            if !sent {
+                debug!("response receiver dropped, stopping reader");
+            }
+            sent
+        });
+        rx
+    }
+    /// Move the connection onto a background reader thread, handing each
+    /// response to `handle` until it returns false or the connection fails.
+    /// Responses that fail to deserialize are logged and skipped.
+    pub(crate) fn spawn_reader<F: FnMut(Response) -> bool + Send + 'static>(mut self, mut handle: F) {
+        thread::spawn(move || {
+            loop {
+                match self.get_response() {
+                    Ok(resp) => {
+                        if !handle(resp) {
+                            break;
+                        }
+                    },
+                    Err(GpsdError::Json { line: s, source: e }) => {
+                        warn!("skipping undeserializable response '{}': {}", s, e);
+                    },
+                    Err(e) => {
+                        warn!("reader thread stopping: {}", e);
+                        break;
+                    }
+                }
+            }
+            self.finish();
+        });
+    }
+    /// Returns an iterator over responses from GPSD.
+    ///
+    /// Each call to `next` blocks in the same way as `get_response`. The
+    /// iterator ends once gpsd closes the connection.
+    pub fn iter(&mut self) -> Responses<'_> {
+        Responses { conn: self, done: false }
+    }
     /// Polls for responses from GPSD, blocking if necessary.
     ///
     /// Ideally, you run this in a loop somewhere to process messages.
+    ///
+    /// A line that can't be parsed (a truncated write, say) is returned as an
+    /// `GpsdError::Json` error carrying the raw line; the next call
+    /// carries on from the following line.
     pub fn get_response(&mut self) -> GpsdResult<Response> {
+        self.get_envelope().map(|e| e.parsed)
+    }
+    /// Like `get_response`, but also returning the line gpsd sent, for
+    /// logging it exactly as received.
+    pub fn get_envelope(&mut self) -> GpsdResult<Envelope> {
+        match self.deferred.pop_front() {
+            Some(r) => r,
+            None => self.read_envelope()
+        }
+    }
+    /// Read the next response from gpsd itself.
+    fn read_envelope(&mut self) -> GpsdResult<Envelope> {
         loop {
-            let mut buf = String::new();
-            let read_result = self.inner.read_line(&mut buf);
+            if self.closer.is_closing() {
+                return Err(Self::closed_error());
+            }
+            if self.announce_connected {
+                self.announce_connected = false;
+                self.connection_event(ConnectionEvent::Connected);
+            }
+            let err = match self.read_line() {
+                Ok(0) => Self::closed_error(),
+                Ok(_) => {
+                    let buf = decode_line(mem::take(&mut self.line));
+                    if let Some(ref mut rec) = self.recorder {
+                        if let Err(e) = rec.record(&buf) {
+                            warn!("recording failed, stopping recording: {}", e);
+                            self.recorder = None;
+                        }
+                    }
+                    if buf.trim().is_empty() {
+                        debug!("empty line received from GPSD");
+                        continue;
+                    }
+                    debug!("raw GPSD data: {}", buf);
+                    let resp = parse_envelope(buf, self.commands.raw(), self.parse_mode);
+                    match resp {
+                        Ok(ref e) => *self.stats.messages.entry(e.parsed.class()).or_insert(0) += 1,
+                        Err(_) => self.stats.parse_errors += 1
+                    }
+                    match resp {
+                        Ok(Envelope { parsed: Response::Tpv(ref tpv), .. }) => {
+                            if let Some(ref mut d) = self.decimator {
+                                if !d.accept(tpv) {
+                                    debug!("dropping TPV by decimation");
+                                    continue;
+                                }
+                            }
+                        },
+                        Ok(Envelope { parsed: Response::Version { ref release, proto_major, proto_minor, .. }, .. }) => {
+                            let release = release.clone();
+                            self.connection_event(ConnectionEvent::VersionReceived { release, proto_major, proto_minor });
+                        },
+                        Ok(Envelope { parsed: Response::Watch(ref w), .. }) => {
+                            if let Some(asked) = self.watch_pending.take() {
+                                if watch_matches(&asked, w) {
+                                    self.reconnect_event(ReconnectEvent::WatchRestored);
+                                } else {
+                                    warn!("gpsd didn't restore the watch after reconnecting: asked for {:?}, got {:?}", asked, w);
+                                    self.reconnect_event(ReconnectEvent::WatchMismatch { reply: w.clone() });
+                                }
+                            }
+                            if w.enable {
+                                self.connection_event(ConnectionEvent::WatchEstablished);
+                            }
+                        },
+                        _ => {}
+                    }
+                    return resp;
+                },
+                Err(e) => {
+                    match e {
+                        GpsdError::Io(ref e) if !is_timeout(e) => {},
+                        GpsdError::Disconnected(..) | GpsdError::Unhealthy(..) => {},
+                        _ => return Err(e)
+                    }
+                    e
+                }
+            };
+            self.connection_event(ConnectionEvent::Disconnected(err.to_string()));
+            if self.closer.is_closing() {
+                return Err(Self::closed_error());
+            }
+            self.try_reconnect(err)?;
+        }
+    }
+}
+/// How long to wait for a connection attempt before starting the next one in
+/// parallel, as suggested by RFC 8305.
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
 
-            if let Ok(size) = read_result {
-                if size == 0 {
-                    bail!(errors::ErrorKind::GpsdFailed(String::from("Gpsd Connection Closed")));
+/// Order `addrs` for connecting: alternating between address families, and
+/// starting with whichever family comes first (so the resolver's preference
+/// for IPv6 or IPv4 is kept).
+fn interleave_families(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+    let first_v6 = addrs.first().map(|a| a.is_ipv6()).unwrap_or(false);
+    let (mut same, mut other): (Vec<_>, Vec<_>) = addrs.iter().partition(|a| a.is_ipv6() == first_v6);
+    same.reverse();
+    other.reverse();
+    let mut ret = Vec::with_capacity(addrs.len());
+    while let Some(a) = same.pop() {
+        ret.push(a);
+        if let Some(b) = other.pop() {
+            ret.push(b);
+        }
+    }
+    ret.extend(other.into_iter().rev());
+    ret
+}
+/// Connect to one of `addrs`, optionally giving up on each after `timeout`.
+///
+/// This is "happy eyeballs" (RFC 8305): addresses are tried alternating
+/// between IPv6 and IPv4, and if an attempt hasn't finished after
+/// `ATTEMPT_DELAY`, the next one is started alongside it. The first to
+/// succeed is used; the error returned is that of the last to fail.
+fn connect_addrs(addrs: &[SocketAddr], timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let attempt = move |addr: &SocketAddr| match timeout {
+        Some(t) => TcpStream::connect_timeout(addr, t),
+        None => TcpStream::connect(addr)
+    };
+    let addrs = interleave_families(addrs);
+    match addrs.len() {
+        0 => return Err(io::Error::new(io::ErrorKind::InvalidInput, "no addresses to connect to")),
+        1 => return attempt(&addrs[0]),
+        _ => {}
+    }
+    let (tx, rx) = mpsc::channel();
+    let (mut started, mut failed) = (0, 0);
+    loop {
+        if started < addrs.len() {
+            let (tx, addr) = (tx.clone(), addrs[started]);
+            // Losing attempts finish in the background; their sockets are
+            // closed when the send fails or the result is dropped.
+            thread::spawn(move || { let _ = tx.send((addr, attempt(&addr))); });
+            started += 1;
+        }
+        let res = if started < addrs.len() {
+            match rx.recv_timeout(ATTEMPT_DELAY) {
+                Ok(r) => r,
+                Err(_) => continue
+            }
+        } else {
+            // We hold a sender, so this can't fail.
+            rx.recv().expect("connection attempts vanished")
+        };
+        match res {
+            (_, Ok(s)) => return Ok(s),
+            (addr, Err(e)) => {
+                debug!("connecting to {} failed: {}", addr, e);
+                failed += 1;
+                if failed == addrs.len() {
+                    return Err(e);
                 }
             }
+        }
+    }
+}
+/// Whether an I/O error is the result of a read timing out.
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
+/// Build a `?WATCH` command.
+fn watch_command(watch: bool, json: bool, raw: RawLevel) -> String {
+    let watch_data = json!({
+        "class": "WATCH",
+        "enable": watch,
+        "json": json,
+        "raw": u8::from(raw),
+    });
+    format!("?WATCH={}\n", watch_data)
+}
+/// The watch a `watch_command` asks for.
+fn watch_object(watch: bool, json: bool, raw: RawLevel) -> WatchObject {
+    WatchObject { enable: watch, json, raw: Some(raw), ..Default::default() }
+}
+/// Whether gpsd's WATCH `reply` confirms the watch we asked for.
+fn watch_matches(asked: &WatchObject, reply: &WatchObject) -> bool {
+    asked.enable == reply.enable &&
+        asked.json == reply.json &&
+        asked.raw.unwrap_or_default() == reply.raw.unwrap_or_default() &&
+        match asked.device {
+            Some(ref d) => reply.device.as_ref().is_some_and(|r| d.matches(r)),
+            None => true
+        }
+}
+/// Turn a line read from gpsd into a string.
+///
+/// Bytes that aren't valid UTF-8 (from a driver hiccup, say) are replaced,
+/// so the line fails to parse, or comes through as `Response::Raw`, like any
+/// other garbage, rather than failing the read.
+fn decode_line(buf: Vec<u8>) -> String {
+    match String::from_utf8(buf) {
+        Ok(s) => s,
+        Err(e) => {
+            debug!("invalid UTF-8 received from GPSD");
+            String::from_utf8_lossy(e.as_bytes()).into_owned()
+        }
+    }
+}
+/// Parse one line of gpsd output.
+///
+/// If `raw` is set, lines that aren't valid JSON are returned as `Response::Raw`.
+fn parse_line(buf: String, raw: bool) -> GpsdResult<Response> {
+    parse_envelope(buf, raw, ParseMode::default()).map(|e| e.parsed)
+}
+/// Parse one line of gpsd output, keeping the line (without its line ending)
+/// alongside the response.
+///
+/// Lines rejected by `ParseMode::Strict` fail with `GpsdError::Json`, as any
+/// other bad line does.
+fn parse_envelope(mut buf: String, raw: bool, mode: ParseMode) -> GpsdResult<Envelope> {
+    let data = serde_json::from_str(&buf);
+    debug!("serde output: {:?}", data);
+    let len = buf.trim_end_matches(&['\r', '\n'][..]).len();
+    let parsed = match data {
+        Ok(parsed) => parsed,
+        Err(e) => match unknown_class(&buf) {
+            Some(data) if mode == ParseMode::Lenient => Response::Unknown(data),
+            _ if raw => Response::Raw(buf.clone()),
+            _ => {
+                debug!("deserializing response failed: {:?}", e);
+                bail!(GpsdError::Json { line: buf, source: e });
+            }
+        }
+    };
+    if mode == ParseMode::Strict {
+        let unknown = parsed.unknown_fields();
+        if !unknown.is_empty() {
+            let e = serde::de::Error::custom(format!("unknown fields in {}: {}", parsed.class(), unknown.join(", ")));
+            bail!(GpsdError::Json { line: buf, source: e });
+        }
+    }
+    buf.truncate(len);
+    Ok(Envelope { raw: buf, parsed })
+}
+/// The classes gpsd sends that `Response` has variants for.
+const KNOWN_CLASSES: &[&str] = &["TPV", "SKY", "POLL", "DEVICE", "DEVICES", "WATCH", "VERSION", "ERROR"];
+/// If `buf` is a JSON object with a class we don't know, the object.
+fn unknown_class(buf: &str) -> Option<serde_json::Value> {
+    let data: serde_json::Value = serde_json::from_str(buf).ok()?;
+    match data["class"].as_str() {
+        Some(class) if !KNOWN_CLASSES.contains(&class) => {},
+        _ => return None
+    }
+    Some(data)
+}
+
+/// A blocking iterator over responses from a `GpsdConnection`.
+///
+/// Created by `GpsdConnection::iter`, or by iterating over a `&mut GpsdConnection`.
+pub struct Responses<'a> {
+    conn: &'a mut GpsdConnection,
+    done: bool
+}
+impl<'a> Iterator for Responses<'a> {
+    type Item = GpsdResult<Response>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        next_response(self.conn, &mut self.done)
+    }
+}
+impl<'a> IntoIterator for &'a mut GpsdConnection {
+    type Item = GpsdResult<Response>;
+    type IntoIter = Responses<'a>;
+
+    fn into_iter(self) -> Responses<'a> {
+        self.iter()
+    }
+}
+/// An owning blocking iterator over responses from a `GpsdConnection`.
+///
+/// Created by calling `into_iter` on a `GpsdConnection`.
+pub struct IntoResponses {
+    conn: GpsdConnection,
+    done: bool
+}
+impl Iterator for IntoResponses {
+    type Item = GpsdResult<Response>;
 
-            if buf == "" {
-                debug!("empty line received from GPSD");
+    fn next(&mut self) -> Option<Self::Item> {
+        next_response(&mut self.conn, &mut self.done)
+    }
+}
+impl IntoIterator for GpsdConnection {
+    type Item = GpsdResult<Response>;
+    type IntoIter = IntoResponses;
+
+    fn into_iter(self) -> IntoResponses {
+        IntoResponses { conn: self, done: false }
+    }
+}
+/// The guts of the writers' `consume`: `write` each of `responses` to
+/// `target` until they end, returning how many it took. Responses that
+/// couldn't be parsed are logged and skipped; any other error, from the
+/// stream or from writing, stops it. `flush` is called either way.
+pub(crate) fn consume<T, I, W, F>(target: &mut T, responses: I, mut write: W, flush: F) -> GpsdResult<u64>
+    where I: IntoIterator<Item = GpsdResult<Response>>,
+          W: FnMut(&mut T, &Response) -> GpsdResult<bool>,
+          F: FnOnce(&mut T) -> GpsdResult<()> {
+    let mut written = 0;
+    let mut res = Ok(());
+    for resp in responses {
+        let resp = match resp {
+            Ok(resp) => resp,
+            Err(GpsdError::Json { line: s, source: e }) => {
+                warn!("skipping undeserializable response '{}': {}", s, e);
                 continue;
+            },
+            Err(e) => {
+                res = Err(e);
+                break;
             }
-            debug!("raw GPSD data: {}", buf);
-            let data = serde_json::from_str(&buf);
-            debug!("serde output: {:?}", data);
-            match data {
-                Err(e) => {
-                    if self.raw_data {
-                        return Ok(Response::Raw(buf))
-                    } else {
-                        debug!("deserializing response failed: {:?}", e);
-                        bail!(errors::ErrorKind::DeserFailed(buf, e));
-                    }
-                },
-                Ok(x) => return Ok(x)
+        };
+        match write(target, &resp) {
+            Ok(w) => written += u64::from(w),
+            Err(e) => {
+                res = Err(e);
+                break;
             }
         }
     }
+    let flushed = flush(target);
+    res.and(flushed).map(|_| written)
+}
+/// Fetch the next response for an iterator, ending iteration when gpsd hangs up.
+fn next_response(conn: &mut GpsdConnection, done: &mut bool) -> Option<GpsdResult<Response>> {
+    if *done {
+        return None;
+    }
+    match conn.get_response() {
+        Err(GpsdError::Disconnected(..)) => {
+            *done = true;
+            None
+        },
+        x => Some(x)
+    }
 }
@@ -0,0 +1,124 @@
+//! GPS health metrics, in Prometheus's text exposition format.
+//!
+//! A `GpsMetrics` watches the responses from a connection and keeps the
+//! latest of what an operator would alert on, for each device: the fix mode,
+//! satellites used and visible, HDOP, speed and how long ago the last fix
+//! was. Feed it every response with `observe`, and the connection's counters
+//! with `set_stats`, then serve `render` from a `/metrics` endpoint:
+//!
+//! ```text
+//! # HELP gpsd_fix_mode Fix mode: 0 unknown, 1 no fix, 2 2D, 3 3D.
+//! # TYPE gpsd_fix_mode gauge
+//! gpsd_fix_mode{device="/dev/ttyUSB0"} 3
+//! ```
+//!
+//! Fix age is measured from when the fix arrived, not from its timestamp, so
+//! it's right even if the receiver's clock (or the host's) is off. Gauges
+//! for something a device hasn't reported are left out, rather than being
+//! zero.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Instant;
+use crate::stats::ConnectionStats;
+use crate::types::{FixMode, Response, SkyResponse, TpvResponse};
+
+/// The latest state of one device.
+#[derive(Debug, Clone, Default)]
+struct DeviceState {
+    mode: Option<FixMode>,
+    speed: Option<f64>,
+    used: Option<usize>,
+    visible: Option<usize>,
+    hdop: Option<f64>,
+    last_fix: Option<Instant>
+}
+
+/// Reads a gauge's value for a device, given the time.
+type Gauge = fn(&DeviceState, Instant) -> Option<f64>;
+
+/// Metrics for the devices on a connection.
+#[derive(Debug, Clone, Default)]
+pub struct GpsMetrics {
+    /// By device path; reports that don't say which device go under `""`.
+    devices: BTreeMap<String, DeviceState>,
+    stats: ConnectionStats
+}
+impl GpsMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Update the metrics from a response. Only TPV and SKY reports (and
+    /// the ones in a POLL) change anything.
+    pub fn observe(&mut self, resp: &Response) {
+        match *resp {
+            Response::Tpv(ref tpv) => self.observe_tpv(tpv),
+            Response::Sky(ref sky) => self.observe_sky(sky),
+            Response::Poll { ref tpv, ref sky, .. } => {
+                tpv.iter().for_each(|t| self.observe_tpv(t));
+                sky.iter().for_each(|s| self.observe_sky(s));
+            },
+            _ => {}
+        }
+    }
+    fn observe_tpv(&mut self, tpv: &TpvResponse) {
+        let dev = self.devices.entry(tpv.device().unwrap_or("").to_owned()).or_default();
+        dev.mode = tpv.mode();
+        dev.speed = tpv.speed();
+        if tpv.mode() >= Some(FixMode::Fix2d) {
+            dev.last_fix = Some(Instant::now());
+        }
+    }
+    fn observe_sky(&mut self, sky: &SkyResponse) {
+        let dev = self.devices.entry(sky.device.as_deref().unwrap_or("").to_owned()).or_default();
+        dev.used = Some(sky.used_satellites().count());
+        dev.visible = Some(sky.satellites.len());
+        dev.hdop = sky.hdop.or_else(|| sky.computed_dops().map(|d| d.hdop));
+    }
+    /// Take the connection's counters (from `GpsdConnection::stats`) for the
+    /// reconnect, parse error and message counts.
+    pub fn set_stats(&mut self, stats: &ConnectionStats) {
+        self.stats = stats.clone();
+    }
+    /// Every metric, in the text exposition format.
+    pub fn render(&self) -> String {
+        let now = Instant::now();
+        let mut out = String::new();
+        let gauges: [(&str, &str, Gauge); 6] = [
+            ("gpsd_fix_mode", "Fix mode: 0 unknown, 1 no fix, 2 2D, 3 3D.",
+             |d, _| d.mode.map(|m| f64::from(u8::from(m)))),
+            ("gpsd_satellites_used", "Satellites used in the fix.", |d, _| d.used.map(|n| n as f64)),
+            ("gpsd_satellites_visible", "Satellites in view.", |d, _| d.visible.map(|n| n as f64)),
+            ("gpsd_hdop", "Horizontal dilution of precision.", |d, _| d.hdop),
+            ("gpsd_speed_meters_per_second", "Speed over ground.", |d, _| d.speed),
+            ("gpsd_last_fix_age_seconds", "Time since the last 2D or 3D fix arrived.",
+             |d, now| d.last_fix.map(|t| now.duration_since(t).as_secs_f64()))
+        ];
+        for &(name, help, value) in &gauges {
+            let _ = write!(out, "# HELP {} {}\n# TYPE {} gauge\n", name, help, name);
+            for (device, state) in &self.devices {
+                if let Some(v) = value(state, now).filter(|v| v.is_finite()) {
+                    let _ = writeln!(out, "{}{{device=\"{}\"}} {}", name, escape(device), v);
+                }
+            }
+        }
+        let counters = [
+            ("gpsd_reconnects_total", "Times the connection to gpsd was re-established.", self.stats.reconnects),
+            ("gpsd_parse_errors_total", "Lines from gpsd that couldn't be parsed.", self.stats.parse_errors)
+        ];
+        for &(name, help, value) in &counters {
+            let _ = write!(out, "# HELP {} {}\n# TYPE {} counter\n{} {}\n", name, help, name, name, value);
+        }
+        out += "# HELP gpsd_messages_total Responses received from gpsd, by class.\n";
+        out += "# TYPE gpsd_messages_total counter\n";
+        for (class, n) in &self.stats.messages {
+            let _ = writeln!(out, "gpsd_messages_total{{class=\"{}\"}} {}", escape(class), n);
+        }
+        out
+    }
+}
+
+/// `s`, escaped for use as a label value.
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,143 @@
+//! An in-memory transport, for testing code that uses this crate.
+//!
+//! A `MockTransport` stands in for gpsd: queue up the lines you want gpsd to
+//! send, hand a clone of it to `GpsdConnection::from_transport`, and then check
+//! what commands your code sent.
+//!
+//! ```rust
+//! # use unbounded_gpsd::GpsdConnection;
+//! # use unbounded_gpsd::mock::MockTransport;
+//! # use unbounded_gpsd::types::Response;
+//! let mock = MockTransport::new();
+//! mock.push_line(r#"{"class":"VERSION","release":"3.17","rev":"3.17","proto_major":3,"proto_minor":12}"#);
+//! mock.close();
+//! let mut conn = GpsdConnection::from_transport(mock.clone()).unwrap();
+//! conn.version().unwrap();
+//! match conn.get_response().unwrap() {
+//!     Response::Version { release, .. } => assert_eq!(release, "3.17"),
+//!     _ => panic!()
+//! }
+//! assert_eq!(mock.commands(), vec!["?VERSION;"]);
+//! ```
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use crate::transport::{ShutdownHandle, Transport, WriteHandle};
+
+#[derive(Default)]
+struct State {
+    input: VecDeque<u8>,
+    written: Vec<u8>,
+    closed: bool,
+    read_timeout: Option<Duration>
+}
+
+/// A transport that reads from a queue and records what's written to it.
+///
+/// Clones share the same queue and record, so keep one for yourself.
+///
+/// Reading from an empty queue blocks until more is pushed (possibly from
+/// another thread) or the read timeout expires, just like a quiet socket.
+/// Once `close` has been called and the queue runs dry, reads return
+/// end-of-file, as if gpsd had hung up.
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<State>>,
+    cond: Arc<Condvar>
+}
+impl MockTransport {
+    /// An open transport with nothing queued.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // A panic elsewhere shouldn't stop a test from inspecting the state.
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Queue `line` to be read, adding the newline.
+    pub fn push_line(&self, line: &str) {
+        let mut st = self.lock();
+        st.input.extend(line.as_bytes());
+        st.input.push_back(b'\n');
+        self.cond.notify_all();
+    }
+    /// Queue raw bytes to be read, e.g. to test partial lines.
+    pub fn push_bytes(&self, bytes: &[u8]) {
+        self.lock().input.extend(bytes);
+        self.cond.notify_all();
+    }
+    /// Hang up once everything queued has been read.
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.cond.notify_all();
+    }
+    /// Everything written to the transport so far.
+    pub fn written(&self) -> Vec<u8> {
+        self.lock().written.clone()
+    }
+    /// The complete lines written to the transport so far, without their
+    /// newlines.
+    pub fn commands(&self) -> Vec<String> {
+        let st = self.lock();
+        let text = String::from_utf8_lossy(&st.written);
+        let mut lines = text.split('\n').map(String::from).collect::<Vec<_>>();
+        // The last piece is whatever follows the final newline.
+        lines.pop();
+        lines
+    }
+    /// Forget everything written so far.
+    pub fn clear_written(&self) {
+        self.lock().written.clear();
+    }
+}
+impl Read for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut st = self.lock();
+        let deadline = st.read_timeout.map(|t| Instant::now() + t);
+        while st.input.is_empty() && !st.closed {
+            match deadline {
+                Some(d) => {
+                    let now = Instant::now();
+                    if now >= d {
+                        return Err(io::Error::new(io::ErrorKind::WouldBlock, "mock read timed out"));
+                    }
+                    st = self.cond.wait_timeout(st, d - now).unwrap_or_else(|e| e.into_inner()).0;
+                },
+                None => st = self.cond.wait(st).unwrap_or_else(|e| e.into_inner())
+            }
+        }
+        let n = buf.len().min(st.input.len());
+        for (b, x) in buf.iter_mut().zip(st.input.drain(..n)) {
+            *b = x;
+        }
+        Ok(n)
+    }
+}
+impl Write for MockTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+impl Transport for MockTransport {
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.lock().read_timeout = dur;
+        Ok(())
+    }
+    fn shutdown(&self) -> io::Result<()> {
+        self.close();
+        Ok(())
+    }
+    fn shutdown_handle(&self) -> Option<ShutdownHandle> {
+        let m = self.clone();
+        Some(Box::new(move || m.close()))
+    }
+    fn write_handle(&self) -> Option<WriteHandle> {
+        Some(Box::new(self.clone()))
+    }
+}
@@ -0,0 +1,95 @@
+//! Merging responses from several gpsd servers.
+//!
+//! A `MultiGpsd` reads from any number of connections, each on its own
+//! thread, and merges what they receive into one stream, labelling each
+//! response with the endpoint it came from.
+
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+use crate::errors::GpsdError;
+use crate::types::Response;
+use crate::{GpsdConnection, GpsdResult};
+
+/// A response (or error) from one of the sources of a `MultiGpsd`.
+#[derive(Debug)]
+pub struct SourcedResponse {
+    /// Name of the source, as given to `MultiGpsd::add` (for `connect`, the
+    /// endpoint address).
+    pub source: String,
+    /// What the source received. Errors other than `GpsdError::Json` mean the
+    /// source has stopped, and are the last thing it sends.
+    pub response: GpsdResult<Response>
+}
+
+/// Several gpsd connections, merged into one stream of responses.
+///
+/// Each source buffers up to `capacity` responses; a source whose buffer is
+/// full stops reading until the consumer catches up.
+pub struct MultiGpsd {
+    tx: SyncSender<SourcedResponse>,
+    rx: Receiver<SourcedResponse>,
+    sources: Vec<String>
+}
+impl MultiGpsd {
+    /// An empty set of sources.
+    pub fn new(capacity: usize) -> Self {
+        let (tx, rx) = mpsc::sync_channel(capacity);
+        Self { tx, rx, sources: Vec::new() }
+    }
+    /// Connect to each of `endpoints` (anything `GpsdConnection::new` accepts,
+    /// as a string), and enable watcher mode on each.
+    ///
+    /// Fails if any endpoint can't be reached. To ride out some endpoints
+    /// being down, make the connections yourself (e.g. with a reconnect
+    /// policy) and `add` them.
+    pub fn connect<S: AsRef<str>>(endpoints: &[S], capacity: usize) -> GpsdResult<Self> {
+        let mut ret = Self::new(capacity);
+        for ep in endpoints {
+            let ep = ep.as_ref();
+            let mut conn = GpsdConnection::new(ep)?;
+            conn.watch(true)?;
+            ret.add(ep, conn);
+        }
+        Ok(ret)
+    }
+    /// Start reading from `conn`, labelling its responses with `source`.
+    pub fn add(&mut self, source: &str, mut conn: GpsdConnection) {
+        let tx = self.tx.clone();
+        let name = source.to_owned();
+        self.sources.push(name.clone());
+        thread::spawn(move || {
+            loop {
+                let response = conn.get_response();
+                let fatal = match response {
+                    Err(ref e) => !matches!(*e, GpsdError::Json { .. }),
+                    Ok(_) => false
+                };
+                if fatal {
+                    if let Err(ref e) = response {
+                        warn!("source {} stopping: {}", name, e);
+                    }
+                }
+                if tx.send(SourcedResponse { source: name.clone(), response }).is_err() || fatal {
+                    break;
+                }
+            }
+        });
+    }
+    /// Names of the sources added so far, whether or not they're still running.
+    pub fn sources(&self) -> &[String] {
+        &self.sources
+    }
+    /// Wait for the next response from any source.
+    ///
+    /// Blocks forever once every source has stopped; use `into_receiver` if
+    /// you need to notice that.
+    pub fn recv(&self) -> SourcedResponse {
+        // We hold a sender ourselves, so the channel can't disconnect.
+        self.rx.recv().expect("MultiGpsd channel closed")
+    }
+    /// Give up the ability to add sources, and get the merged stream. The
+    /// receiver disconnects once every source has stopped.
+    pub fn into_receiver(self) -> Receiver<SourcedResponse> {
+        self.rx
+    }
+}
@@ -0,0 +1,475 @@
+//! Converting between reports and NMEA 0183.
+//!
+//! An `NmeaEncoder` renders TPV reports as GGA and RMC sentences, and SKY
+//! reports as GSA and GSV ones, each with its checksum and CRLF. Some fields
+//! of a sentence come from the other kind of report (GGA has the satellite
+//! count and HDOP, GSA the fix mode), so the encoder remembers the last one
+//! of each it was given; feed it everything with `encode`.
+//!
+//! Sentences use the `GP` talker unless told otherwise, since that's what
+//! older chartplotters expect even from multi-constellation receivers.
+//!
+//! Going the other way, an `NmeaDecoder` reads the pseudo-NMEA gpsd sends
+//! with `nmea: true` set in the watch (or that a receiver sends directly),
+//! and turns RMC, GGA, GSA and GSV sentences into the usual TPV and SKY
+//! responses. Those lines arrive from a connection as `Response::Raw`.
+
+use std::collections::BTreeMap;
+use std::time::UNIX_EPOCH;
+use serde_json::{Map, Value};
+use crate::errors::{GpsdError, GpsdResult};
+use crate::timestamp::{to_system_time, Timestamp};
+use crate::types::{FixMode, Response, SkyResponse, TpvResponse};
+use crate::units::Speed;
+
+/// Satellites per GSV sentence.
+const GSV_SATELLITES: usize = 4;
+/// Satellite slots in a GSA sentence.
+const GSA_SATELLITES: usize = 12;
+
+/// The NMEA checksum of a sentence's `body`: everything between the `$` and
+/// the `*`.
+pub fn checksum(body: &str) -> u8 {
+    body.bytes().fold(0, |acc, b| acc ^ b)
+}
+/// `body` as a complete sentence.
+fn sentence(body: &str) -> String {
+    format!("${}*{:02X}\r\n", body, checksum(body))
+}
+
+/// `t` as NMEA's `hhmmss.ss` time and `ddmmyy` date.
+fn time_and_date(t: Timestamp) -> (String, String) {
+    let since = to_system_time(t).duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (days, secs) = (since.as_secs() / 86400, since.as_secs() % 86400);
+    let time = format!("{:02}{:02}{:02}.{:02}", secs / 3600, secs / 60 % 60, secs % 60, since.subsec_millis() / 10);
+    // Civil date from days since the epoch; see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+    let z = days + 719_468;
+    let (era, doe) = (z / 146_097, z % 146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (time, format!("{:02}{:02}{:02}", day, month, year % 100))
+}
+/// `degrees` as NMEA's `(d)ddmm.mmmmm` and hemisphere letter.
+fn coordinate(degrees: f64, width: usize, hemispheres: (char, char)) -> (String, char) {
+    let hemisphere = if degrees < 0.0 { hemispheres.1 } else { hemispheres.0 };
+    // Round in units of 1e-5 minutes, so 59.999996' carries into a degree.
+    let units = (degrees.abs() * 60.0 * 1e5).round() as u64;
+    let (deg, min) = (units / 6_000_000, (units % 6_000_000) as f64 / 1e5);
+    (format!("{:0w$}{:08.5}", deg, min, w = width), hemisphere)
+}
+/// `v` to `decimals` places, or nothing if it's missing.
+fn optional(v: Option<f64>, decimals: usize) -> String {
+    v.map(|v| format!("{:.*}", decimals, v)).unwrap_or_default()
+}
+
+/// Renders reports as NMEA 0183 sentences.
+#[derive(Debug, Clone)]
+pub struct NmeaEncoder {
+    talker: String,
+    last_sky: Option<SkyResponse>,
+    last_mode: Option<FixMode>
+}
+impl Default for NmeaEncoder {
+    fn default() -> Self {
+        Self { talker: "GP".into(), last_sky: None, last_mode: None }
+    }
+}
+impl NmeaEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Use `talker` (e.g. `"GN"`) as the talker ID.
+    pub fn with_talker(mut self, talker: &str) -> Self {
+        self.talker = talker.to_owned();
+        self
+    }
+    /// The sentences for `resp`: GGA and RMC for a TPV, GSA and GSV for a
+    /// SKY, and those for each report in a POLL. Other responses give none,
+    /// as do TPVs without a position.
+    pub fn encode(&mut self, resp: &Response) -> Vec<String> {
+        match *resp {
+            Response::Tpv(ref tpv) => self.encode_tpv(tpv),
+            Response::Sky(ref sky) => self.encode_sky(sky),
+            Response::Poll { ref tpv, ref sky, .. } => {
+                let mut out: Vec<String> = sky.iter().flat_map(|s| self.encode_sky(s)).collect();
+                out.extend(tpv.iter().flat_map(|t| self.encode_tpv(t)));
+                out
+            },
+            _ => vec![]
+        }
+    }
+    fn encode_tpv(&mut self, tpv: &TpvResponse) -> Vec<String> {
+        if tpv.mode().is_some() {
+            self.last_mode = tpv.mode();
+        }
+        self.gga(tpv).into_iter().chain(self.rmc(tpv)).collect()
+    }
+    fn encode_sky(&mut self, sky: &SkyResponse) -> Vec<String> {
+        let mut out = vec![self.gsa(sky)];
+        out.extend(self.gsv(sky));
+        self.last_sky = Some(sky.clone());
+        out
+    }
+    /// A GGA sentence (fix data) for the report, or `None` if it hasn't got
+    /// a valid position. The satellite count and HDOP come from the last
+    /// SKY given to `encode`, if any.
+    pub fn gga(&self, tpv: &TpvResponse) -> Option<String> {
+        let pos = tpv.position()?;
+        let (lat, ns) = coordinate(pos.lat.degrees(), 2, ('N', 'S'));
+        let (lon, ew) = coordinate(pos.lon.degrees(), 3, ('E', 'W'));
+        let quality = match tpv.mode() {
+            Some(FixMode::Fix2d) | Some(FixMode::Fix3d) => 1,
+            _ => 0
+        };
+        let (used, hdop) = match self.last_sky {
+            Some(ref sky) => (format!("{:02}", sky.used_satellites().count()), optional(sky.hdop, 1)),
+            None => (String::new(), String::new())
+        };
+        let alt = optional(pos.alt_msl, 1);
+        let sep = optional(tpv.extra().get("geoidSep").and_then(|v| v.as_f64()), 1);
+        let body = format!("{}GGA,{},{},{},{},{},{},{},{},{},{},{},{},,",
+                           self.talker, tpv.time().map(|t| time_and_date(t).0).unwrap_or_default(),
+                           lat, ns, lon, ew, quality, used, hdop,
+                           alt, if pos.alt_msl.is_some() { "M" } else { "" },
+                           sep, if sep.is_empty() { "" } else { "M" });
+        Some(sentence(&body))
+    }
+    /// An RMC sentence (recommended minimum data) for the report, or `None`
+    /// if it hasn't got both a valid position and a time.
+    pub fn rmc(&self, tpv: &TpvResponse) -> Option<String> {
+        let (pos, t) = (tpv.position()?, tpv.time()?);
+        let (time, date) = time_and_date(t);
+        let (lat, ns) = coordinate(pos.lat.degrees(), 2, ('N', 'S'));
+        let (lon, ew) = coordinate(pos.lon.degrees(), 3, ('E', 'W'));
+        let valid = matches!(tpv.mode(), Some(FixMode::Fix2d) | Some(FixMode::Fix3d));
+        let body = format!("{}RMC,{},{},{},{},{},{},{},{},{},,,{}",
+                           self.talker, time, if valid { 'A' } else { 'V' }, lat, ns, lon, ew,
+                           optional(tpv.ground_speed().map(Speed::knots), 2),
+                           optional(tpv.track(), 1), date, if valid { 'A' } else { 'N' });
+        Some(sentence(&body))
+    }
+    /// A GSA sentence (DOPs and satellites used) for the report. The fix mode
+    /// comes from the last TPV given to `encode`.
+    pub fn gsa(&self, sky: &SkyResponse) -> String {
+        let mode = match self.last_mode {
+            Some(FixMode::Fix2d) => 2,
+            Some(FixMode::Fix3d) => 3,
+            _ => 1
+        };
+        let mut prns: Vec<String> = sky.used_satellites()
+            .take(GSA_SATELLITES)
+            .map(|s| format!("{:02}", s.prn))
+            .collect();
+        prns.resize(GSA_SATELLITES, String::new());
+        let body = format!("{}GSA,A,{},{},{},{},{}", self.talker, mode, prns.join(","),
+                           optional(sky.pdop, 1), optional(sky.hdop, 1), optional(sky.vdop, 1));
+        sentence(&body)
+    }
+    /// The GSV sentences (satellites in view) for the report, four
+    /// satellites to a sentence.
+    pub fn gsv(&self, sky: &SkyResponse) -> Vec<String> {
+        let count = sky.satellites.len();
+        if count == 0 {
+            return vec![sentence(&format!("{}GSV,1,1,00", self.talker))];
+        }
+        let total = count.div_ceil(GSV_SATELLITES);
+        sky.satellites.chunks(GSV_SATELLITES).enumerate().map(|(i, sats)| {
+            let mut body = format!("{}GSV,{},{},{:02}", self.talker, total, i + 1, count);
+            for s in sats {
+                let snr = if s.signal_strength > 0.0 {
+                    format!("{:02}", s.signal_strength.round().min(99.0) as u32)
+                } else {
+                    String::new()
+                };
+                body += &format!(",{:02},{:02},{:03},{}", s.prn, s.elevation, s.azimuth, snr);
+            }
+            sentence(&body)
+        }).collect()
+    }
+}
+
+/// The timestamp error gpsd assumes for receivers that don't say.
+const DEFAULT_EPT: f64 = 0.005;
+
+/// A sentence's talker ID and type, and the fields after them.
+fn split(line: &str) -> GpsdResult<Option<(&str, &str, Vec<&str>)>> {
+    let line = line.trim_end();
+    // NMEA 0183 is ASCII, which the field slicing below relies on.
+    if !line.is_ascii() {
+        bail!(GpsdError::Protocol(format!("not an NMEA sentence: '{}'", line)));
+    }
+    let body = match line.strip_prefix('$') {
+        Some(body) => body,
+        None => bail!(GpsdError::Protocol(format!("not an NMEA sentence: '{}'", line)))
+    };
+    let body = match body.rfind('*') {
+        Some(i) => {
+            let sum = u8::from_str_radix(&body[i + 1..], 16).ok();
+            if sum != Some(checksum(&body[..i])) {
+                bail!(GpsdError::Protocol(format!("bad NMEA checksum: '{}'", line)));
+            }
+            &body[..i]
+        },
+        None => body
+    };
+    let mut fields: Vec<&str> = body.split(',').collect();
+    let address = fields.remove(0);
+    // Proprietary sentences ($P...) aren't ours to read.
+    if address.starts_with('P') || address.len() != 5 {
+        return Ok(None);
+    }
+    Ok(Some((&address[..2], &address[2..], fields)))
+}
+/// The NMEA 4.10 system ID (1 = GPS, 2 = GLONASS, 3 = Galileo, 4 = BeiDou,
+/// 5 = QZSS, 6 = NavIC) for a talker, or 0 for a combined (`GN`) or unknown
+/// one.
+fn system_of(talker: &str) -> u8 {
+    match talker {
+        "GP" => 1,
+        "GL" => 2,
+        "GA" => 3,
+        "GB" | "BD" => 4,
+        "GQ" => 5,
+        "GI" => 6,
+        _ => 0
+    }
+}
+/// gpsd's `gnssid` for an NMEA system ID. GPS talkers also report SBAS
+/// satellites, so they're left for the PRN to tell apart.
+fn gnss_id(system: u8) -> Option<u8> {
+    match system {
+        2 => Some(6),
+        3 => Some(2),
+        4 => Some(3),
+        5 => Some(5),
+        6 => Some(7),
+        _ => None
+    }
+}
+/// Field `i`, or "" if the sentence is too short to have it.
+fn field<'a>(fields: &[&'a str], i: usize) -> &'a str {
+    fields.get(i).copied().unwrap_or("")
+}
+/// Field `i` as a number, if it's there.
+fn number(fields: &[&str], i: usize) -> GpsdResult<Option<f64>> {
+    match field(fields, i) {
+        "" => Ok(None),
+        f => f.parse().map(Some)
+            .map_err(|_| GpsdError::Protocol(format!("bad number in NMEA field {}: '{}'", i, f)))
+    }
+}
+/// Fields `i` and `i + 1` (`ddmm.mmm` and hemisphere) as signed degrees.
+fn degrees(fields: &[&str], i: usize) -> GpsdResult<Option<f64>> {
+    let v = match number(fields, i)? {
+        Some(v) => v,
+        None => return Ok(None)
+    };
+    let deg = (v / 100.0).trunc();
+    let abs = deg + (v - deg * 100.0) / 60.0;
+    Ok(Some(match field(fields, i + 1) {
+        "S" | "W" => -abs,
+        _ => abs
+    }))
+}
+/// The fix data from a GGA sentence.
+#[derive(Debug, Clone)]
+struct Gga {
+    time: String,
+    alt: Option<f64>,
+    geoid_sep: Option<f64>
+}
+/// The fix mode and DOPs from a GSA sentence.
+#[derive(Debug, Clone, Default)]
+struct Gsa {
+    mode: Option<u8>,
+    pdop: Option<f64>,
+    hdop: Option<f64>,
+    vdop: Option<f64>
+}
+
+/// Reads NMEA sentences into TPV and SKY responses.
+///
+/// A TPV comes from each RMC sentence, with the altitude from the GGA of the
+/// same fix if there was one, and the fix mode from the last GSA.
+///
+/// A SKY comes from the GSVs of each fix. Multi-constellation receivers send
+/// a group of GSVs per system (`GPGSV`, `GLGSV`, `GAGSV` and so on), which
+/// are merged into one SKY once the last group of the fix is in. Which group
+/// is last isn't known until the groups come round again, so until then a
+/// SKY comes from every group, each with the satellites of the fix so far.
+/// Satellites are marked used if the latest GSA for their system lists them,
+/// whether the receiver sends a GSA per talker or `GNGSA`s with system IDs.
+#[derive(Debug, Clone, Default)]
+pub struct NmeaDecoder {
+    gga: Option<Gga>,
+    gsa: Option<Gsa>,
+    /// The satellites used, by NMEA system ID (0 if the GSA didn't say).
+    used: BTreeMap<u8, Vec<u16>>,
+    satellites: Vec<Value>,
+    /// The GSV groups seen in this fix, by talker and signal ID, in order.
+    gsv_groups: Vec<String>,
+    /// The group that ended the last fix, once the order is known.
+    last_gsv_group: Option<String>,
+    /// Whether a SKY has been sent with every group of this fix.
+    sky_sent: bool
+}
+impl NmeaDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Read one sentence, returning the response it completes, if any.
+    /// Sentences of other types are ignored.
+    ///
+    /// Lines that aren't NMEA, or have a bad checksum, fail with
+    /// `GpsdError::Protocol`.
+    pub fn decode(&mut self, line: &str) -> GpsdResult<Option<Response>> {
+        let (talker, kind, f) = match split(line)? {
+            Some(s) => s,
+            None => return Ok(None)
+        };
+        match kind {
+            "GGA" => {
+                self.gga = Some(Gga {
+                    time: field(&f, 0).to_owned(),
+                    alt: number(&f, 8)?,
+                    geoid_sep: number(&f, 10)?
+                });
+                Ok(None)
+            },
+            "GSA" => {
+                let mut used = vec![];
+                for i in 2..14 {
+                    if let Some(prn) = number(&f, i)? {
+                        used.push(prn as u16);
+                    }
+                }
+                let system = number(&f, 17)?.map_or_else(|| system_of(talker), |s| s as u8);
+                self.used.insert(system, used);
+                self.gsa = Some(Gsa {
+                    mode: number(&f, 1)?.map(|m| m as u8),
+                    pdop: number(&f, 14)?,
+                    hdop: number(&f, 15)?,
+                    vdop: number(&f, 16)?
+                });
+                Ok(None)
+            },
+            "GSV" => self.gsv(talker, &f),
+            "RMC" => self.rmc(&f),
+            _ => Ok(None)
+        }
+    }
+    /// `decode`, for sources reading straight from a receiver: sentences
+    /// that can't be read are logged and skipped, as gpsd does.
+    pub(crate) fn decode_or_skip(&mut self, line: &str) -> Option<Response> {
+        self.decode(line).unwrap_or_else(|e| {
+            warn!("skipping NMEA sentence: {}", e);
+            None
+        })
+    }
+    fn gsv(&mut self, talker: &str, f: &[&str]) -> GpsdResult<Option<Response>> {
+        let (total, num) = (number(f, 0)?.unwrap_or(1.0), number(f, 1)?.unwrap_or(1.0));
+        // NMEA 4.10 adds a signal ID after the satellites, so one system can
+        // send a group per signal.
+        let has_signal = f.len() > 3 && (f.len() - 3) % 4 == 1;
+        let group = if has_signal { format!("{}{}", talker, field(f, f.len() - 1)) } else { talker.to_owned() };
+        let mut unsent = None;
+        if num <= 1.0 {
+            if self.gsv_groups.contains(&group) {
+                // The groups have come round again, so a new fix has begun.
+                self.last_gsv_group = self.gsv_groups.pop();
+                self.gsv_groups.clear();
+                if !self.sky_sent && !self.satellites.is_empty() {
+                    unsent = Some(self.sky()?);
+                }
+                self.satellites.clear();
+                self.sky_sent = false;
+            }
+            self.gsv_groups.push(group.clone());
+        }
+        let (system, by_system) = (system_of(talker), &self.used);
+        let used = |prn: &u16| {
+            let listed = |s: &u8| by_system.get(s).is_some_and(|u| u.contains(prn));
+            if system == 0 { by_system.keys().any(listed) } else { listed(&system) || listed(&0) }
+        };
+        let satellites = if has_signal { f.len() - 1 } else { f.len() };
+        for i in (3..satellites).step_by(4) {
+            let prn = match number(f, i)? {
+                Some(prn) => prn as u16,
+                None => continue
+            };
+            let mut sat = json!({
+                "PRN": prn,
+                "el": number(f, i + 1)?.unwrap_or(0.0) as u32,
+                "az": number(f, i + 2)?.unwrap_or(0.0) as u32,
+                "ss": number(f, i + 3)?.unwrap_or(0.0),
+                "used": used(&prn)
+            });
+            if let Some(id) = gnss_id(system) {
+                sat["gnssid"] = id.into();
+            }
+            self.satellites.push(sat);
+        }
+        if unsent.is_some() || num < total {
+            return Ok(unsent);
+        }
+        if self.last_gsv_group.as_ref().is_some_and(|last| *last != group) {
+            return Ok(None);
+        }
+        self.sky_sent = true;
+        Ok(Some(self.sky()?))
+    }
+    /// A SKY with the satellites of this fix so far.
+    fn sky(&self) -> GpsdResult<Response> {
+        let gsa = self.gsa.clone().unwrap_or_default();
+        let sky = json!({
+            "satellites": self.satellites,
+            "pdop": gsa.pdop,
+            "hdop": gsa.hdop,
+            "vdop": gsa.vdop
+        });
+        Ok(Response::Sky(serde_json::from_value(sky)?))
+    }
+    fn rmc(&mut self, f: &[&str]) -> GpsdResult<Option<Response>> {
+        let (time, date) = (field(f, 0), field(f, 8));
+        if time.len() < 6 || date.len() != 6 {
+            return Ok(None);
+        }
+        let year: u32 = date[4..].parse()
+            .map_err(|_| GpsdError::Protocol(format!("bad NMEA date: '{}'", date)))?;
+        let year = if year < 80 { 2000 + year } else { 1900 + year };
+        let mut tpv = Map::new();
+        tpv.insert("time".into(), format!("{}-{}-{}T{}:{}:{}Z", year, &date[2..4], &date[..2],
+                                          &time[..2], &time[2..4], &time[4..]).into());
+        let valid = field(f, 1) == "A";
+        let gga = self.gga.take().filter(|g| g.time == time);
+        let alt = gga.as_ref().and_then(|g| g.alt);
+        let mode = match self.gsa.as_ref().and_then(|g| g.mode) {
+            _ if !valid => 1,
+            Some(m @ 2..=3) => m,
+            _ => if alt.is_some() { 3 } else { 2 }
+        };
+        tpv.insert("mode".into(), mode.into());
+        if valid {
+            tpv.insert("ept".into(), DEFAULT_EPT.into());
+            let values = [
+                ("lat", degrees(f, 2)?),
+                ("lon", degrees(f, 4)?),
+                ("speed", number(f, 6)?.map(|kn| Speed::from_knots(kn).mps())),
+                ("track", number(f, 7)?),
+                ("alt", alt),
+                ("geoidSep", gga.and_then(|g| g.geoid_sep))
+            ];
+            for (name, value) in values {
+                if let Some(value) = value {
+                    tpv.insert(name.into(), value.into());
+                }
+            }
+        }
+        Ok(Some(Response::Tpv(serde_json::from_value::<TpvResponse>(Value::Object(tpv))?)))
+    }
+}
@@ -0,0 +1,165 @@
+//! Using gpsd from your own event loop, without threads or async runtimes.
+//!
+//! `ResponseParser` does no I/O at all: `feed` it bytes however you get them,
+//! and take parsed responses out with `poll_response`.
+//!
+//! `NonBlockingConnection` puts a TCP connection to gpsd into non-blocking
+//! mode and exposes its file descriptor, so you can register it with epoll,
+//! `poll(2)`, mio (via `mio::unix::SourceFd`) or similar. When the descriptor
+//! is readable, call `on_readable`, then `poll_response` until it returns
+//! `None`. When it's writable and `wants_write` is true, call `on_writable`.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, RawFd};
+use crate::errors::GpsdError;
+use crate::types::{RawLevel, Response};
+use crate::{is_timeout, parse_line, watch_command, GpsdResult};
+
+/// An incremental parser for gpsd output.
+#[derive(Debug, Default)]
+pub struct ResponseParser {
+    buf: Vec<u8>,
+    raw_data: bool
+}
+impl ResponseParser {
+    /// A parser with nothing buffered.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Whether to return lines that aren't JSON as `Response::Raw`, as when
+    /// raw mode is enabled with `?WATCH`.
+    pub fn set_raw(&mut self, raw: bool) {
+        self.raw_data = raw;
+    }
+    /// Add bytes received from gpsd. They needn't be whole lines.
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+    }
+    /// Number of bytes buffered that aren't yet part of a complete line.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+    /// Take the next complete response, if there is one.
+    ///
+    /// Errors parsing one line don't affect later ones, so keep calling this
+    /// until it returns `None`.
+    pub fn poll_response(&mut self) -> Option<GpsdResult<Response>> {
+        while let Some(pos) = self.buf.iter().position(|&b| b == b'\n') {
+            let line = self.buf.drain(..pos + 1).collect::<Vec<u8>>();
+            let line = String::from_utf8_lossy(&line).into_owned();
+            if line.trim().is_empty() {
+                debug!("empty line received from GPSD");
+                continue;
+            }
+            debug!("raw GPSD data: {}", line);
+            return Some(parse_line(line, self.raw_data));
+        }
+        None
+    }
+}
+
+/// A non-blocking connection to gpsd.
+///
+/// None of its methods block. Commands are queued, and sent as far as the
+/// socket allows; the rest goes out from `on_writable`.
+pub struct NonBlockingConnection {
+    stream: TcpStream,
+    parser: ResponseParser,
+    out: Vec<u8>
+}
+impl NonBlockingConnection {
+    /// Connect to a given address. The connection itself is made in blocking
+    /// mode.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> GpsdResult<Self> {
+        Self::from_stream(TcpStream::connect(addr)?)
+    }
+    /// Use an already-connected socket, switching it to non-blocking mode.
+    pub fn from_stream(stream: TcpStream) -> GpsdResult<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self { stream, parser: ResponseParser::new(), out: Vec::new() })
+    }
+    /// The underlying socket.
+    pub fn get_ref(&self) -> &TcpStream {
+        &self.stream
+    }
+    /// Read everything available from the socket into the parser.
+    ///
+    /// Returns the number of bytes read. If gpsd has closed the connection,
+    /// fails with `GpsdError::Disconnected`; responses received before that can
+    /// still be taken with `poll_response`.
+    pub fn on_readable(&mut self) -> GpsdResult<usize> {
+        let mut total = 0;
+        let mut chunk = [0; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => bail!(GpsdError::closed()),
+                Ok(n) => {
+                    self.parser.feed(&chunk[..n]);
+                    total += n;
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(ref e) if is_timeout(e) => return Ok(total),
+                Err(e) => return Err(e.into())
+            }
+        }
+    }
+    /// Add bytes received some other way, as for `ResponseParser::feed`.
+    pub fn feed(&mut self, data: &[u8]) {
+        self.parser.feed(data);
+    }
+    /// Take the next complete response, if there is one.
+    pub fn poll_response(&mut self) -> Option<GpsdResult<Response>> {
+        self.parser.poll_response()
+    }
+    /// Whether there are queued commands waiting for the socket to become
+    /// writable.
+    pub fn wants_write(&self) -> bool {
+        !self.out.is_empty()
+    }
+    /// Send as much of the queued commands as the socket will take.
+    pub fn on_writable(&mut self) -> GpsdResult<()> {
+        while !self.out.is_empty() {
+            match self.stream.write(&self.out) {
+                Ok(0) => bail!(GpsdError::closed()),
+                Ok(n) => { self.out.drain(..n); },
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(ref e) if is_timeout(e) => break,
+                Err(e) => return Err(e.into())
+            }
+        }
+        Ok(())
+    }
+    fn send(&mut self, msg: &str) -> GpsdResult<()> {
+        self.out.extend_from_slice(msg.as_bytes());
+        self.on_writable()
+    }
+    /// Enable or disable watcher mode.
+    pub fn watch(&mut self, watch: bool) -> GpsdResult<()> {
+        self.watch_raw(watch, true, RawLevel::Off)
+    }
+    /// Enable RAW mode. See `GpsdConnection::watch_raw` for the meaning of `raw`.
+    pub fn watch_raw(&mut self, watch: bool, json: bool, raw: RawLevel) -> GpsdResult<()> {
+        self.parser.set_raw(raw.is_on());
+        self.send(&watch_command(watch, json, raw))
+    }
+    /// Request data from the last-seen fixes on all active GPS devices.
+    pub fn poll(&mut self) -> GpsdResult<()> {
+        self.send("?POLL;\n")
+    }
+    /// Ask for the server's version (triggers a Response::Version).
+    pub fn version(&mut self) -> GpsdResult<()> {
+        self.send("?VERSION;\n")
+    }
+    /// Ask for the server's devices (triggers a Response::Devices)
+    pub fn devices(&mut self) -> GpsdResult<()> {
+        self.send("?DEVICES;\n")
+    }
+}
+#[cfg(unix)]
+impl AsRawFd for NonBlockingConnection {
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
+}
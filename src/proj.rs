@@ -0,0 +1,78 @@
+//! Transforming fixes into other coordinate reference systems.
+//!
+//! Only available with the `proj` feature enabled, using
+//! [proj4rs](https://crates.io/crates/proj4rs), a pure Rust port of PROJ. A
+//! `Projector` takes WGS84 positions to a target CRS given by EPSG code (say,
+//! 27700 for the British National Grid) or by a PROJ string:
+//!
+//! ```rust
+//! use unbounded_gpsd::proj::Projector;
+//! use unbounded_gpsd::types::Position;
+//!
+//! let bng = Projector::epsg(27700).unwrap();
+//! let pos = Position::new(51.5, -0.12, None, None).unwrap();
+//! let p = bng.position(&pos).unwrap();
+//! assert!((p.x - 530_600.0).abs() < 500.0 && (p.y - 180_100.0).abs() < 500.0);
+//! ```
+//!
+//! Output coordinates are in the target's units, usually meters; geographic
+//! targets come out in degrees, as longitude and latitude. Heights are taken
+//! relative to the ellipsoid, from `altHAE` or from `altMSL` plus `geoidSep`
+//! (see `Position::height_above_ellipsoid`), and sea level failing both.
+
+use proj4rs::adaptors::transform_vertex_3d;
+use proj4rs::errors::Error;
+use proj4rs::Proj;
+use crate::types::{Position, TpvResponse};
+
+/// The CRS gpsd reports in.
+const WGS84: &str = "+proj=longlat +ellps=WGS84 +datum=WGS84 +no_defs";
+
+/// A point in a projected CRS.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Projected {
+    /// Easting, or longitude in degrees for geographic targets.
+    pub x: f64,
+    /// Northing, or latitude in degrees.
+    pub y: f64,
+    /// Height, or zero if the position didn't have one.
+    pub z: f64
+}
+
+/// Transforms WGS84 positions into a target CRS.
+pub struct Projector {
+    wgs84: Proj,
+    target: Proj
+}
+impl Projector {
+    /// Transform into the CRS with EPSG code `code`.
+    pub fn epsg(code: u16) -> Result<Self, Error> {
+        Self::to(Proj::from_epsg_code(code)?)
+    }
+    /// Transform into the CRS described by `def`: a PROJ string (starting
+    /// with `+`), or `EPSG:` and a code.
+    pub fn new(def: &str) -> Result<Self, Error> {
+        Self::to(Proj::from_user_string(def)?)
+    }
+    fn to(target: Proj) -> Result<Self, Error> {
+        Ok(Projector { wgs84: Proj::from_proj_string(WGS84)?, target })
+    }
+    /// Transform `lat` and `lon` degrees, at `height` meters.
+    pub fn project(&self, lat: f64, lon: f64, height: f64) -> Result<Projected, Error> {
+        let (mut x, mut y, z) = transform_vertex_3d(&self.wgs84, &self.target, (lon.to_radians(), lat.to_radians(), height))?;
+        if self.target.is_latlong() {
+            x = x.to_degrees();
+            y = y.to_degrees();
+        }
+        Ok(Projected { x, y, z })
+    }
+    /// Transform `pos`.
+    pub fn position(&self, pos: &Position) -> Result<Projected, Error> {
+        let height = pos.height_above_ellipsoid().or(pos.alt_msl).unwrap_or(0.0);
+        self.project(pos.lat.degrees(), pos.lon.degrees(), height)
+    }
+    /// Transform the report's position, if it has one.
+    pub fn tpv(&self, tpv: &TpvResponse) -> Option<Result<Projected, Error>> {
+        tpv.position().map(|p| self.position(&p))
+    }
+}
@@ -0,0 +1,232 @@
+//! Protocol Buffers messages for reports, using
+//! [prost](https://crates.io/crates/prost).
+//!
+//! Only available with the `prost` feature enabled. The schema is
+//! `proto/gpsd.proto` in the crate, for generating types in other languages;
+//! the structs here are what `prost-build` makes of it, written out so that
+//! building this crate doesn't need `protoc`. Keep the two in step: the
+//! tests check the structs against encodings worked out from the schema.
+//!
+//! Reports convert into messages with `From`, ready to encode:
+//!
+//! ```rust
+//! use prost::Message;
+//! use unbounded_gpsd::proto::{self, Report};
+//! use unbounded_gpsd::types::Response;
+//!
+//! let resp = Response::from_json_line(r#"{"class":"TPV","mode":1}"#).unwrap();
+//! let report: Option<Report> = (&resp).into();
+//! let bytes = report.unwrap().encode_to_vec();
+//! match Report::decode(&bytes[..]).unwrap().report {
+//!     Some(proto::report::Report::Tpv(tpv)) => assert_eq!(tpv.mode, 1),
+//!     _ => unreachable!()
+//! }
+//! ```
+//!
+//! Only the fields in the schema are carried; gpsd's other fields (the ones
+//! in a report's `extra`) are dropped.
+
+use crate::timestamp::to_epoch_nanos;
+use crate::types::{DeviceObject, Response, SatelliteObject, SkyResponse, TpvResponse};
+
+/// A time-position-velocity report (gpsd's TPV).
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Tpv {
+    #[prost(string, optional, tag = "1")]
+    pub device: Option<String>,
+    /// Time of the fix, in nanoseconds since the Unix epoch.
+    #[prost(int64, optional, tag = "2")]
+    pub time_unix_nanos: Option<i64>,
+    /// Fix type: 0 unknown, 1 no fix, 2 2D, 3 3D.
+    #[prost(uint32, tag = "3")]
+    pub mode: u32,
+    #[prost(double, optional, tag = "4")]
+    pub lat: Option<f64>,
+    #[prost(double, optional, tag = "5")]
+    pub lon: Option<f64>,
+    #[prost(double, optional, tag = "6")]
+    pub alt: Option<f64>,
+    #[prost(double, optional, tag = "7")]
+    pub track: Option<f64>,
+    #[prost(double, optional, tag = "8")]
+    pub speed: Option<f64>,
+    #[prost(double, optional, tag = "9")]
+    pub climb: Option<f64>,
+    #[prost(double, optional, tag = "10")]
+    pub ept: Option<f64>,
+    #[prost(double, optional, tag = "11")]
+    pub epx: Option<f64>,
+    #[prost(double, optional, tag = "12")]
+    pub epy: Option<f64>,
+    #[prost(double, optional, tag = "13")]
+    pub epv: Option<f64>,
+    #[prost(double, optional, tag = "14")]
+    pub epd: Option<f64>,
+    #[prost(double, optional, tag = "15")]
+    pub eps: Option<f64>,
+    #[prost(double, optional, tag = "16")]
+    pub epc: Option<f64>
+}
+/// One satellite in a sky view.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Satellite {
+    #[prost(uint32, tag = "1")]
+    pub prn: u32,
+    #[prost(uint32, optional, tag = "2")]
+    pub gnss_id: Option<u32>,
+    #[prost(uint32, tag = "3")]
+    pub azimuth: u32,
+    #[prost(uint32, tag = "4")]
+    pub elevation: u32,
+    #[prost(double, tag = "5")]
+    pub signal_strength: f64,
+    #[prost(bool, tag = "6")]
+    pub used: bool
+}
+/// The satellites in view, with dilutions of precision (gpsd's SKY).
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Sky {
+    #[prost(string, optional, tag = "1")]
+    pub device: Option<String>,
+    #[prost(int64, optional, tag = "2")]
+    pub time_unix_nanos: Option<i64>,
+    #[prost(double, optional, tag = "3")]
+    pub xdop: Option<f64>,
+    #[prost(double, optional, tag = "4")]
+    pub ydop: Option<f64>,
+    #[prost(double, optional, tag = "5")]
+    pub vdop: Option<f64>,
+    #[prost(double, optional, tag = "6")]
+    pub tdop: Option<f64>,
+    #[prost(double, optional, tag = "7")]
+    pub hdop: Option<f64>,
+    #[prost(double, optional, tag = "8")]
+    pub pdop: Option<f64>,
+    #[prost(double, optional, tag = "9")]
+    pub gdop: Option<f64>,
+    #[prost(message, repeated, tag = "10")]
+    pub satellites: Vec<Satellite>
+}
+/// A device gpsd knows about (gpsd's DEVICE).
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Device {
+    #[prost(string, optional, tag = "1")]
+    pub path: Option<String>,
+    #[prost(bool, tag = "2")]
+    pub active: bool,
+    #[prost(int64, optional, tag = "3")]
+    pub activated_unix_nanos: Option<i64>,
+    #[prost(string, optional, tag = "4")]
+    pub driver: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    pub subtype: Option<String>,
+    #[prost(uint32, optional, tag = "6")]
+    pub bps: Option<u32>,
+    #[prost(double, optional, tag = "7")]
+    pub cycle: Option<f64>
+}
+/// Any one of the reports above.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Report {
+    #[prost(oneof = "report::Report", tags = "1, 2, 3")]
+    pub report: Option<report::Report>
+}
+/// The contents of a `Report`.
+pub mod report {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Report {
+        #[prost(message, tag = "1")]
+        Tpv(super::Tpv),
+        #[prost(message, tag = "2")]
+        Sky(super::Sky),
+        #[prost(message, tag = "3")]
+        Device(super::Device)
+    }
+}
+
+impl From<&TpvResponse> for Tpv {
+    fn from(tpv: &TpvResponse) -> Self {
+        let e = tpv.errors();
+        let value = |est: Option<crate::units::ErrorEstimate>| est.map(|e| e.value);
+        Tpv {
+            device: tpv.device().map(str::to_owned),
+            time_unix_nanos: tpv.time().as_ref().and_then(to_epoch_nanos),
+            mode: tpv.mode().map_or(0, |m| u8::from(m).into()),
+            lat: tpv.lat(),
+            lon: tpv.lon(),
+            alt: tpv.alt(),
+            track: tpv.track(),
+            speed: tpv.speed(),
+            climb: tpv.climb(),
+            ept: value(e.time),
+            epx: value(e.lon),
+            epy: value(e.lat),
+            epv: value(e.alt),
+            epd: value(e.track),
+            eps: value(e.speed),
+            epc: value(e.climb)
+        }
+    }
+}
+impl From<&SatelliteObject> for Satellite {
+    fn from(sat: &SatelliteObject) -> Self {
+        Satellite {
+            prn: sat.prn.into(),
+            gnss_id: sat.gnss_id.map(u32::from),
+            azimuth: sat.azimuth,
+            elevation: sat.elevation,
+            signal_strength: sat.signal_strength,
+            used: sat.used
+        }
+    }
+}
+impl From<&SkyResponse> for Sky {
+    fn from(sky: &SkyResponse) -> Self {
+        Sky {
+            device: sky.device.as_ref().map(|d| d.as_str().to_owned()),
+            time_unix_nanos: sky.time.as_ref().and_then(to_epoch_nanos),
+            xdop: sky.xdop,
+            ydop: sky.ydop,
+            vdop: sky.vdop,
+            tdop: sky.tdop,
+            hdop: sky.hdop,
+            pdop: sky.pdop,
+            gdop: sky.gdop,
+            satellites: sky.satellites.iter().map(Satellite::from).collect()
+        }
+    }
+}
+impl From<&DeviceObject> for Device {
+    fn from(dev: &DeviceObject) -> Self {
+        let mut out = Device { path: dev.path().map(str::to_owned), active: dev.is_active(), ..Device::default() };
+        match *dev {
+            DeviceObject::ActiveSeenPackets { activated, ref driver, ref subtype, bps, cycle, .. } => {
+                out.activated_unix_nanos = to_epoch_nanos(&activated);
+                out.driver = Some(driver.clone());
+                out.subtype = subtype.clone();
+                out.bps = bps;
+                out.cycle = cycle.map(f64::from);
+            },
+            DeviceObject::Active { activated, ref subtype, bps, cycle, .. } => {
+                out.activated_unix_nanos = to_epoch_nanos(&activated);
+                out.subtype = subtype.clone();
+                out.bps = bps;
+                out.cycle = cycle.map(f64::from);
+            },
+            DeviceObject::Inactive { .. } => {}
+        }
+        out
+    }
+}
+/// The response as a `Report`, if it's a TPV, SKY or DEVICE report.
+impl From<&Response> for Option<Report> {
+    fn from(resp: &Response) -> Self {
+        let report = match *resp {
+            Response::Tpv(ref tpv) => report::Report::Tpv(tpv.into()),
+            Response::Sky(ref sky) => report::Report::Sky(sky.into()),
+            Response::Device(ref dev) => report::Report::Device(dev.into()),
+            _ => return None
+        };
+        Some(Report { report: Some(report) })
+    }
+}
@@ -0,0 +1,72 @@
+//! Report values as [uom](https://crates.io/crates/uom) quantities.
+//!
+//! Only available with the `uom` feature enabled. gpsd's numbers are in
+//! implicit units (meters, meters per second, degrees, seconds); the
+//! accessors here put them in typed quantities instead, so they can't be
+//! mixed up or added to something of the wrong dimension.
+//!
+//! The types from `units` convert too, e.g. `Velocity::from(speed)`.
+
+use uom::si::angle::degree;
+use uom::si::f64::{Angle, Length, Time, Velocity};
+use uom::si::length::meter;
+use uom::si::time::second;
+use uom::si::velocity::meter_per_second;
+use crate::types::TpvResponse;
+use crate::units::{self, Bearing, ErrorEstimate, ErrorUnit, Speed};
+
+impl From<Speed> for Velocity {
+    fn from(s: Speed) -> Self {
+        Velocity::new::<meter_per_second>(s.mps())
+    }
+}
+impl From<units::Length> for Length {
+    fn from(l: units::Length) -> Self {
+        Length::new::<meter>(l.meters())
+    }
+}
+impl From<Bearing> for Angle {
+    fn from(b: Bearing) -> Self {
+        Angle::new::<degree>(b.degrees())
+    }
+}
+impl ErrorEstimate {
+    /// The estimate as a length, if it's in meters.
+    pub fn length(self) -> Option<Length> {
+        Some(self).filter(|e| e.unit == ErrorUnit::Meters).map(|e| Length::new::<meter>(e.value))
+    }
+    /// The estimate as a velocity, if it's in meters per second.
+    pub fn velocity(self) -> Option<Velocity> {
+        Some(self).filter(|e| e.unit == ErrorUnit::MetersPerSecond).map(|e| Velocity::new::<meter_per_second>(e.value))
+    }
+    /// The estimate as an angle, if it's in degrees.
+    pub fn angle(self) -> Option<Angle> {
+        Some(self).filter(|e| e.unit == ErrorUnit::Degrees).map(|e| Angle::new::<degree>(e.value))
+    }
+    /// The estimate as a time, if it's in seconds.
+    pub fn time(self) -> Option<Time> {
+        Some(self).filter(|e| e.unit == ErrorUnit::Seconds).map(|e| Time::new::<second>(e.value))
+    }
+}
+impl TpvResponse {
+    /// Speed over ground, if reported.
+    pub fn speed_quantity(&self) -> Option<Velocity> {
+        self.ground_speed().map(Velocity::from)
+    }
+    /// Climb (positive) or sink (negative) rate, if reported.
+    pub fn climb_quantity(&self) -> Option<Velocity> {
+        self.climb_rate().map(Velocity::from)
+    }
+    /// Altitude, if reported.
+    pub fn alt_quantity(&self) -> Option<Length> {
+        self.altitude().map(Length::from)
+    }
+    /// Course over ground from true north, if reported.
+    pub fn track_quantity(&self) -> Option<Angle> {
+        self.course().map(Angle::from)
+    }
+    /// Estimated timestamp error (95% confidence), if reported.
+    pub fn time_err_quantity(&self) -> Option<Time> {
+        self.errors().time.and_then(ErrorEstimate::time)
+    }
+}
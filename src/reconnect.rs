@@ -0,0 +1,114 @@
+//! Automatically reconnecting to gpsd when the connection drops.
+//!
+//! gpsd restarts, USB receivers re-enumerate, and networks go away. With a
+//! `ReconnectPolicy` set on a `GpsdConnection`, a dropped connection is
+//! transparently re-established (with exponential backoff between attempts),
+//! and the caller is told what's going on through `ReconnectEvent`s.
+//!
+//! Once reconnected, the last watch sent (by whatever means) is sent again,
+//! and gpsd's WATCH reply is checked against it, so the stream doesn't
+//! silently resume with no data.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::types::WatchObject;
+
+/// How to go about reconnecting.
+///
+/// The delay before attempt `n` (counting from 1) is
+/// `initial_delay * multiplier^(n - 1)`, capped at `max_delay`, then randomly
+/// varied by up to `jitter` (a fraction, e.g. 0.2 for ±20%) so that many
+/// clients don't all hammer a restarted gpsd at once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    /// Delay before the first attempt.
+    pub initial_delay: Duration,
+    /// Upper bound on the delay between attempts, before jitter.
+    pub max_delay: Duration,
+    /// Factor the delay grows by after each failed attempt.
+    pub multiplier: f64,
+    /// Fraction of the delay to randomly vary it by.
+    pub jitter: f64,
+    /// Give up after this many failed attempts; `None` retries forever.
+    pub max_retries: Option<u32>
+}
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_retries: None
+        }
+    }
+}
+impl ReconnectPolicy {
+    /// The delay before attempt `attempt` (counting from 1), without jitter.
+    pub fn base_delay(&self, attempt: u32) -> Duration {
+        let exp = self.multiplier.powi(attempt.saturating_sub(1) as i32);
+        let secs = (self.initial_delay.as_secs_f64() * exp).min(self.max_delay.as_secs_f64());
+        Duration::from_secs_f64(secs.max(0.0))
+    }
+    /// The delay before attempt `attempt`, with jitter applied.
+    pub(crate) fn delay(&self, attempt: u32, rng: &mut Jitter) -> Duration {
+        let base = self.base_delay(attempt).as_secs_f64();
+        let factor = 1.0 + self.jitter * (2.0 * rng.next_f64() - 1.0);
+        Duration::from_secs_f64((base * factor).max(0.0))
+    }
+    /// Whether another attempt is allowed after `attempts` failures.
+    pub(crate) fn may_retry(&self, attempts: u32) -> bool {
+        self.max_retries.map(|m| attempts < m).unwrap_or(true)
+    }
+}
+
+/// Something that happened while reconnecting.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReconnectEvent {
+    /// The connection was lost, for the given reason.
+    Disconnected(String),
+    /// About to wait `delay`, then make reconnection attempt `attempt`.
+    Attempt {
+        attempt: u32,
+        delay: Duration
+    },
+    /// Reconnection attempt `attempt` failed.
+    AttemptFailed {
+        attempt: u32,
+        error: String
+    },
+    /// The connection was re-established, after `attempts` attempts.
+    Reconnected {
+        attempts: u32
+    },
+    /// gpsd confirmed the watch that was re-sent after reconnecting.
+    WatchRestored,
+    /// gpsd's reply to the re-sent watch doesn't match what was asked for
+    /// (e.g. the device being watched has gone away). `reply` is the WATCH
+    /// object gpsd sent.
+    WatchMismatch {
+        reply: WatchObject
+    },
+    /// Gave up after `attempts` failed attempts. The error that caused the
+    /// disconnection is returned to the caller.
+    GaveUp {
+        attempts: u32
+    }
+}
+
+/// A small xorshift generator, which is plenty for jittering delays.
+pub(crate) struct Jitter(u64);
+impl Jitter {
+    pub(crate) fn new() -> Self {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Jitter(seed | 1)
+    }
+    /// A number in [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
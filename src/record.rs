@@ -0,0 +1,119 @@
+//! Recording gpsd sessions to JSON Lines files.
+//!
+//! With a `Recorder` set on a `GpsdConnection`, every line received from gpsd
+//! is written out, along with the time it arrived, before being parsed as
+//! usual. Each line of the recording is one `RecordedLine`, e.g.
+//!
+//! ```text
+//! {"time":"2017-07-01T12:00:00.123Z","line":"{\"class\":\"VERSION\",...}"}
+//! ```
+//!
+//! Recordings can be fed back in with `replay`.
+//!
+//! For sharing with other gpsd tools, a recorder can write the lines as
+//! `gpspipe -w` does instead (`RecordFormat::Gpspipe`), each optionally
+//! prefixed with its arrival time as `gpspipe -t` (or `-tu`, or `-tuu`) does:
+//!
+//! ```text
+//! 2017-07-01 12:00:00: {"class":"VERSION",...}
+//! ```
+//!
+//! gpspipe writes these times in the local time zone; a recorder writes them
+//! in UTC, which is what gpspipe writes with `TZ=UTC`. `replay` reads this
+//! format too, timing the replay by the prefixes if there are any (and
+//! taking them to be UTC).
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::timestamp::{self, format_millis, to_epoch_nanos, Timestamp};
+
+/// One line received from gpsd.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordedLine {
+    /// When the line arrived.
+    #[serde(with = "crate::timestamp::rfc3339")]
+    pub time: Timestamp,
+    /// The line, without its trailing newline.
+    pub line: String
+}
+
+/// How a `Recorder` writes its recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum RecordFormat {
+    /// One `RecordedLine` per line (the default).
+    #[default]
+    Json,
+    /// Lines as gpsd sent them, like `gpspipe -w`, with arrival times if
+    /// asked for.
+    Gpspipe(Option<GpspipeTime>)
+}
+/// The arrival time prefixes `gpspipe` can write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GpspipeTime {
+    /// `-t`: `2017-07-01 12:00:00`.
+    Seconds,
+    /// `-tu`: `2017-07-01 12:00:00.123456`.
+    Micros,
+    /// `-tuu`: seconds since the epoch, `1498910400.123456`.
+    Epoch
+}
+impl GpspipeTime {
+    /// `t` formatted as gpspipe would, in UTC.
+    fn format(self, t: &Timestamp) -> String {
+        let nanos = to_epoch_nanos(t).unwrap_or(0);
+        let micros = nanos.rem_euclid(1_000_000_000) / 1000;
+        // format_millis gives `2017-07-01T12:00:00.123Z`.
+        let secs = format_millis(t)[..19].replacen('T', " ", 1);
+        match self {
+            GpspipeTime::Seconds => secs,
+            GpspipeTime::Micros => format!("{}.{:06}", secs, micros),
+            GpspipeTime::Epoch => format!("{}.{:06}", nanos.div_euclid(1_000_000_000), micros)
+        }
+    }
+}
+
+/// Writes received lines to a recording.
+pub struct Recorder {
+    out: Box<dyn Write + Send>,
+    format: RecordFormat,
+    lines: u64
+}
+impl Recorder {
+    /// Record to `out`.
+    pub fn new<W: Write + Send + 'static>(out: W) -> Self {
+        Self { out: Box::new(out), format: RecordFormat::Json, lines: 0 }
+    }
+    /// Write the recording in `format`.
+    pub fn with_format(mut self, format: RecordFormat) -> Self {
+        self.format = format;
+        self
+    }
+    /// Record to a new file at `path`, replacing any existing file.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+    /// Record a line that arrived just now.
+    ///
+    /// Each line is flushed as it's written, so a recording survives the
+    /// program being killed mid-session.
+    pub fn record(&mut self, line: &str) -> io::Result<()> {
+        let rec = RecordedLine {
+            time: timestamp::now(),
+            line: line.trim_end_matches(['\r', '\n']).to_owned()
+        };
+        match self.format {
+            RecordFormat::Json => serde_json::to_writer(&mut self.out, &rec)?,
+            RecordFormat::Gpspipe(None) => self.out.write_all(rec.line.as_bytes())?,
+            RecordFormat::Gpspipe(Some(t)) => write!(self.out, "{}: {}", t.format(&rec.time), rec.line)?
+        }
+        self.out.write_all(b"\n")?;
+        self.out.flush()?;
+        self.lines += 1;
+        Ok(())
+    }
+    /// Number of lines recorded so far.
+    pub fn lines(&self) -> u64 {
+        self.lines
+    }
+}
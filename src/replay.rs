@@ -0,0 +1,148 @@
+//! Replaying recorded sessions as if they came from gpsd.
+//!
+//! A `ReplayTransport` reads a recording made with `record`, and hands its
+//! lines to a `GpsdConnection` like a live gpsd would:
+//!
+//! ```rust,no_run
+//! # use unbounded_gpsd::GpsdConnection;
+//! # use unbounded_gpsd::replay::ReplayTransport;
+//! let replay = ReplayTransport::open("session.jsonl").unwrap().with_speed(10.0);
+//! let conn = GpsdConnection::from_transport(replay).unwrap();
+//! for resp in conn {
+//!     println!("{:?}", resp);
+//! }
+//! ```
+//!
+//! By default lines are replayed as fast as they can be read. With a speed
+//! set, they're spaced out like the original session, sped up or slowed down
+//! by that factor. Commands written to the transport are discarded. The
+//! connection is closed once the recording runs out.
+//!
+//! Recordings in `gpspipe -w` format work too, with or without the arrival
+//! times `gpspipe -t` adds (see `record::RecordFormat`), taken to be in
+//! UTC. Without them, the lines are replayed as fast as they can be read,
+//! whatever the speed.
+
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::timestamp::{self, Timestamp};
+use crate::record::RecordedLine;
+use crate::transport::Transport;
+
+/// A transport that plays back a recorded session.
+pub struct ReplayTransport {
+    reader: Box<dyn BufRead + Send>,
+    speed: Option<f64>,
+    /// The first line's arrival time, and when we replayed it.
+    anchor: Option<(Timestamp, Instant)>,
+    pending: Vec<u8>,
+    due: Option<Instant>,
+    read_timeout: Cell<Option<Duration>>
+}
+impl ReplayTransport {
+    /// Replay the recording at `path`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::from_reader(BufReader::new(File::open(path)?)))
+    }
+    /// Replay a recording read from `reader`.
+    pub fn from_reader<R: BufRead + Send + 'static>(reader: R) -> Self {
+        Self {
+            reader: Box::new(reader),
+            speed: None,
+            anchor: None,
+            pending: Vec::new(),
+            due: None,
+            read_timeout: Cell::new(None)
+        }
+    }
+    /// Honour the recording's timing, at `speed` times the original rate
+    /// (e.g. 1.0 for real time, 10.0 for ten times faster).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `speed` isn't positive.
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        assert!(speed > 0.0, "replay speed must be positive");
+        self.speed = Some(speed);
+        self
+    }
+    /// Load the next recorded line into `pending`. Returns false at the end
+    /// of the recording.
+    fn next_line(&mut self) -> io::Result<bool> {
+        let mut buf = String::new();
+        loop {
+            buf.clear();
+            if self.reader.read_line(&mut buf)? == 0 {
+                return Ok(false);
+            }
+            if !buf.trim().is_empty() {
+                break;
+            }
+        }
+        let (time, line) = parse_line(buf.trim())?;
+        if let (Some(speed), Some(time)) = (self.speed, time) {
+            let now = Instant::now();
+            let (t0, i0) = *self.anchor.get_or_insert((time, now));
+            let offset = timestamp::elapsed(t0, time).unwrap_or_default();
+            self.due = Some(i0 + offset.div_f64(speed));
+        }
+        self.pending = line.into_bytes();
+        self.pending.push(b'\n');
+        Ok(true)
+    }
+}
+/// A recorded line's arrival time, if it has one, and the line itself:
+/// either a `RecordedLine`, or a line as gpspipe writes it.
+fn parse_line(buf: &str) -> io::Result<(Option<Timestamp>, String)> {
+    if buf.starts_with('{') {
+        return Ok(match serde_json::from_str::<RecordedLine>(buf) {
+            Ok(rec) => (Some(rec.time), rec.line),
+            Err(_) => (None, buf.to_owned())
+        });
+    }
+    let split = buf.find(": {").map(|i| (&buf[..i], &buf[i + 2..]));
+    match split.and_then(|(time, line)| Some((timestamp::parse(time)?, line))) {
+        Some((time, line)) => Ok((Some(time), line.to_owned())),
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, format!("unrecognised recorded line: {}", buf)))
+    }
+}
+impl Read for ReplayTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() && !self.next_line()? {
+            return Ok(0);
+        }
+        if let Some(due) = self.due {
+            let wait = due.saturating_duration_since(Instant::now());
+            match self.read_timeout.get() {
+                Some(t) if t < wait => {
+                    thread::sleep(t);
+                    return Err(io::Error::new(io::ErrorKind::WouldBlock, "next replayed line not due yet"));
+                },
+                _ => thread::sleep(wait)
+            }
+            self.due = None;
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending[..n]);
+        self.pending.drain(..n);
+        Ok(n)
+    }
+}
+impl Write for ReplayTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+impl Transport for ReplayTransport {
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.read_timeout.set(dur);
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+//! TPV reports as ROS `sensor_msgs/NavSatFix` messages.
+//!
+//! `NavSatFix` has the fields of the ROS 2 message, with the same names and
+//! types, so a bridge node can copy them straight into its generated message
+//! type, or serialize one to JSON for rosbridge. Conversion follows the
+//! message's documented semantics rather than gpsd's:
+//!
+//! * `altitude` is above the WGS84 ellipsoid, not mean sea level: `altHAE`
+//!   if gpsd sent it, otherwise `altMSL` (or `alt`) plus `geoidSep`, and NaN
+//!   if neither is available.
+//! * `position_covariance` is in meters², east-north-up and row-major, from
+//!   `epx`, `epy` and `epv`. Those are 95% confidence bounds, so each is
+//!   scaled to one standard deviation before squaring. If any of them is
+//!   missing, the covariance is all zeros and its type is unknown.
+//! * `status` is `STATUS_NO_FIX` unless there's a 2D or 3D fix; a fix is
+//!   `STATUS_SBAS_FIX` if gpsd says it's differential, `STATUS_GBAS_FIX` if
+//!   it's RTK, and `STATUS_FIX` otherwise.
+//!
+//! A TPV report doesn't say which constellations went into the fix, so
+//! `service` is `SERVICE_GPS` unless `with_service` works it out from a SKY
+//! report.
+
+use std::convert::TryFrom;
+use crate::timestamp::to_epoch_nanos;
+use crate::types::{Constellation, FixMode, SkyResponse, TpvResponse};
+use crate::units::{ErrorEstimate, ErrorUnit};
+
+/// `builtin_interfaces/Time`: seconds and nanoseconds since the epoch.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Time {
+    pub sec: i32,
+    pub nanosec: u32
+}
+
+/// `std_msgs/Header`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Header {
+    /// When the fix was for. Zero if the report had no time (or one that
+    /// doesn't fit), in which case the node should stamp the message itself.
+    pub stamp: Time,
+    /// The frame the fix is of: the GPS antenna's, by ROS convention.
+    pub frame_id: String
+}
+
+/// `sensor_msgs/NavSatStatus`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NavSatStatus {
+    /// One of the `STATUS_` constants.
+    pub status: i8,
+    /// The `SERVICE_` constants for the systems used, or'd together.
+    pub service: u16
+}
+impl NavSatStatus {
+    pub const STATUS_NO_FIX: i8 = -1;
+    pub const STATUS_FIX: i8 = 0;
+    pub const STATUS_SBAS_FIX: i8 = 1;
+    pub const STATUS_GBAS_FIX: i8 = 2;
+    pub const SERVICE_GPS: u16 = 1;
+    pub const SERVICE_GLONASS: u16 = 2;
+    pub const SERVICE_COMPASS: u16 = 4;
+    pub const SERVICE_GALILEO: u16 = 8;
+}
+
+/// `sensor_msgs/NavSatFix`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NavSatFix {
+    pub header: Header,
+    pub status: NavSatStatus,
+    /// Degrees, positive north of the equator; NaN without a fix.
+    pub latitude: f64,
+    /// Degrees, positive east of the prime meridian; NaN without a fix.
+    pub longitude: f64,
+    /// Meters above the WGS84 ellipsoid, or NaN.
+    pub altitude: f64,
+    pub position_covariance: [f64; 9],
+    /// One of the `COVARIANCE_TYPE_` constants.
+    pub position_covariance_type: u8
+}
+impl NavSatFix {
+    pub const COVARIANCE_TYPE_UNKNOWN: u8 = 0;
+    pub const COVARIANCE_TYPE_APPROXIMATED: u8 = 1;
+    pub const COVARIANCE_TYPE_DIAGONAL_KNOWN: u8 = 2;
+    pub const COVARIANCE_TYPE_KNOWN: u8 = 3;
+
+    /// The message for a TPV report, in frame `frame_id`.
+    pub fn from_tpv(tpv: &TpvResponse, frame_id: &str) -> Self {
+        let stamp = tpv.time().as_ref().and_then(to_epoch_nanos)
+            .and_then(|n| Some(Time {
+                sec: i32::try_from(n.div_euclid(1_000_000_000)).ok()?,
+                nanosec: n.rem_euclid(1_000_000_000) as u32
+            }))
+            .unwrap_or_default();
+        let status = match tpv.mode() {
+            Some(FixMode::Fix2d) | Some(FixMode::Fix3d) => match tpv.extra().get("status").and_then(|v| v.as_u64()) {
+                Some(2) => NavSatStatus::STATUS_SBAS_FIX,
+                Some(3) | Some(4) => NavSatStatus::STATUS_GBAS_FIX,
+                _ => NavSatStatus::STATUS_FIX
+            },
+            _ => NavSatStatus::STATUS_NO_FIX
+        };
+        let pos = tpv.position();
+        let altitude = pos.and_then(|p| p.height_above_ellipsoid());
+        // One standard deviation, squared.
+        let variance = |e: Option<ErrorEstimate>| e.filter(|e| e.unit == ErrorUnit::Meters).map(|e| e.one_sigma().powi(2));
+        let e = tpv.errors();
+        let (covariance, covariance_type) = match (variance(e.lon), variance(e.lat), variance(e.alt)) {
+            (Some(east), Some(north), Some(up)) =>
+                ([east, 0.0, 0.0, 0.0, north, 0.0, 0.0, 0.0, up], Self::COVARIANCE_TYPE_DIAGONAL_KNOWN),
+            _ => ([0.0; 9], Self::COVARIANCE_TYPE_UNKNOWN)
+        };
+        NavSatFix {
+            header: Header { stamp, frame_id: frame_id.to_owned() },
+            status: NavSatStatus { status, service: NavSatStatus::SERVICE_GPS },
+            latitude: pos.map_or(f64::NAN, |p| p.lat.degrees()),
+            longitude: pos.map_or(f64::NAN, |p| p.lon.degrees()),
+            altitude: altitude.unwrap_or(f64::NAN),
+            position_covariance: covariance,
+            position_covariance_type: covariance_type
+        }
+    }
+    /// Set `status.service` from the systems of the satellites `sky` says
+    /// were used, if it says any were. QZSS counts as GPS, which it augments.
+    pub fn with_service(mut self, sky: &SkyResponse) -> Self {
+        let service = sky.used_satellites().fold(0, |s, sat| s | match sat.constellation() {
+            Constellation::Gps | Constellation::Qzss => NavSatStatus::SERVICE_GPS,
+            Constellation::Glonass => NavSatStatus::SERVICE_GLONASS,
+            Constellation::BeiDou => NavSatStatus::SERVICE_COMPASS,
+            Constellation::Galileo => NavSatStatus::SERVICE_GALILEO,
+            _ => 0
+        });
+        if service != 0 {
+            self.status.service = service;
+        }
+        self
+    }
+}
@@ -0,0 +1,159 @@
+//! Connecting to gpsd through a SOCKS5 proxy.
+//!
+//! Implements the client side of RFC 1928 (CONNECT only), with optional
+//! username/password authentication from RFC 1929. Hostnames are passed to the
+//! proxy unresolved, so the proxy does the DNS lookup.
+
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::time::Duration;
+use crate::errors::GpsdError;
+use crate::{GpsdConnection, GpsdResult, Stream};
+
+const VERSION: u8 = 5;
+const AUTH_NONE: u8 = 0x00;
+const AUTH_PASSWORD: u8 = 0x02;
+const AUTH_NO_ACCEPTABLE: u8 = 0xFF;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// A SOCKS5 proxy to connect through.
+#[derive(Debug, Clone)]
+pub struct Socks5Proxy {
+    /// Address of the proxy server.
+    pub addr: SocketAddr,
+    /// Username and password to authenticate with, if the proxy requires it.
+    pub auth: Option<(String, String)>
+}
+impl Socks5Proxy {
+    /// A proxy at `addr`, without authentication.
+    pub fn new(addr: SocketAddr) -> Self {
+        Self { addr, auth: None }
+    }
+    /// A proxy at `addr`, authenticating with a username and password.
+    pub fn with_auth(addr: SocketAddr, username: &str, password: &str) -> Self {
+        Self { addr, auth: Some((username.into(), password.into())) }
+    }
+    /// Open a connection to `host` (a hostname or IP address) on `port`
+    /// through the proxy.
+    pub fn connect(&self, host: &str, port: u16) -> GpsdResult<TcpStream> {
+        self.connect_timeout(host, port, None)
+    }
+    /// Like `connect`, but giving up if connecting to the proxy, or any
+    /// read or write in the handshake, takes longer than `timeout`. The
+    /// stream is handed back without timeouts set.
+    pub fn connect_timeout(&self, host: &str, port: u16, timeout: Option<Duration>) -> GpsdResult<TcpStream> {
+        let mut stream = match timeout {
+            Some(t) => TcpStream::connect_timeout(&self.addr, t)?,
+            None => TcpStream::connect(self.addr)?
+        };
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+        self.handshake(&mut stream, host, port)?;
+        stream.set_read_timeout(None)?;
+        stream.set_write_timeout(None)?;
+        Ok(stream)
+    }
+    fn handshake<S: Read + Write>(&self, stream: &mut S, host: &str, port: u16) -> GpsdResult<()> {
+        let method = if self.auth.is_some() { AUTH_PASSWORD } else { AUTH_NONE };
+        stream.write_all(&[VERSION, 1, method])?;
+        let mut reply = [0; 2];
+        stream.read_exact(&mut reply)?;
+        if reply[0] != VERSION {
+            bail!(proxy_error(format!("unexpected SOCKS version {}", reply[0])));
+        }
+        match reply[1] {
+            AUTH_NONE => {},
+            AUTH_PASSWORD if self.auth.is_some() => {
+                let (ref user, ref pass) = *self.auth.as_ref().unwrap();
+                if user.len() > 255 || pass.len() > 255 {
+                    bail!(proxy_error("username or password too long".into()));
+                }
+                let mut msg = vec![1, user.len() as u8];
+                msg.extend(user.as_bytes());
+                msg.push(pass.len() as u8);
+                msg.extend(pass.as_bytes());
+                stream.write_all(&msg)?;
+                stream.read_exact(&mut reply)?;
+                if reply[1] != 0 {
+                    bail!(proxy_error("authentication rejected".into()));
+                }
+            },
+            AUTH_NO_ACCEPTABLE => bail!(proxy_error("no acceptable authentication method".into())),
+            m => bail!(proxy_error(format!("proxy chose unrequested auth method {}", m)))
+        }
+        let mut req = vec![VERSION, CMD_CONNECT, 0];
+        match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                req.push(ATYP_IPV4);
+                req.extend(&ip.octets());
+            },
+            Ok(IpAddr::V6(ip)) => {
+                req.push(ATYP_IPV6);
+                req.extend(&ip.octets());
+            },
+            Err(_) => {
+                if host.len() > 255 {
+                    bail!(proxy_error("hostname too long".into()));
+                }
+                req.push(ATYP_DOMAIN);
+                req.push(host.len() as u8);
+                req.extend(host.as_bytes());
+            }
+        }
+        req.extend(&port.to_be_bytes());
+        stream.write_all(&req)?;
+        let mut head = [0; 4];
+        stream.read_exact(&mut head)?;
+        if head[1] != 0 {
+            bail!(proxy_error(format!("connect failed: {}", reply_message(head[1]))));
+        }
+        // Skip the bound address, which we don't need.
+        let addr_len = match head[3] {
+            ATYP_IPV4 => 4,
+            ATYP_IPV6 => 16,
+            ATYP_DOMAIN => {
+                let mut len = [0; 1];
+                stream.read_exact(&mut len)?;
+                len[0] as usize
+            },
+            t => bail!(proxy_error(format!("unknown address type {}", t)))
+        };
+        let mut rest = vec![0; addr_len + 2];
+        stream.read_exact(&mut rest)?;
+        Ok(())
+    }
+}
+/// An error for the proxy misbehaving or refusing us.
+fn proxy_error(s: String) -> GpsdError {
+    GpsdError::Protocol(format!("SOCKS5 proxy: {}", s))
+}
+/// Describe a SOCKS5 reply code.
+fn reply_message(rep: u8) -> &'static str {
+    match rep {
+        1 => "general SOCKS server failure",
+        2 => "connection not allowed by ruleset",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "command not supported",
+        8 => "address type not supported",
+        _ => "unknown error"
+    }
+}
+
+impl GpsdConnection {
+    /// Make a new connection to gpsd at `host` and `port`, through a SOCKS5 proxy.
+    pub fn connect_via_socks5(proxy: &Socks5Proxy, host: &str, port: u16) -> GpsdResult<Self> {
+        let proxy = proxy.clone();
+        let host = host.to_owned();
+        Self::with_connector(Box::new(move || {
+            proxy.connect(&host, port)
+                .map(|s| Box::new(s) as Stream)
+                .map_err(io::Error::from)
+        }))
+    }
+}
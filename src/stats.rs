@@ -0,0 +1,29 @@
+//! Counters for monitoring a connection's health.
+//!
+//! `GpsdConnection::stats` returns a snapshot of what the connection has done
+//! since it was created: how much it has read and written, how many of each
+//! class of response it has seen, and how often things went wrong.
+
+use std::collections::BTreeMap;
+
+/// A snapshot of a connection's counters.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// Bytes received from gpsd.
+    pub bytes_read: u64,
+    /// Bytes sent to gpsd, including commands sent with a `CommandHandle`.
+    pub bytes_written: u64,
+    /// Responses received, by class (e.g. `"TPV"`; see `Response::class`).
+    /// Responses dropped by decimation are counted.
+    pub messages: BTreeMap<&'static str, u64>,
+    /// Lines that couldn't be parsed.
+    pub parse_errors: u64,
+    /// Times the connection was re-established.
+    pub reconnects: u64
+}
+impl ConnectionStats {
+    /// The total number of responses received.
+    pub fn total_messages(&self) -> u64 {
+        self.messages.values().sum()
+    }
+}
@@ -0,0 +1,255 @@
+//! Storing fixes in an SQLite database.
+//!
+//! Only available with the `sqlite` feature enabled, which bundles SQLite
+//! through [rusqlite](https://crates.io/crates/rusqlite). A `TrackStore`
+//! keeps each TPV report as a row in a `tpv` table and, if asked to, a
+//! summary of each SKY report in a `sky` table:
+//!
+//! ```sql
+//! CREATE TABLE tpv (
+//!     id INTEGER PRIMARY KEY,
+//!     time INTEGER,   -- nanoseconds since the epoch
+//!     device TEXT,
+//!     mode INTEGER,   -- 0 to 3, as gpsd sends it
+//!     lat REAL, lon REAL, alt REAL, speed REAL, track REAL, climb REAL,
+//!     ept REAL, epx REAL, epy REAL, epv REAL,
+//!     json TEXT NOT NULL  -- the whole report
+//! );
+//! CREATE TABLE sky (
+//!     id INTEGER PRIMARY KEY,
+//!     time INTEGER,
+//!     device TEXT,
+//!     visible INTEGER NOT NULL, used INTEGER NOT NULL,
+//!     hdop REAL, vdop REAL, pdop REAL, gdop REAL
+//! );
+//! ```
+//!
+//! Both tables are indexed on `time`, and on `device` then `time`, so
+//! extracting a stretch of track is quick however big the database gets.
+//! The schema is created when a store is opened, if it isn't there already;
+//! other tables and indexes can be added alongside through `connection`.
+//!
+//! `tpv_between` and `sky_between` get reports back out for a time range,
+//! with the TPV reports exactly as they were stored. The `device` columns
+//! hold normalized paths (see `DevicePath::normalized`), and devices asked
+//! for are normalized the same way, so `/dev//ttyUSB0/` finds the reports
+//! from `/dev/ttyUSB0`.
+
+use std::path::Path;
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use crate::timestamp::{from_epoch_nanos, to_epoch_nanos, Timestamp};
+use crate::types::{normalize_path, Response, SkyResponse, TpvResponse};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS tpv (
+        id INTEGER PRIMARY KEY,
+        time INTEGER,
+        device TEXT,
+        mode INTEGER,
+        lat REAL, lon REAL, alt REAL, speed REAL, track REAL, climb REAL,
+        ept REAL, epx REAL, epy REAL, epv REAL,
+        json TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS tpv_time ON tpv (time);
+    CREATE INDEX IF NOT EXISTS tpv_device_time ON tpv (device, time);
+    CREATE TABLE IF NOT EXISTS sky (
+        id INTEGER PRIMARY KEY,
+        time INTEGER,
+        device TEXT,
+        visible INTEGER NOT NULL, used INTEGER NOT NULL,
+        hdop REAL, vdop REAL, pdop REAL, gdop REAL
+    );
+    CREATE INDEX IF NOT EXISTS sky_time ON sky (time);
+    CREATE INDEX IF NOT EXISTS sky_device_time ON sky (device, time);
+";
+
+/// What a `TrackStore` keeps of a SKY report.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SkySummary {
+    /// Time of the report, if it had one.
+    #[serde(default, with = "crate::timestamp::rfc3339::option")]
+    pub time: Option<Timestamp>,
+    /// Name of the originating device, if reported.
+    pub device: Option<String>,
+    /// Number of satellites in view.
+    pub visible: u32,
+    /// Number of satellites used in the solution.
+    pub used: u32,
+    /// Horizontal d.o.p.
+    pub hdop: Option<f64>,
+    /// Altitude d.o.p.
+    pub vdop: Option<f64>,
+    /// Spherical d.o.p.
+    pub pdop: Option<f64>,
+    /// Hyperspherical d.o.p.
+    pub gdop: Option<f64>
+}
+impl<'a> From<&'a SkyResponse> for SkySummary {
+    fn from(sky: &'a SkyResponse) -> Self {
+        SkySummary {
+            time: sky.time,
+            device: sky.device.as_ref().map(|d| d.to_string()),
+            visible: sky.satellites.len() as u32,
+            used: sky.used_satellites().count() as u32,
+            hdop: sky.hdop,
+            vdop: sky.vdop,
+            pdop: sky.pdop,
+            gdop: sky.gdop
+        }
+    }
+}
+
+/// Column `idx` of `row`, as a timestamp.
+fn time_column(row: &Row, idx: usize) -> Result<Option<Timestamp>> {
+    let nanos: Option<i64> = row.get(idx)?;
+    nanos.map(|n| from_epoch_nanos(i128::from(n)).ok_or(rusqlite::Error::IntegralValueOutOfRange(idx, n)))
+        .transpose()
+}
+/// `t` as stored.
+fn time_param(t: Option<Timestamp>) -> Option<i64> {
+    t.as_ref().and_then(to_epoch_nanos)
+}
+
+fn insert_tpv(conn: &Connection, tpv: &TpvResponse) -> Result<()> {
+    let json = serde_json::to_string(tpv)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    let e = tpv.errors();
+    conn.prepare_cached(
+        "INSERT INTO tpv (time, device, mode, lat, lon, alt, speed, track, climb, ept, epx, epy, epv, json)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)"
+    )?.execute(params![
+        time_param(tpv.time()), tpv.device().map(normalize_path), tpv.mode().map(u8::from),
+        tpv.lat(), tpv.lon(), tpv.alt(), tpv.speed(), tpv.track(), tpv.climb(),
+        e.time.map(|e| e.value), e.lon.map(|e| e.value), e.lat.map(|e| e.value), e.alt.map(|e| e.value),
+        json
+    ])?;
+    Ok(())
+}
+fn insert_sky(conn: &Connection, sky: &SkyResponse) -> Result<()> {
+    let s = SkySummary::from(sky);
+    conn.prepare_cached(
+        "INSERT INTO sky (time, device, visible, used, hdop, vdop, pdop, gdop)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)"
+    )?.execute(params![time_param(s.time), s.device.as_deref().map(normalize_path), s.visible, s.used, s.hdop, s.vdop, s.pdop, s.gdop])?;
+    Ok(())
+}
+fn store(conn: &Connection, sky: bool, resp: &Response) -> Result<bool> {
+    match *resp {
+        Response::Tpv(ref tpv) => insert_tpv(conn, tpv).map(|_| true),
+        Response::Sky(ref s) if sky => insert_sky(conn, s).map(|_| true),
+        _ => Ok(false)
+    }
+}
+/// `select` on `table` for rows from `from` (inclusive) to `to`
+/// (exclusive), from `device` if given, in time order.
+fn between<T, F>(conn: &Connection, select: &str, table: &str, from: Timestamp, to: Timestamp,
+                 device: Option<&str>, f: F) -> Result<Vec<T>>
+    where F: FnMut(&Row) -> Result<T> {
+    let sql = format!(
+        "SELECT {} FROM {} WHERE {}time >= ?1 AND time < ?2 ORDER BY time, id",
+        select, table, if device.is_some() { "device = ?3 AND " } else { "" }
+    );
+    let (from, to) = (time_param(Some(from)), time_param(Some(to)));
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let rows = match device {
+        Some(d) => stmt.query_map(params![from, to, normalize_path(d)], f)?.collect(),
+        None => stmt.query_map(params![from, to], f)?.collect()
+    };
+    rows
+}
+
+/// Fixes (and optionally satellite summaries) stored in an SQLite database.
+pub struct TrackStore {
+    conn: Connection,
+    sky: bool
+}
+impl TrackStore {
+    /// Open the database at `path`, creating it if need be.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+    /// A store in a new in-memory database.
+    pub fn open_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+    /// A store in an already opened database.
+    pub fn from_connection(conn: Connection) -> Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn, sky: false })
+    }
+    /// Whether `store` keeps summaries of SKY reports too. It doesn't by
+    /// default.
+    pub fn with_sky(mut self, sky: bool) -> Self {
+        self.sky = sky;
+        self
+    }
+    /// The underlying database connection.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+    /// Store a TPV report.
+    pub fn insert_tpv(&self, tpv: &TpvResponse) -> Result<()> {
+        insert_tpv(&self.conn, tpv)
+    }
+    /// Store a summary of a SKY report, whether or not `with_sky` was set.
+    pub fn insert_sky(&self, sky: &SkyResponse) -> Result<()> {
+        insert_sky(&self.conn, sky)
+    }
+    /// Store the response if it's a TPV report, or a SKY report and the
+    /// store keeps those. Returns whether it was stored.
+    pub fn store(&self, resp: &Response) -> Result<bool> {
+        store(&self.conn, self.sky, resp)
+    }
+    /// Store each of `responses` that `store` would, in one transaction,
+    /// which is much quicker than one at a time. Returns how many were
+    /// stored; if any can't be, none are.
+    pub fn store_all<'a, I: IntoIterator<Item = &'a Response>>(&mut self, responses: I) -> Result<u64> {
+        let tx = self.conn.transaction()?;
+        let mut stored = 0;
+        for resp in responses {
+            stored += u64::from(store(&tx, self.sky, resp)?);
+        }
+        tx.commit()?;
+        Ok(stored)
+    }
+    /// TPV reports from `from` (inclusive) to `to` (exclusive), from
+    /// `device` if given, in time order. Reports without a time aren't
+    /// included.
+    pub fn tpv_between(&self, from: Timestamp, to: Timestamp, device: Option<&str>) -> Result<Vec<TpvResponse>> {
+        between(&self.conn, "json", "tpv", from, to, device, |row| {
+            let json: String = row.get(0)?;
+            serde_json::from_str(&json).map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))
+        })
+    }
+    /// SKY summaries from `from` (inclusive) to `to` (exclusive), from
+    /// `device` if given, in time order.
+    pub fn sky_between(&self, from: Timestamp, to: Timestamp, device: Option<&str>) -> Result<Vec<SkySummary>> {
+        between(&self.conn, "time, device, visible, used, hdop, vdop, pdop, gdop", "sky", from, to, device, |row| {
+            Ok(SkySummary {
+                time: time_column(row, 0)?,
+                device: row.get(1)?,
+                visible: row.get(2)?,
+                used: row.get(3)?,
+                hdop: row.get(4)?,
+                vdop: row.get(5)?,
+                pdop: row.get(6)?,
+                gdop: row.get(7)?
+            })
+        })
+    }
+    /// The times of the first and last stored TPV reports, from `device` if
+    /// given, or `None` if there aren't any with times.
+    pub fn time_span(&self, device: Option<&str>) -> Result<Option<(Timestamp, Timestamp)>> {
+        let sql = "SELECT min(time), max(time) FROM tpv WHERE time IS NOT NULL AND (?1 IS NULL OR device = ?1)";
+        let span = self.conn.query_row(sql, params![device.map(normalize_path)], |row| Ok((time_column(row, 0)?, time_column(row, 1)?)))
+            .optional()?;
+        Ok(span.and_then(|(first, last)| first.zip(last)))
+    }
+    /// The devices TPV reports have been stored from, as normalized paths.
+    pub fn devices(&self) -> Result<Vec<String>> {
+        let mut stmt = self.conn.prepare_cached("SELECT DISTINCT device FROM tpv WHERE device IS NOT NULL ORDER BY device")?;
+        let rows = stmt.query_map([], |row| row.get(0))?.collect();
+        rows
+    }
+}
@@ -0,0 +1,194 @@
+//! Running an async connection as a managed tokio task.
+//!
+//! Only available with the `tokio` feature enabled. `spawn` moves an
+//! `AsyncGpsdConnection` onto a task of its own, which reads responses into a
+//! channel and sends whatever commands it's given, so a service can own a
+//! `GpsdTask` alongside its other tasks and shut it down the same way.
+//!
+//! Commands can also be sent with `TaskCommands::request`, which resolves with
+//! gpsd's reply rather than leaving it to turn up in the response stream.
+//!
+//! ```rust,no_run
+//! # use unbounded_gpsd::asynchronous::AsyncGpsdConnection;
+//! # use unbounded_gpsd::types::{Command, WatchObject};
+//! # async fn run() {
+//! let conn = AsyncGpsdConnection::new("127.0.0.1:2947").await.unwrap();
+//! let mut task = unbounded_gpsd::task::spawn(conn, 16);
+//! task.commands().send(Command::Watch(WatchObject::default())).await.unwrap();
+//! while let Some(resp) = task.recv().await {
+//!     println!("{:?}", resp);
+//! }
+//! task.join().await.unwrap();
+//! # }
+//! ```
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use std::collections::VecDeque;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use crate::asynchronous::AsyncGpsdConnection;
+use crate::errors::{GpsdError, GpsdResult};
+use crate::types::{Command, Response};
+
+/// A command for the task, and where to send its reply if someone's waiting
+/// for it.
+struct Envelope {
+    cmd: Command,
+    reply: Option<oneshot::Sender<Response>>
+}
+
+/// A cloneable sender of commands to a `GpsdTask`.
+#[derive(Clone)]
+pub struct TaskCommands(mpsc::Sender<Envelope>);
+impl TaskCommands {
+    async fn post(&self, cmd: Command, reply: Option<oneshot::Sender<Response>>) -> GpsdResult<()> {
+        match self.0.send(Envelope { cmd, reply }).await {
+            Ok(()) => Ok(()),
+            Err(_) => bail!(GpsdError::Disconnected(String::from("gpsd task has stopped")))
+        }
+    }
+    /// Send `cmd` to gpsd. Waits if the task's command buffer is full.
+    pub async fn send(&self, cmd: Command) -> GpsdResult<()> {
+        self.post(cmd, None).await
+    }
+    /// Send `cmd` to gpsd, and wait for its reply (see `Command::is_reply`),
+    /// failing with `GpsdError::ServerError` if it's an ERROR. The reply
+    /// isn't also sent to the response stream.
+    ///
+    /// `Raw` commands can't be sent this way, as their replies can't be told
+    /// apart.
+    pub async fn request(&self, cmd: Command) -> GpsdResult<Response> {
+        if !cmd.expects_reply() {
+            bail!(::std::io::Error::new(::std::io::ErrorKind::InvalidInput, "can't wait for the reply to a raw command"));
+        }
+        let (tx, rx) = oneshot::channel();
+        self.post(cmd, Some(tx)).await?;
+        match rx.await {
+            Ok(Response::Error { message }) => bail!(GpsdError::ServerError { message }),
+            Ok(resp) => Ok(resp),
+            Err(_) => bail!(GpsdError::Disconnected(String::from("gpsd task stopped before replying")))
+        }
+    }
+}
+
+/// A connection being read on its own task.
+///
+/// Dropping it aborts the task; use `into_parts` to keep the task running
+/// without it.
+pub struct GpsdTask {
+    commands: TaskCommands,
+    responses: mpsc::Receiver<Response>,
+    handle: Option<JoinHandle<GpsdResult<()>>>
+}
+
+/// Spawn the read loop for `conn` on the current tokio runtime, buffering up
+/// to `capacity` responses (and commands).
+///
+/// Responses that fail to deserialize are logged and skipped. The task stops
+/// when gpsd closes the connection (finishing with `Ok`), when reading or
+/// writing fails (finishing with the error), or when the response receiver is
+/// dropped. Once the responses fill the buffer, it stops reading until they're
+/// consumed.
+///
+/// # Panics
+///
+/// Panics if not called from within a tokio runtime, or if `capacity` is 0.
+pub fn spawn<S>(mut conn: AsyncGpsdConnection<S>, capacity: usize) -> GpsdTask
+    where S: AsyncRead + AsyncWrite + Unpin + Send + 'static {
+    let (cmd_tx, mut cmd_rx) = mpsc::channel::<Envelope>(capacity);
+    let (resp_tx, resp_rx) = mpsc::channel(capacity);
+    let handle = tokio::spawn(async move {
+        let mut commands_open = true;
+        // Commands sent that gpsd is yet to reply to, in order.
+        let mut pending: VecDeque<Envelope> = VecDeque::new();
+        loop {
+            // Reading is cancellation safe, so it's fine to drop a read in
+            // progress to send a command.
+            tokio::select! {
+                cmd = cmd_rx.recv(), if commands_open => match cmd {
+                    Some(env) => {
+                        conn.send_command(&env.cmd).await?;
+                        if env.cmd.expects_reply() {
+                            pending.push_back(env);
+                        }
+                    },
+                    None => commands_open = false
+                },
+                resp = conn.get_response() => match resp {
+                    Ok(mut resp) => {
+                        if pending.front().map(|p| p.cmd.is_reply(&resp)).unwrap_or(false) {
+                            if let Some(tx) = pending.pop_front().and_then(|p| p.reply) {
+                                // If the requester has given up, the reply
+                                // goes to the stream after all.
+                                match tx.send(resp) {
+                                    Ok(()) => continue,
+                                    Err(r) => resp = r
+                                }
+                            }
+                        }
+                        if resp_tx.send(resp).await.is_err() {
+                            debug!("response receiver dropped, stopping task");
+                            return Ok(());
+                        }
+                    },
+                    Err(GpsdError::Json { line, source }) => {
+                        warn!("skipping undeserializable response '{}': {}", line, source);
+                    },
+                    Err(GpsdError::Disconnected(..)) => return Ok(()),
+                    Err(e) => return Err(e)
+                }
+            }
+        }
+    });
+    GpsdTask { commands: TaskCommands(cmd_tx), responses: resp_rx, handle: Some(handle) }
+}
+
+impl GpsdTask {
+    /// A sender for commands to the task. Clone it to send from elsewhere.
+    pub fn commands(&self) -> &TaskCommands {
+        &self.commands
+    }
+    /// Wait for the next response. Returns `None` once the task has stopped
+    /// and every response it read has been received.
+    pub async fn recv(&mut self) -> Option<Response> {
+        self.responses.recv().await
+    }
+    /// Stop the task.
+    pub fn abort(&self) {
+        if let Some(ref h) = self.handle {
+            h.abort();
+        }
+    }
+    /// Whether the task has stopped.
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().map(|h| h.is_finished()).unwrap_or(true)
+    }
+    /// Wait for the task to finish, returning how it did. Responses not yet
+    /// received are discarded, and the task stops at the next one it reads.
+    ///
+    /// A task that was aborted (or panicked) finishes with
+    /// `GpsdError::Disconnected`.
+    pub async fn join(mut self) -> GpsdResult<()> {
+        // Stop reading, so the task isn't left waiting on a full channel.
+        self.responses.close();
+        let handle = self.handle.take().expect("GpsdTask joined twice");
+        match handle.await {
+            Ok(res) => res,
+            Err(e) => bail!(GpsdError::Disconnected(format!("gpsd task failed: {}", e)))
+        }
+    }
+    /// Take the task apart, into the command sender, the response receiver and
+    /// the task's join handle. The task then carries on until it stops of its
+    /// own accord, or is aborted through the handle.
+    pub fn into_parts(mut self) -> (TaskCommands, mpsc::Receiver<Response>, JoinHandle<GpsdResult<()>>) {
+        let handle = self.handle.take().expect("GpsdTask taken apart twice");
+        // `Drop` can't be bypassed, so swap in dummies for it to drop.
+        let responses = ::std::mem::replace(&mut self.responses, mpsc::channel(1).1);
+        (self.commands.clone(), responses, handle)
+    }
+}
+impl Drop for GpsdTask {
+    fn drop(&mut self) {
+        self.abort();
+    }
+}
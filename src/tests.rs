@@ -7,7 +7,7 @@ use std::time::Duration;
 #[test]
 fn gpsfake_basic() {
     let mut cmd = Command::new("gpsfake")
-        .args(&["-1", "stockholm_walk.nmea"])
+        .args(["-1", "stockholm_walk.nmea"])
         .spawn()
         .unwrap();
     thread::sleep(Duration::from_millis(1000));
@@ -20,12 +20,12 @@ fn gpsfake_basic() {
         }
         let resp = conn.get_response();
         if let Err(e) = resp {
-            if let &ErrorKind::DeserFailed(_, ref e) = e.kind() {
-                if let Category::Eof = e.classify() {
+            if let GpsdError::Json { ref source, .. } = e {
+                if let Category::Eof = source.classify() {
                     continue;
                 }
             }
-            if let &ErrorKind::Io(..) = e.kind() {
+            if let GpsdError::Io(..) = e {
                 return;
             }
             panic!("error: {:?}", e);
@@ -33,9 +33,10 @@ fn gpsfake_basic() {
     }
 }
 #[test]
+#[allow(clippy::zombie_processes)]
 fn gpsfake_poll() {
     Command::new("gpsfake")
-        .args(&["-1", "stockholm_walk.nmea"])
+        .args(["-1", "stockholm_walk.nmea"])
         .spawn()
         .unwrap();
     thread::sleep(Duration::from_millis(1000));
@@ -45,14 +46,2341 @@ fn gpsfake_poll() {
     conn.poll().unwrap();
     let resp = conn.get_response();
     if let Err(e) = resp {
-        if let &ErrorKind::DeserFailed(_, ref e) = e.kind() {
-            if let Category::Eof = e.classify() {
+        if let GpsdError::Json { ref source, .. } = e {
+            if let Category::Eof = source.classify() {
                 return;
             }
         }
-        if let &ErrorKind::Io(..) = e.kind() {
+        if let GpsdError::Io(..) = e {
             return;
         }
         panic!("error: {:?}", e);
     }
 }
+/// Spawn a throwaway listener that sends `lines` to the first client, then hangs up.
+fn fake_gpsd(lines: &'static [&'static str]) -> ::std::net::SocketAddr {
+    use std::io::Write;
+    let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        for line in lines {
+            stream.write_all(line.as_bytes()).unwrap();
+            stream.write_all(b"\n").unwrap();
+        }
+    });
+    addr
+}
+const VERSION_LINE: &str = r#"{"class":"VERSION","release":"3.17","rev":"3.17","proto_major":3,"proto_minor":12}"#;
+const TPV_LINE: &str = r#"{"class":"TPV","device":"/dev/ttyUSB0","mode":3,"time":"2017-07-01T12:00:00.000Z","ept":0.005,"lat":59.345,"lon":18.054,"alt":78.2,"track":320.9,"speed":0.06,"climb":0.1}"#;
+#[test]
+fn iter_ends_on_close() {
+    let addr = fake_gpsd(&[VERSION_LINE, TPV_LINE]);
+    let conn = GpsdConnection::new(addr).unwrap();
+    let resps = conn.into_iter().collect::<GpsdResult<Vec<_>>>().unwrap();
+    assert_eq!(resps.len(), 2);
+    match resps[1] {
+        Response::Tpv(TpvResponse::Fix3D { lat, .. }) => assert_eq!(lat, 59.345),
+        ref x => panic!("unexpected response: {:?}", x)
+    }
+}
+#[test]
+fn client_dispatches_by_class() {
+    use std::sync::{Arc, Mutex};
+    use client::GpsdClient;
+    let addr = fake_gpsd(&[VERSION_LINE, TPV_LINE, TPV_LINE]);
+    let mut client = GpsdClient::new(GpsdConnection::new(addr).unwrap());
+    let tpvs = Arc::new(Mutex::new(0));
+    let all = Arc::new(Mutex::new(0));
+    let (t, a) = (tpvs.clone(), all.clone());
+    client.on_tpv(move |_| *t.lock().unwrap() += 1)
+        .on_response(move |_| *a.lock().unwrap() += 1);
+    client.start();
+    client.join().unwrap();
+    assert_eq!(*tpvs.lock().unwrap(), 2);
+    assert_eq!(*all.lock().unwrap(), 3);
+}
+#[test]
+fn udp_source_splits_datagrams() {
+    use udp::UdpSource;
+    let mut src = UdpSource::bind("127.0.0.1:0").unwrap();
+    src.set_read_timeout(Some(Duration::from_millis(1000))).unwrap();
+    let sender = ::std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let nmea = "$GPRMC,104426.591,A,5920.7019,N,01803.2893,E,0.117980,320.93,141204,,*0F";
+    let dgram = format!("{}\r\n{}\r\n", TPV_LINE, nmea);
+    sender.send_to(dgram.as_bytes(), src.local_addr().unwrap()).unwrap();
+    match src.get_response().unwrap() {
+        Response::Tpv(TpvResponse::Fix3D { .. }) => {},
+        x => panic!("unexpected response: {:?}", x)
+    }
+    match src.get_response().unwrap() {
+        Response::Raw(ref s) => assert_eq!(s, nmea),
+        x => panic!("unexpected response: {:?}", x)
+    }
+}
+#[test]
+fn udp_source_decodes_nmea() {
+    use udp::UdpSource;
+    let mut src = UdpSource::bind("127.0.0.1:0").unwrap();
+    src.set_read_timeout(Some(Duration::from_millis(1000))).unwrap();
+    src.set_decode_nmea(true);
+    let sender = ::std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let nmea = "$GPRMC,104426.591,A,5920.7019,N,01803.2893,E,0.117980,320.93,141204,,*0F";
+    let dgram = format!("$GPGSA,bad*00\r\n{}\r\n!AIVDM,1,1,,A,13aEOK?P00PD2wVMdLDRhgvL289?,0*26\r\n", nmea);
+    sender.send_to(dgram.as_bytes(), src.local_addr().unwrap()).unwrap();
+    match src.get_response().unwrap() {
+        Response::Tpv(ref tpv) => assert_eq!(tpv.speed().map(|s| (s * 1e3).round()), Some(61.0)),
+        x => panic!("unexpected response: {:?}", x)
+    }
+    match src.get_response().unwrap() {
+        Response::Raw(ref s) => assert!(s.starts_with("!AIVDM")),
+        x => panic!("unexpected response: {:?}", x)
+    }
+}
+#[cfg(all(feature = "bluetooth", target_os = "linux"))]
+#[test]
+fn bdaddr_roundtrip() {
+    use bluetooth::BdAddr;
+    let addr: BdAddr = "00:1a:7D:da:71:13".parse().unwrap();
+    assert_eq!(addr.0, [0x00, 0x1a, 0x7d, 0xda, 0x71, 0x13]);
+    assert_eq!(addr.to_string(), "00:1A:7D:DA:71:13");
+    assert!("00:1a:7d:da:71".parse::<BdAddr>().is_err());
+    assert!("00:1a:7d:da:71:13:00".parse::<BdAddr>().is_err());
+}
+#[test]
+fn spawn_channel_delivers_responses() {
+    let addr = fake_gpsd(&[VERSION_LINE, "{garbage", TPV_LINE]);
+    let rx = GpsdConnection::new(addr).unwrap().spawn_channel(1);
+    let resps = rx.iter().collect::<Vec<_>>();
+    assert_eq!(resps.len(), 2);
+    match resps[0] {
+        Response::Version { ref release, .. } => assert_eq!(release, "3.17"),
+        ref x => panic!("unexpected response: {:?}", x)
+    }
+}
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn hub_fans_out_to_subscribers() {
+    use asynchronous::AsyncGpsdConnection;
+    use hub::GpsdHub;
+    let addr = fake_gpsd(&[VERSION_LINE, TPV_LINE]);
+    let conn = AsyncGpsdConnection::new(addr).await.unwrap();
+    let hub = GpsdHub::spawn(conn, 4);
+    let mut a = hub.subscribe();
+    let mut b = hub.subscribe();
+    assert_eq!(hub.receiver_count(), 2);
+    for rx in [&mut a, &mut b].iter_mut() {
+        match *rx.recv().await.unwrap() {
+            Response::Version { .. } => {},
+            ref x => panic!("unexpected response: {:?}", x)
+        }
+        match *rx.recv().await.unwrap() {
+            Response::Tpv(..) => {},
+            ref x => panic!("unexpected response: {:?}", x)
+        }
+        assert!(rx.recv().await.is_err());
+    }
+}
+/// A TPV at `secs` seconds past noon, with the given track and speed.
+fn tpv_at(secs: u32, track: f64, speed: f64) -> TpvResponse {
+    serde_json::from_value(json!({
+        "device": "/dev/ttyUSB0", "mode": 2, "ept": 0.005, "lat": 59.0, "lon": 18.0,
+        "time": format!("2017-07-01T12:{:02}:{:02}.000Z", secs / 60, secs % 60),
+        "track": track, "speed": speed
+    })).unwrap()
+}
+#[test]
+fn heading_monitor_needs_sustained_change() {
+    use heading::{HeadingEvent, HeadingMonitor};
+    let mut mon = HeadingMonitor::new(30.0, Duration::from_secs(3));
+    assert_eq!(mon.update(&tpv_at(0, 350.0, 5.0)), None);
+    // A brief swerve across north doesn't count...
+    assert_eq!(mon.update(&tpv_at(1, 40.0, 5.0)), None);
+    assert_eq!(mon.update(&tpv_at(2, 355.0, 5.0)), None);
+    // ...and neither does jitter while stationary.
+    assert_eq!(mon.update(&tpv_at(3, 180.0, 0.1)), None);
+    assert!(mon.stability().unwrap() > 0.75);
+    for s in 4..7 {
+        assert_eq!(mon.update(&tpv_at(s, 80.0, 5.0)), None);
+    }
+    match mon.update(&tpv_at(7, 80.0, 5.0)) {
+        Some(HeadingEvent::CourseChanged { from, to, delta, .. }) => {
+            assert_eq!((from, to, delta), (350.0, 80.0, 90.0));
+        },
+        x => panic!("unexpected event: {:?}", x)
+    }
+    assert_eq!(mon.course(), Some(80.0));
+}
+#[cfg(feature = "tls")]
+#[test]
+fn tls_rejects_plaintext_server() {
+    use std::sync::Arc;
+    use rustls::{ClientConfig, RootCertStore};
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions().unwrap()
+        .with_root_certificates(RootCertStore::empty())
+        .with_no_client_auth();
+    let config = Arc::new(config);
+    let addr = fake_gpsd(&[VERSION_LINE]);
+    assert!(GpsdConnection::connect_tls("localhost", addr.port(), config.clone()).is_err());
+    assert!(GpsdConnection::connect_tls("not a hostname", addr.port(), config).is_err());
+}
+#[test]
+fn socks5_connect_with_auth() {
+    use std::io::{Read, Write};
+    use socks::Socks5Proxy;
+    let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy = Socks5Proxy::with_auth(listener.local_addr().unwrap(), "user", "hunter2");
+    thread::spawn(move || {
+        let (mut s, _) = listener.accept().unwrap();
+        let mut greeting = [0; 3];
+        s.read_exact(&mut greeting).unwrap();
+        assert_eq!(greeting, [5, 1, 2]);
+        s.write_all(&[5, 2]).unwrap();
+        let mut auth = [0; 14];
+        s.read_exact(&mut auth).unwrap();
+        assert_eq!(&auth, b"\x01\x04user\x07hunter2");
+        s.write_all(&[1, 0]).unwrap();
+        let mut req = [0; 19];
+        s.read_exact(&mut req).unwrap();
+        assert_eq!(&req, b"\x05\x01\x00\x03\x0cgpsd.example\x0b\x83");
+        s.write_all(&[5, 0, 0, 1, 10, 0, 0, 1, 0x0b, 0x83]).unwrap();
+        s.write_all(VERSION_LINE.as_bytes()).unwrap();
+        s.write_all(b"\n").unwrap();
+    });
+    let mut conn = GpsdConnection::connect_via_socks5(&proxy, "gpsd.example", 2947).unwrap();
+    match conn.get_response().unwrap() {
+        Response::Version { .. } => {},
+        x => panic!("unexpected response: {:?}", x)
+    }
+}
+#[test]
+fn socks5_handshake_times_out() {
+    use std::time::Instant;
+    use socks::Socks5Proxy;
+    // A proxy that accepts the connection and then says nothing.
+    let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy = Socks5Proxy::new(listener.local_addr().unwrap());
+    let start = Instant::now();
+    match proxy.connect_timeout("gpsd.example", 2947, Some(Duration::from_millis(100))) {
+        Err(GpsdError::Io(ref e)) if is_timeout(e) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+    assert!(start.elapsed() < Duration::from_secs(5));
+    // The builder's connect timeout covers the proxy too.
+    let builder = GpsdConnection::builder().host("gpsd.example").proxy(proxy).timeout(Duration::from_millis(100));
+    assert!(matches!(builder.connect(), Err(GpsdError::Io(ref e)) if is_timeout(e)));
+    drop(listener);
+}
+#[test]
+fn speed_calibration_fit() {
+    use calibration::SpeedCalibrator;
+    let mut cal = SpeedCalibrator::new(Some("/dev/ttyUSB0".into()));
+    assert!(cal.estimate().is_none());
+    for i in 0..50 {
+        let gps = i as f64 * 0.5;
+        // A reference reading 2% high with a 0.1 m/s offset, plus a little noise.
+        let noise = if i % 2 == 0 { 0.01 } else { -0.01 };
+        cal.add(gps, gps * 1.02 + 0.1 + noise);
+    }
+    let fit = cal.estimate().unwrap();
+    assert_eq!(fit.samples, 50);
+    assert!((fit.scale - 1.02).abs() < 0.001, "scale {}", fit.scale);
+    assert!((fit.bias - 0.1).abs() < 0.01, "bias {}", fit.bias);
+    assert!(fit.residual_stddev < 0.02);
+    assert!(!fit.is_consistent(0.01, 0.05));
+    assert!(fit.is_consistent(0.05, 0.2));
+    assert!((fit.apply(10.0) - 10.3).abs() < 0.01);
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    assert!(SpeedCalibrator::new(Some("/dev//ttyUSB0/".into())).add_tpv(&tpv, 1.0));
+    assert!(!SpeedCalibrator::new(Some("/dev/ttyUSB1".into())).add_tpv(&tpv, 1.0));
+}
+/// Spawn a listener that serves one entry of `sessions` to each successive
+/// client, hanging up after each, then stops listening.
+fn fake_gpsd_sessions(sessions: &'static [&'static [&'static str]]) -> ::std::net::SocketAddr {
+    use std::io::Write;
+    let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for lines in sessions {
+            let (mut stream, _) = listener.accept().unwrap();
+            for line in lines.iter() {
+                stream.write_all(line.as_bytes()).unwrap();
+                stream.write_all(b"\n").unwrap();
+            }
+        }
+    });
+    addr
+}
+#[test]
+fn reconnect_with_backoff() {
+    use std::sync::{Arc, Mutex};
+    use reconnect::{ReconnectEvent, ReconnectPolicy};
+    let addr = fake_gpsd_sessions(&[&[VERSION_LINE], &[TPV_LINE]]);
+    let mut conn = GpsdConnection::new(addr).unwrap();
+    conn.set_reconnect(Some(ReconnectPolicy {
+        initial_delay: Duration::from_millis(10),
+        max_delay: Duration::from_millis(40),
+        jitter: 0.0,
+        max_retries: Some(3),
+        ..Default::default()
+    }));
+    let events = Arc::new(Mutex::new(vec![]));
+    let ev = events.clone();
+    conn.on_reconnect(move |e| ev.lock().unwrap().push(e.clone()));
+    match conn.get_response().unwrap() {
+        Response::Version { .. } => {},
+        x => panic!("unexpected response: {:?}", x)
+    }
+    match conn.get_response().unwrap() {
+        Response::Tpv(..) => {},
+        x => panic!("unexpected response: {:?}", x)
+    }
+    assert!(conn.get_response().is_err());
+    let events = events.lock().unwrap();
+    assert_eq!(events[1], ReconnectEvent::Attempt { attempt: 1, delay: Duration::from_millis(10) });
+    assert_eq!(events[2], ReconnectEvent::Reconnected { attempts: 1 });
+    assert_eq!(events.last(), Some(&ReconnectEvent::GaveUp { attempts: 3 }));
+    assert!(matches!(events[events.len() - 2], ReconnectEvent::AttemptFailed { attempt: 3, .. }));
+    assert!(events.contains(&ReconnectEvent::Attempt { attempt: 3, delay: Duration::from_millis(40) }));
+}
+#[test]
+fn inactivity_timeout_and_partial_lines() {
+    use std::io::Write;
+    let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut s, _) = listener.accept().unwrap();
+        let (head, tail) = TPV_LINE.split_at(40);
+        s.write_all(head.as_bytes()).unwrap();
+        thread::sleep(Duration::from_millis(150));
+        s.write_all(tail.as_bytes()).unwrap();
+        s.write_all(b"\n").unwrap();
+        thread::sleep(Duration::from_millis(1000));
+    });
+    let mut conn = GpsdConnection::connect_timeout(addr, Duration::from_millis(500)).unwrap();
+    conn.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    let mut timeouts = 0;
+    loop {
+        match conn.get_response() {
+            Ok(Response::Tpv(..)) => break,
+            Err(GpsdError::Io(ref e)) if is_timeout(e) => timeouts += 1,
+            x => panic!("unexpected result: {:?}", x)
+        }
+    }
+    assert!(timeouts > 0);
+    conn.set_read_timeout(None).unwrap();
+    conn.set_inactivity_timeout(Some(Duration::from_millis(100)));
+    match conn.get_response() {
+        Err(GpsdError::Timeout(..)) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+}
+#[test]
+fn watchdog_probes_then_gives_up() {
+    use std::io::{BufRead, BufReader, Write};
+    let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (s, _) = listener.accept().unwrap();
+        let mut r = BufReader::new(s.try_clone().unwrap());
+        let mut s = s;
+        // Answer the first probe, ignore the second.
+        let mut line = String::new();
+        r.read_line(&mut line).unwrap();
+        assert_eq!(line, "?POLL;\n");
+        s.write_all(br#"{"class":"POLL","time":"2017-07-01T12:00:00.000Z","active":0,"tpv":[],"sky":[]}"#).unwrap();
+        s.write_all(b"\n").unwrap();
+        line.clear();
+        r.read_line(&mut line).unwrap();
+        assert_eq!(line, "?POLL;\n");
+        thread::sleep(Duration::from_millis(1000));
+    });
+    let mut conn = GpsdConnection::new(addr).unwrap();
+    conn.set_watchdog(Some(Duration::from_millis(50)));
+    match conn.get_response() {
+        Ok(Response::Poll { .. }) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+    // Going unanswered is an error, not the end of the responses.
+    let mut responses = conn.iter();
+    match responses.next() {
+        Some(Err(GpsdError::Unhealthy(..))) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+}
+#[test]
+fn builder_connects_and_watches() {
+    use std::io::{BufRead, BufReader, Write};
+    use types::WatchObject;
+    let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut s, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(s.try_clone().unwrap()).read_line(&mut line).unwrap();
+        s.write_all(VERSION_LINE.as_bytes()).unwrap();
+        s.write_all(b"\n").unwrap();
+        line
+    });
+    let mut conn = GpsdConnection::builder()
+        .host("127.0.0.1")
+        .port(addr.port())
+        .timeout(Duration::from_secs(1))
+        .watch(WatchObject { json: true, device: Some("/dev/ttyUSB0".into()), ..Default::default() })
+        .connect()
+        .unwrap();
+    match conn.get_response() {
+        Ok(Response::Version { .. }) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+    let sent = server.join().unwrap();
+    assert!(sent.starts_with("?WATCH={"));
+    let watch: WatchObject = serde_json::from_str(sent["?WATCH=".len()..].trim_end().trim_end_matches(';')).unwrap();
+    assert!(watch.enable && watch.json && !watch.nmea);
+    assert_eq!(watch.device.as_deref(), Some("/dev/ttyUSB0"));
+    assert!(watch.remote.is_none());
+}
+#[cfg(unix)]
+#[test]
+fn custom_transport() {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+    let (ours, theirs) = UnixStream::pair().unwrap();
+    let mut conn = GpsdConnection::from_transport(ours).unwrap();
+    conn.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+    conn.version().unwrap();
+    let mut line = String::new();
+    BufReader::new(theirs.try_clone().unwrap()).read_line(&mut line).unwrap();
+    assert_eq!(line, "?VERSION;\n");
+    let mut theirs = theirs;
+    writeln!(theirs, "{}", VERSION_LINE).unwrap();
+    drop(theirs);
+    match conn.get_response() {
+        Ok(Response::Version { .. }) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+    // The transport was handed over once, so there's nothing to reconnect to.
+    conn.set_reconnect(Some(reconnect::ReconnectPolicy {
+        initial_delay: Duration::from_millis(1),
+        max_retries: Some(1),
+        ..Default::default()
+    }));
+    match conn.get_response() {
+        Err(GpsdError::Disconnected(..)) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+}
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn async_custom_transport() {
+    use asynchronous::AsyncGpsdConnection;
+    use tokio::io::AsyncWriteExt;
+    let (ours, mut theirs) = tokio::io::duplex(1024);
+    let mut conn = AsyncGpsdConnection::from_transport(ours);
+    theirs.write_all(TPV_LINE.as_bytes()).await.unwrap();
+    theirs.write_all(b"\n").await.unwrap();
+    match conn.get_response().await {
+        Ok(Response::Tpv(TpvResponse::Fix3D { .. })) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+}
+#[test]
+fn mock_transport_records_commands() {
+    use mock::MockTransport;
+    let mock = MockTransport::new();
+    let mut conn = GpsdConnection::from_transport(mock.clone()).unwrap();
+    conn.set_read_timeout(Some(Duration::from_millis(20))).unwrap();
+    conn.watch(true).unwrap();
+    conn.poll().unwrap();
+    assert_eq!(mock.commands(), vec![r#"?WATCH={"class":"WATCH","enable":true,"json":true,"raw":0}"#, "?POLL;"]);
+    match conn.get_response() {
+        Err(GpsdError::Io(ref e)) if is_timeout(e) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+    let feeder = mock.clone();
+    thread::spawn(move || {
+        feeder.push_bytes(&TPV_LINE.as_bytes()[..20]);
+        thread::sleep(Duration::from_millis(5));
+        feeder.push_bytes(&TPV_LINE.as_bytes()[20..]);
+        feeder.push_bytes(b"\n");
+        feeder.close();
+    });
+    conn.set_read_timeout(None).unwrap();
+    match conn.get_response() {
+        Ok(Response::Tpv(TpvResponse::Fix3D { .. })) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+    match conn.get_response() {
+        Err(GpsdError::Disconnected(..)) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+}
+#[test]
+fn response_parser_feeds_incrementally() {
+    use nonblocking::ResponseParser;
+    let mut parser = ResponseParser::new();
+    let (a, b) = TPV_LINE.split_at(30);
+    parser.feed(a.as_bytes());
+    assert!(parser.poll_response().is_none());
+    parser.feed(b.as_bytes());
+    parser.feed(b"\n\nnot json\n");
+    parser.feed(VERSION_LINE.as_bytes());
+    match parser.poll_response() {
+        Some(Ok(Response::Tpv(..))) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+    match parser.poll_response() {
+        Some(Err(GpsdError::Json { .. })) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+    assert!(parser.poll_response().is_none());
+    assert_eq!(parser.buffered(), VERSION_LINE.len());
+}
+#[test]
+fn nonblocking_connection_never_blocks() {
+    use std::io::{BufRead, BufReader, Write};
+    use nonblocking::NonBlockingConnection;
+    let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        let (mut s, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(s.try_clone().unwrap()).read_line(&mut line).unwrap();
+        assert_eq!(line, "?VERSION;\n");
+        s.write_all(VERSION_LINE.as_bytes()).unwrap();
+        s.write_all(b"\n").unwrap();
+    });
+    let mut conn = NonBlockingConnection::connect(addr).unwrap();
+    conn.version().unwrap();
+    assert!(!conn.wants_write());
+    let mut got = None;
+    for _ in 0..100 {
+        match conn.on_readable() {
+            Ok(_) => {},
+            Err(GpsdError::Disconnected(..)) => {},
+            Err(e) => panic!("unexpected error: {}", e)
+        }
+        if let Some(r) = conn.poll_response() {
+            got = Some(r.unwrap());
+            break;
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    match got {
+        Some(Response::Version { .. }) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+}
+#[test]
+fn demux_routes_by_device() {
+    use demux::Demux;
+    use mock::MockTransport;
+    let mock = MockTransport::new();
+    mock.push_line(VERSION_LINE);
+    mock.push_line(TPV_LINE);
+    mock.push_line(&TPV_LINE.replace("/dev/ttyUSB0", "/dev/ttyUSB1"));
+    mock.push_line(&TPV_LINE.replace("/dev/ttyUSB0", "/dev/ttyACM0"));
+    mock.close();
+    let demux = Demux::new(4);
+    let usb0 = demux.device("/dev/ttyUSB0");
+    let usb1 = demux.device("/dev/ttyUSB1");
+    let rest = demux.default_stream();
+    GpsdConnection::from_transport(mock).unwrap().spawn_demux(demux);
+    let devices = |rx: Receiver<Response>| rx.iter().map(|r| r.device().map(String::from)).collect::<Vec<_>>();
+    assert_eq!(devices(usb0), vec![Some("/dev/ttyUSB0".to_owned())]);
+    assert_eq!(devices(usb1), vec![Some("/dev/ttyUSB1".to_owned())]);
+    assert_eq!(devices(rest), vec![None, Some("/dev/ttyACM0".to_owned())]);
+}
+#[test]
+fn decimation_thins_tpvs() {
+    use decimate::{Decimation, Decimator};
+    use mock::MockTransport;
+    let mut every = Decimator::new(Decimation::EveryNth(3));
+    let kept = (0..7).filter(|&i| every.accept(&tpv_at(i, 0.0, 1.0))).count();
+    assert_eq!(kept, 3);
+    // 10 Hz reports, of which we only want one a second.
+    let mock = MockTransport::new();
+    for i in 0..25 {
+        let time = format!("2017-07-01T12:00:{:02}.{}00Z", i / 10, i % 10);
+        mock.push_line(&TPV_LINE.replace("2017-07-01T12:00:00.000Z", &time));
+        if i == 12 {
+            mock.push_line(VERSION_LINE);
+        }
+    }
+    mock.close();
+    let mut conn = GpsdConnection::from_transport(mock).unwrap();
+    conn.set_decimation(Some(Decimation::MinInterval(Duration::from_secs(1))));
+    let resps = conn.into_iter().collect::<GpsdResult<Vec<_>>>().unwrap();
+    let secs = resps.iter().map(|r| match *r {
+        Response::Tpv(ref t) => timestamp::format_millis(&t.time().unwrap())[17..23].to_string(),
+        _ => "version".into()
+    }).collect::<Vec<_>>();
+    assert_eq!(secs, vec!["00.000", "01.000", "version", "02.000"]);
+}
+#[test]
+fn recorder_tees_received_lines() {
+    use mock::MockTransport;
+    use record::{RecordedLine, Recorder};
+    let path = ::std::env::temp_dir().join(format!("unbounded-gpsd-record-{}.jsonl", ::std::process::id()));
+    let mock = MockTransport::new();
+    mock.push_line(VERSION_LINE);
+    mock.push_line("garbage");
+    mock.push_line(TPV_LINE);
+    mock.close();
+    let mut conn = GpsdConnection::from_transport(mock).unwrap();
+    conn.set_recorder(Some(Recorder::create(&path).unwrap()));
+    let resps = conn.iter().collect::<Vec<_>>();
+    assert_eq!(resps.len(), 3);
+    assert!(resps[1].is_err());
+    let text = ::std::fs::read_to_string(&path).unwrap();
+    ::std::fs::remove_file(&path).unwrap();
+    let recs = text.lines().map(|l| serde_json::from_str::<RecordedLine>(l).unwrap()).collect::<Vec<_>>();
+    let lines = recs.iter().map(|r| &r.line[..]).collect::<Vec<_>>();
+    assert_eq!(lines, vec![VERSION_LINE, "garbage", TPV_LINE]);
+    assert!(recs[0].time <= recs[2].time);
+}
+#[test]
+fn replay_honours_timing() {
+    use std::time::Instant;
+    use replay::ReplayTransport;
+    let recording = format!(
+        "{{\"time\":\"2017-07-01T12:00:00Z\",\"line\":{}}}\n\n{{\"time\":\"2017-07-01T12:00:02Z\",\"line\":{}}}\n",
+        serde_json::to_string(VERSION_LINE).unwrap(),
+        serde_json::to_string(TPV_LINE).unwrap()
+    );
+    let fast = ReplayTransport::from_reader(::std::io::Cursor::new(recording.clone()));
+    let resps = GpsdConnection::from_transport(fast).unwrap().into_iter().collect::<GpsdResult<Vec<_>>>().unwrap();
+    assert_eq!(resps.len(), 2);
+    // Two seconds of recording at 20x takes about 100ms.
+    let timed = ReplayTransport::from_reader(::std::io::Cursor::new(recording)).with_speed(20.0);
+    let mut conn = GpsdConnection::from_transport(timed).unwrap();
+    conn.set_read_timeout(Some(Duration::from_millis(30))).unwrap();
+    let start = Instant::now();
+    let mut timeouts = 0;
+    let mut got = vec![];
+    loop {
+        match conn.get_response() {
+            Ok(r) => got.push(r),
+            Err(GpsdError::Io(ref e)) if is_timeout(e) => timeouts += 1,
+            Err(GpsdError::Disconnected(..)) => break,
+            Err(e) => panic!("unexpected error: {}", e)
+        }
+    }
+    assert_eq!(got.len(), 2);
+    assert!(timeouts >= 2);
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
+#[test]
+fn gpspipe_recordings_round_trip() {
+    use mock::MockTransport;
+    use record::{GpspipeTime, RecordFormat, Recorder};
+    use replay::ReplayTransport;
+    let path = ::std::env::temp_dir().join(format!("unbounded-gpsd-gpspipe-{}.log", ::std::process::id()));
+    let mock = MockTransport::new();
+    mock.push_line(VERSION_LINE);
+    mock.push_line(TPV_LINE);
+    mock.close();
+    let mut conn = GpsdConnection::from_transport(mock).unwrap();
+    let rec = Recorder::create(&path).unwrap().with_format(RecordFormat::Gpspipe(Some(GpspipeTime::Epoch)));
+    conn.set_recorder(Some(rec));
+    assert_eq!(conn.iter().count(), 2);
+    let text = ::std::fs::read_to_string(&path).unwrap();
+    ::std::fs::remove_file(&path).unwrap();
+    for (line, orig) in text.lines().zip(vec![VERSION_LINE, TPV_LINE]) {
+        let (time, json) = line.split_once(": ").unwrap();
+        assert_eq!(json, orig);
+        let (secs, micros) = time.split_once('.').unwrap();
+        assert!(secs.parse::<u64>().unwrap() > 1_500_000_000);
+        assert_eq!(micros.len(), 6);
+    }
+    // Every gpspipe flavour replays, along with the crate's own format.
+    let recording = format!(
+        "{}\n2017-07-01 12:00:00: {}\n2017-07-01 12:00:00.500000: {}\n1498910401.000000: {}\n{{\"time\":\"2017-07-01T12:00:01.5Z\",\"line\":{}}}\n",
+        VERSION_LINE, TPV_LINE, TPV_LINE, TPV_LINE,
+        serde_json::to_string(TPV_LINE).unwrap()
+    );
+    let replay = ReplayTransport::from_reader(::std::io::Cursor::new(recording)).with_speed(100.0);
+    let resps = GpsdConnection::from_transport(replay).unwrap().into_iter().collect::<GpsdResult<Vec<_>>>().unwrap();
+    assert_eq!(resps.len(), 5);
+    assert!(resps[1..].iter().all(|r| matches!(r, Response::Tpv(..))));
+    let bad = ReplayTransport::from_reader(::std::io::Cursor::new("yesterday: {}\n"));
+    assert!(GpsdConnection::from_transport(bad).unwrap().get_response().is_err());
+}
+#[cfg(feature = "websocket")]
+#[test]
+#[allow(clippy::result_large_err)]
+fn websocket_transport_reassembles_lines() {
+    use tungstenite::handshake::server::{Request, Response as HsResponse};
+    use tungstenite::Message;
+    let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (s, _) = listener.accept().unwrap();
+        let mut ws = tungstenite::accept_hdr(s, |_: &Request, mut resp: HsResponse| {
+            resp.headers_mut().insert("Sec-WebSocket-Protocol", "binary".parse().unwrap());
+            Ok(resp)
+        }).unwrap();
+        let cmd = ws.read().unwrap().into_data();
+        let (a, b) = VERSION_LINE.split_at(20);
+        ws.send(Message::text(a)).unwrap();
+        ws.send(Message::binary(format!("{}\n", b).into_bytes())).unwrap();
+        ws.close(None).unwrap();
+        let _ = ws.read();
+        cmd
+    });
+    let mut conn = GpsdConnection::connect_websocket(&format!("ws://{}/", addr)).unwrap();
+    conn.version().unwrap();
+    match conn.get_response() {
+        Ok(Response::Version { .. }) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+    match conn.get_response() {
+        Err(GpsdError::Disconnected(..)) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+    assert_eq!(&server.join().unwrap()[..], b"?VERSION;\n");
+}
+#[test]
+fn multi_gpsd_labels_sources() {
+    use mock::MockTransport;
+    use multi::MultiGpsd;
+    let mut multi = MultiGpsd::new(4);
+    for (name, lines) in [("mast", &[VERSION_LINE, TPV_LINE][..]), ("cabin", &[TPV_LINE][..])].iter() {
+        let mock = MockTransport::new();
+        for l in lines.iter() {
+            mock.push_line(l);
+        }
+        mock.close();
+        multi.add(name, GpsdConnection::from_transport(mock).unwrap());
+    }
+    assert_eq!(multi.sources(), &["mast".to_owned(), "cabin".to_owned()]);
+    let mut got = multi.into_receiver().iter()
+        .map(|s| (s.source, match s.response {
+            Ok(Response::Tpv(..)) => "tpv",
+            Ok(Response::Version { .. }) => "version",
+            Err(GpsdError::Disconnected(..)) => "closed",
+            Ok(_) | Err(_) => "other"
+        }))
+        .collect::<Vec<_>>();
+    got.sort();
+    assert_eq!(got, vec![
+        ("cabin".into(), "closed"), ("cabin".into(), "tpv"),
+        ("mast".into(), "closed"), ("mast".into(), "tpv"), ("mast".into(), "version")
+    ]);
+}
+#[test]
+fn happy_eyeballs_ordering_and_fallback() {
+    let v4 = |p| SocketAddr::from(([127, 0, 0, 1], p));
+    let v6 = |p| SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], p));
+    assert_eq!(interleave_families(&[v6(1), v6(2), v6(3), v4(4), v4(5)]), vec![v6(1), v4(4), v6(2), v4(5), v6(3)]);
+    assert_eq!(interleave_families(&[v4(1), v6(2), v6(3), v6(4)]), vec![v4(1), v6(2), v6(3), v6(4)]);
+    // An address refusing connections falls through to the next one.
+    let dead = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let refused = dead.local_addr().unwrap();
+    drop(dead);
+    let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let good = listener.local_addr().unwrap();
+    let s = connect_addrs(&[refused, good], Some(Duration::from_secs(10))).unwrap();
+    assert_eq!(s.peer_addr().unwrap(), good);
+    // Everything failing reports an error rather than hanging.
+    assert!(connect_addrs(&[refused, refused], Some(Duration::from_secs(1))).is_err());
+}
+#[cfg(feature = "mdns")]
+#[test]
+fn mdns_response_parsing() {
+    use discovery::{parse_response, query, DiscoveredGpsd, SERVICE};
+    let q = query(SERVICE);
+    assert_eq!(&q[12..], &b"\x05_gpsd\x04_tcp\x05local\x00\x00\x0c\x00\x01"[..]);
+    // A response echoing the question, with the PTR answer and SRV, TXT and
+    // A records (names compressed against the question) in the additionals.
+    let mut r = vec![0, 0, 0x84, 0, 0, 1, 0, 1, 0, 0, 0, 3];
+    r.extend(&q[12..]);
+    let rr = |r: &mut Vec<u8>, name: &[u8], ty: u16, data: &[u8]| {
+        r.extend(name);
+        r.extend(&ty.to_be_bytes());
+        r.extend(&[0x80, 1, 0, 0, 0x11, 0x94]);
+        r.extend(&(data.len() as u16).to_be_bytes());
+        r.extend(data);
+    };
+    // `\xc0\x0c` points at `_gpsd._tcp.local` in the question.
+    rr(&mut r, b"\xc0\x0c", 12, b"\x04boat\xc0\x0c");
+    rr(&mut r, b"\x04boat\xc0\x0c", 33, b"\x00\x00\x00\x00\x0b\x83\x04mast\xc0\x17");
+    rr(&mut r, b"\x04boat\xc0\x0c", 16, b"\x00");
+    rr(&mut r, b"\x04mast\xc0\x17", 1, &[192, 168, 1, 20]);
+    let found = parse_response(&r, SERVICE).unwrap();
+    assert_eq!(found, vec![DiscoveredGpsd {
+        instance: "boat._gpsd._tcp.local".into(),
+        host: "mast.local".into(),
+        port: 2947,
+        addrs: vec!["192.168.1.20".parse().unwrap()]
+    }]);
+    assert_eq!(found[0].socket_addrs(), vec!["192.168.1.20:2947".parse().unwrap()]);
+    // Queries and truncated packets are rejected.
+    assert!(parse_response(&q, SERVICE).is_none());
+    assert!(parse_response(&r[..r.len() - 2], SERVICE).is_none());
+}
+#[test]
+fn close_unwatches_and_stops_readers() {
+    use std::io::Read;
+    use mock::MockTransport;
+    use client::GpsdClient;
+    let mock = MockTransport::new();
+    mock.push_line(VERSION_LINE);
+    let mut conn = GpsdConnection::from_transport(mock.clone()).unwrap();
+    conn.get_response().unwrap();
+    conn.close().unwrap();
+    assert_eq!(mock.commands(), vec![r#"?WATCH={"enable":false};"#]);
+    // A close handle interrupts a read blocked on another thread, even with
+    // a reconnect policy set.
+    let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (mut s, _) = listener.accept().unwrap();
+        let mut got = String::new();
+        s.read_to_string(&mut got).unwrap();
+        got
+    });
+    let mut conn = GpsdConnection::new(addr).unwrap();
+    conn.set_reconnect(Some(ReconnectPolicy::default()));
+    let handle = conn.close_handle();
+    let rx = conn.spawn_channel(1);
+    handle.close();
+    assert!(rx.recv().is_err());
+    assert_eq!(server.join().unwrap(), "?WATCH={\"enable\":false};\n");
+    // Dropping a running client stops and joins its reader.
+    let mock = MockTransport::new();
+    let mut client = GpsdClient::new(GpsdConnection::from_transport(mock.clone()).unwrap());
+    let errors = ::std::sync::Arc::new(::std::sync::Mutex::new(0));
+    let e = errors.clone();
+    client.on_read_error(move |_| *e.lock().unwrap() += 1);
+    client.start();
+    drop(client);
+    assert_eq!(mock.commands(), vec![r#"?WATCH={"enable":false};"#]);
+    assert_eq!(*errors.lock().unwrap(), 0);
+}
+#[test]
+fn connection_events_track_link_state() {
+    use std::sync::{Arc, Mutex};
+    use events::ConnectionEvent;
+    use reconnect::ReconnectPolicy;
+    let addr = fake_gpsd_sessions(&[&[VERSION_LINE, r#"{"class":"WATCH","enable":true,"json":true}"#], &[TPV_LINE]]);
+    let mut conn = GpsdConnection::new(addr).unwrap();
+    conn.set_reconnect(Some(ReconnectPolicy {
+        initial_delay: Duration::from_millis(10),
+        jitter: 0.0,
+        max_retries: Some(1),
+        ..Default::default()
+    }));
+    let events = Arc::new(Mutex::new(vec![]));
+    let ev = events.clone();
+    conn.on_connection_event(move |e| ev.lock().unwrap().push(e.clone()));
+    assert_eq!(conn.iter().filter(|r| r.is_ok()).count(), 3);
+    let events = events.lock().unwrap();
+    assert_eq!(events[..5], [
+        ConnectionEvent::Connected,
+        ConnectionEvent::VersionReceived { release: "3.17".into(), proto_major: 3, proto_minor: 12 },
+        ConnectionEvent::WatchEstablished,
+        ConnectionEvent::Disconnected("gpsd connection closed".into()),
+        ConnectionEvent::Reconnecting { attempt: 1, delay: Duration::from_millis(10) }
+    ]);
+    assert_eq!(events[5], ConnectionEvent::Connected);
+    // The second session ends, and reconnecting to it fails.
+    assert!(matches!(events[events.len() - 2..], [ConnectionEvent::Disconnected(..), ConnectionEvent::Reconnecting { .. }]));
+}
+#[test]
+fn client_keeps_latest_reports() {
+    use client::GpsdClient;
+    use mock::MockTransport;
+    let mock = MockTransport::new();
+    mock.push_line(TPV_LINE);
+    mock.push_line(r#"{"class":"SKY","device":"/dev/ttyUSB1","satellites":[]}"#);
+    mock.push_line(r#"{"class":"TPV","mode":1,"time":"2017-07-01T12:00:01.000Z"}"#);
+    mock.close();
+    let mut client = GpsdClient::new(GpsdConnection::from_transport(mock).unwrap());
+    let latest = client.latest();
+    assert!(client.latest_fix().is_none());
+    client.start();
+    client.join().unwrap();
+    // Untagged reports count as the latest from "any" device only.
+    assert!(matches!(latest.latest_fix(), Some(TpvResponse::NoFix { .. })));
+    assert!(matches!(latest.latest_fix_for("/dev/ttyUSB0"), Some(TpvResponse::Fix3D { .. })));
+    assert_eq!(latest.latest_sky().unwrap().device.as_deref(), Some("/dev/ttyUSB1"));
+    assert!(latest.latest_sky_for("/dev/ttyUSB0").is_none());
+    let mut devices = latest.devices();
+    devices.sort();
+    assert_eq!(devices, vec!["/dev/ttyUSB0", "/dev/ttyUSB1"]);
+}
+#[test]
+fn resyncs_after_garbage_lines() {
+    use mock::MockTransport;
+    let mock = MockTransport::new();
+    mock.push_bytes(b"\xff\xfe{\"class\":\"TP\n");
+    mock.push_line(r#"{"class":"TPV","device":"/dev/ttyUSB0","mo{"class":"SKY"}"#);
+    mock.push_line(TPV_LINE);
+    mock.close();
+    let mut conn = GpsdConnection::from_transport(mock).unwrap();
+    let mut raw = vec![];
+    for _ in 0..2 {
+        match conn.get_response() {
+            Err(GpsdError::Json { line, .. }) => raw.push(line),
+            x => panic!("unexpected response: {:?}", x)
+        }
+    }
+    assert_eq!(raw[0], "\u{fffd}\u{fffd}{\"class\":\"TP\n");
+    assert!(raw[1].ends_with("\"SKY\"}\n"));
+    assert!(matches!(conn.get_response(), Ok(Response::Tpv(..))));
+}
+#[test]
+fn buffer_overflow_policies() {
+    use buffer::{bounded, OverflowPolicy};
+    let (tx, rx) = bounded(2, OverflowPolicy::DropOldest);
+    (1..=4).for_each(|i| tx.send(i).unwrap());
+    assert_eq!((rx.try_recv(), rx.try_recv(), rx.try_recv()), (Some(3), Some(4), None));
+    let stats = rx.stats();
+    assert_eq!((stats.accepted, stats.dropped, stats.high_water, stats.len), (4, 2, 2, 0));
+    let (tx, rx) = bounded(2, OverflowPolicy::DropNewest);
+    (1..=4).for_each(|i| tx.send(i).unwrap());
+    drop(tx);
+    assert_eq!(rx.iter().collect::<Vec<_>>(), vec![1, 2]);
+    assert_eq!(rx.stats().dropped, 2);
+    // Blocking waits for the consumer, and gives up if it goes away.
+    let (tx, rx) = bounded(1, OverflowPolicy::Block);
+    let sender = thread::spawn(move || {
+        let res = (1..=3).map(|i| tx.send(i)).collect::<Vec<_>>();
+        (res, tx.stats().blocked)
+    });
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Some(1));
+    assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Some(2));
+    thread::sleep(Duration::from_millis(50));
+    drop(rx);
+    let (res, blocked) = sender.join().unwrap();
+    assert_eq!(res, vec![Ok(()), Ok(()), Ok(())]);
+    assert!(blocked >= 1);
+    // And a buffered reader drops stale reports rather than stalling.
+    let mock = mock::MockTransport::new();
+    for _ in 0..5 {
+        mock.push_line(TPV_LINE);
+    }
+    mock.push_line(VERSION_LINE);
+    mock.close();
+    let rx = GpsdConnection::from_transport(mock).unwrap().spawn_buffered(1, OverflowPolicy::DropOldest);
+    while !rx.is_disconnected() {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(matches!(rx.recv(), Some(Response::Version { .. })));
+    assert_eq!(rx.stats().dropped, 5);
+}
+#[test]
+fn command_handle_sends_while_reading() {
+    use mock::MockTransport;
+    let mock = MockTransport::new();
+    let conn = GpsdConnection::from_transport(mock.clone()).unwrap();
+    let commands = conn.command_handle();
+    let rx = conn.spawn_channel(4);
+    // The reader is blocked with nothing to read, but commands still go out.
+    thread::sleep(Duration::from_millis(20));
+    let c = commands.clone();
+    thread::spawn(move || c.watch(true).unwrap()).join().unwrap();
+    commands.poll().unwrap();
+    assert_eq!(mock.commands(), vec![
+        r#"?WATCH={"class":"WATCH","enable":true,"json":true,"raw":0}"#.to_owned(),
+        "?POLL;".to_owned()
+    ]);
+    mock.push_line(TPV_LINE);
+    assert!(matches!(rx.recv_timeout(Duration::from_secs(5)), Ok(Response::Tpv(..))));
+    // RAW mode asked for through a handle applies to the reader.
+    commands.watch_raw(true, false, RawLevel::Hexdump).unwrap();
+    mock.push_line("$GPGGA,raw");
+    assert!(matches!(rx.recv_timeout(Duration::from_secs(5)), Ok(Response::Raw(..))));
+}
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn async_timeouts_keep_partial_lines() {
+    use asynchronous::AsyncGpsdConnection;
+    use tokio::io::AsyncWriteExt;
+    let (ours, mut theirs) = tokio::io::duplex(1024);
+    let mut conn = AsyncGpsdConnection::from_transport(ours);
+    let (head, tail) = TPV_LINE.split_at(40);
+    theirs.write_all(head.as_bytes()).await.unwrap();
+    match conn.next_response_timeout(Duration::from_millis(20)).await {
+        Err(GpsdError::Timeout(..)) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+    theirs.write_all(tail.as_bytes()).await.unwrap();
+    theirs.write_all(b"\n").await.unwrap();
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+    assert!(matches!(conn.next_response_before(deadline).await, Ok(Response::Tpv(..))));
+}
+#[test]
+fn watch_restored_after_reconnect() {
+    use std::sync::{Arc, Mutex};
+    use mock::MockTransport;
+    use reconnect::{ReconnectEvent, ReconnectPolicy};
+    let sessions = (0..3).map(|_| MockTransport::new()).collect::<Vec<_>>();
+    sessions[0].push_line(TPV_LINE);
+    sessions[1].push_line(r#"{"class":"WATCH","enable":true,"json":true,"nmea":false,"raw":0}"#);
+    sessions[2].push_line(r#"{"class":"WATCH","enable":false,"json":false}"#);
+    sessions.iter().for_each(|s| s.close());
+    let mut pending = sessions.clone().into_iter();
+    let mut conn = GpsdConnection::with_transport(move || {
+        pending.next().ok_or_else(|| ::std::io::Error::from(::std::io::ErrorKind::ConnectionRefused))
+    }).unwrap();
+    conn.set_reconnect(Some(ReconnectPolicy {
+        initial_delay: Duration::from_millis(1),
+        max_retries: Some(1),
+        ..Default::default()
+    }));
+    let events = Arc::new(Mutex::new(vec![]));
+    let ev = events.clone();
+    conn.on_reconnect(move |e| ev.lock().unwrap().push(e.clone()));
+    conn.watch(true).unwrap();
+    let watch = r#"?WATCH={"class":"WATCH","enable":true,"json":true,"raw":0}"#;
+    assert_eq!(conn.iter().filter(|r| matches!(r, Ok(Response::Watch(..)))).count(), 2);
+    assert!(sessions.iter().all(|s| s.commands() == vec![watch]));
+    let events = events.lock().unwrap();
+    assert!(events.contains(&ReconnectEvent::WatchRestored));
+    assert!(events.iter().any(|e| matches!(e, ReconnectEvent::WatchMismatch { reply } if !reply.enable)));
+}
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn task_sends_commands_and_reads() {
+    use asynchronous::AsyncGpsdConnection;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    let (ours, theirs) = tokio::io::duplex(1024);
+    let mut task = task::spawn(AsyncGpsdConnection::from_transport(ours), 4);
+    task.commands().send(types::Command::Version).await.unwrap();
+    let mut theirs = BufReader::new(theirs);
+    let mut line = String::new();
+    theirs.read_line(&mut line).await.unwrap();
+    assert_eq!(line, "?VERSION;\n");
+    theirs.get_mut().write_all(format!("{}\n", VERSION_LINE).as_bytes()).await.unwrap();
+    assert!(matches!(task.recv().await, Some(Response::Version { .. })));
+    drop(theirs);
+    assert!(task.recv().await.is_none());
+    task.join().await.unwrap();
+}
+#[test]
+fn request_waits_for_reply() {
+    use mock::MockTransport;
+    use types::{Command, DeviceSettings, DriverMode};
+    let mock = MockTransport::new();
+    mock.push_line(TPV_LINE);
+    mock.push_line(r#"{"class":"DEVICE","path":"/dev/ttyACM0","activated":"2017-07-01T12:00:00.000Z"}"#);
+    mock.push_line(r#"{"class":"DEVICE","path":"/dev/ttyUSB0","activated":"2017-07-01T12:00:00.000Z","native":1}"#);
+    mock.push_line(r#"{"class":"ERROR","message":"Can't perform DEVICE configuration, device not found."}"#);
+    mock.close();
+    let mut conn = GpsdConnection::from_transport(mock.clone()).unwrap();
+    let settings = DeviceSettings { path: Some("/dev/ttyUSB0".into()), native: Some(DriverMode::Native), ..Default::default() };
+    match conn.request(&Command::Device(settings.clone())).unwrap() {
+        Response::Device(dev) => assert_eq!(dev.path(), Some("/dev/ttyUSB0")),
+        x => panic!("unexpected reply: {:?}", x)
+    }
+    // Without a path, any DEVICE report is taken for the reply.
+    let other = Response::Device(serde_json::from_str(r#"{"class":"DEVICE","path":"/dev/ttyACM0"}"#).unwrap());
+    assert!(!Command::Device(settings.clone()).is_reply(&other));
+    assert!(Command::Device(DeviceSettings::default()).is_reply(&other));
+    match conn.request(&Command::Device(settings)) {
+        Err(GpsdError::ServerError { message }) => assert!(message.starts_with("Can't perform DEVICE")),
+        x => panic!("unexpected reply: {:?}", x)
+    }
+    assert_eq!(mock.commands()[0], r#"?DEVICE={"path":"/dev/ttyUSB0","native":1}"#);
+    // The TPV and the other device's report that arrived first are still there.
+    assert!(matches!(conn.get_response(), Ok(Response::Tpv(..))));
+    assert!(matches!(conn.get_response(), Ok(Response::Device(ref d)) if d.path() == Some("/dev/ttyACM0")));
+    assert!(conn.request(&Command::Raw("?FOO;".into())).is_err());
+}
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn task_requests_resolve_with_replies() {
+    use asynchronous::AsyncGpsdConnection;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    let (ours, theirs) = tokio::io::duplex(1024);
+    let mut task = task::spawn(AsyncGpsdConnection::from_transport(ours), 4);
+    let commands = task.commands().clone();
+    let req = tokio::spawn(async move { commands.request(types::Command::Version).await });
+    let mut theirs = BufReader::new(theirs);
+    let mut line = String::new();
+    theirs.read_line(&mut line).await.unwrap();
+    assert_eq!(line, "?VERSION;\n");
+    theirs.get_mut().write_all(format!("{}\n{}\n", TPV_LINE, VERSION_LINE).as_bytes()).await.unwrap();
+    assert!(matches!(req.await.unwrap(), Ok(Response::Version { .. })));
+    // Only the TPV goes to the stream.
+    assert!(matches!(task.recv().await, Some(Response::Tpv(..))));
+    drop(theirs);
+    assert!(task.recv().await.is_none());
+}
+#[cfg(feature = "codec")]
+#[test]
+fn codec_frames_lines() {
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+    use codec::GpsdCodec;
+    use types::Command;
+    let mut codec = GpsdCodec::new().with_max_line_length(300);
+    let mut buf = BytesMut::new();
+    buf.extend_from_slice(format!("{}\n\ngarbage\n{}", TPV_LINE, &TPV_LINE[..20]).as_bytes());
+    assert!(matches!(codec.decode(&mut buf).unwrap(), Some(Ok(Response::Tpv(..)))));
+    assert!(matches!(codec.decode(&mut buf).unwrap(), Some(Err(GpsdError::Json { .. }))));
+    assert!(codec.decode(&mut buf).unwrap().is_none());
+    buf.extend_from_slice(format!("{}\n", &TPV_LINE[20..]).as_bytes());
+    assert!(matches!(codec.decode(&mut buf).unwrap(), Some(Ok(Response::Tpv(..)))));
+    // An overlong line is reported once, and skipped up to its newline.
+    buf.extend_from_slice(&[b'x'; 400]);
+    assert!(matches!(codec.decode(&mut buf).unwrap(), Some(Err(..))));
+    buf.extend_from_slice(format!("yyy\n{}\n", VERSION_LINE).as_bytes());
+    assert!(matches!(codec.decode(&mut buf).unwrap(), Some(Ok(Response::Version { .. }))));
+    // Encoding a raw watch switches on raw decoding.
+    let mut out = BytesMut::new();
+    codec.encode(Command::Watch(WatchObject { raw: Some(RawLevel::Hexdump), ..Default::default() }), &mut out).unwrap();
+    assert!(out.starts_with(b"?WATCH={"));
+    buf.extend_from_slice(b"$GPGGA");
+    assert!(matches!(codec.decode_eof(&mut buf).unwrap(), Some(Ok(Response::Raw(ref s))) if s == "$GPGGA"));
+}
+#[test]
+fn stats_count_traffic() {
+    use mock::MockTransport;
+    let mock = MockTransport::new();
+    mock.push_line(VERSION_LINE);
+    mock.push_line(TPV_LINE);
+    mock.push_line(TPV_LINE);
+    mock.push_line("not json");
+    mock.close();
+    let mut conn = GpsdConnection::from_transport(mock.clone()).unwrap();
+    conn.version().unwrap();
+    conn.command_handle().poll().unwrap();
+    while conn.get_response().map(|_| true).unwrap_or_else(|e| matches!(e, GpsdError::Json { .. })) {}
+    let stats = conn.stats();
+    assert_eq!(stats.bytes_read as usize, VERSION_LINE.len() + 2 * TPV_LINE.len() + "not json".len() + 4);
+    assert_eq!(stats.bytes_written as usize, mock.written().len());
+    assert_eq!((stats.messages["TPV"], stats.messages["VERSION"], stats.total_messages()), (2, 1, 3));
+    assert_eq!((stats.parse_errors, stats.reconnects), (1, 0));
+}
+#[test]
+fn gpsd_urls() {
+    use std::io::{BufRead, BufReader};
+    use builder::parse_url;
+    let parsed = |u: &str| parse_url(u).ok();
+    assert_eq!(parsed("gpsd://boat:3000/dev/ttyACM0"), Some(("boat".into(), 3000, Some("/dev/ttyACM0".into()))));
+    assert_eq!(parsed("GPSD://boat/"), Some(("boat".into(), 2947, None)));
+    assert_eq!(parsed("gpsd://[::1]:2948"), Some(("::1".into(), 2948, None)));
+    assert_eq!(parsed("gpsd:///dev/gps0"), Some(("localhost".into(), 2947, Some("/dev/gps0".into()))));
+    for bad in &["boat:2947", "http://boat", "gpsd://boat:port", "gpsd://[::1", "gpsd://[::1]x", "gpsd://::1"] {
+        assert!(parse_url(bad).is_err(), "{} parsed", bad);
+    }
+    let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("gpsd://127.0.0.1:{}/dev/ttyACM0", listener.local_addr().unwrap().port());
+    let server = thread::spawn(move || {
+        let (s, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(s).read_line(&mut line).unwrap();
+        line
+    });
+    let _conn = GpsdConnection::connect_url(&url).unwrap();
+    let sent = server.join().unwrap();
+    let watch: types::WatchObject = serde_json::from_str(sent["?WATCH=".len()..].trim_end().trim_end_matches(';')).unwrap();
+    assert!(watch.enable && watch.json);
+    assert_eq!(watch.device.as_deref(), Some("/dev/ttyACM0"));
+}
+#[test]
+fn fix_modes_are_typed() {
+    use types::{FixMode, TpvResponse};
+    match parse_line(TPV_LINE.into(), false) {
+        Ok(Response::Tpv(ref tpv @ TpvResponse::Fix3D { mode: FixMode::Fix3d, .. })) => {
+            assert!(tpv.mode().unwrap() >= FixMode::Fix2d);
+            assert!(serde_json::to_string(tpv).unwrap().contains(r#""mode":3"#));
+        },
+        x => panic!("unexpected result: {:?}", x)
+    }
+    match parse_line(r#"{"class":"TPV","mode":0}"#.into(), false) {
+        Ok(Response::Tpv(tpv)) => assert_eq!(tpv.mode(), Some(FixMode::Unknown)),
+        x => panic!("unexpected result: {:?}", x)
+    }
+    assert!(parse_line(TPV_LINE.replace(r#""mode":3"#, r#""mode":7"#), false).is_err());
+}
+#[test]
+fn device_flags_round_trip() {
+    use types::{DeviceFlags, DeviceObject};
+    let line = r#"{"class":"DEVICE","path":"/dev/ttyUSB0","activated":"2017-07-01T12:00:00.000Z","flags":17,"driver":"u-blox"}"#;
+    match parse_line(line.into(), false) {
+        Ok(Response::Device(ref dev @ DeviceObject::ActiveSeenPackets { flags, .. })) => {
+            assert!(flags.contains(DeviceFlags::GPS) && !flags.intersects(DeviceFlags::RTCM2 | DeviceFlags::AIS));
+            assert_eq!(flags.bits(), 0x11);
+            assert!(serde_json::to_string(dev).unwrap().contains(r#""flags":17"#));
+        },
+        x => panic!("unexpected result: {:?}", x)
+    }
+}
+#[test]
+fn tpv_accessors() {
+    let tpv = match parse_line(TPV_LINE.into(), false) {
+        Ok(Response::Tpv(tpv)) => tpv,
+        x => panic!("unexpected result: {:?}", x)
+    };
+    assert_eq!((tpv.lat(), tpv.lon(), tpv.alt()), (Some(59.345), Some(18.054), Some(78.2)));
+    assert_eq!((tpv.speed(), tpv.track(), tpv.climb()), (Some(0.06), Some(320.9), Some(0.1)));
+    assert!(tpv.time().is_some());
+    let nofix = match parse_line(r#"{"class":"TPV","mode":1,"time":"2017-07-01T12:00:01.000Z"}"#.into(), false) {
+        Ok(Response::Tpv(tpv)) => tpv,
+        x => panic!("unexpected result: {:?}", x)
+    };
+    assert_eq!((nofix.lat(), nofix.speed(), nofix.alt()), (None, None, None));
+}
+#[test]
+fn tpv_flattens_into_fix() {
+    let fix = match parse_line(TPV_LINE.into(), false) {
+        Ok(Response::Tpv(tpv)) => tpv.into_fix().unwrap(),
+        x => panic!("unexpected result: {:?}", x)
+    };
+    assert_eq!((fix.lat, fix.lon, fix.alt, fix.time_err), (59.345, 18.054, Some(78.2), Some(0.005)));
+    assert_eq!(fix.device.as_deref(), Some("/dev/ttyUSB0"));
+    match parse_line(r#"{"class":"TPV","mode":1,"time":"2017-07-01T12:00:01.000Z"}"#.into(), false) {
+        Ok(Response::Tpv(tpv)) => assert!(tpv.into_fix().is_none()),
+        x => panic!("unexpected result: {:?}", x)
+    }
+}
+#[test]
+fn responses_round_trip() {
+    let lines = [
+        VERSION_LINE,
+        TPV_LINE,
+        r#"{"class":"TPV","device":"/dev/ttyUSB0","mode":2,"time":"2017-07-01T12:00:00.000Z","ept":0.005,"lat":59.0,"lon":18.0,"speed":1.5}"#,
+        r#"{"class":"TPV","mode":1,"time":"2017-07-01T12:00:01.000Z"}"#,
+        r#"{"class":"TPV","mode":0}"#,
+        r#"{"class":"SKY","device":"/dev/ttyUSB0","hdop":1.2,"satellites":[{"PRN":5,"az":120,"el":45,"ss":38,"used":true}]}"#,
+        r#"{"class":"DEVICE","path":"/dev/ttyUSB0","activated":"2017-07-01T12:00:00.000Z","flags":1,"driver":"u-blox","bps":9600}"#,
+        r#"{"class":"DEVICE","path":"/dev/ttyUSB1","activated":"2017-07-01T12:00:00.000Z"}"#,
+        r#"{"class":"DEVICE","path":"/dev/ttyUSB2"}"#,
+        r#"{"class":"WATCH","enable":true,"json":true,"device":"/dev/ttyUSB0"}"#,
+        r#"{"class":"ERROR","message":"Unrecognized request"}"#
+    ];
+    for line in &lines {
+        let resp = parse_line((*line).into(), false).unwrap();
+        let json = serde_json::to_string(&resp).unwrap();
+        let again = parse_line(json.clone(), false).unwrap();
+        assert_eq!(resp, again, "{} changed to {}", line, json);
+        assert_eq!(resp.clone(), resp);
+    }
+}
+#[test]
+fn device_activity() {
+    use types::DeviceObject;
+    let dev = |line: &str| match parse_line(line.into(), false) {
+        Ok(Response::Device(dev)) => dev,
+        x => panic!("unexpected result: {:?}", x)
+    };
+    let active: DeviceObject = dev(r#"{"class":"DEVICE","path":"/dev/ttyUSB1","activated":"2017-07-01T12:00:00.000Z"}"#);
+    let inactive = dev(r#"{"class":"DEVICE","path":"/dev/ttyUSB2"}"#);
+    assert!(active.is_active() && !inactive.is_active());
+    assert_eq!(inactive.path(), Some("/dev/ttyUSB2"));
+}
+#[test]
+fn reports_display_as_one_liners() {
+    let show = |line: &str| parse_line(line.into(), false).unwrap().to_string();
+    assert_eq!(show(TPV_LINE), "/dev/ttyUSB0 3D fix 2017-07-01T12:00:00.000Z 59.345000,18.054000 alt 78.2m speed 0.06m/s track 320.9°");
+    assert_eq!(show(r#"{"class":"TPV","mode":1,"time":"2017-07-01T12:00:01.000Z"}"#), "- no fix 2017-07-01T12:00:01.000Z");
+    assert_eq!(show(r#"{"class":"SKY","device":"/dev/ttyUSB0","hdop":1.2,"satellites":[{"PRN":5,"az":120,"el":45,"ss":38,"used":true},{"PRN":7,"az":10,"el":5,"ss":12,"used":false}]}"#),
+               "/dev/ttyUSB0 1/2 satellites used hdop 1.2");
+    assert_eq!(show(r#"{"class":"DEVICE","path":"/dev/ttyUSB0","activated":"2017-07-01T12:00:00.000Z","flags":1,"driver":"u-blox","bps":9600}"#),
+               "/dev/ttyUSB0 u-blox 9600 bps active");
+    assert_eq!(show(r#"{"class":"DEVICE","path":"/dev/ttyUSB2"}"#), "/dev/ttyUSB2 inactive");
+    assert_eq!(show(VERSION_LINE), "gpsd 3.17 (rev 3.17, protocol 3.12)");
+}
+#[test]
+fn parity_is_typed() {
+    use types::{DeviceObject, DeviceSettings, Parity};
+    let line = r#"{"class":"DEVICE","path":"/dev/ttyUSB0","activated":"2017-07-01T12:00:00.000Z","parity":"E"}"#;
+    match parse_line(line.into(), false) {
+        Ok(Response::Device(DeviceObject::Active { parity: Some(Parity::Even), .. })) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+    assert_eq!("odd".parse::<Parity>().unwrap(), Parity::Odd);
+    assert_eq!(serde_json::from_str::<Parity>(r#""n""#).unwrap(), Parity::None);
+    assert!(serde_json::from_str::<Parity>(r#""X""#).is_err());
+    let settings = DeviceSettings { parity: Some(Parity::Odd), ..Default::default() };
+    assert_eq!(serde_json::to_string(&settings).unwrap(), r#"{"parity":"O"}"#);
+}
+#[test]
+fn stop_bits_are_typed() {
+    use types::{DeviceObject, DeviceSettings, StopBits};
+    let line = r#"{"class":"DEVICE","path":"/dev/ttyUSB0","activated":"2017-07-01T12:00:00.000Z","stopbits":1}"#;
+    match parse_line(line.into(), false) {
+        Ok(Response::Device(DeviceObject::Active { stopbits: Some(StopBits::One), .. })) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+    assert_eq!(serde_json::from_str::<StopBits>(r#""2""#).unwrap(), StopBits::Two);
+    assert!(serde_json::from_str::<StopBits>("3").is_err());
+    assert!(serde_json::from_str::<StopBits>(r#""two""#).is_err());
+    let settings = DeviceSettings { stopbits: Some(StopBits::Two), ..Default::default() };
+    assert_eq!(serde_json::to_string(&settings).unwrap(), r#"{"stopbits":2}"#);
+}
+#[test]
+fn driver_modes_are_typed() {
+    use types::{DeviceObject, DriverMode};
+    let line = r#"{"class":"DEVICE","path":"/dev/ttyUSB0","activated":"2017-07-01T12:00:00.000Z","native":0}"#;
+    match parse_line(line.into(), false) {
+        Ok(Response::Device(DeviceObject::Active { native: Some(DriverMode::Nmea), .. })) => {},
+        x => panic!("unexpected result: {:?}", x)
+    }
+    assert!(serde_json::from_str::<DriverMode>("2").is_err());
+    assert_eq!(serde_json::to_string(&DriverMode::Native).unwrap(), "1");
+}
+#[test]
+fn sky_dops_keep_full_precision() {
+    match parse_line(r#"{"class":"SKY","hdop":1.23456789012,"pdop":2.5,"satellites":[]}"#.into(), false) {
+        Ok(Response::Sky(sky)) => assert_eq!((sky.hdop, sky.pdop, sky.vdop), (Some(1.23456789012), Some(2.5), None)),
+        x => panic!("unexpected result: {:?}", x)
+    }
+}
+#[test]
+fn satellites_know_their_constellation() {
+    use types::Constellation;
+    let line = r#"{"class":"SKY","satellites":[
+        {"PRN":5,"az":120,"el":45,"ss":38,"used":true},
+        {"PRN":70,"az":10,"el":5,"ss":12,"used":false},
+        {"PRN":133,"az":10,"el":5,"ss":12,"used":false},
+        {"PRN":305,"az":10,"el":5,"ss":12,"used":false},
+        {"PRN":5,"az":10,"el":5,"ss":12,"used":false,"gnssid":3},
+        {"PRN":400,"az":10,"el":5,"ss":12,"used":false}]}"#;
+    let sky = match parse_line(line.replace('\n', ""), false) {
+        Ok(Response::Sky(sky)) => sky,
+        x => panic!("unexpected result: {:?}", x)
+    };
+    let got = sky.satellites.iter().map(|s| s.constellation()).collect::<Vec<_>>();
+    assert_eq!(got, vec![Constellation::Gps, Constellation::Glonass, Constellation::Sbas,
+                         Constellation::Galileo, Constellation::BeiDou, Constellation::Unknown]);
+}
+#[test]
+fn fractional_signal_strengths() {
+    let line = r#"{"class":"SKY","satellites":[{"PRN":5,"az":120,"el":45,"ss":38,"used":true},{"PRN":7,"az":10,"el":5,"ss":27.5,"used":false}]}"#;
+    match parse_line(line.into(), false) {
+        Ok(Response::Sky(sky)) => {
+            let ss = sky.satellites.iter().map(|s| s.signal_strength).collect::<Vec<_>>();
+            assert_eq!(ss, vec![38.0, 27.5]);
+        },
+        x => panic!("unexpected result: {:?}", x)
+    }
+}
+#[test]
+fn borrowed_parse_avoids_copies() {
+    use std::borrow::Cow;
+    use borrowed::{self, ResponseRef};
+    use types::FixMode;
+    match borrowed::parse(TPV_LINE).unwrap() {
+        ResponseRef::Tpv(tpv) => {
+            assert!(matches!(tpv.device, Some(Cow::Borrowed("/dev/ttyUSB0"))));
+            assert_eq!((tpv.mode, tpv.lat, tpv.alt), (Some(FixMode::Fix3d), Some(59.345), Some(78.2)));
+            assert!(tpv.time.is_some());
+        },
+        x => panic!("unexpected result: {:?}", x)
+    }
+    match borrowed::parse(r#"{"class":"SKY","device":"\/dev\/ttyUSB0","hdop":1.5,"satellites":[]}"#).unwrap() {
+        ResponseRef::Sky(sky) => assert!(matches!(sky.device, Some(Cow::Owned(ref d)) if d == "/dev/ttyUSB0")),
+        x => panic!("unexpected result: {:?}", x)
+    }
+    assert_eq!(borrowed::parse(VERSION_LINE).unwrap(), ResponseRef::Other);
+    assert!(borrowed::parse("{").is_err());
+}
+#[test]
+fn unknown_fields_are_kept() {
+    let line = r#"{"class":"TPV","device":"/dev/ttyUSB0","mode":3,"time":"2017-11-22T10:22:43.000Z","ept":0.005,"lat":59.345,"lon":18.07,"alt":78.2,"track":10.0,"speed":0.5,"climb":0.1,"leapseconds":18,"geoidSep":24.1}"#;
+    let resp = parse_line(line.into(), false).unwrap();
+    match resp {
+        Response::Tpv(TpvResponse::Fix3D { ref extra, .. }) => {
+            assert_eq!(extra.len(), 2);
+            assert_eq!(extra["leapseconds"], 18);
+            assert_eq!(extra["geoidSep"], 24.1);
+        },
+        ref x => panic!("unexpected result: {:?}", x)
+    }
+    let json = serde_json::to_string(&resp).unwrap();
+    assert_eq!(serde_json::from_str::<Response>(&json).unwrap(), resp);
+    let fix = match resp {
+        Response::Tpv(tpv) => tpv.into_fix().unwrap(),
+        _ => unreachable!()
+    };
+    assert_eq!(fix.extra["leapseconds"], 18);
+    match parse_line(r#"{"class":"SKY","nSat":1,"satellites":[]}"#.into(), false) {
+        Ok(Response::Sky(sky)) => assert_eq!(sky.extra.keys().collect::<Vec<_>>(), vec!["nSat"]),
+        x => panic!("unexpected result: {:?}", x)
+    }
+}
+#[test]
+fn envelopes_keep_the_raw_line() {
+    use mock::MockTransport;
+    let line = r#"{"class":"TPV","mode":1,"time":"2017-11-22T10:22:43.000Z","lat":59.3450}"#;
+    let mock = MockTransport::new();
+    mock.push_bytes(format!("{}\r\n", line).as_bytes());
+    mock.push_line("not json");
+    mock.close();
+    let mut conn = GpsdConnection::from_transport(mock).unwrap();
+    let env = conn.get_envelope().unwrap();
+    assert_eq!(env.raw, line);
+    assert_eq!(env.parsed.class(), "TPV");
+    match conn.get_envelope() {
+        Err(GpsdError::Json { ref line, .. }) => assert_eq!(line, "not json\n"),
+        x => panic!("unexpected result: {:?}", x)
+    }
+}
+#[test]
+fn device_paths_compare_normalized() {
+    use latest::LatestState;
+    use types::DevicePath;
+    let dev = DevicePath::new("/dev/ttyUSB0");
+    assert!(dev.matches("/dev//ttyUSB0/") && dev.matches("/dev/./ttyUSB0"));
+    assert!(!dev.matches("/dev/ttyUSB1") && dev != "/dev//ttyUSB0");
+    assert_eq!(DevicePath::from("tcp://host//x").normalized(), "tcp://host//x");
+    assert_eq!(DevicePath::from("///").normalized(), "/");
+    #[cfg(unix)]
+    {
+        let dir = ::std::env::temp_dir().join(format!("gpsd-devpath-{}", ::std::process::id()));
+        ::std::fs::create_dir_all(&dir).unwrap();
+        let (file, link) = (dir.join("ttyFAKE"), dir.join("by-id"));
+        ::std::fs::write(&file, b"").unwrap();
+        let _ = ::std::fs::remove_file(&link);
+        ::std::os::unix::fs::symlink(&file, &link).unwrap();
+        let dev = DevicePath::new(link.to_str().unwrap());
+        assert!(dev.same_device(file.to_str().unwrap()) && !dev.matches(file.to_str().unwrap()));
+        ::std::fs::remove_dir_all(&dir).unwrap();
+    }
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    assert_eq!(tpv.device(), Some("/dev/ttyUSB0"));
+    let state = LatestState::new();
+    state.update(&Response::Tpv(tpv));
+    assert!(state.latest_fix_for("/dev//ttyUSB0").is_some());
+    let asked = WatchObject::default().with_device("/dev/ttyUSB0/");
+    let reply = WatchObject { device: Some("/dev/ttyUSB0".into()), ..asked.clone() };
+    assert!(watch_matches(&asked, &reply));
+}
+#[test]
+fn std_time_accessors() {
+    use std::time::{Duration, UNIX_EPOCH};
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    let t = tpv.system_time().unwrap();
+    assert_eq!(t.duration_since(UNIX_EPOCH).unwrap(), Duration::from_secs(1_498_910_400));
+    assert_eq!(tpv.time_error(), Some(Duration::from_millis(5)));
+    let fix = tpv.into_fix().unwrap();
+    assert_eq!((fix.system_time(), fix.time_error()), (t, Some(Duration::from_millis(5))));
+    let sky: SkyResponse = serde_json::from_str(r#"{"time":"2017-07-01T12:00:00.000Z","satellites":[]}"#).unwrap();
+    assert_eq!(sky.system_time(), Some(t));
+    let dustbin: TpvResponse = serde_json::from_str(r#"{"mode":0,"ept":-1}"#).unwrap();
+    assert_eq!(dustbin.time_error(), None);
+}
+#[test]
+fn strict_and_lenient_parsing() {
+    use mock::MockTransport;
+    let odd_tpv = r#"{"class":"TPV","mode":1,"time":"2017-07-01T12:00:00.000Z","leapseconds":18}"#;
+    let toff = r#"{"class":"TOFF","device":"/dev/ttyUSB0","real_sec":1498910400}"#;
+    let feed = || {
+        let mock = MockTransport::new();
+        for line in &[odd_tpv, toff, TPV_LINE] {
+            mock.push_line(line);
+        }
+        mock.close();
+        GpsdConnection::from_transport(mock).unwrap()
+    };
+    let mut conn = feed();
+    assert!(matches!(conn.get_response(), Ok(Response::Tpv(TpvResponse::NoFix { .. }))));
+    match conn.get_response() {
+        Ok(ref unknown @ Response::Unknown(ref data)) => {
+            assert_eq!(data["real_sec"], 1498910400);
+            // Serialized as it came, without a second class.
+            assert_eq!(serde_json::to_value(unknown).unwrap(), serde_json::from_str::<serde_json::Value>(toff).unwrap());
+            assert!(serde_json::from_str::<Response>(toff).is_err());
+        },
+        x => panic!("unexpected result: {:?}", x)
+    }
+    let mut conn = feed();
+    conn.set_parse_mode(ParseMode::Strict);
+    match conn.get_response() {
+        Err(GpsdError::Json { ref source, .. }) => assert!(source.to_string().contains("leapseconds")),
+        x => panic!("unexpected result: {:?}", x)
+    }
+    assert!(matches!(conn.get_response(), Err(GpsdError::Json { .. })));
+    assert!(matches!(conn.get_response(), Ok(Response::Tpv(..))));
+}
+#[test]
+fn standalone_line_parsing() {
+    use types::parse_lines;
+    let resp: Response = format!("  {}\r\n", TPV_LINE).parse().unwrap();
+    assert_eq!(resp, Response::from_json_line(TPV_LINE).unwrap());
+    assert!("{".parse::<Response>().is_err());
+    let log = format!("{}\n\n{}\nnot json\n{}", VERSION_LINE, TPV_LINE, TPV_LINE);
+    let parsed = parse_lines(log.as_bytes()).collect::<Vec<_>>();
+    assert_eq!(parsed.len(), 4);
+    assert!(matches!(parsed[0], Ok(Response::Version { .. })));
+    assert!(matches!(parsed[2], Err(GpsdError::Json { .. })));
+    assert!(matches!(parsed[3], Ok(Response::Tpv(..))));
+}
+#[test]
+fn tpv_round_trips_for_every_field_combination() {
+    let fields = [
+        r#""device":"/dev/ttyUSB0""#, r#""time":"2017-07-01T12:00:00.000Z""#, r#""mode":3"#, r#""ept":0.005"#,
+        r#""lat":59.345"#, r#""lon":18.054"#, r#""alt":78.2"#, r#""epv":3.1"#, r#""track":320.9"#,
+        r#""speed":0.06"#, r#""climb":0.1"#, r#""epc":0.2"#, r#""leapseconds":18"#
+    ];
+    for set in 0..1u32 << fields.len() {
+        let chosen = (0..fields.len()).filter(|i| set & 1 << i != 0).map(|i| fields[i]).collect::<Vec<_>>();
+        let line = format!(r#"{{"class":"TPV",{}}}"#, chosen.join(",")).replace("TPV\",}", "TPV\"}");
+        let resp = parse_line(line.clone(), false).unwrap();
+        let expected = if set & 1 << (fields.len() - 1) != 0 { vec!["leapseconds"] } else { vec![] };
+        assert_eq!(resp.unknown_fields(), expected, "{} lost fields: {:?}", line, resp);
+        let json = serde_json::to_string(&resp).unwrap();
+        assert_eq!(parse_line(json.clone(), false).unwrap(), resp, "{} changed to {}", line, json);
+    }
+    let odd = r#"{"class":"TPV","mode":1,"time":"2017-07-01T12:00:00.000Z","lat":59.345}"#;
+    assert!(matches!(parse_line(odd.into(), false), Ok(Response::Tpv(TpvResponse::Dustbin { lat: Some(_), .. }))));
+}
+#[test]
+fn satellites_sort_and_hash() {
+    use std::collections::{BTreeSet, HashSet};
+    let sky = |line: &str| match parse_line(line.into(), false) {
+        Ok(Response::Sky(sky)) => sky,
+        x => panic!("unexpected result: {:?}", x)
+    };
+    let before = sky(r#"{"class":"SKY","satellites":[{"PRN":7,"az":10,"el":5,"ss":27,"used":false},{"PRN":5,"az":120,"el":45,"ss":38,"used":true,"gnssid":0},{"PRN":5,"az":90,"el":30,"ss":30,"used":true,"gnssid":6}]}"#);
+    let after = sky(r#"{"class":"SKY","satellites":[{"PRN":5,"az":120,"el":45,"ss":38,"used":true,"gnssid":0},{"PRN":9,"az":200,"el":60,"ss":41.5,"used":true}]}"#);
+    let sorted = before.satellites.iter().cloned().collect::<BTreeSet<_>>();
+    assert_eq!(sorted.iter().map(|s| s.id()).collect::<Vec<_>>(), vec![(None, 7), (Some(0), 5), (Some(6), 5)]);
+    let (a, b) = (before.satellites.iter().collect::<HashSet<_>>(), after.satellites.iter().collect::<HashSet<_>>());
+    assert_eq!(a.intersection(&b).map(|s| s.id()).collect::<Vec<_>>(), vec![(Some(0), 5)]);
+    assert_eq!(before.used_satellites().count(), 2);
+    assert_eq!(after.used_satellites().map(|s| s.prn).collect::<Vec<_>>(), vec![5, 9]);
+}
+#[test]
+fn dops_from_the_satellite_view() {
+    let sky = |sats: &str, hdop: &str| match parse_line(format!(r#"{{"class":"SKY",{}"satellites":[{}]}}"#, hdop, sats), false) {
+        Ok(Response::Sky(sky)) => sky,
+        x => panic!("unexpected result: {:?}", x)
+    };
+    let sat = |prn, az, el, used| format!(r#"{{"PRN":{},"az":{},"el":{},"ss":30,"used":{}}}"#, prn, az, el, used);
+    let spread = [sat(1, 0, 90, true), sat(2, 0, 0, true), sat(3, 120, 0, true), sat(4, 240, 0, true)].join(",");
+    let dops = sky(&spread, "").computed_dops().unwrap();
+    assert!((dops.xdop - dops.ydop).abs() < 1e-9, "{:?}", dops);
+    assert!((dops.pdop.powi(2) - dops.hdop.powi(2) - dops.vdop.powi(2)).abs() < 1e-9);
+    assert!(dops.gdop > dops.pdop && dops.hdop > 0.0);
+    let mut reported = sky(&spread, r#""hdop":1.5,"#);
+    assert!(reported.fill_missing_dops());
+    assert_eq!((reported.hdop, reported.vdop), (Some(1.5), Some(dops.vdop)));
+    let unused = [sat(1, 0, 90, true), sat(2, 0, 0, true), sat(3, 120, 0, true), sat(4, 240, 0, false)].join(",");
+    assert!(!sky(&unused, "").fill_missing_dops());
+    let bunched = [sat(1, 45, 30, true), sat(2, 45, 30, true), sat(3, 45, 30, true), sat(4, 45, 30, true)].join(",");
+    assert!(sky(&bunched, "").computed_dops().is_none());
+}
+#[test]
+fn response_kinds() {
+    use std::collections::HashMap;
+    let lines = [TPV_LINE, TPV_LINE, r#"{"class":"TOFF","real_sec":1498910400}"#, r#"{"class":"ERROR","message":"oops"}"#];
+    let mut counts = HashMap::new();
+    for line in &lines {
+        *counts.entry(parse_line(line.to_string(), false).unwrap().kind()).or_insert(0) += 1;
+    }
+    assert_eq!((counts[&ResponseKind::Tpv], counts[&ResponseKind::Unknown], counts[&ResponseKind::Error]), (2, 1, 1));
+    let toff = parse_line(lines[2].to_string(), false).unwrap();
+    assert_eq!((toff.class(), toff.class_name()), ("UNKNOWN", "TOFF"));
+    assert_eq!(Response::Raw("$GPGGA".into()).class_name(), "RAW");
+    assert_eq!(ResponseKind::Sky.to_string(), "SKY");
+}
+#[test]
+fn raw_levels_are_typed() {
+    let watch: WatchObject = serde_json::from_str(r#"{"enable":true,"raw":2}"#).unwrap();
+    assert_eq!(watch.raw, Some(RawLevel::Verbatim));
+    assert!(serde_json::from_str::<WatchObject>(r#"{"enable":true,"raw":7}"#).is_err());
+    let json = serde_json::to_value(WatchObject::default().with_raw(RawLevel::Hexdump)).unwrap();
+    assert_eq!(json["raw"], 1);
+    assert!(RawLevel::Hexdump.is_on() && !RawLevel::default().is_on());
+    assert_eq!(watch_command(true, false, RawLevel::Verbatim), "?WATCH={\"class\":\"WATCH\",\"enable\":true,\"json\":false,\"raw\":2}\n");
+}
+#[test]
+fn tpv_positions() {
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    assert_eq!(tpv.position(), Position::new(59.345, 18.054, Some(78.2), None));
+    let newer: TpvResponse = serde_json::from_str(r#"{"mode":3,"time":"2017-07-01T12:00:00.000Z","ept":0.005,"lat":59.345,"lon":18.054,"alt":78.2,"altMSL":78.2,"altHAE":102.5,"speed":0.0,"climb":0.0}"#).unwrap();
+    assert_eq!(newer.position().unwrap().height_above_ellipsoid(), Some(102.5));
+    // Without altHAE, it is the height above sea level plus the separation.
+    let separated: TpvResponse = serde_json::from_str(r#"{"mode":3,"time":"2017-07-01T12:00:00.000Z","ept":0.005,"lat":59.345,"lon":18.054,"altMSL":78.2,"geoidSep":24.3,"speed":0.0,"climb":0.0}"#).unwrap();
+    assert_eq!(separated.position().unwrap().height_above_ellipsoid(), Some(78.2 + 24.3));
+    assert_eq!(tpv.position().unwrap().height_above_ellipsoid(), None);
+    let garbage: TpvResponse = serde_json::from_str(r#"{"mode":2,"time":"2017-07-01T12:00:00.000Z","ept":0.005,"lat":359.9,"lon":18.054,"speed":0.0}"#).unwrap();
+    assert!(garbage.lat().is_some() && garbage.position().is_none());
+    assert!(TpvResponse::default().position().is_none());
+    assert!(Position::new(0.0, -180.5, None, None).is_none());
+}
+#[test]
+fn renamed_tpv_fields() {
+    // gpsd 3.20 and later send altMSL, and may leave out alt.
+    let current: TpvResponse = serde_json::from_str(r#"{"mode":3,"time":"2017-07-01T12:00:00.000Z","ept":0.005,"lat":59.345,"lon":18.054,"altMSL":78.2,"altHAE":102.5,"speed":0.0,"climb":0.0}"#).unwrap();
+    assert!(matches!(current, TpvResponse::Fix3D { alt, .. } if alt == 78.2));
+    assert!(!current.extra().contains_key("altMSL") && current.extra().contains_key("altHAE"));
+    assert_eq!(current.position().and_then(|p| p.alt_msl), Some(78.2));
+    let json = serde_json::to_string(&current).unwrap();
+    assert!(json.contains(r#""alt":78.2"#) && !json.contains("altMSL"), "{}", json);
+    assert_eq!(serde_json::from_str::<TpvResponse>(&json).unwrap(), current);
+    let both: TpvResponse = serde_json::from_str(r#"{"mode":3,"time":"2017-07-01T12:00:00.000Z","ept":0.005,"lat":59.345,"lon":18.054,"alt":78.0,"altMSL":78.2,"speed":0.0,"climb":0.0}"#).unwrap();
+    assert_eq!(both.alt(), Some(78.0));
+    assert_eq!(both.extra().get("altMSL").and_then(serde_json::Value::as_f64), Some(78.2));
+    let old: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    assert_eq!(old.alt(), Some(78.2));
+}
+#[test]
+fn coordinates_are_range_checked() {
+    assert_eq!(Latitude::new(-90.0).map(Latitude::degrees), Ok(-90.0));
+    assert!(Latitude::new(90.5).is_err() && Latitude::new(f64::NAN).is_err());
+    assert!(Longitude::new(180.0).is_ok() && Longitude::new(-180.1).is_err());
+    assert_eq!(serde_json::from_str::<Latitude>("59.345").unwrap().degrees(), 59.345);
+    let err = serde_json::from_str::<Longitude>("999.0").unwrap_err();
+    assert!(err.to_string().contains("invalid longitude"), "{}", err);
+    let pos: Position = serde_json::from_str(r#"{"lat":59.345,"lon":18.054,"alt_msl":null,"alt_hae":null}"#).unwrap();
+    assert_eq!(serde_json::to_string(&pos).unwrap(), r#"{"lat":59.345,"lon":18.054,"alt_msl":null,"alt_hae":null,"geoid_sep":null}"#);
+    assert!(serde_json::from_str::<Position>(r#"{"lat":-91.0,"lon":18.054,"alt_msl":null,"alt_hae":null}"#).is_err());
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    assert_eq!(tpv.latitude().map(f64::from), Some(59.345));
+}
+#[test]
+fn unit_conversions() {
+    use units::{Length, Speed};
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+    let s = Speed::from_knots(10.0);
+    assert!(close(s.mps(), 5.144444444444445) && close(s.knots(), 10.0));
+    assert!(close(Speed::from_kmh(36.0).mps(), 10.0) && close(Speed::from_mps(10.0).kmh(), 36.0));
+    assert!(close(Speed::from_mph(60.0).kmh(), 96.56064) && close(Speed::from_mps(1.0).mph(), 2.2369362920544025));
+    assert!(close(Length::from_feet(1000.0).meters(), 304.8) && close(Length::from_meters(78.2).feet(), 256.56167979002623));
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    assert_eq!(tpv.ground_speed(), Some(Speed::from_mps(0.06)));
+    assert_eq!(tpv.altitude().map(|a| a.to_string()), Some("78.2 m".into()));
+    assert_eq!(tpv.climb_rate().unwrap().mps(), 0.1);
+}
+#[test]
+fn bearings_wrap_around() {
+    use units::Bearing;
+    assert_eq!(Bearing::new(-10.0).degrees(), 350.0);
+    assert_eq!(Bearing::new(720.0).degrees(), 0.0);
+    assert_eq!(Bearing::new(-1e-20).degrees(), 0.0);
+    assert_eq!(Bearing::new(10.0) - Bearing::new(350.0), 20.0);
+    assert_eq!(Bearing::new(350.0).difference(Bearing::new(10.0)), -20.0);
+    assert_eq!(Bearing::new(0.0) - Bearing::new(180.0), 180.0);
+    assert_eq!((Bearing::new(350.0) + 20.0).degrees(), 10.0);
+    assert_eq!(Bearing::new(90.0).reciprocal().degrees(), 270.0);
+    assert_eq!(serde_json::from_str::<Bearing>("-90").unwrap().degrees(), 270.0);
+    let tpv: TpvResponse = serde_json::from_str(r#"{"mode":2,"time":"2017-07-01T12:00:00.000Z","ept":0.005,"lat":59.0,"lon":18.0,"track":359.5,"magtrack":4.5,"speed":1.0}"#).unwrap();
+    assert_eq!(tpv.magnetic_course().unwrap() - tpv.course().unwrap(), 5.0);
+}
+#[test]
+fn error_estimates_know_their_units() {
+    use units::{ErrorEstimate, ErrorUnit};
+    let tpv: TpvResponse = serde_json::from_str(r#"{"mode":3,"time":"2017-07-01T12:00:00.000Z","ept":0.005,"lat":59.0,"epy":3.92,"lon":18.0,"epx":2.5,"alt":78.2,"epv":9.0,"speed":1.0,"eps":0.3,"climb":0.0}"#).unwrap();
+    let errors = tpv.errors();
+    assert_eq!(errors.time, Some(ErrorEstimate::new(0.005, ErrorUnit::Seconds)));
+    assert_eq!(errors.speed.map(|e| e.unit), Some(ErrorUnit::MetersPerSecond));
+    assert_eq!((errors.track, errors.climb), (None, None));
+    let lat = errors.lat.unwrap();
+    assert!((lat.one_sigma() - 2.0).abs() < 1e-3 && (lat.at_99_percent() - 5.152).abs() < 1e-3);
+    assert_eq!(lat.to_string(), "±3.92 m (95%)");
+    assert_eq!(TpvResponse::default().errors(), Default::default());
+}
+#[test]
+fn tolerant_timestamps() {
+    let expected = serde_json::from_str::<TpvResponse>(r#"{"mode":1,"time":"2017-07-01T12:00:00.000Z"}"#).unwrap().time();
+    let sent = [
+        r#""2017-07-01T12:00:00Z""#, r#""2017-07-01T12:00:00""#, r#""2017-07-01 12:00:00.000z""#,
+        r#""2017-07-01T14:00:00+02:00""#, r#""2017-07-01T13:00:00.000+0100""#, r#""2017-07-01T12:00:00.0000000000001Z""#,
+        r#""2017-07-01T12:00:00.Z""#, "1498910400", "1498910400.0"
+    ];
+    for t in &sent {
+        let tpv = serde_json::from_str::<TpvResponse>(&format!(r#"{{"mode":1,"time":{}}}"#, t)).unwrap();
+        assert_eq!(tpv.time(), expected, "{}", t);
+    }
+    let frac = serde_json::from_str::<TpvResponse>(r#"{"mode":1,"time":"2017-07-01T12:00:00.123456789123Z"}"#).unwrap();
+    assert_eq!(timestamp::elapsed(expected.unwrap(), frac.time().unwrap()), Some(Duration::from_nanos(123_456_789)));
+    for bad in &[r#""2017-07-01""#, r#""yesterday at noon""#, r#""2017-07-01X12:00:00Z""#] {
+        assert!(serde_json::from_str::<TpvResponse>(&format!(r#"{{"mode":1,"time":{}}}"#, bad)).is_err(), "{}", bad);
+    }
+}
+#[test]
+fn timestamps_stand_alone() {
+    #[cfg(any(feature = "chrono", feature = "time"))]
+    use std::convert::TryFrom;
+    use timestamp::Timestamp;
+    let t = timestamp::parse("2017-07-01T12:00:00.120Z").unwrap();
+    assert_eq!((t.to_string(), t.unix_nanos()), ("2017-07-01T12:00:00.120Z".to_owned(), 1_498_910_400_120_000_000));
+    assert_eq!(serde_json::to_string(&t).unwrap(), r#""2017-07-01T12:00:00.120Z""#);
+    assert_eq!(serde_json::from_str::<Timestamp>(r#""2017-07-01 14:00:00.12+02:00""#).unwrap(), t);
+    assert_eq!(timestamp::parse("2016-02-29T23:59:60Z"), timestamp::parse("2016-03-01T00:00:00Z"));
+    assert_eq!(Timestamp::from_unix_nanos(-1).unwrap().to_string(), "1969-12-31T23:59:59.999999999Z");
+    assert_eq!(Timestamp::from_unix_nanos(-62_167_219_200 * 1_000_000_000).unwrap().to_string(), "0000-01-01T00:00:00Z");
+    assert!(Timestamp::from_unix_nanos(253_402_300_800 * 1_000_000_000).is_none());
+    assert_eq!(timestamp::parse("1498910400.12"), Some(t));
+    // Too long for the nanoseconds to fit, though the seconds alone do.
+    let huge = "9".repeat(36);
+    for bad in &["2017-02-29T12:00:00Z", "2017-07-01T24:00:00Z", "2017-07-01T12:00:00+2:00", &huge] {
+        assert!(timestamp::parse(bad).is_none(), "{}", bad);
+    }
+    #[cfg(feature = "chrono")]
+    {
+        let dt = chrono::DateTime::<chrono::Utc>::from(t);
+        assert_eq!((dt.timestamp(), dt.timestamp_subsec_millis()), (1_498_910_400, 120));
+        assert_eq!(Timestamp::try_from(dt), Ok(t));
+        assert!(Timestamp::try_from(chrono::DateTime::<chrono::Utc>::MAX_UTC).is_err());
+    }
+    #[cfg(feature = "time")]
+    {
+        let odt = time::OffsetDateTime::from(t);
+        assert_eq!((odt.unix_timestamp(), odt.millisecond()), (1_498_910_400, 120));
+        assert_eq!(Timestamp::try_from(odt), Ok(t));
+    }
+}
+#[cfg(feature = "geo")]
+#[test]
+fn geo_conversions() {
+    use geo_types::{Coord, Point};
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    let point: Option<Point<f64>> = (&tpv).into();
+    assert_eq!(point, Some(Point::new(18.054, 59.345)));
+    let coord: Option<Coord<f64>> = (&TpvResponse::default()).into();
+    assert!(coord.is_none());
+    let fix = tpv.into_fix().unwrap();
+    assert_eq!(Coord::from(&fix), Coord { x: 18.054, y: 59.345 });
+}
+#[cfg(feature = "geojson")]
+#[test]
+fn geojson_output() {
+    use geojson::{feature, feature_collection, Track};
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    let point = feature(&tpv).unwrap();
+    assert_eq!(point["geometry"], json!({"type": "Point", "coordinates": [18.054, 59.345, 78.2]}));
+    assert_eq!(point["properties"]["time"], "2017-07-01T12:00:00.000Z");
+    assert_eq!((&point["properties"]["mode"], &point["properties"]["device"]), (&json!(3), &json!("/dev/ttyUSB0")));
+    let nofix = TpvResponse::default();
+    assert!(feature(&nofix).is_none());
+    assert_eq!(feature_collection(vec![&tpv, &nofix])["features"].as_array().unwrap().len(), 1);
+    let mut track = Track::new();
+    assert!(track.push(&tpv) && !track.push(&nofix));
+    assert!(track.to_feature()["geometry"].is_null());
+    let moved: TpvResponse = serde_json::from_str(r#"{"class":"TPV","mode":2,"time":"2017-07-01T12:00:01.000Z","ept":0.005,"lat":59.346,"lon":18.055,"speed":1.5}"#).unwrap();
+    track.extend(vec![&moved]);
+    let line = track.to_feature();
+    assert_eq!(line["geometry"]["coordinates"], json!([[18.054, 59.345, 78.2], [18.055, 59.346]]));
+    assert_eq!(line["properties"]["coordinateProperties"]["speed"], json!([0.06, 1.5]));
+    assert_eq!(line["properties"]["coordinateProperties"]["climb"], json!([0.1, null]));
+    assert_eq!(track.to_feature_collection()["features"][1], feature(&moved).unwrap());
+    let hae: TpvResponse = serde_json::from_str(&TPV_LINE.replace(r#""alt":78.2"#, r#""altMSL":78.2,"altHAE":102.5"#)).unwrap();
+    assert_eq!(feature(&hae).unwrap()["geometry"]["coordinates"], json!([18.054, 59.345, 102.5]));
+    let sep: TpvResponse = serde_json::from_str(&TPV_LINE.replace(r#""alt":78.2"#, r#""alt":78.2,"geoidSep":24.0"#)).unwrap();
+    assert_eq!(feature(&sep).unwrap()["geometry"]["coordinates"], json!([18.054, 59.345, 102.2]));
+}
+#[test]
+fn gpx_tracks() {
+    use gpx::GpxWriter;
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    let mut gpx = GpxWriter::new(Vec::new()).unwrap();
+    assert!(gpx.write_waypoint(&tpv, "Start & <end>").unwrap());
+    assert!(!gpx.write_tpv(&TpvResponse::default()).unwrap());
+    assert!(gpx.write_tpv(&tpv).unwrap());
+    gpx.new_segment().unwrap();
+    assert!(gpx.write_tpv(&tpv).unwrap());
+    assert_eq!(gpx.write_waypoint(&tpv, "late").unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(gpx.points(), 2);
+    let out = String::from_utf8(gpx.finish().unwrap()).unwrap();
+    assert!(out.contains("<name>Start &amp; &lt;end&gt;</name>"));
+    let trkpt = "<trkpt lat=\"59.345\" lon=\"18.054\"><ele>78.2</ele><time>2017-07-01T12:00:00.000Z</time><fix>3d</fix><extensions><gpxtpx:TrackPointExtension><gpxtpx:speed>0.06</gpxtpx:speed><gpxtpx:course>320.9</gpxtpx:course></gpxtpx:TrackPointExtension></extensions></trkpt>\n";
+    assert_eq!(out.matches(trkpt).count(), 2);
+    assert!(out.contains("</trkseg>\n<trkseg>\n") && out.ends_with("</trkseg>\n</trk>\n</gpx>\n"));
+}
+#[test]
+fn kml_output() {
+    use kml::{placemark, write_kmz, KmlTrack};
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    let here = placemark(&tpv, "Rig <1>").unwrap();
+    assert!(here.contains("<name>Rig &lt;1&gt;</name>"));
+    assert!(here.contains("<coordinates>18.054,59.345,78.2</coordinates>"));
+    assert!(here.contains("<when>2017-07-01T12:00:00.000Z</when>"));
+    assert!(placemark(&TpvResponse::default(), "none").is_none());
+    let mut track = KmlTrack::new();
+    assert!(track.push(&tpv));
+    let flat: TpvResponse = serde_json::from_str(r#"{"class":"TPV","mode":2,"time":"2017-07-01T12:00:01.000Z","ept":0.005,"lat":59.346,"lon":18.055,"speed":1.5}"#).unwrap();
+    assert!(track.push(&flat) && track.len() == 2);
+    let doc = track.to_kml("drive");
+    assert!(doc.contains("<altitudeMode>clampToGround</altitudeMode>"));
+    assert!(doc.contains("<when>2017-07-01T12:00:00.000Z</when>\n<when>2017-07-01T12:00:01.000Z</when>\n<gx:coord>18.054 59.345 78.2</gx:coord>\n<gx:coord>18.055 59.346</gx:coord>\n"));
+    let mut kmz = Vec::new();
+    write_kmz(&doc, &mut kmz).unwrap();
+    assert!(kmz.starts_with(b"PK\x03\x04"));
+    // Stored, so the document is in there as is, after a 30-byte header and the name.
+    assert_eq!(&kmz[30..37], b"doc.kml");
+    assert_eq!(&kmz[37..37 + doc.len()], doc.as_bytes());
+    let end = &kmz[kmz.len() - 22..];
+    assert_eq!(&end[..4], b"PK\x05\x06");
+    assert_eq!(end[16..20], ((37 + doc.len()) as u32).to_le_bytes());
+}
+#[test]
+fn nmea_sentences() {
+    use nmea::{checksum, NmeaEncoder};
+    assert_eq!(checksum("GPGLL,4916.45,N,12311.12,W,225444,A,"), 0x1D);
+    let valid = |s: &str| {
+        let body = &s[1..s.len() - 5];
+        s.starts_with('$') && s.ends_with("\r\n") && s[s.len() - 4..s.len() - 2] == format!("{:02X}", checksum(body))
+    };
+    let sky = parse_line(r#"{"class":"SKY","hdop":0.9,"pdop":1.6,"vdop":1.3,"satellites":[{"PRN":7,"az":10,"el":5,"ss":27,"used":false},{"PRN":5,"az":120,"el":45,"ss":38,"used":true},{"PRN":9,"az":200,"el":60,"ss":41.5,"used":true},{"PRN":12,"az":300,"el":20,"ss":0,"used":false},{"PRN":30,"az":45,"el":75,"ss":44,"used":true}]}"#.into(), false).unwrap();
+    let tpv = parse_line(TPV_LINE.into(), false).unwrap();
+    let mut enc = NmeaEncoder::new();
+    let out = enc.encode(&tpv);
+    assert_eq!(out.len(), 2);
+    assert!(out[0].starts_with("$GPGGA,120000.00,5920.70000,N,01803.24000,E,1,,,78.2,M,,,,*"), "{}", out[0]);
+    assert!(out[1].starts_with("$GPRMC,120000.00,A,5920.70000,N,01803.24000,E,0.12,320.9,010717,,,A*"), "{}", out[1]);
+    let out = enc.encode(&sky);
+    assert!(out[0].starts_with("$GPGSA,A,3,05,09,30,,,,,,,,,,1.6,0.9,1.3*"), "{}", out[0]);
+    assert_eq!(out.len(), 3);
+    assert!(out[1].starts_with("$GPGSV,2,1,05,07,05,010,27,05,45,120,38,09,60,200,42,12,20,300,*"), "{}", out[1]);
+    assert!(out[2].starts_with("$GPGSV,2,2,05,30,75,045,44*"), "{}", out[2]);
+    let out = NmeaEncoder::new().with_talker("GN").encode(&tpv);
+    assert!(out[0].starts_with("$GNGGA,"));
+    let again = enc.encode(&tpv);
+    assert!(again[0].contains(",1,03,0.9,78.2,M,"), "{}", again[0]);
+    assert!(again.iter().chain(&out).all(|s| valid(s)));
+    assert!(enc.encode(&Response::Tpv(TpvResponse::default())).is_empty());
+}
+#[test]
+fn nmea_decoding() {
+    use nmea::{NmeaDecoder, NmeaEncoder};
+    let mut dec = NmeaDecoder::new();
+    let lines = [
+        "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47",
+        "$GPGSA,A,3,04,05,,09,12,,,24,,,,,2.5,1.3,2.1*39",
+        "$GPGSV,2,1,08,01,40,083,46,02,17,308,41,12,07,344,39,14,22,228,45*75",
+        "$GPGSV,2,2,08,04,30,100,,05,55,200,33,09,60,050,40*4E",
+        "$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A",
+        "$PGRMZ,246,f,3*1B"
+    ];
+    let out: Vec<Response> = lines.iter().filter_map(|l| dec.decode(l).unwrap()).collect();
+    assert_eq!(out.len(), 2);
+    let sky = match out[0] {
+        Response::Sky(ref sky) => sky,
+        ref x => panic!("unexpected result: {:?}", x)
+    };
+    assert_eq!((sky.satellites.len(), sky.hdop), (7, Some(1.3)));
+    assert_eq!(sky.used_satellites().map(|s| s.prn).collect::<Vec<_>>(), vec![12, 4, 5, 9]);
+    let tpv = match out[1] {
+        Response::Tpv(ref tpv) => tpv,
+        ref x => panic!("unexpected result: {:?}", x)
+    };
+    assert_eq!(tpv.mode(), Some(FixMode::Fix3d));
+    assert!((tpv.lat().unwrap() - 48.1173).abs() < 1e-9 && (tpv.lon().unwrap() - 11.516_666_666).abs() < 1e-6);
+    assert_eq!((tpv.alt(), tpv.extra()["geoidSep"].as_f64()), (Some(545.4), Some(46.9)));
+    assert!((tpv.ground_speed().unwrap().knots() - 22.4).abs() < 1e-9);
+    assert_eq!(timestamp::format_millis(&tpv.time().unwrap()), "1994-03-23T12:35:19.000Z");
+    assert!(matches!(dec.decode("$GPRMC,123519,A,4807.038,N*00"), Err(GpsdError::Protocol(..))));
+    assert!(matches!(dec.decode(TPV_LINE), Err(GpsdError::Protocol(..))));
+    assert!(matches!(dec.decode("$aéxy,1"), Err(GpsdError::Protocol(..))));
+    let bad_date = "$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,aéb12,003.1,W";
+    assert!(matches!(dec.decode(bad_date), Err(GpsdError::Protocol(..))));
+    let void = dec.decode("$GPRMC,123520,V,,,,,,,230394,,,N").unwrap();
+    assert!(matches!(void, Some(Response::Tpv(ref t)) if t.mode() == Some(FixMode::NoFix) && t.lat().is_none()));
+    // What we write, we can read.
+    let mut enc = NmeaEncoder::new();
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    let mut dec = NmeaDecoder::new();
+    let back: Vec<Response> = enc.encode(&Response::Tpv(tpv)).iter().filter_map(|l| dec.decode(l).unwrap()).collect();
+    assert!(matches!(back[..], [Response::Tpv(ref t)] if t.alt() == Some(78.2) && (t.lat().unwrap() - 59.345).abs() < 1e-9));
+    assert!(WatchObject::default().with_nmea().sends_raw());
+}
+#[test]
+fn nmea_decoding_merges_constellations() {
+    use nmea::NmeaDecoder;
+    use types::Constellation;
+    let gsa = |prns: &[&str], system: u8| {
+        let mut slots = prns.to_vec();
+        slots.resize(12, "");
+        format!("$GNGSA,A,3,{},1.6,0.9,1.3,{}", slots.join(","), system)
+    };
+    // Galileo's PRN 5 isn't GPS's, and isn't used.
+    let fix = [
+        gsa(&["05", "09"], 1),
+        gsa(&["65"], 2),
+        gsa(&["11"], 3),
+        "$GPGSV,1,1,03,05,45,120,38,09,60,200,42,07,10,010,20".to_owned(),
+        "$GLGSV,1,1,01,65,30,100,35".to_owned(),
+        "$GAGSV,1,1,02,05,20,300,30,11,50,050,33".to_owned()
+    ];
+    let mut dec = NmeaDecoder::new();
+    let skies = |dec: &mut NmeaDecoder| -> Vec<SkyResponse> {
+        fix.iter().filter_map(|l| match dec.decode(l).unwrap() {
+            Some(Response::Sky(sky)) => Some(sky),
+            None => None,
+            x => panic!("unexpected result: {:?}", x)
+        }).collect()
+    };
+    // Until the groups come round again, every group gives a SKY.
+    let first = skies(&mut dec);
+    assert_eq!(first.iter().map(|s| s.satellites.len()).collect::<Vec<_>>(), vec![3, 4, 6]);
+    let second = skies(&mut dec);
+    assert_eq!(second.len(), 1);
+    assert_eq!(second[0], first[2]);
+    let used: Vec<_> = second[0].used_satellites().map(|s| (s.constellation(), s.prn)).collect();
+    assert_eq!(used, vec![(Constellation::Gps, 5), (Constellation::Gps, 9), (Constellation::Glonass, 65), (Constellation::Galileo, 11)]);
+    assert_eq!(second[0].hdop, Some(0.9));
+    // If the last group stops coming, the fix goes out when the next begins.
+    assert!(fix[..5].iter().all(|l| dec.decode(l).unwrap().is_none()));
+    match dec.decode(&fix[3]).unwrap() {
+        Some(Response::Sky(ref sky)) => assert_eq!(sky.satellites.len(), 4),
+        x => panic!("unexpected result: {:?}", x)
+    }
+    // An NMEA 4.10 signal ID on the end isn't a satellite.
+    let mut dec = NmeaDecoder::new();
+    match dec.decode("$GPGSV,1,1,01,05,45,120,38,1").unwrap() {
+        Some(Response::Sky(ref sky)) => assert_eq!(sky.satellites.len(), 1),
+        x => panic!("unexpected result: {:?}", x)
+    }
+}
+#[cfg(feature = "uom")]
+#[test]
+fn uom_quantities() {
+    use uom::si::angle::radian;
+    use uom::si::f64::Length;
+    use uom::si::length::foot;
+    use uom::si::time::millisecond;
+    use uom::si::velocity::kilometer_per_hour;
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    assert!((tpv.speed_quantity().unwrap().get::<kilometer_per_hour>() - 0.216).abs() < 1e-9);
+    assert!((tpv.alt_quantity().unwrap().get::<foot>() - 78.2 / 0.3048).abs() < 1e-9);
+    assert!((tpv.track_quantity().unwrap().get::<radian>() - 320.9f64.to_radians()).abs() < 1e-9);
+    assert!((tpv.time_err_quantity().unwrap().get::<millisecond>() - 5.0).abs() < 1e-9);
+    assert!(tpv.climb_quantity().is_some() && TpvResponse::default().speed_quantity().is_none());
+    let err = tpv.errors();
+    assert!(err.time.unwrap().length().is_none());
+    let _: Length = units::Length::from_feet(1.0).into();
+}
+#[test]
+fn ecef_and_enu() {
+    use geodesy::{Ecef, Enu, LocalFrame, WGS84_A};
+    let close = |a: f64, b: f64, tol: f64| (a - b).abs() < tol;
+    let equator = Ecef::from_geodetic(0.0, 0.0, 0.0);
+    assert!(close(equator.x, WGS84_A, 1e-6) && close(equator.y, 0.0, 1e-6) && close(equator.z, 0.0, 1e-6));
+    let pole = Ecef::from_geodetic(90.0, 0.0, 0.0);
+    assert!(close(pole.z, 6_356_752.314_245, 1e-3));
+    let p = Ecef::from_geodetic(59.345, 18.054, 102.5);
+    let (lat, lon, h) = p.to_geodetic();
+    assert!(close(lat, 59.345, 1e-9) && close(lon, 18.054, 1e-9) && close(h, 102.5, 1e-4), "{} {} {}", lat, lon, h);
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    let frame = LocalFrame::at(tpv.position().unwrap());
+    let here = frame.enu_of(&tpv).unwrap();
+    assert!(close(here.east, 0.0, 1e-6) && close(here.north, 0.0, 1e-6) && close(here.up, 0.0, 1e-6));
+    // 0.001 degrees north is about 111 m, at the same height.
+    let north = Ecef::from_geodetic(59.346, 18.054, 78.2);
+    let enu = frame.to_enu(north);
+    assert!(close(enu.north, 111.5, 0.5) && close(enu.east, 0.0, 1e-6) && enu.up < 0.0, "{:?}", enu);
+    let back = frame.from_enu(Enu { east: 10.0, north: -20.0, up: 5.0 });
+    let enu = frame.to_enu(back);
+    assert!(close(enu.east, 10.0, 1e-6) && close(enu.north, -20.0, 1e-6) && close(enu.up, 5.0, 1e-6));
+    let separated = Position { geoid_sep: Some(24.3), ..tpv.position().unwrap() };
+    let (_, _, h) = Ecef::from(separated).to_geodetic();
+    assert!(close(h, 78.2 + 24.3, 1e-4), "{}", h);
+    assert!(close(frame.to_enu(LocalFrame::at(separated).origin()).up, 24.3, 1e-4));
+    let sent: TpvResponse = serde_json::from_str(r#"{"class":"TPV","mode":3,"ecefx":3101538.11,"ecefy":1011172.01,"ecefz":5463845.85}"#).unwrap();
+    assert_eq!(sent.ecef(), Some(Ecef { x: 3101538.11, y: 1011172.01, z: 5463845.85 }));
+    assert!(TpvResponse::default().ecef().is_none());
+}
+#[cfg(feature = "utm")]
+#[test]
+fn utm_and_mgrs() {
+    use utm::Utm;
+    let close = |a: f64, b: f64, tol: f64| (a - b).abs() < tol;
+    let origin = Utm::from_geodetic(0.0, 3.0).unwrap();
+    assert_eq!((origin.zone, origin.band), (31, 'N'));
+    assert!(close(origin.easting, 500_000.0, 1e-6) && close(origin.northing, 0.0, 1e-6));
+    // On the central meridian, northing is the scaled meridian arc.
+    let mid = Utm::from_geodetic(45.0, -75.0).unwrap();
+    assert!(close(mid.northing, 0.9996 * 4_984_944.378, 1e-2), "{:?}", mid);
+    let (east, west) = (Utm::from_geodetic(-33.9, 21.0).unwrap(), Utm::from_geodetic(-33.9, 15.0).unwrap());
+    assert!(close(east.easting + west.easting, 1_000_000.0, 1e-6) && !east.is_north() && east.band == 'H');
+    // The Washington Monument, which the MGRS documentation puts at
+    // 18SUJ2348706483 (to within the precision of its coordinates here).
+    let monument = Position::new(38.889_467, -77.035_239, None, None).unwrap();
+    let utm = monument.utm().unwrap();
+    assert!(close(utm.easting, 323_487.0, 10.0) && close(utm.northing, 4_306_483.0, 10.0), "{}", utm);
+    assert_eq!(utm.to_string(), format!("18S {} {}", utm.easting.floor(), utm.northing.floor()));
+    assert_eq!(monument.mgrs(2).unwrap(), "18SUJ2306");
+    assert_eq!(monument.mgrs(0).unwrap(), "18SUJ");
+    assert_eq!(monument.mgrs(5).unwrap().len(), 15);
+    assert_eq!(Utm::from_geodetic(60.0, 5.0).unwrap().zone, 32);
+    assert_eq!(Utm::from_geodetic(78.0, 15.0).unwrap().zone, 33);
+    assert!(Utm::from_geodetic(85.0, 0.0).is_none());
+}
+#[test]
+fn distances_and_bearings() {
+    use geodesy::{haversine, vincenty};
+    let close = |a: f64, b: f64, tol: f64| (a - b).abs() < tol;
+    assert!(close(haversine(0.0, 0.0, 0.0, 1.0), 111_195.08, 0.01));
+    // Flinders Peak to Buninyong, Vincenty's own worked example.
+    let dms = |d: f64, m: f64, s: f64| d.signum() * (d.abs() + m / 60.0 + s / 3600.0);
+    let g = vincenty(dms(-37.0, 57.0, 3.72030), dms(144.0, 25.0, 29.52440),
+                     dms(-37.0, 39.0, 10.15610), dms(143.0, 55.0, 35.38390)).unwrap();
+    assert!(close(g.distance, 54_972.271, 0.001), "{:?}", g);
+    assert!(close(g.initial_bearing.degrees(), dms(306.0, 52.0, 5.37), 1e-4), "{:?}", g);
+    assert!(close(g.final_bearing.degrees(), dms(307.0, 10.0, 25.07), 1e-4), "{:?}", g);
+    let home = Position::new(59.345, 18.054, None, None).unwrap();
+    let north = Position::new(60.345, 18.054, None, None).unwrap();
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    assert!(close(tpv.distance_to(&home).unwrap(), 0.0, 1e-9));
+    assert!(close(home.distance_to(&north), 111_500.0, 500.0) && close(home.bearing_to(&north).degrees(), 0.0, 1e-9));
+    // Antipodes: Vincenty gives up, and the spherical fallback stands in.
+    let (a, b) = (Position::new(0.0, 0.0, None, None).unwrap(), Position::new(0.5, 179.7, None, None).unwrap());
+    assert!(a.geodesic_to(&b).is_none() && close(a.distance_to(&b), 19_970_000.0, 100_000.0));
+    assert!(TpvResponse::default().distance_to(&home).is_none());
+}
+#[cfg(feature = "proj")]
+#[test]
+fn proj_transforms() {
+    use proj::Projector;
+    let close = |a: f64, b: f64, tol: f64| (a - b).abs() < tol;
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    // SWEREF 99 TM is UTM zone 33 on GRS80, so it should agree with plain UTM.
+    let sweref = Projector::epsg(3006).unwrap();
+    let utm = Projector::new("+proj=utm +zone=33 +ellps=WGS84").unwrap();
+    let (a, b) = (sweref.tpv(&tpv).unwrap().unwrap(), utm.tpv(&tpv).unwrap().unwrap());
+    assert!(close(a.x, b.x, 0.01) && close(a.y, b.y, 0.01), "{:?} {:?}", a, b);
+    assert!(close(a.x, 674_000.0, 1_000.0) && close(a.y, 6_582_000.0, 1_000.0), "{:?}", a);
+    let wgs84 = Projector::new("EPSG:4326").unwrap().tpv(&tpv).unwrap().unwrap();
+    assert!(close(wgs84.x, 18.054, 1e-9) && close(wgs84.y, 59.345, 1e-9));
+    let separated = Position { geoid_sep: Some(24.3), ..tpv.position().unwrap() };
+    assert!(close(utm.position(&separated).unwrap().z, 78.2 + 24.3, 1e-6));
+    assert!(sweref.tpv(&TpvResponse::default()).is_none());
+    assert!(Projector::new("not a projection").is_err());
+}
+#[test]
+fn csv_export() {
+    use csv::{CsvColumn, CsvWriter};
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    let mut csv = CsvWriter::new(Vec::new()).unwrap();
+    csv.write_tpv(&tpv).unwrap();
+    csv.write_tpv(&TpvResponse::default()).unwrap();
+    assert_eq!(csv.rows(), 2);
+    assert_eq!(String::from_utf8(csv.finish().unwrap()).unwrap(), concat!(
+        "time,lat,lon,alt,speed,track,mode,ept,epx,epy,epv,epd,eps,epc\n",
+        "2017-07-01T12:00:00.000Z,59.345,18.054,78.2,0.06,320.9,3,0.005,,,,,,\n",
+        ",,,,,,,,,,,,,\n"));
+    let mut csv = CsvWriter::with_columns(Vec::new(), &[CsvColumn::Lon, CsvColumn::Lat]).unwrap();
+    let garbage = parse_line("{garbage".into(), false);
+    let stream = vec![Ok(serde_json::from_str(VERSION_LINE).unwrap()), garbage, Ok(Response::Tpv(tpv.clone()))];
+    assert_eq!(csv.consume(stream).unwrap(), 1);
+    let stream = vec![Ok(Response::Tpv(tpv)), Err(GpsdError::Disconnected("gone".into()))];
+    assert!(matches!(csv.consume(stream), Err(GpsdError::Disconnected(_))));
+    assert_eq!(String::from_utf8(csv.finish().unwrap()).unwrap(), "lon,lat\n18.054,59.345\n18.054,59.345\n");
+}
+#[cfg(feature = "parquet")]
+#[test]
+fn parquet_export() {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Float64Type, TimestampNanosecondType, UInt8Type};
+    use columnar::{ParquetWriter, TpvBatcher};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    let mut batcher = TpvBatcher::new();
+    batcher.push(&tpv);
+    batcher.push(&TpvResponse::default());
+    let batch = batcher.finish();
+    assert!(batcher.is_empty());
+    assert_eq!((batch.num_rows(), batch.num_columns()), (2, 16));
+    assert_eq!(batch.column_by_name("lat").unwrap().as_primitive::<Float64Type>().value(0), 59.345);
+    assert_eq!(batch.column_by_name("alt").unwrap().null_count(), 1);
+    let path = ::std::env::temp_dir().join(format!("unbounded-gpsd-{}.parquet", ::std::process::id()));
+    let mut writer = ParquetWriter::create(&path).unwrap().with_batch_size(2);
+    let stream = (0..5).map(|_| Ok(Response::Tpv(tpv.clone())))
+        .chain(vec![parse_line("{garbage".into(), false), Ok(serde_json::from_str(VERSION_LINE).unwrap())]);
+    assert_eq!(writer.consume(stream).unwrap(), 5);
+    let stream = vec![Ok(Response::Tpv(tpv.clone())), Err(GpsdError::Disconnected("gone".into()))];
+    assert!(matches!(writer.consume(stream), Err(GpsdError::Disconnected(_))));
+    assert_eq!(writer.rows(), 6);
+    writer.finish().unwrap();
+    let reader = ParquetRecordBatchReaderBuilder::try_new(::std::fs::File::open(&path).unwrap()).unwrap().build().unwrap();
+    let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    ::std::fs::remove_file(&path).unwrap();
+    assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 6);
+    let b = &batches[0];
+    assert_eq!(b.schema(), columnar::schema());
+    assert_eq!(b.column_by_name("time").unwrap().as_primitive::<TimestampNanosecondType>().value(0), 1_498_910_400_000_000_000);
+    assert_eq!(b.column_by_name("mode").unwrap().as_primitive::<UInt8Type>().value(0), 3);
+    assert_eq!(b.column_by_name("device").unwrap().as_string::<i32>().value(0), "/dev/ttyUSB0");
+    assert_eq!(b.column_by_name("ept").unwrap().as_primitive::<Float64Type>().value(0), 0.005);
+}
+#[test]
+fn ros_navsatfix() {
+    use ros::{NavSatFix, NavSatStatus};
+    let tpv = |extra: &str| serde_json::from_str::<TpvResponse>(&TPV_LINE.replace(r#""climb":0.1"#, &format!(r#""climb":0.1{}"#, extra))).unwrap();
+    let fix = NavSatFix::from_tpv(&tpv(""), "gps");
+    assert_eq!((fix.header.stamp.sec, fix.header.stamp.nanosec, &fix.header.frame_id[..]), (1_498_910_400, 0, "gps"));
+    assert_eq!((fix.status.status, fix.status.service), (NavSatStatus::STATUS_FIX, NavSatStatus::SERVICE_GPS));
+    assert_eq!((fix.latitude, fix.longitude), (59.345, 18.054));
+    // Only MSL altitude, with no geoid separation to get to the ellipsoid.
+    assert!(fix.altitude.is_nan());
+    assert_eq!((fix.position_covariance, fix.position_covariance_type), ([0.0; 9], NavSatFix::COVARIANCE_TYPE_UNKNOWN));
+    let fix = NavSatFix::from_tpv(&tpv(r#","epx":3.92,"epy":1.96,"epv":7.84,"geoidSep":22.5,"status":3"#), "gps");
+    assert_eq!((fix.altitude, fix.status.status), (78.2 + 22.5, NavSatStatus::STATUS_GBAS_FIX));
+    assert_eq!(fix.position_covariance_type, NavSatFix::COVARIANCE_TYPE_DIAGONAL_KNOWN);
+    let c = fix.position_covariance;
+    for (got, sigma) in [(c[0], 2.0), (c[4], 1.0), (c[8], 4.0)] {
+        assert!((got - sigma * sigma).abs() < 1e-3, "{:?}", c);
+    }
+    assert_eq!((c[1], c[3], c[5]), (0.0, 0.0, 0.0));
+    let no_fix = NavSatFix::from_tpv(&Default::default(), "gps");
+    assert_eq!(no_fix.status.status, NavSatStatus::STATUS_NO_FIX);
+    assert!(no_fix.latitude.is_nan() && no_fix.header.stamp.sec == 0);
+    let sky: SkyResponse = serde_json::from_str(r#"{"class":"SKY","satellites":[{"PRN":5,"el":40,"az":120,"ss":35.0,"used":true,"gnssid":0},{"PRN":3,"el":40,"az":120,"ss":35.0,"used":true,"gnssid":2},{"PRN":70,"el":40,"az":120,"ss":35.0,"used":false}]}"#).unwrap();
+    assert_eq!(fix.with_service(&sky).status.service, NavSatStatus::SERVICE_GPS | NavSatStatus::SERVICE_GALILEO);
+}
+#[test]
+fn cot_events() {
+    use cot::CotGenerator;
+    let tpv: TpvResponse = serde_json::from_str(&TPV_LINE.replace(r#""climb":0.1"#, r#""climb":0.1,"epx":3.92,"epy":3.92,"epv":7.84,"geoidSep":22.5"#)).unwrap();
+    let cot = CotGenerator::new("gpsd-<1>").with_callsign("Rover & co").with_stale(Duration::from_secs(30));
+    let xml = cot.event(&tpv).unwrap();
+    assert!(xml.starts_with(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?><event version="2.0" uid="gpsd-&lt;1&gt;" type="a-f-G-U-C" how="m-g" "#,
+        r#"time="2017-07-01T12:00:00.000Z" start="2017-07-01T12:00:00.000Z" stale="2017-07-01T12:00:30.000Z">"#,
+        r#"<point lat="59.345" lon="18.054" hae="100.7" ce="2.828"#)), "{}", xml);
+    assert!(xml.contains(r#" le="4.000"#), "{}", xml);
+    assert!(xml.ends_with(concat!(
+        r#"<detail><contact callsign="Rover &amp; co"/><track course="320.9" speed="0.06"/>"#,
+        r#"<precisionlocation geopointsrc="GPS" altsrc="GPS"/></detail></event>"#)), "{}", xml);
+    // No ellipsoid height or errors.
+    let xml = CotGenerator::new("u").with_type("a-f-G-E-V-C").event(&serde_json::from_str(TPV_LINE).unwrap()).unwrap();
+    assert!(xml.contains(r#" type="a-f-G-E-V-C" "#) && xml.contains(r#" hae="9999999" ce="9999999" le="9999999"/>"#), "{}", xml);
+    assert!(xml.contains(r#"altsrc="???""#) && !xml.contains("<contact"));
+    let eph: TpvResponse = serde_json::from_str(&TPV_LINE.replace(r#""climb":0.1"#, r#""climb":0.1,"eph":4.8955"#)).unwrap();
+    assert!(CotGenerator::new("u").event(&eph).unwrap().contains(r#" ce="2.828"#));
+    assert!(CotGenerator::new("u").event(&TpvResponse::default()).is_none());
+}
+#[cfg(feature = "prost")]
+#[test]
+fn prost_messages() {
+    use prost::Message;
+    use proto::{report, Report};
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    let msg = proto::Tpv::from(&tpv);
+    assert_eq!((msg.device.as_deref(), msg.time_unix_nanos, msg.mode), (Some("/dev/ttyUSB0"), Some(1_498_910_400_000_000_000), 3));
+    assert_eq!((msg.lat, msg.alt, msg.ept, msg.epx), (Some(59.345), Some(78.2), Some(0.005), None));
+    assert_eq!(proto::Tpv::decode(&msg.encode_to_vec()[..]).unwrap(), msg);
+    let sky = Response::from_json_line(r#"{"class":"SKY","hdop":1.2,"satellites":[{"PRN":5,"el":40,"az":120,"ss":35.0,"used":true,"gnssid":0}]}"#).unwrap();
+    match Option::<Report>::from(&sky).unwrap().report {
+        Some(report::Report::Sky(s)) => {
+            assert_eq!((s.hdop, s.vdop, s.satellites.len()), (Some(1.2), None, 1));
+            assert_eq!((s.satellites[0].prn, s.satellites[0].gnss_id, s.satellites[0].used), (5, Some(0), true));
+        },
+        other => panic!("{:?}", other)
+    }
+    let dev = Response::from_json_line(r#"{"class":"DEVICE","path":"/dev/ttyUSB0","activated":"2017-07-01T12:00:00.000Z","flags":1,"driver":"u-blox","bps":9600}"#).unwrap();
+    let report = Option::<Report>::from(&dev).unwrap();
+    match Report::decode(&report.encode_to_vec()[..]).unwrap().report {
+        Some(report::Report::Device(d)) => {
+            assert!(d.active);
+            assert_eq!((d.path.as_deref(), d.driver.as_deref(), d.bps), (Some("/dev/ttyUSB0"), Some("u-blox"), Some(9600)));
+        },
+        other => panic!("{:?}", other)
+    }
+    assert!(Option::<Report>::from(&Response::from_json_line(VERSION_LINE).unwrap()).is_none());
+}
+#[cfg(feature = "prost")]
+#[test]
+fn prost_messages_match_schema() {
+    use prost::Message;
+    use proto::{report, Device, Report, Satellite, Sky, Tpv};
+    // Encoded by hand from the tags and types in proto/gpsd.proto, so the
+    // structs can't quietly drift from it.
+    let tpv = Tpv { device: Some("d".into()), time_unix_nanos: Some(0), mode: 3, lat: Some(1.5), epc: Some(1.5), ..Tpv::default() };
+    let tpv_bytes = [
+        0x0a, 0x1a,
+        0x0a, 0x01, b'd',
+        0x10, 0x00,
+        0x18, 0x03,
+        0x21, 0, 0, 0, 0, 0, 0, 0xf8, 0x3f,
+        0x81, 0x01, 0, 0, 0, 0, 0, 0, 0xf8, 0x3f
+    ];
+    let sat = Satellite { prn: 5, gnss_id: Some(0), used: true, ..Satellite::default() };
+    let sky = Sky { hdop: Some(1.5), satellites: vec![sat], ..Sky::default() };
+    let sky_bytes = [
+        0x12, 0x11,
+        0x39, 0, 0, 0, 0, 0, 0, 0xf8, 0x3f,
+        0x52, 0x06, 0x08, 0x05, 0x10, 0x00, 0x30, 0x01
+    ];
+    let dev = Device { path: Some("p".into()), active: true, bps: Some(9600), ..Device::default() };
+    let dev_bytes = [
+        0x1a, 0x08,
+        0x0a, 0x01, b'p',
+        0x10, 0x01,
+        0x30, 0x80, 0x4b
+    ];
+    for (msg, bytes) in [(report::Report::Tpv(tpv), &tpv_bytes[..]), (report::Report::Sky(sky), &sky_bytes[..]), (report::Report::Device(dev), &dev_bytes[..])] {
+        let report = Report { report: Some(msg) };
+        assert_eq!(report.encode_to_vec(), bytes);
+        assert_eq!(Report::decode(bytes).unwrap(), report);
+    }
+}
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_track_store() {
+    use storage::TrackStore;
+    let at = |s: &str| timestamp::parse(s).unwrap();
+    let mut store = TrackStore::open_in_memory().unwrap().with_sky(true);
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    let later = Response::from_json_line(&TPV_LINE.replace("12:00:00", "12:00:05").replace("/ttyUSB0", "//ttyUSB1")).unwrap();
+    let sky = Response::from_json_line(r#"{"class":"SKY","device":"/dev/ttyUSB0","time":"2017-07-01T12:00:01.000Z","hdop":1.2,"satellites":[{"PRN":5,"el":40,"az":120,"ss":35.0,"used":true},{"PRN":7,"el":10,"az":200,"ss":20.0,"used":false}]}"#).unwrap();
+    let stream = vec![Response::from_json_line(VERSION_LINE).unwrap(), Response::Tpv(tpv.clone()), sky, later];
+    assert_eq!(store.store_all(&stream).unwrap(), 3);
+    assert!(store.store(&Response::Tpv(TpvResponse::default())).unwrap());
+    let (from, to) = (at("2017-07-01T12:00:00Z"), at("2017-07-01T12:00:05Z"));
+    assert_eq!(store.tpv_between(from, to, None).unwrap(), vec![tpv]);
+    assert_eq!(store.tpv_between(from, at("2017-07-01T13:00:00Z"), Some("/dev/ttyUSB1")).unwrap().len(), 1);
+    assert_eq!(store.time_span(None).unwrap(), Some((from, to)));
+    assert_eq!(store.time_span(Some("/dev/ttyUSB0")).unwrap(), Some((from, from)));
+    assert_eq!(store.time_span(Some("/dev//ttyUSB0/")).unwrap(), Some((from, from)));
+    assert_eq!(store.time_span(Some("/dev/nothing")).unwrap(), None);
+    assert_eq!(store.devices().unwrap(), vec!["/dev/ttyUSB0", "/dev/ttyUSB1"]);
+    let skies = store.sky_between(from, to, Some("/dev/ttyUSB0")).unwrap();
+    assert_eq!((skies.len(), skies[0].visible, skies[0].used, skies[0].hdop), (1, 2, 1, Some(1.2)));
+    assert_eq!(skies[0].time, Some(at("2017-07-01T12:00:01Z")));
+    let rows: i64 = store.connection().query_row("SELECT count(*) FROM tpv WHERE time IS NULL", [], |r| r.get(0)).unwrap();
+    assert_eq!(rows, 1);
+}
+#[cfg(unix)]
+#[test]
+fn chrony_samples() {
+    use chrony::{encode_sample, socket_path, ChronySock, Leap, TimeClass, TimeReport, SAMPLE_SIZE};
+    use std::convert::TryInto;
+    use std::os::unix::net::UnixDatagram;
+    let pps = Response::from_json_line(r#"{"class":"PPS","device":"/dev/ttyS0","real_sec":1498910400,"real_nsec":0,"clock_sec":1498910399,"clock_nsec":999500000,"precision":-20}"#).unwrap();
+    let report = TimeReport::from_response(&pps).unwrap();
+    assert_eq!((report.class, report.device.as_deref(), report.precision), (TimeClass::Pps, Some("/dev/ttyS0"), Some(-20)));
+    assert!((report.offset() - 0.0005).abs() < 1e-12);
+    let toff = Response::from_json_line(r#"{"class":"TOFF","device":"/dev/ttyUSB0","real_sec":1498910400}"#).unwrap();
+    assert!(TimeReport::from_response(&toff).is_none());
+    assert!(TimeReport::from_response(&Response::from_json_line(TPV_LINE).unwrap()).is_none());
+    assert_eq!(socket_path("/dev/ttyS0"), std::path::PathBuf::from("/run/chrony.ttyS0.sock"));
+
+    let sample = encode_sample(&report, Leap::Insert);
+    assert_eq!(sample.len(), SAMPLE_SIZE);
+    let long = std::mem::size_of::<std::os::raw::c_long>();
+    let f64_at = |i: usize| f64::from_ne_bytes(sample[i..i + 8].try_into().unwrap());
+    let i32_at = |i: usize| i32::from_ne_bytes(sample[i..i + 4].try_into().unwrap());
+    assert!((f64_at(2 * long) - 0.0005).abs() < 1e-12);
+    assert_eq!((i32_at(2 * long + 8), i32_at(2 * long + 12), i32_at(2 * long + 20)), (0, 1, 0x534f_434b));
+
+    let path = ::std::env::temp_dir().join(format!("unbounded-gpsd-chrony-{}.sock", ::std::process::id()));
+    let _ = ::std::fs::remove_file(&path);
+    let chronyd = UnixDatagram::bind(&path).unwrap();
+    let mut sock = ChronySock::connect(&path).unwrap();
+    assert!(sock.send_response(&pps).unwrap() && !sock.send_response(&toff).unwrap());
+    assert_eq!(sock.samples(), 1);
+    let mut buf = [0; 64];
+    let n = chronyd.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..n], &encode_sample(&report, Leap::Normal)[..]);
+    ::std::fs::remove_file(&path).unwrap();
+}
+#[test]
+fn influx_lines() {
+    use influx::LineProtocol;
+    let lp = LineProtocol::new();
+    let tpv = Response::from_json_line(TPV_LINE).unwrap();
+    assert_eq!(lp.format(&tpv).unwrap(), "tpv,device=/dev/ttyUSB0,mode=3 lat=59.345,lon=18.054,alt=78.2,track=320.9,speed=0.06,climb=0.1,ept=0.005 1498910400000000000");
+    assert!(lp.tpv(&serde_json::from_str(r#"{"mode":1}"#).unwrap()).is_none());
+    let lp = LineProtocol::new().with_prefix("gps ").with_tag("vehicle", "van,7");
+    let sky = Response::from_json_line(r#"{"class":"SKY","hdop":1.2,"satellites":[{"PRN":5,"el":40,"az":120,"ss":35.0,"used":true},{"PRN":7,"el":10,"az":20,"ss":0.0,"used":false}]}"#).unwrap();
+    assert_eq!(lp.format(&sky).unwrap(), r"gps\ sky,vehicle=van\,7 hdop=1.2,satellites=2i,used=1i");
+    let pps = Response::from_json_line(r#"{"class":"PPS","device":"/dev/ttyS0","real_sec":1498910400,"real_nsec":0,"clock_sec":1498910399,"clock_nsec":999500000,"precision":-20}"#).unwrap();
+    let line = lp.format(&pps).unwrap();
+    assert!(line.starts_with(r"gps\ pps,device=/dev/ttyS0,vehicle=van\,7 offset=0.0005,") && line.ends_with(",precision=-20i 1498910400000000000"), "{}", line);
+    let toff = Response::from_json_line(r#"{"class":"TOFF","device":"/dev/ttyS0","real_sec":1498910400,"real_nsec":0,"clock_sec":1498910400,"clock_nsec":250000000}"#).unwrap();
+    assert_eq!(lp.format(&toff).unwrap(), r"gps\ toff,device=/dev/ttyS0,vehicle=van\,7 offset=-0.25 1498910400000000000");
+    assert!(lp.format(&Response::from_json_line(VERSION_LINE).unwrap()).is_none());
+}
+#[test]
+fn prometheus_metrics() {
+    use metrics::GpsMetrics;
+    let mut metrics = GpsMetrics::new();
+    let out = metrics.render();
+    assert!(out.contains("# TYPE gpsd_fix_mode gauge\n# HELP gpsd_satellites_used"));
+    assert!(out.contains("# TYPE gpsd_reconnects_total counter\ngpsd_reconnects_total 0\n"));
+    metrics.observe(&Response::from_json_line(TPV_LINE).unwrap());
+    metrics.observe(&Response::from_json_line(r#"{"class":"SKY","device":"/dev/ttyUSB0","hdop":1.2,"satellites":[{"PRN":5,"el":40,"az":120,"ss":35.0,"used":true},{"PRN":7,"el":10,"az":20,"ss":0.0,"used":false}]}"#).unwrap());
+    metrics.observe(&Response::from_json_line(r#"{"class":"TPV","device":"/dev/tty\"odd\"","mode":1}"#).unwrap());
+    let mut stats = stats::ConnectionStats { reconnects: 2, ..Default::default() };
+    stats.messages.insert("TPV", 5);
+    metrics.set_stats(&stats);
+    let out = metrics.render();
+    for line in &["gpsd_fix_mode{device=\"/dev/ttyUSB0\"} 3\n", "gpsd_fix_mode{device=\"/dev/tty\\\"odd\\\"\"} 1\n",
+                  "gpsd_satellites_used{device=\"/dev/ttyUSB0\"} 1\n", "gpsd_satellites_visible{device=\"/dev/ttyUSB0\"} 2\n",
+                  "gpsd_hdop{device=\"/dev/ttyUSB0\"} 1.2\n", "gpsd_speed_meters_per_second{device=\"/dev/ttyUSB0\"} 0.06\n",
+                  "gpsd_reconnects_total 2\n", "gpsd_messages_total{class=\"TPV\"} 5\n"] {
+        assert!(out.contains(line), "{} not in\n{}", line, out);
+    }
+    let ages: Vec<&str> = out.lines().filter(|l| l.starts_with("gpsd_last_fix_age_seconds{")).collect();
+    assert_eq!(ages.len(), 1);
+    assert!(ages[0].starts_with("gpsd_last_fix_age_seconds{device=\"/dev/ttyUSB0\"} "));
+}
+#[cfg(all(feature = "geoclue", target_os = "linux"))]
+#[test]
+fn geoclue_service() {
+    use geoclue::{GeoClueServer, BUS_NAME, MANAGER_PATH};
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+    use zbus::blocking::{connection::Builder, proxy, Proxy};
+    use zbus::proxy::CacheProperties;
+    use zbus::zvariant::OwnedObjectPath;
+    // A private bus, killed however the test ends.
+    struct Bus(std::process::Child);
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+    let mut bus = Bus(Command::new("dbus-daemon")
+        .args(["--session", "--nofork", "--print-address"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap());
+    let mut addr = String::new();
+    BufReader::new(bus.0.stdout.take().unwrap()).read_line(&mut addr).unwrap();
+    let connect = || Builder::address(addr.trim()).unwrap().build().unwrap();
+    let server = GeoClueServer::serve(connect()).unwrap();
+    let app = connect();
+    // Uncached, so property reads don't race the change signals.
+    let proxy = |path: &str, iface: &'static str| -> Proxy<'static> {
+        proxy::Builder::new(&app).destination(BUS_NAME).unwrap().path(path.to_owned()).unwrap()
+            .interface(iface).unwrap().cache_properties(CacheProperties::No).build().unwrap()
+    };
+    let manager = proxy(MANAGER_PATH, "org.freedesktop.GeoClue2.Manager");
+    let path: OwnedObjectPath = manager.call("GetClient", &()).unwrap();
+    assert_eq!(manager.call::<_, _, OwnedObjectPath>("GetClient", &()).unwrap(), path);
+    assert_ne!(manager.call::<_, _, OwnedObjectPath>("CreateClient", &()).unwrap(), path);
+    let client = proxy(path.as_str(), "org.freedesktop.GeoClue2.Client");
+    let tpv: TpvResponse = serde_json::from_str(TPV_LINE).unwrap();
+    assert_eq!(server.update(&tpv).unwrap(), 0);
+    client.call_method("Start", &()).unwrap();
+    assert!(manager.get_property::<bool>("InUse").unwrap());
+    assert_eq!(server.update(&tpv).unwrap(), 1);
+    let loc: OwnedObjectPath = client.get_property("Location").unwrap();
+    let loc = proxy(loc.as_str(), "org.freedesktop.GeoClue2.Location");
+    assert_eq!((loc.get_property::<f64>("Latitude").unwrap(), loc.get_property::<f64>("Longitude").unwrap()), (59.345, 18.054));
+    assert_eq!((loc.get_property::<f64>("Speed").unwrap(), loc.get_property::<f64>("Heading").unwrap()), (0.06, 320.9));
+    assert_eq!(loc.get_property::<(u64, u64)>("Timestamp").unwrap(), (1_498_910_400, 0));
+    client.set_property("DistanceThreshold", 1000u32).unwrap();
+    assert_eq!(server.update(&tpv).unwrap(), 0);
+    manager.call_method("DeleteClient", &(&path,)).unwrap();
+    assert!(!manager.get_property::<bool>("InUse").unwrap());
+    // Clients go when the app that made them leaves the bus.
+    let other = connect();
+    let call = |path: &str, iface: &str, method: &str| other.call_method(Some(BUS_NAME), path, Some(iface), method, &());
+    let msg = call(MANAGER_PATH, "org.freedesktop.GeoClue2.Manager", "GetClient").unwrap();
+    let path: OwnedObjectPath = msg.body().deserialize().unwrap();
+    call(path.as_str(), "org.freedesktop.GeoClue2.Client", "Start").unwrap();
+    assert!(manager.get_property::<bool>("InUse").unwrap());
+    other.close().unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while manager.get_property::<bool>("InUse").unwrap() {
+        assert!(Instant::now() < deadline, "client outlived its app");
+        thread::sleep(Duration::from_millis(10));
+    }
+    let gone = proxy(path.as_str(), "org.freedesktop.GeoClue2.Client");
+    assert!(gone.get_property::<bool>("Active").is_err());
+}
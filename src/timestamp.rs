@@ -0,0 +1,347 @@
+//! The timestamp type used in reports.
+//!
+//! Times are `Timestamp`s: points in time in UTC, to the nanosecond, from
+//! the year 0 to 9999. The type is the crate's own, so the `chrono` feature
+//! (on by default) and the `time` feature only add conversions to and from
+//! `chrono::DateTime<Utc>` and `time::OffsetDateTime`, and either, both or
+//! neither can be enabled. `to_system_time`, and the `system_time`
+//! accessors on reports, need neither.
+//!
+//! Timestamps are serialized as RFC 3339 strings, as gpsd sends them.
+//! Parsing is more forgiving, to cope with what gpsd versions and
+//! receivers actually produce: a space or lowercase `t` between date and
+//! time, a missing or lowercase `Z`, an offset without a colon, any number
+//! of fractional digits (beyond nanoseconds, they're dropped), and seconds
+//! since the epoch as a number, as very old versions sent.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+/// Seconds since the epoch at the start of the year 0.
+const MIN_SECS: i128 = -62_167_219_200;
+/// Seconds since the epoch at the start of the year 10000.
+const MAX_SECS: i128 = 253_402_300_800;
+
+/// A point in time, in UTC, to the nanosecond, from the year 0 to 9999.
+///
+/// It displays and serializes as RFC 3339, with as many fractional digits
+/// (none, 3, 6 or 9) as it takes: `2017-07-01T12:00:00.123Z`.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(i128);
+impl Timestamp {
+    /// The time `nanos` nanoseconds after the epoch, or `None` if that's
+    /// outside the years 0 to 9999.
+    pub fn from_unix_nanos(nanos: i128) -> Option<Self> {
+        Some(Timestamp(nanos)).filter(|_| (MIN_SECS * NANOS_PER_SEC..MAX_SECS * NANOS_PER_SEC).contains(&nanos))
+    }
+    /// Nanoseconds since the epoch.
+    pub fn unix_nanos(&self) -> i128 {
+        self.0
+    }
+    /// Whole seconds since the epoch, and nanoseconds after that.
+    fn split(&self) -> (i64, u32) {
+        (self.0.div_euclid(NANOS_PER_SEC) as i64, self.0.rem_euclid(NANOS_PER_SEC) as u32)
+    }
+    /// The date and time of day.
+    fn civil(&self) -> Civil {
+        let (secs, nanos) = self.split();
+        let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+        let secs = secs.rem_euclid(86_400) as u32;
+        Civil { year, month, day, hour: secs / 3600, minute: secs / 60 % 60, second: secs % 60, nanos }
+    }
+}
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = self.civil();
+        write!(f, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", c.year, c.month, c.day, c.hour, c.minute, c.second)?;
+        match c.nanos {
+            0 => {},
+            n if n % 1_000_000 == 0 => write!(f, ".{:03}", n / 1_000_000)?,
+            n if n % 1000 == 0 => write!(f, ".{:06}", n / 1000)?,
+            n => write!(f, ".{:09}", n)?
+        }
+        f.write_str("Z")
+    }
+}
+impl fmt::Debug for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+impl From<Timestamp> for SystemTime {
+    fn from(t: Timestamp) -> Self {
+        let n = t.0.unsigned_abs();
+        let since = Duration::new((n / 1_000_000_000) as u64, (n % 1_000_000_000) as u32);
+        if t.0 >= 0 { UNIX_EPOCH + since } else { UNIX_EPOCH - since }
+    }
+}
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_str(self)
+    }
+}
+impl<'de> Deserialize<'de> for Timestamp {
+    /// Parse a timestamp as gpsd might send it; see the module documentation.
+    fn deserialize<D: Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        rfc3339::deserialize(de)
+    }
+}
+
+/// The error for a time outside the years a `Timestamp` can hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfRange;
+impl fmt::Display for OutOfRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("time outside the years 0 to 9999")
+    }
+}
+impl ::std::error::Error for OutOfRange {}
+
+#[cfg(feature = "chrono")]
+impl From<Timestamp> for chrono::DateTime<chrono::Utc> {
+    fn from(t: Timestamp) -> Self {
+        let (secs, nanos) = t.split();
+        chrono::DateTime::from_timestamp(secs, nanos).expect("chrono covers the years 0 to 9999")
+    }
+}
+#[cfg(feature = "chrono")]
+impl TryFrom<chrono::DateTime<chrono::Utc>> for Timestamp {
+    type Error = OutOfRange;
+
+    fn try_from(t: chrono::DateTime<chrono::Utc>) -> Result<Self, OutOfRange> {
+        let nanos = i128::from(t.timestamp()) * NANOS_PER_SEC + i128::from(t.timestamp_subsec_nanos());
+        Timestamp::from_unix_nanos(nanos).ok_or(OutOfRange)
+    }
+}
+#[cfg(feature = "time")]
+impl From<Timestamp> for time::OffsetDateTime {
+    fn from(t: Timestamp) -> Self {
+        time::OffsetDateTime::from_unix_timestamp_nanos(t.0).expect("time covers the years 0 to 9999")
+    }
+}
+#[cfg(feature = "time")]
+impl TryFrom<time::OffsetDateTime> for Timestamp {
+    type Error = OutOfRange;
+
+    fn try_from(t: time::OffsetDateTime) -> Result<Self, OutOfRange> {
+        Timestamp::from_unix_nanos(t.unix_timestamp_nanos()).ok_or(OutOfRange)
+    }
+}
+
+/// `t` as a `SystemTime`.
+pub fn to_system_time(t: Timestamp) -> SystemTime {
+    t.into()
+}
+
+/// The current time.
+pub(crate) fn now() -> Timestamp {
+    // chrono reads the clock through JavaScript on wasm32, where
+    // SystemTime::now panics.
+    #[cfg(feature = "chrono")]
+    let nanos = chrono::Utc::now().timestamp_nanos_opt().map(i128::from);
+    #[cfg(not(feature = "chrono"))]
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).ok().and_then(|d| i128::try_from(d.as_nanos()).ok());
+    nanos.and_then(Timestamp::from_unix_nanos).unwrap_or(Timestamp(0))
+}
+/// How long after `from` `to` is, or `None` if it's earlier.
+pub(crate) fn elapsed(from: Timestamp, to: Timestamp) -> Option<Duration> {
+    let nanos = u128::try_from(to.0 - from.0).ok()?;
+    Some(Duration::new((nanos / 1_000_000_000) as u64, (nanos % 1_000_000_000) as u32))
+}
+/// `t` as gpsd writes it, to the millisecond: `2017-07-01T12:00:00.000Z`.
+pub(crate) fn format_millis(t: &Timestamp) -> String {
+    let c = t.civil();
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z", c.year, c.month, c.day,
+            c.hour, c.minute, c.second, c.nanos / 1_000_000)
+}
+/// The time `nanos` nanoseconds after the epoch.
+pub(crate) fn from_epoch_nanos(nanos: i128) -> Option<Timestamp> {
+    Timestamp::from_unix_nanos(nanos)
+}
+/// Nanoseconds since the epoch, if that fits in an `i64` (until 2262).
+pub(crate) fn to_epoch_nanos(t: &Timestamp) -> Option<i64> {
+    i64::try_from(t.0).ok()
+}
+
+/// A `Timestamp`'s date and time of day.
+struct Civil {
+    year: i64,
+    month: u32,
+    day: u32,
+    hour: u32,
+    minute: u32,
+    second: u32,
+    nanos: u32
+}
+// Days to and from dates in the proleptic Gregorian calendar; see
+// http://howardhinnant.github.io/date_algorithms.html.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let (month, day) = (i64::from(month), i64::from(day));
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+/// Parse a strict RFC 3339 timestamp, with a leap second read as the
+/// start of the next minute.
+fn parse_rfc3339(s: &str) -> Option<Timestamp> {
+    let digits = |s: &str| -> Option<u32> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse().ok()
+    };
+    let b = s.as_bytes();
+    if b.len() < 20 || b[4] != b'-' || b[7] != b'-' || b[10] != b'T' || b[13] != b':' || b[16] != b':' {
+        return None;
+    }
+    let (year, month, day) = (i64::from(digits(&s[..4])?), digits(&s[5..7])?, digits(&s[8..10])?);
+    let (hour, minute, second) = (digits(&s[11..13])?, digits(&s[14..16])?, digits(&s[17..19])?);
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) ||
+        hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let mut zone = &s[19..];
+    let mut nanos = 0;
+    if let Some(frac) = zone.strip_prefix('.') {
+        let len = frac.bytes().take_while(u8::is_ascii_digit).count();
+        if len == 0 || len > 9 {
+            return None;
+        }
+        nanos = digits(&format!("{:0<9}", &frac[..len]))?;
+        zone = &frac[len..];
+    }
+    let offset = match zone.as_bytes() {
+        b"Z" => 0,
+        &[sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+            let (h, m) = (digits(&zone[1..3])?, digits(&zone[4..6])?);
+            if h > 23 || m > 59 {
+                return None;
+            }
+            let offset = i64::from(h * 3600 + m * 60);
+            if sign == b'-' { -offset } else { offset }
+        },
+        _ => return None
+    };
+    let secs = days_from_civil(year, month, day) * 86_400 +
+        i64::from(hour * 3600 + minute * 60 + second) - offset;
+    Timestamp::from_unix_nanos(i128::from(secs) * NANOS_PER_SEC + i128::from(nanos))
+}
+
+/// Rewrite the timestamp variations gpsd and receivers produce as strict
+/// RFC 3339.
+fn tidy(s: &str) -> Option<String> {
+    let s = s.trim();
+    if !s.is_ascii() || s.len() < 19 {
+        return None;
+    }
+    let (date, rest) = s.split_at(10);
+    let rest = match rest.as_bytes()[0] {
+        b'T' | b't' | b' ' => &rest[1..],
+        _ => return None
+    };
+    let (time, zone) = match rest.find(&['Z', 'z', '+', '-'][..]) {
+        Some(i) => rest.split_at(i),
+        None => (rest, "Z")
+    };
+    let time = match time.find('.') {
+        Some(i) if i + 1 == time.len() => &time[..i],
+        Some(i) => &time[..time.len().min(i + 10)],
+        None => time
+    };
+    let zone = match zone {
+        "z" => "Z".to_owned(),
+        z if z.len() == 5 && !z.contains(':') => format!("{}:{}", &z[..3], &z[3..]),
+        z => z.to_owned()
+    };
+    Some(format!("{}T{}{}", date, time, zone))
+}
+
+/// Parse a timestamp in any of the forms the module documentation lists,
+/// with seconds since the epoch given as text, like `1498910400.123456`.
+pub(crate) fn parse(s: &str) -> Option<Timestamp> {
+    let s = s.trim();
+    if !s.starts_with(|c: char| c.is_ascii_digit()) || s.contains(['-', ':']) {
+        return tidy(s).and_then(|t| parse_rfc3339(&t));
+    }
+    // Exactly, rather than through an f64, which can be a microsecond out.
+    let (secs, frac) = s.split_once('.').unwrap_or((s, ""));
+    if !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let frac = &frac[..frac.len().min(9)];
+    let nanos = format!("{:0<9}", frac).parse::<i128>().ok()?;
+    from_epoch_nanos(secs.parse::<i128>().ok()?.checked_mul(1_000_000_000)?.checked_add(nanos)?)
+}
+
+/// Serde helpers for `Timestamp` fields, as RFC 3339 strings.
+pub(crate) mod rfc3339 {
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer};
+    use serde::Serializer;
+    use super::{parse_rfc3339, tidy, Timestamp};
+
+    pub fn serialize<S: Serializer>(t: &Timestamp, ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_str(t)
+    }
+
+    /// Parse a timestamp as gpsd might send it; see the module documentation.
+    pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<Timestamp, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Sent {
+            Text(String),
+            Epoch(f64)
+        }
+        match Sent::deserialize(de)? {
+            Sent::Text(s) => tidy(&s).and_then(|t| parse_rfc3339(&t))
+                .ok_or_else(|| D::Error::custom(format!("invalid timestamp '{}'", s))),
+            Sent::Epoch(secs) => Some(secs * 1e9).filter(|n| n.is_finite())
+                .and_then(|n| super::from_epoch_nanos(n as i128))
+                .ok_or_else(|| D::Error::custom(format!("invalid timestamp {}", secs)))
+        }
+    }
+
+    /// For `Option<Timestamp>` fields. Use with `#[serde(default)]`.
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+        use super::super::Timestamp;
+
+        pub fn serialize<S: Serializer>(t: &Option<Timestamp>, ser: S) -> Result<S::Ok, S::Error> {
+            match *t {
+                Some(ref t) => super::serialize(t, ser),
+                None => ser.serialize_none()
+            }
+        }
+        pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<Option<Timestamp>, D::Error> {
+            #[derive(Deserialize)]
+            struct Wrapped(#[serde(with = "super")] Timestamp);
+            Ok(Option::<Wrapped>::deserialize(de)?.map(|w| w.0))
+        }
+    }
+}
@@ -0,0 +1,44 @@
+//! TLS connections to remote gpsd instances.
+//!
+//! Only available with the `tls` feature enabled. gpsd itself doesn't speak
+//! TLS, but it's common to expose it across the WAN behind a TLS terminator
+//! like stunnel or nginx; this lets you connect to one of those, using rustls.
+//!
+//! You build the `rustls::ClientConfig` yourself, so you choose the root
+//! certificates (and client certificates, if any) to use.
+
+use std::convert::TryFrom;
+use std::io;
+use std::net::TcpStream;
+use std::sync::Arc;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, StreamOwned};
+use crate::{GpsdConnection, GpsdResult, Stream};
+
+impl GpsdConnection {
+    /// Make a new TLS connection to `host` on `port`, verifying the server's
+    /// certificate against `host` as configured in `config`.
+    ///
+    /// The TLS handshake completes before this returns.
+    pub fn connect_tls(host: &str, port: u16, config: Arc<ClientConfig>) -> GpsdResult<Self> {
+        let host = host.to_owned();
+        Self::with_connector(Box::new(move || tls_stream(&host, port, config.clone())))
+    }
+}
+/// Open a TCP connection to `host`, then wrap it in TLS.
+fn tls_stream(host: &str, port: u16, config: Arc<ClientConfig>) -> io::Result<Stream> {
+    let sock = TcpStream::connect((host, port))?;
+    wrap(sock, host, config)
+}
+/// Perform a TLS handshake over `sock`, verifying the server as `host`.
+pub(crate) fn wrap(sock: TcpStream, host: &str, config: Arc<ClientConfig>) -> io::Result<Stream> {
+    let name = ServerName::try_from(host.to_owned())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let conn = ClientConnection::new(config, name)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stream = StreamOwned::new(conn, sock);
+    while stream.conn.is_handshaking() {
+        stream.conn.complete_io(&mut stream.sock)?;
+    }
+    Ok(Box::new(stream))
+}
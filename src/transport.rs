@@ -0,0 +1,123 @@
+//! The byte streams a `GpsdConnection` can run over.
+//!
+//! gpsd's protocol is just lines of JSON in both directions, so it doesn't
+//! much matter what carries them. Anything that implements `Transport` (which
+//! is little more than `Read + Write`) can be used with
+//! `GpsdConnection::from_transport` or `GpsdConnection::with_transport`: a
+//! serial bridge, a pipe to `ssh`, a custom tunnel, and so on.
+//!
+//! `AsyncGpsdConnection` is similarly generic over tokio's `AsyncRead +
+//! AsyncWrite`. For tests, `mock::MockTransport` stands in for gpsd.
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+/// Shuts down a transport's reading side, from any thread. See
+/// `Transport::shutdown_handle`.
+pub type ShutdownHandle = Box<dyn Fn() + Send + Sync>;
+
+/// A separate writer for a transport. See `Transport::write_handle`.
+pub type WriteHandle = Box<dyn Write + Send>;
+
+/// A bidirectional byte stream to gpsd.
+pub trait Transport: Read + Write + Send {
+    /// Set the timeout for reads, as for `TcpStream::set_read_timeout`.
+    ///
+    /// Read timeouts, inactivity timeouts and the watchdog all rely on this.
+    /// The default implementation only accepts `None`; transports that can't
+    /// time out reads are still usable, just without those features.
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        match dur {
+            None => Ok(()),
+            Some(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "transport doesn't support read timeouts"))
+        }
+    }
+    /// Shut the transport down in both directions, once the last command has
+    /// been written. The default implementation does nothing, leaving it to
+    /// be closed when dropped.
+    fn shutdown(&self) -> io::Result<()> {
+        Ok(())
+    }
+    /// A handle that shuts down the reading side of the transport, making any
+    /// read blocked on another thread return end-of-file. Writing should
+    /// still work afterwards.
+    ///
+    /// `GpsdConnection::close_handle` relies on this to stop a connection
+    /// being read on a background thread. The default implementation returns
+    /// `None`, in which case that waits for the next read to finish of its own
+    /// accord (so setting a read timeout helps).
+    fn shutdown_handle(&self) -> Option<ShutdownHandle> {
+        None
+    }
+    /// A writer for the transport that can be used while it's being read
+    /// elsewhere, e.g. a clone of a socket.
+    ///
+    /// `handle::CommandHandle` writes with this if it can. The default
+    /// implementation returns `None`, in which case commands sent through a
+    /// handle wait for the connection to send them.
+    fn write_handle(&self) -> Option<WriteHandle> {
+        None
+    }
+}
+impl Transport for TcpStream {
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, dur)
+    }
+    fn shutdown(&self) -> io::Result<()> {
+        TcpStream::shutdown(self, Shutdown::Both)
+    }
+    fn shutdown_handle(&self) -> Option<ShutdownHandle> {
+        let s = self.try_clone().ok()?;
+        Some(Box::new(move || { let _ = s.shutdown(Shutdown::Read); }))
+    }
+    fn write_handle(&self) -> Option<WriteHandle> {
+        Some(Box::new(self.try_clone().ok()?))
+    }
+}
+#[cfg(unix)]
+impl Transport for UnixStream {
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        UnixStream::set_read_timeout(self, dur)
+    }
+    fn shutdown(&self) -> io::Result<()> {
+        UnixStream::shutdown(self, Shutdown::Both)
+    }
+    fn shutdown_handle(&self) -> Option<ShutdownHandle> {
+        let s = self.try_clone().ok()?;
+        Some(Box::new(move || { let _ = s.shutdown(Shutdown::Read); }))
+    }
+    fn write_handle(&self) -> Option<WriteHandle> {
+        Some(Box::new(self.try_clone().ok()?))
+    }
+}
+#[cfg(feature = "tls")]
+impl Transport for rustls::StreamOwned<rustls::ClientConnection, TcpStream> {
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        self.get_ref().set_read_timeout(dur)
+    }
+    // Shutting down the socket directly skips the TLS close_notify, but gpsd
+    // doesn't care how the connection ends.
+    fn shutdown(&self) -> io::Result<()> {
+        Transport::shutdown(self.get_ref())
+    }
+    fn shutdown_handle(&self) -> Option<ShutdownHandle> {
+        self.get_ref().shutdown_handle()
+    }
+}
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+        (**self).set_read_timeout(dur)
+    }
+    fn shutdown(&self) -> io::Result<()> {
+        (**self).shutdown()
+    }
+    fn shutdown_handle(&self) -> Option<ShutdownHandle> {
+        (**self).shutdown_handle()
+    }
+    fn write_handle(&self) -> Option<WriteHandle> {
+        (**self).write_handle()
+    }
+}
@@ -2,12 +2,324 @@
 //!
 //! For further information (or where documentation may be sparse), refer to the
 //! GPSD API documentation [here](http://www.catb.org/gpsd/gpsd_json.html).
-use chrono::*;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::BufRead;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use serde_json::Value;
+pub use crate::timestamp::Timestamp;
+use crate::timestamp::{format_millis, to_system_time};
+use crate::{GpsdError, GpsdResult};
 
 fn serde_true() -> bool { true }
 fn serde_false() -> bool { false }
+/// An error estimate in seconds as a `Duration`, if it makes sense as one.
+fn seconds(secs: f64) -> Option<Duration> {
+    Duration::try_from_secs_f64(secs).ok()
+}
 
-#[derive(Serialize, Deserialize, Debug)]
+bitflags! {
+    /// The packet types gpsd has seen from a device, as reported in its
+    /// DEVICE object.
+    ///
+    /// Bits gpsd defines in future are kept; `bits()` gives the raw value.
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    #[serde(from = "u8", into = "u8")]
+    pub struct DeviceFlags: u8 {
+        /// GPS data seen.
+        const GPS = 0x01;
+        /// RTCM2 data seen.
+        const RTCM2 = 0x02;
+        /// RTCM3 data seen.
+        const RTCM3 = 0x04;
+        /// AIS data seen.
+        const AIS = 0x08;
+    }
+}
+impl From<u8> for DeviceFlags {
+    fn from(bits: u8) -> Self {
+        Self::from_bits_retain(bits)
+    }
+}
+impl From<DeviceFlags> for u8 {
+    fn from(flags: DeviceFlags) -> u8 {
+        flags.bits()
+    }
+}
+/// The type of fix a TPV report describes, sent by gpsd as the numbers 0-3.
+///
+/// Variants are ordered by quality, so `mode >= FixMode::Fix2d` means there's
+/// a position.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum FixMode {
+    /// 0: the receiver hasn't said.
+    Unknown,
+    /// 1: no fix.
+    NoFix,
+    /// 2: latitude and longitude only.
+    Fix2d,
+    /// 3: latitude, longitude and altitude.
+    Fix3d
+}
+/// A value gpsd (or the caller) gave for a field that isn't one the field
+/// can take, like a fix mode of 7.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidValue {
+    /// The field, e.g. `"fix mode"`.
+    pub what: &'static str,
+    /// The offending value.
+    pub value: String
+}
+impl InvalidValue {
+    fn new<T: fmt::Display>(what: &'static str, value: T) -> Self {
+        Self { what, value: value.to_string() }
+    }
+}
+impl fmt::Display for InvalidValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid {}: {}", self.what, self.value)
+    }
+}
+impl ::std::error::Error for InvalidValue {}
+impl ::std::convert::TryFrom<u8> for FixMode {
+    type Error = InvalidValue;
+    fn try_from(n: u8) -> Result<Self, InvalidValue> {
+        match n {
+            0 => Ok(FixMode::Unknown),
+            1 => Ok(FixMode::NoFix),
+            2 => Ok(FixMode::Fix2d),
+            3 => Ok(FixMode::Fix3d),
+            n => Err(InvalidValue::new("fix mode", n))
+        }
+    }
+}
+impl From<FixMode> for u8 {
+    fn from(mode: FixMode) -> u8 {
+        mode as u8
+    }
+}
+/// Serial port parity, sent by gpsd as `"N"`, `"O"` or `"E"`.
+///
+/// Reading is lenient: any case, and the words `none`, `odd` and `even`, are
+/// accepted too.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "String")]
+pub enum Parity {
+    /// No parity bit.
+    None,
+    /// Odd parity.
+    Odd,
+    /// Even parity.
+    Even
+}
+impl Parity {
+    /// The letter gpsd uses for this parity.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Parity::None => "N",
+            Parity::Odd => "O",
+            Parity::Even => "E"
+        }
+    }
+}
+impl ::std::str::FromStr for Parity {
+    type Err = InvalidValue;
+    fn from_str(s: &str) -> Result<Self, InvalidValue> {
+        match &s.trim().to_ascii_lowercase()[..] {
+            "n" | "none" => Ok(Parity::None),
+            "o" | "odd" => Ok(Parity::Odd),
+            "e" | "even" => Ok(Parity::Even),
+            _ => Err(InvalidValue::new("parity", s))
+        }
+    }
+}
+impl ::std::convert::TryFrom<String> for Parity {
+    type Error = InvalidValue;
+    fn try_from(s: String) -> Result<Self, InvalidValue> {
+        s.parse()
+    }
+}
+impl ::serde::Serialize for Parity {
+    fn serialize<S: ::serde::Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
+        ser.serialize_str(self.as_str())
+    }
+}
+/// Serial port stop bits, sent by gpsd as the number 1 or 2.
+///
+/// Reading also accepts the numbers as strings, which older gpsd versions
+/// sent.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "StopBitsRepr", into = "u8")]
+pub enum StopBits {
+    /// One stop bit.
+    One,
+    /// Two stop bits.
+    Two
+}
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StopBitsRepr {
+    Number(u8),
+    Text(String)
+}
+impl ::std::convert::TryFrom<u8> for StopBits {
+    type Error = InvalidValue;
+    fn try_from(n: u8) -> Result<Self, InvalidValue> {
+        match n {
+            1 => Ok(StopBits::One),
+            2 => Ok(StopBits::Two),
+            n => Err(InvalidValue::new("stop bits", n))
+        }
+    }
+}
+impl ::std::convert::TryFrom<StopBitsRepr> for StopBits {
+    type Error = InvalidValue;
+    fn try_from(repr: StopBitsRepr) -> Result<Self, InvalidValue> {
+        match repr {
+            StopBitsRepr::Number(n) => Self::try_from(n),
+            StopBitsRepr::Text(s) => s.trim().parse::<u8>().ok()
+                .and_then(|n| Self::try_from(n).ok())
+                .ok_or_else(|| InvalidValue::new("stop bits", s))
+        }
+    }
+}
+impl From<StopBits> for u8 {
+    fn from(bits: StopBits) -> u8 {
+        match bits {
+            StopBits::One => 1,
+            StopBits::Two => 2
+        }
+    }
+}
+/// Which protocol a device is being driven with, sent by gpsd as the number
+/// 0 or 1 (the `native` field).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum DriverMode {
+    /// 0: NMEA.
+    Nmea,
+    /// 1: the device's alternate mode (binary, if it has one).
+    Native
+}
+impl ::std::convert::TryFrom<u8> for DriverMode {
+    type Error = InvalidValue;
+    fn try_from(n: u8) -> Result<Self, InvalidValue> {
+        match n {
+            0 => Ok(DriverMode::Nmea),
+            1 => Ok(DriverMode::Native),
+            n => Err(InvalidValue::new("driver mode", n))
+        }
+    }
+}
+impl From<DriverMode> for u8 {
+    fn from(mode: DriverMode) -> u8 {
+        mode as u8
+    }
+}
+/// The name gpsd uses for a device: usually a path like `/dev/ttyUSB0`, but
+/// possibly a URL such as `tcp://host:port`.
+///
+/// Equality is exact, as gpsd compares names. `matches` ignores differences
+/// in how a path is spelt (`/dev//ttyUSB0/` and `/dev/ttyUSB0` match), and
+/// on Unix, `same_device` also sees through symlinks like
+/// `/dev/serial/by-id/...`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct DevicePath(String);
+impl DevicePath {
+    /// A device named `path`.
+    pub fn new<S: Into<String>>(path: S) -> Self {
+        DevicePath(path.into())
+    }
+    /// The name, exactly as given.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+    /// The path with repeated and trailing slashes and `.` components
+    /// removed. Names that aren't absolute paths are left alone.
+    pub fn normalized(&self) -> Cow<'_, str> {
+        normalize_path(&self.0)
+    }
+    /// Whether `other` names this device, ignoring differences in spelling
+    /// (see `normalized`).
+    pub fn matches(&self, other: &str) -> bool {
+        self.normalized() == normalize_path(other)
+    }
+    /// Like `matches`, but also true if both are paths to the same file once
+    /// symlinks are resolved.
+    #[cfg(unix)]
+    pub fn same_device(&self, other: &str) -> bool {
+        if self.matches(other) {
+            return true;
+        }
+        match (::std::fs::canonicalize(&self.0), ::std::fs::canonicalize(other)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false
+        }
+    }
+}
+pub(crate) fn normalize_path(path: &str) -> Cow<'_, str> {
+    if !path.starts_with('/') {
+        return Cow::Borrowed(path);
+    }
+    let mut ret = String::with_capacity(path.len());
+    for part in path.split('/').filter(|p| !p.is_empty() && *p != ".") {
+        ret.push('/');
+        ret.push_str(part);
+    }
+    if ret.is_empty() {
+        ret.push('/');
+    }
+    if ret == path { Cow::Borrowed(path) } else { Cow::Owned(ret) }
+}
+impl ::std::ops::Deref for DevicePath {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+impl AsRef<str> for DevicePath {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+impl From<String> for DevicePath {
+    fn from(path: String) -> Self {
+        DevicePath(path)
+    }
+}
+impl<'a> From<&'a str> for DevicePath {
+    fn from(path: &'a str) -> Self {
+        DevicePath(path.to_owned())
+    }
+}
+impl From<DevicePath> for String {
+    fn from(path: DevicePath) -> String {
+        path.0
+    }
+}
+impl PartialEq<str> for DevicePath {
+    fn eq(&self, other: &str) -> bool {
+        self.0 == other
+    }
+}
+impl<'a> PartialEq<&'a str> for DevicePath {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.0 == *other
+    }
+}
+impl fmt::Display for DevicePath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 /// A time-position-velocity (TPV) report.
 ///
@@ -20,15 +332,31 @@ fn serde_false() -> bool { false }
 ///
 /// The field documentation is exactly the same across variants; it may be omitted
 /// for brevity.
+///
+/// A report gets the first variant that has every field it needs and room
+/// for every field gpsd sent, so serializing one and parsing it again gives
+/// the same variant back. (Values built by hand only do if they're in the
+/// variant parsing would have picked.)
+///
+/// More variants may be added, so matches need a wildcard arm; the accessors
+/// (`lat()`, `mode()` and so on) work whichever variant you get.
+///
+/// Latitude and longitude are kept as the plain numbers gpsd sent rather than
+/// as `Latitude` and `Longitude`, so that a report with a coordinate out of
+/// range (a receiver reporting garbage, say) still parses and keeps the rest
+/// of its data. `position()`, `latitude()` and `longitude()` give the
+/// range-checked versions.
+#[non_exhaustive]
 pub enum TpvResponse {
     /// 3D GPS fix, with speed and climb data.
     Fix3D {
         /// Name of originating device.
-        device: Option<String>,
+        device: Option<DevicePath>,
         /// Timestamp.
-        time: DateTime<Utc>,
-        /// Fix type: 0 = unknown, 1 = no fix, 2 = 2D fix, 3 = 3D fix.
-        mode: u8,
+        #[serde(with = "crate::timestamp::rfc3339")]
+        time: Timestamp,
+        /// Fix type.
+        mode: FixMode,
         /// Estimated timestamp error (seconds, 95% confidence).
         #[serde(rename = "ept")]
         time_err: f64,
@@ -64,16 +392,21 @@ pub enum TpvResponse {
         climb: f64,
         /// Climb/sink error estimate in meters/sec, 95% confidence.
         #[serde(rename = "epc")]
-        climb_err: Option<f64>
+        climb_err: Option<f64>,
+        /// Fields this crate doesn't know about (yet), e.g. from newer gpsd
+        /// versions.
+        #[serde(flatten)]
+        extra: HashMap<String, Value>
     },
     /// 2D GPS fix, with speed data.
     Fix2D {
         /// Name of originating device.
-        device: Option<String>,
+        device: Option<DevicePath>,
         /// Timestamp.
-        time: DateTime<Utc>,
-        /// Fix type: 0 = unknown, 1 = no fix, 2 = 2D fix, 3 = 3D fix.
-        mode: u8,
+        #[serde(with = "crate::timestamp::rfc3339")]
+        time: Timestamp,
+        /// Fix type.
+        mode: FixMode,
         /// Estimated timestamp error (seconds, 95% confidence).
         #[serde(rename = "ept")]
         time_err: f64,
@@ -93,6 +426,8 @@ pub enum TpvResponse {
         speed: f64,
         #[serde(rename = "eps")]
         speed_err: Option<f64>,
+        #[serde(flatten)]
+        extra: HashMap<String, Value>
     },
     /// Fix with lat/lon, and an unknown smattering of fields.
     /// You'll get this variant if a fix is obtained (lat/lon available), but GPSD
@@ -102,11 +437,12 @@ pub enum TpvResponse {
     /// so we can see what sort of strange data your GPSD is sending!
     LatLonOnly {
         /// Name of originating device.
-        device: Option<String>,
+        device: Option<DevicePath>,
         /// Timestamp.
-        time: DateTime<Utc>,
-        /// Fix type: 0 = unknown, 1 = no fix, 2 = 2D fix, 3 = 3D fix.
-        mode: u8,
+        #[serde(with = "crate::timestamp::rfc3339")]
+        time: Timestamp,
+        /// Fix type.
+        mode: FixMode,
         /// Estimated timestamp error (seconds, 95% confidence).
         #[serde(rename = "ept")]
         time_err: f64,
@@ -134,24 +470,32 @@ pub enum TpvResponse {
         climb: Option<f64>,
         #[serde(rename = "epc")]
         climb_err: Option<f64>,
+        #[serde(flatten)]
+        extra: HashMap<String, Value>
     },
     /// No fix.
     NoFix {
         /// Name of originating device.
-        device: Option<String>,
+        device: Option<DevicePath>,
         /// Timestamp.
-        time: DateTime<Utc>,
-        /// Fix type: 0 = unknown, 1 = no fix, 2 = 2D fix, 3 = 3D fix.
-        mode: u8
+        #[serde(with = "crate::timestamp::rfc3339")]
+        time: Timestamp,
+        /// Fix type.
+        mode: FixMode,
+        #[serde(flatten)]
+        extra: HashMap<String, Value>
     },
     /// Possibly no useful data whatsoever.
     Nothing {
         /// Name of originating device.
-        device: Option<String>,
+        device: Option<DevicePath>,
         /// Timestamp.
-        time: Option<DateTime<Utc>>,
-        /// Fix type: 0 = unknown, 1 = no fix, 2 = 2D fix, 3 = 3D fix.
-        mode: Option<u8>
+        #[serde(default, with = "crate::timestamp::rfc3339::option")]
+        time: Option<Timestamp>,
+        /// Fix type.
+        mode: Option<FixMode>,
+        #[serde(flatten)]
+        extra: HashMap<String, Value>
     },
     /// Something else! You'll get this variant if GPSD sent data that doesn't
     /// exactly fit into any of the categories above.
@@ -159,9 +503,10 @@ pub enum TpvResponse {
     /// If you are getting this variant, we'd greatly appreciate it if you filed an issue,
     /// so we can see what sort of strange data your GPSD is sending!
     Dustbin {
-        device: Option<String>,
-        time: Option<DateTime<Utc>>,
-        mode: Option<u8>,
+        device: Option<DevicePath>,
+        #[serde(default, with = "crate::timestamp::rfc3339::option")]
+        time: Option<Timestamp>,
+        mode: Option<FixMode>,
         #[serde(rename = "ept")]
         time_err: Option<f64>,
         lat: Option<f64>,
@@ -182,15 +527,422 @@ pub enum TpvResponse {
         climb: Option<f64>,
         #[serde(rename = "epc")]
         climb_err: Option<f64>,
+        #[serde(flatten)]
+        extra: HashMap<String, Value>
     },
 }
+/// TPV attributes gpsd has renamed since 3.10, as (name sent now, field
+/// older versions sent it as, version of the change).
+///
+/// A report with only the newer name has it read into the older field, and
+/// serializes with the older name; one with both keeps the newer one in
+/// `extra`. These can't be serde aliases: the versions in between send both
+/// names, which serde would reject as a duplicate field.
+///
+/// `alt` is the only one. `altHAE` is the height above the ellipsoid, which
+/// `alt` never was, and `eph` and `sep` are new attributes (the old `epe`
+/// was never part of the JSON protocol), so those are only in `extra`.
+pub const RENAMED_TPV_FIELDS: &[(&str, &str, &str)] = &[
+    ("altMSL", "alt", "3.20"),
+];
+/// Take out of `extra` whichever renamed attribute stands in for `field`.
+fn take_renamed_field(extra: &mut HashMap<String, Value>, field: &str) -> Option<f64> {
+    let new = RENAMED_TPV_FIELDS.iter()
+        .filter(|&&(_, old, _)| old == field)
+        .find(|&&(new, _, _)| extra.get(new).and_then(Value::as_f64).is_some())?.0;
+    extra.remove(new).and_then(|v| v.as_f64())
+}
+/// Every field a TPV report can have, before sorting it into a variant.
+#[derive(Deserialize)]
+struct TpvFields {
+    device: Option<DevicePath>,
+    #[serde(default, with = "crate::timestamp::rfc3339::option")]
+    time: Option<Timestamp>,
+    mode: Option<FixMode>,
+    #[serde(rename = "ept")]
+    time_err: Option<f64>,
+    lat: Option<f64>,
+    #[serde(rename = "epy")]
+    lat_err: Option<f64>,
+    lon: Option<f64>,
+    #[serde(rename = "epx")]
+    lon_err: Option<f64>,
+    alt: Option<f64>,
+    #[serde(rename = "epv")]
+    alt_err: Option<f64>,
+    track: Option<f64>,
+    #[serde(rename = "epd")]
+    track_err: Option<f64>,
+    speed: Option<f64>,
+    #[serde(rename = "eps")]
+    speed_err: Option<f64>,
+    climb: Option<f64>,
+    #[serde(rename = "epc")]
+    climb_err: Option<f64>,
+    #[serde(flatten)]
+    extra: HashMap<String, Value>
+}
+impl<'de> ::serde::Deserialize<'de> for TpvResponse {
+    fn deserialize<D: ::serde::Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        let TpvFields { device, time, mode, time_err, lat, lat_err, lon, lon_err, alt, alt_err,
+                        track, track_err, speed, speed_err, climb, climb_err, mut extra } = TpvFields::deserialize(de)?;
+        let alt = alt.or_else(|| take_renamed_field(&mut extra, "alt"));
+        let vertical = alt.is_some() || alt_err.is_some() || climb.is_some() || climb_err.is_some();
+        let horizontal = time_err.is_some() || lat.is_some() || lat_err.is_some() || lon.is_some() ||
+            lon_err.is_some() || track.is_some() || track_err.is_some() || speed.is_some() || speed_err.is_some();
+        Ok(match (time, mode, time_err, lat, lon) {
+            (Some(time), Some(mode), Some(time_err), Some(lat), Some(lon)) => match (alt, speed, climb) {
+                (Some(alt), Some(speed), Some(climb)) => TpvResponse::Fix3D {
+                    device, time, mode, time_err, lat, lat_err, lon, lon_err, alt, alt_err,
+                    track, track_err, speed, speed_err, climb, climb_err, extra
+                },
+                (None, Some(speed), None) if alt_err.is_none() && climb_err.is_none() => TpvResponse::Fix2D {
+                    device, time, mode, time_err, lat, lat_err, lon, lon_err,
+                    track, track_err, speed, speed_err, extra
+                },
+                (alt, speed, climb) => TpvResponse::LatLonOnly {
+                    device, time, mode, time_err, lat, lat_err, lon, lon_err, alt, alt_err,
+                    track, track_err, speed, speed_err, climb, climb_err, extra
+                }
+            },
+            (Some(time), Some(mode), ..) if !vertical && !horizontal =>
+                TpvResponse::NoFix { device, time, mode, extra },
+            (time, mode, ..) if !vertical && !horizontal =>
+                TpvResponse::Nothing { device, time, mode, extra },
+            (time, mode, time_err, lat, lon) => TpvResponse::Dustbin {
+                device, time, mode, time_err, lat, lat_err, lon, lon_err, alt, alt_err,
+                track, track_err, speed, speed_err, climb, climb_err, extra
+            }
+        })
+    }
+}
 impl Default for TpvResponse {
     fn default() -> TpvResponse {
-        TpvResponse::Nothing { device: None, time: None, mode: None }
+        TpvResponse::Nothing { device: None, time: None, mode: None, extra: HashMap::new() }
+    }
+}
+impl TpvResponse {
+    /// Name of the originating device, if reported.
+    pub fn device(&self) -> Option<&str> {
+        match *self {
+            TpvResponse::Fix3D { ref device, .. } |
+            TpvResponse::Fix2D { ref device, .. } |
+            TpvResponse::LatLonOnly { ref device, .. } |
+            TpvResponse::NoFix { ref device, .. } |
+            TpvResponse::Nothing { ref device, .. } |
+            TpvResponse::Dustbin { ref device, .. } => device.as_deref()
+        }
+    }
+    /// Latitude in degrees, if reported.
+    pub fn lat(&self) -> Option<f64> {
+        match *self {
+            TpvResponse::Fix3D { lat, .. } |
+            TpvResponse::Fix2D { lat, .. } |
+            TpvResponse::LatLonOnly { lat, .. } => Some(lat),
+            TpvResponse::Dustbin { lat, .. } => lat,
+            _ => None
+        }
+    }
+    /// Longitude in degrees, if reported.
+    pub fn lon(&self) -> Option<f64> {
+        match *self {
+            TpvResponse::Fix3D { lon, .. } |
+            TpvResponse::Fix2D { lon, .. } |
+            TpvResponse::LatLonOnly { lon, .. } => Some(lon),
+            TpvResponse::Dustbin { lon, .. } => lon,
+            _ => None
+        }
+    }
+    /// Altitude in meters, if reported.
+    pub fn alt(&self) -> Option<f64> {
+        match *self {
+            TpvResponse::Fix3D { alt, .. } => Some(alt),
+            TpvResponse::LatLonOnly { alt, .. } |
+            TpvResponse::Dustbin { alt, .. } => alt,
+            _ => None
+        }
+    }
+    /// Course over ground in degrees from true north, if reported.
+    pub fn track(&self) -> Option<f64> {
+        match *self {
+            TpvResponse::Fix3D { track, .. } |
+            TpvResponse::Fix2D { track, .. } |
+            TpvResponse::LatLonOnly { track, .. } |
+            TpvResponse::Dustbin { track, .. } => track,
+            _ => None
+        }
+    }
+    /// Speed over ground in meters per second, if reported.
+    pub fn speed(&self) -> Option<f64> {
+        match *self {
+            TpvResponse::Fix3D { speed, .. } |
+            TpvResponse::Fix2D { speed, .. } => Some(speed),
+            TpvResponse::LatLonOnly { speed, .. } |
+            TpvResponse::Dustbin { speed, .. } => speed,
+            _ => None
+        }
+    }
+    /// Climb (positive) or sink (negative) rate in meters per second, if
+    /// reported.
+    pub fn climb(&self) -> Option<f64> {
+        match *self {
+            TpvResponse::Fix3D { climb, .. } => Some(climb),
+            TpvResponse::LatLonOnly { climb, .. } |
+            TpvResponse::Dustbin { climb, .. } => climb,
+            _ => None
+        }
+    }
+    /// The fix type, if reported.
+    pub fn mode(&self) -> Option<FixMode> {
+        match *self {
+            TpvResponse::Fix3D { mode, .. } |
+            TpvResponse::Fix2D { mode, .. } |
+            TpvResponse::LatLonOnly { mode, .. } |
+            TpvResponse::NoFix { mode, .. } => Some(mode),
+            TpvResponse::Nothing { mode, .. } |
+            TpvResponse::Dustbin { mode, .. } => mode
+        }
+    }
+    /// Time of the report, if it has one.
+    pub fn time(&self) -> Option<Timestamp> {
+        match *self {
+            TpvResponse::Fix3D { time, .. } |
+            TpvResponse::Fix2D { time, .. } |
+            TpvResponse::LatLonOnly { time, .. } |
+            TpvResponse::NoFix { time, .. } => Some(time),
+            TpvResponse::Nothing { time, .. } |
+            TpvResponse::Dustbin { time, .. } => time
+        }
+    }
+    /// Time of the report as a `SystemTime`, if it has one.
+    pub fn system_time(&self) -> Option<SystemTime> {
+        self.time().map(to_system_time)
+    }
+    /// Estimated timestamp error (95% confidence), if reported.
+    pub fn time_error(&self) -> Option<Duration> {
+        match *self {
+            TpvResponse::Fix3D { time_err, .. } |
+            TpvResponse::Fix2D { time_err, .. } |
+            TpvResponse::LatLonOnly { time_err, .. } => seconds(time_err),
+            TpvResponse::Dustbin { time_err, .. } => time_err.and_then(seconds),
+            _ => None
+        }
+    }
+    /// Fields this crate doesn't know about, whichever variant this is.
+    pub fn extra(&self) -> &HashMap<String, Value> {
+        match *self {
+            TpvResponse::Fix3D { ref extra, .. } |
+            TpvResponse::Fix2D { ref extra, .. } |
+            TpvResponse::LatLonOnly { ref extra, .. } |
+            TpvResponse::NoFix { ref extra, .. } |
+            TpvResponse::Nothing { ref extra, .. } |
+            TpvResponse::Dustbin { ref extra, .. } => extra
+        }
+    }
+    /// Latitude, if reported and within ±90 degrees.
+    pub fn latitude(&self) -> Option<Latitude> {
+        Latitude::new(self.lat()?).ok()
+    }
+    /// Longitude, if reported and within ±180 degrees.
+    pub fn longitude(&self) -> Option<Longitude> {
+        Longitude::new(self.lon()?).ok()
+    }
+    /// Where the report says the receiver is, if it has a latitude and
+    /// longitude in range.
+    ///
+    /// Newer gpsd versions send `altMSL` and `altHAE`; older ones only send
+    /// `alt`, which is taken to be above mean sea level.
+    pub fn position(&self) -> Option<Position> {
+        let extra = |k: &str| self.extra().get(k).and_then(Value::as_f64);
+        let (lat, lon) = (self.latitude()?, self.longitude()?);
+        Some(Position {
+            lat,
+            lon,
+            alt_msl: extra("altMSL").or_else(|| self.alt()),
+            alt_hae: extra("altHAE"),
+            geoid_sep: extra("geoidSep")
+        })
+    }
+    /// Flatten a report with a position and time into a `Fix`. Returns
+    /// `None` for reports without one.
+    pub fn into_fix(self) -> Option<Fix> {
+        let fix = match self {
+            TpvResponse::Fix3D { device, time, mode, time_err, lat, lat_err, lon, lon_err, alt, alt_err,
+                                 track, track_err, speed, speed_err, climb, climb_err, extra } => Fix {
+                device, time, mode: Some(mode), time_err: Some(time_err), lat, lat_err, lon, lon_err,
+                alt: Some(alt), alt_err, track, track_err, speed: Some(speed), speed_err,
+                climb: Some(climb), climb_err, extra
+            },
+            TpvResponse::Fix2D { device, time, mode, time_err, lat, lat_err, lon, lon_err,
+                                 track, track_err, speed, speed_err, extra } => Fix {
+                device, time, mode: Some(mode), time_err: Some(time_err), lat, lat_err, lon, lon_err,
+                alt: None, alt_err: None, track, track_err, speed: Some(speed), speed_err,
+                climb: None, climb_err: None, extra
+            },
+            TpvResponse::LatLonOnly { device, time, mode, time_err, lat, lat_err, lon, lon_err, alt, alt_err,
+                                      track, track_err, speed, speed_err, climb, climb_err, extra } => Fix {
+                device, time, mode: Some(mode), time_err: Some(time_err), lat, lat_err, lon, lon_err,
+                alt, alt_err, track, track_err, speed, speed_err, climb, climb_err, extra
+            },
+            TpvResponse::Dustbin { device, time, mode, time_err, lat, lat_err, lon, lon_err, alt, alt_err,
+                                   track, track_err, speed, speed_err, climb, climb_err, extra } => Fix {
+                device, time: time?, mode, time_err, lat: lat?, lat_err, lon: lon?, lon_err,
+                alt, alt_err, track, track_err, speed, speed_err, climb, climb_err, extra
+            },
+            TpvResponse::NoFix { .. } | TpvResponse::Nothing { .. } => return None
+        };
+        Some(fix)
+    }
+}
+/// A latitude in degrees, north positive, known to be within ±90.
+///
+/// Receivers can report garbage coordinates during a cold start; these
+/// fail to construct (or deserialize) rather than reaching application
+/// logic.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[serde(try_from = "f64", into = "f64")]
+pub struct Latitude(f64);
+impl Latitude {
+    /// `degrees` as a latitude, if it's within ±90.
+    pub fn new(degrees: f64) -> Result<Self, InvalidValue> {
+        if (-90.0..=90.0).contains(&degrees) {
+            Ok(Latitude(degrees))
+        } else {
+            Err(InvalidValue::new("latitude", degrees))
+        }
+    }
+    /// The value in degrees.
+    pub fn degrees(self) -> f64 {
+        self.0
+    }
+}
+impl ::std::convert::TryFrom<f64> for Latitude {
+    type Error = InvalidValue;
+    fn try_from(degrees: f64) -> Result<Self, InvalidValue> {
+        Latitude::new(degrees)
+    }
+}
+impl From<Latitude> for f64 {
+    fn from(lat: Latitude) -> f64 {
+        lat.0
+    }
+}
+impl fmt::Display for Latitude {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+/// A longitude in degrees, east positive, known to be within ±180. See
+/// `Latitude`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[serde(try_from = "f64", into = "f64")]
+pub struct Longitude(f64);
+impl Longitude {
+    /// `degrees` as a longitude, if it's within ±180.
+    pub fn new(degrees: f64) -> Result<Self, InvalidValue> {
+        if (-180.0..=180.0).contains(&degrees) {
+            Ok(Longitude(degrees))
+        } else {
+            Err(InvalidValue::new("longitude", degrees))
+        }
+    }
+    /// The value in degrees.
+    pub fn degrees(self) -> f64 {
+        self.0
+    }
+}
+impl ::std::convert::TryFrom<f64> for Longitude {
+    type Error = InvalidValue;
+    fn try_from(degrees: f64) -> Result<Self, InvalidValue> {
+        Longitude::new(degrees)
+    }
+}
+impl From<Longitude> for f64 {
+    fn from(lon: Longitude) -> f64 {
+        lon.0
+    }
+}
+impl fmt::Display for Longitude {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+/// A position, as reported in a TPV. Get one with `TpvResponse::position`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    /// Latitude in degrees: +/- signifies North/South.
+    pub lat: Latitude,
+    /// Longitude in degrees: +/- signifies East/West.
+    pub lon: Longitude,
+    /// Altitude above mean sea level, in meters.
+    pub alt_msl: Option<f64>,
+    /// Altitude above the WGS84 ellipsoid, in meters.
+    pub alt_hae: Option<f64>,
+    /// Height of the geoid (mean sea level) above the ellipsoid, in meters.
+    pub geoid_sep: Option<f64>
+}
+impl Position {
+    /// A position, if `lat` is within ±90 degrees and `lon` within ±180.
+    pub fn new(lat: f64, lon: f64, alt_msl: Option<f64>, alt_hae: Option<f64>) -> Option<Self> {
+        let (lat, lon) = (Latitude::new(lat).ok()?, Longitude::new(lon).ok()?);
+        Some(Position { lat, lon, alt_msl, alt_hae, geoid_sep: None })
+    }
+    /// Height above the WGS84 ellipsoid, in meters: `alt_hae` if gpsd sent
+    /// it, and otherwise `alt_msl` plus `geoid_sep`.
+    pub fn height_above_ellipsoid(&self) -> Option<f64> {
+        self.alt_hae.or_else(|| Some(self.alt_msl? + self.geoid_sep?))
+    }
+}
+/// A TPV report with a position, flattened into one struct. Get one with
+/// `TpvResponse::into_fix`.
+///
+/// Field documentation is as for `TpvResponse::Fix3D`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Fix {
+    pub device: Option<DevicePath>,
+    #[serde(with = "crate::timestamp::rfc3339")]
+    pub time: Timestamp,
+    pub mode: Option<FixMode>,
+    #[serde(rename = "ept")]
+    pub time_err: Option<f64>,
+    pub lat: f64,
+    #[serde(rename = "epy")]
+    pub lat_err: Option<f64>,
+    pub lon: f64,
+    #[serde(rename = "epx")]
+    pub lon_err: Option<f64>,
+    pub alt: Option<f64>,
+    #[serde(rename = "epv")]
+    pub alt_err: Option<f64>,
+    pub track: Option<f64>,
+    #[serde(rename = "epd")]
+    pub track_err: Option<f64>,
+    pub speed: Option<f64>,
+    #[serde(rename = "eps")]
+    pub speed_err: Option<f64>,
+    pub climb: Option<f64>,
+    #[serde(rename = "epc")]
+    pub climb_err: Option<f64>,
+    /// Fields this crate doesn't know about.
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>
+}
+impl Fix {
+    /// Time of the fix as a `SystemTime`.
+    pub fn system_time(&self) -> SystemTime {
+        to_system_time(self.time)
+    }
+    /// Estimated timestamp error (95% confidence), if reported.
+    pub fn time_error(&self) -> Option<Duration> {
+        self.time_err.and_then(seconds)
     }
 }
 /// A single satellite.
-#[derive(Serialize, Deserialize, Debug)]
+///
+/// Satellites sort by `id()`, then by their other fields, so a report's
+/// satellites can be kept in a `BTreeSet` (or a `HashSet`) and compared with
+/// the next report's. Signal strengths compare bit for bit.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SatelliteObject {
     #[serde(rename = "PRN")]
     /// PRN ID of the satellite. 1-63 are GNSS satellites, 64-96 are GLONASS
@@ -203,14 +955,89 @@ pub struct SatelliteObject {
     /// Elevation in degrees.
     pub elevation: u32,
     #[serde(rename = "ss")]
-    /// Signal strength in dB.
-    pub signal_strength: u32,
+    /// Signal strength in dB. Older gpsd versions send whole numbers.
+    pub signal_strength: f64,
     /// Used in current solution? (SBAS/WAAS/EGNOS satellites may be flagged
     /// used if the solution has corrections from them, but not all drivers make
     /// this information available.)
-    pub used: bool
+    pub used: bool,
+    /// The GNSS the satellite belongs to, as gpsd numbers them (0 = GPS, 1 =
+    /// SBAS, 2 = Galileo, 3 = BeiDou, 5 = QZSS, 6 = GLONASS...). Only sent by
+    /// newer gpsd versions; see `constellation()`.
+    #[serde(rename = "gnssid", skip_serializing_if = "Option::is_none")]
+    pub gnss_id: Option<u8>
+}
+impl PartialEq for SatelliteObject {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for SatelliteObject {}
+impl PartialOrd for SatelliteObject {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for SatelliteObject {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.id().cmp(&other.id())
+            .then(self.azimuth.cmp(&other.azimuth))
+            .then(self.elevation.cmp(&other.elevation))
+            .then(self.signal_strength.total_cmp(&other.signal_strength))
+            .then(self.used.cmp(&other.used))
+    }
+}
+impl Hash for SatelliteObject {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+        (self.azimuth, self.elevation, self.signal_strength.to_bits(), self.used).hash(state);
+    }
+}
+/// The satellite system a satellite belongs to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Constellation {
+    Gps,
+    Sbas,
+    Glonass,
+    Galileo,
+    BeiDou,
+    Qzss,
+    /// Some other system, or one that couldn't be worked out.
+    Unknown
+}
+impl SatelliteObject {
+    /// What identifies the satellite: its GNSS, if gpsd sent one, and PRN.
+    pub fn id(&self) -> (Option<u8>, u16) {
+        (self.gnss_id, self.prn)
+    }
+    /// Which system the satellite belongs to: from `gnss_id` if gpsd sent
+    /// it, otherwise guessed from the PRN using gpsd's numbering (1-63 GPS,
+    /// 64-96 GLONASS, 100-164 SBAS, 193-200 QZSS, 201-263 BeiDou, 301-336
+    /// Galileo).
+    pub fn constellation(&self) -> Constellation {
+        if let Some(id) = self.gnss_id {
+            return match id {
+                0 => Constellation::Gps,
+                1 => Constellation::Sbas,
+                2 => Constellation::Galileo,
+                3 => Constellation::BeiDou,
+                5 => Constellation::Qzss,
+                6 => Constellation::Glonass,
+                _ => Constellation::Unknown
+            };
+        }
+        match self.prn {
+            1..=63 => Constellation::Gps,
+            64..=96 => Constellation::Glonass,
+            100..=164 => Constellation::Sbas,
+            193..=200 => Constellation::Qzss,
+            201..=263 => Constellation::BeiDou,
+            301..=336 => Constellation::Galileo,
+            _ => Constellation::Unknown
+        }
+    }
 }
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 /// A sky view report (SKY) of GPS satellite positions.
 ///
 /// If there is no GPS device available, or no skyview has been reported yet,
@@ -229,30 +1056,46 @@ pub struct SatelliteObject {
 /// missing if some of these determinants are singular. It can even happen that
 /// the device reports an error estimate in meters when the corresponding DOP is
 /// unavailable; some devices use more sophisticated error modeling than the
-/// covariance calculation.
+/// covariance calculation. `fill_missing_dops` does that calculation here
+/// for DOPs the device left out.
 pub struct SkyResponse {
     /// Name of originating device.
-    pub device: Option<String>,
+    pub device: Option<DevicePath>,
     /// Timestamp.
-    pub time: Option<DateTime<Utc>>,
+    #[serde(default, with = "crate::timestamp::rfc3339::option")]
+    pub time: Option<Timestamp>,
     /// Longitudinal d.o.p.
-    pub xdop: Option<f32>,
+    pub xdop: Option<f64>,
     /// Latitutinal d.o.p.
-    pub ydop: Option<f32>,
+    pub ydop: Option<f64>,
     /// Altitude d.o.p.
-    pub vdop: Option<f32>,
+    pub vdop: Option<f64>,
     /// Time d.o.p.
-    pub tdop: Option<f32>,
+    pub tdop: Option<f64>,
     /// Horizontal d.o.p.
-    pub hdop: Option<f32>,
+    pub hdop: Option<f64>,
     /// Spherical d.o.p.
-    pub pdop: Option<f32>,
+    pub pdop: Option<f64>,
     /// Hyperspherical d.o.p.
-    pub gdop: Option<f32>,
+    pub gdop: Option<f64>,
     /// Satellites in skyview.
-    pub satellites: Vec<SatelliteObject>
+    pub satellites: Vec<SatelliteObject>,
+    /// Fields this crate doesn't know about (yet), e.g. from newer gpsd
+    /// versions.
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>
+}
+impl SkyResponse {
+    /// The satellites used in the current solution.
+    pub fn used_satellites(&self) -> impl Iterator<Item = &SatelliteObject> {
+        self.satellites.iter().filter(|s| s.used)
+    }
+    /// Time of the report as a `SystemTime`, if it has one.
+    pub fn system_time(&self) -> Option<SystemTime> {
+        self.time.map(to_system_time)
+    }
 }
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 /// Information about a device known to gpsd.
 ///
@@ -263,29 +1106,22 @@ pub struct SkyResponse {
 ///
 /// Basically, the aim here is to reduce the amount of Option unwrapping
 /// you have to do, as gpsd specifies that all these fields are optional.
+///
+/// More variants may be added; `path()` and `is_active()` work for all of them.
+#[non_exhaustive]
 pub enum DeviceObject {
     ActiveSeenPackets {
         /// Name the device for which the control bits are being reported, or
         /// for which they are to be applied. This attribute may be omitted only
         /// when there is exactly one subscribed channel
-        path: Option<String>,
+        path: Option<DevicePath>,
         /// Time the device was activated as an ISO8601 timestamp. If the device
         /// is inactive this attribute is absent.
-        activated: DateTime<Utc>,
-        /// Bit vector of property flags. Currently defined flags are: describe
-        /// packet types seen so far (GPS, RTCM2, RTCM3, AIS). Won't be reported
-        /// if empty, e.g. before gpsd has seen identifiable packets from the
-        /// device.
-        ///
-        /// # Flags
-        ///
-        /// - 0x01: GPS data seen
-        /// - 0x02: RTCM2 data seen
-        /// - 0x04: RTCM3 data seen
-        /// - 0x08: AIS data seen
-        ///
-        /// Yes, I know manual bitflags suck. I'll fix it one day if you bug me.
-        flags: u8,
+        #[serde(with = "crate::timestamp::rfc3339")]
+        activated: Timestamp,
+        /// The packet types seen so far. Won't be reported if empty, e.g.
+        /// before gpsd has seen identifiable packets from the device.
+        flags: DeviceFlags,
         /// GPSD's name for the device driver type. Won't be reported before
         /// gpsd has seen identifiable packets from the device.
         driver: String,
@@ -293,14 +1129,14 @@ pub enum DeviceObject {
         subtype: Option<String>,
         /// Device speed in bits per second.
         bps: Option<u32>,
-        /// N, O or E for no parity, odd, or even.
-        parity: Option<String>,
-        /// Stop bits (1 or 2).
-        stopbits: Option<String>,
-        /// 0 means NMEA mode and 1 means alternate mode (binary if it has one,
-        /// for SiRF and Evermore chipsets in particular). Attempting to set
-        /// this mode on a non-GPS device will yield an error.
-        native: Option<u8>,
+        /// Parity.
+        parity: Option<Parity>,
+        /// Stop bits.
+        stopbits: Option<StopBits>,
+        /// NMEA or alternate mode (binary if it has one, for SiRF and
+        /// Evermore chipsets in particular). Attempting to set this mode on a
+        /// non-GPS device will yield an error.
+        native: Option<DriverMode>,
         /// Device cycle time in seconds.
         cycle: Option<f32>,
         /// Device minimum cycle time in seconds. Reported from ?DEVICE when
@@ -309,21 +1145,59 @@ pub enum DeviceObject {
         minicycle: Option<f32>
     },
     Active {
-        path: Option<String>,
-        activated: DateTime<Utc>,
+        path: Option<DevicePath>,
+        #[serde(with = "crate::timestamp::rfc3339")]
+        activated: Timestamp,
         subtype: Option<String>,
         bps: Option<u32>,
-        parity: Option<String>,
-        stopbits: Option<String>,
-        native: Option<u8>,
+        parity: Option<Parity>,
+        stopbits: Option<StopBits>,
+        native: Option<DriverMode>,
         cycle: Option<f32>,
         minicycle: Option<f32>
     },
     Inactive {
-        path: Option<String>
+        path: Option<DevicePath>
     }
 }
-#[derive(Serialize, Deserialize, Debug)]
+/// How much raw data gpsd sends in watcher mode, sent as the number 0, 1 or
+/// 2 (the WATCH `raw` field).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum RawLevel {
+    /// 0: no raw data.
+    #[default]
+    Off,
+    /// 1: the unprocessed NMEA or AIVDM data stream from whatever device is
+    /// attached. Binary GPS packets are hex-dumped; RTCM2 and RTCM3 packets
+    /// aren't dumped.
+    Hexdump,
+    /// 2: like `Hexdump`, but binary data is reported verbatim.
+    Verbatim
+}
+impl RawLevel {
+    /// Whether gpsd will send raw data at all.
+    pub fn is_on(self) -> bool {
+        self != RawLevel::Off
+    }
+}
+impl ::std::convert::TryFrom<u8> for RawLevel {
+    type Error = InvalidValue;
+    fn try_from(n: u8) -> Result<Self, InvalidValue> {
+        match n {
+            0 => Ok(RawLevel::Off),
+            1 => Ok(RawLevel::Hexdump),
+            2 => Ok(RawLevel::Verbatim),
+            n => Err(InvalidValue::new("raw level", n))
+        }
+    }
+}
+impl From<RawLevel> for u8 {
+    fn from(level: RawLevel) -> u8 {
+        level as u8
+    }
+}
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 /// Information about watcher mode parameters.
 pub struct WatchObject {
     #[serde(default = "serde_true")]
@@ -337,13 +1211,9 @@ pub struct WatchObject {
     /// Enable (true) or disable (false) dumping of binary packets as
     /// pseudo-NMEA. Default is false.
     pub nmea: bool,
-    /// Controls 'raw' mode. When this attribute is set to 1 for a channel, gpsd
-    /// reports the unprocessed NMEA or AIVDM data stream from whatever device
-    /// is attached. Binary GPS packets are hex-dumped. RTCM2 and RTCM3 packets
-    /// are not dumped in raw mode. When this attribute is set to 2 for a
-    /// channel that processes binary data, gpsd reports the received data
-    /// verbatim without hex-dumping.
-    pub raw: Option<u32>,
+    /// Controls 'raw' mode; see `RawLevel`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<RawLevel>,
     #[serde(default = "serde_false")]
     /// If true, apply scaling divisors to output before dumping; default is
     /// false.
@@ -360,9 +1230,11 @@ pub struct WatchObject {
     /// If present, enable watching only of the specified device rather than all
     /// devices. Useful with raw and NMEA modes in which device responses aren't
     /// tagged. Has no effect when used with enable:false.
-    pub device: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device: Option<DevicePath>,
     /// URL of the remote daemon reporting the watch set. If empty, this is a
     /// WATCH response from the local daemon.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub remote: Option<String>
 }
 impl Default for WatchObject {
@@ -380,12 +1252,61 @@ impl Default for WatchObject {
         }
     }
 }
-#[derive(Serialize, Deserialize, Debug)]
+/// Chainable setters, for building watches in one expression:
+///
+/// ```rust
+/// # use unbounded_gpsd::types::WatchObject;
+/// let watch = WatchObject::default().with_json().with_device("/dev/ttyUSB0");
+/// assert!(watch.json && watch.device.is_some());
+/// ```
+impl WatchObject {
+    /// Ask for JSON reports.
+    pub fn with_json(mut self) -> Self {
+        self.json = true;
+        self
+    }
+    /// Watch only `device`.
+    pub fn with_device(mut self, device: &str) -> Self {
+        self.device = Some(device.into());
+        self
+    }
+    /// Ask for TOFF and PPS messages.
+    pub fn with_pps(mut self) -> Self {
+        self.pps = true;
+        self
+    }
+    /// Set the raw mode `level`.
+    pub fn with_raw(mut self, level: RawLevel) -> Self {
+        self.raw = Some(level);
+        self
+    }
+    /// Ask for pseudo-NMEA; see `nmea::NmeaDecoder` for reading it.
+    pub fn with_nmea(mut self) -> Self {
+        self.nmea = true;
+        self
+    }
+    /// Whether gpsd will send lines that aren't JSON with this watch: raw
+    /// data or pseudo-NMEA. These come back as `Response::Raw`.
+    pub fn sends_raw(&self) -> bool {
+        self.nmea || self.raw.is_some_and(RawLevel::is_on)
+    }
+}
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "class")]
 /// A response from GPSD.
 ///
 /// For single-struct variants, the documentation on the struct usually has
 /// more information.
+///
+/// Serializing a response read from gpsd gives JSON that deserializes back
+/// into the same response, with the exception of `Raw` lines, which can't be
+/// serialized, and `Unknown` ones, which only parse back in
+/// `ParseMode::Lenient`.
+///
+/// More variants will be added as more of gpsd's classes (PPS, GST, AIS...)
+/// are supported, so matches need a wildcard arm. `class()` names the class of
+/// any response, and `kind()` says which variant it is.
+#[non_exhaustive]
 pub enum Response {
     #[serde(rename = "TPV")]
     Tpv(TpvResponse),
@@ -394,7 +1315,8 @@ pub enum Response {
     #[serde(rename = "POLL")]
     /// Data from the last-seen fixes on all active GPS devices.
     Poll {
-        time: DateTime<Utc>,
+        #[serde(with = "crate::timestamp::rfc3339")]
+        time: Timestamp,
         /// Count of active devices.
         active: u32,
         tpv: Vec<TpvResponse>,
@@ -421,5 +1343,351 @@ pub enum Response {
     Error {
         message: String
     },
+    Raw(String),
+    /// A response of a class this crate doesn't know about, as gpsd sent it.
+    /// Only returned in `ParseMode::Lenient`. Serializes as the JSON gpsd
+    /// sent.
+    #[serde(untagged, skip_deserializing)]
+    Unknown(Value)
+}
+/// Which variant a `Response` is, for filtering and counting responses
+/// without matching on their data. Get it with `Response::kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum ResponseKind {
+    Tpv,
+    Sky,
+    Poll,
+    Device,
+    Devices,
+    Watch,
+    Version,
+    Error,
+    Raw,
+    Unknown
+}
+impl ResponseKind {
+    /// The class gpsd names responses of this kind with; see
+    /// `Response::class`.
+    pub fn class(self) -> &'static str {
+        match self {
+            ResponseKind::Tpv => "TPV",
+            ResponseKind::Sky => "SKY",
+            ResponseKind::Poll => "POLL",
+            ResponseKind::Device => "DEVICE",
+            ResponseKind::Devices => "DEVICES",
+            ResponseKind::Watch => "WATCH",
+            ResponseKind::Version => "VERSION",
+            ResponseKind::Error => "ERROR",
+            ResponseKind::Raw => "RAW",
+            ResponseKind::Unknown => "UNKNOWN"
+        }
+    }
+}
+impl fmt::Display for ResponseKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.class())
+    }
+}
+impl FromStr for Response {
+    type Err = GpsdError;
+    fn from_str(s: &str) -> GpsdResult<Response> {
+        Response::from_json_line(s)
+    }
+}
+/// Parse every line of a log of gpsd output (from `gpspipe -w`, say, or a
+/// test fixture), skipping blank lines.
+///
+/// A line that can't be parsed gives an error, and parsing carries on with
+/// the next one; the iterator ends at the end of `reader`, or after an I/O
+/// error.
+pub fn parse_lines<R: BufRead>(reader: R) -> impl Iterator<Item = GpsdResult<Response>> {
+    let mut lines = reader.split(b'\n');
+    let mut failed = false;
+    ::std::iter::from_fn(move || {
+        if failed {
+            return None;
+        }
+        loop {
+            let line = match lines.next()? {
+                Ok(l) => crate::decode_line(l),
+                Err(e) => {
+                    failed = true;
+                    return Some(Err(e.into()));
+                }
+            };
+            if !line.trim().is_empty() {
+                return Some(Response::from_json_line(&line));
+            }
+        }
+    })
+}
+/// How to treat output from gpsd that this crate doesn't expect. See
+/// `GpsdConnection::set_parse_mode`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Take what can be understood: unknown fields are kept in the `extra`
+    /// maps of TPV and SKY reports, and responses of unknown classes come
+    /// through as `Response::Unknown`.
+    #[default]
+    Lenient,
+    /// Fail on anything unexpected: unknown fields in TPV and SKY reports,
+    /// and responses of unknown classes, are errors. Useful for checking
+    /// this crate against a new gpsd.
+    Strict
+}
+/// A response, together with the line gpsd sent for it.
+///
+/// Re-serializing a `Response` changes field order and number formatting,
+/// and loses anything this crate doesn't capture, so loggers that need to
+/// keep exactly what gpsd said should keep `raw`. Get one from `GpsdConnection::get_envelope`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Envelope {
+    /// The line as received, without its line ending.
+    pub raw: String,
+    /// The parsed response.
+    pub parsed: Response
+}
+impl DeviceObject {
+    /// The device's path, if reported.
+    pub fn path(&self) -> Option<&str> {
+        match *self {
+            DeviceObject::ActiveSeenPackets { ref path, .. } |
+            DeviceObject::Active { ref path, .. } |
+            DeviceObject::Inactive { ref path } => path.as_deref()
+        }
+    }
+    /// Whether gpsd reports the device as active.
+    pub fn is_active(&self) -> bool {
+        !matches!(*self, DeviceObject::Inactive { .. })
+    }
+}
+impl Response {
+    /// Parse one line of gpsd output, as logged by `gpspipe -w`, without
+    /// needing a connection. Surrounding whitespace is ignored. Also
+    /// available as `line.parse::<Response>()`.
+    pub fn from_json_line(line: &str) -> GpsdResult<Response> {
+        crate::parse_line(line.trim().to_owned(), false)
+    }
+    /// Which variant this is, without the data.
+    pub fn kind(&self) -> ResponseKind {
+        match *self {
+            Response::Tpv(..) => ResponseKind::Tpv,
+            Response::Sky(..) => ResponseKind::Sky,
+            Response::Poll { .. } => ResponseKind::Poll,
+            Response::Device(..) => ResponseKind::Device,
+            Response::Devices { .. } => ResponseKind::Devices,
+            Response::Watch(..) => ResponseKind::Watch,
+            Response::Version { .. } => ResponseKind::Version,
+            Response::Error { .. } => ResponseKind::Error,
+            Response::Raw(..) => ResponseKind::Raw,
+            Response::Unknown(..) => ResponseKind::Unknown
+        }
+    }
+    /// The response's class, as gpsd names it (e.g. `"TPV"`). `Raw` lines
+    /// are `"RAW"`, and `Unknown` ones `"UNKNOWN"`; see `class_name`.
+    pub fn class(&self) -> &'static str {
+        self.kind().class()
+    }
+    /// Like `class`, but for `Unknown` responses the class gpsd actually
+    /// sent (e.g. `"TOFF"`), if it sent one.
+    pub fn class_name(&self) -> &str {
+        match *self {
+            Response::Unknown(ref v) => v.get("class").and_then(Value::as_str).unwrap_or("UNKNOWN"),
+            _ => self.class()
+        }
+    }
+    /// Names of the fields gpsd sent that this crate doesn't know about, as
+    /// `ParseMode::Strict` checks for.
+    pub(crate) fn unknown_fields(&self) -> Vec<&str> {
+        let extras: Vec<&HashMap<String, Value>> = match *self {
+            Response::Tpv(ref tpv) => vec![tpv.extra()],
+            Response::Sky(ref sky) => vec![&sky.extra],
+            Response::Poll { ref tpv, ref sky, .. } =>
+                tpv.iter().map(TpvResponse::extra).chain(sky.iter().map(|s| &s.extra)).collect(),
+            _ => vec![]
+        };
+        extras.into_iter().flat_map(|e| e.keys().map(|k| &k[..])).collect()
+    }
+    /// The device this response is about, for responses that are about a
+    /// single device and say which.
+    pub fn device(&self) -> Option<&str> {
+        match *self {
+            Response::Tpv(ref tpv) => tpv.device(),
+            Response::Sky(ref sky) => sky.device.as_deref(),
+            Response::Device(ref dev) => dev.path(),
+            _ => None
+        }
+    }
+}
+// One-line summaries, in the spirit of cgps, for logs and debugging tools.
+
+impl fmt::Display for FixMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            FixMode::Unknown => "unknown",
+            FixMode::NoFix => "no fix",
+            FixMode::Fix2d => "2D fix",
+            FixMode::Fix3d => "3D fix"
+        })
+    }
+}
+/// `/dev/ttyUSB0 3D fix 2017-07-01T12:00:00.000Z 59.345000,18.054000 alt 78.2m speed 0.06m/s track 320.9°`
+impl fmt::Display for TpvResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.device().unwrap_or("-"), self.mode().unwrap_or(FixMode::Unknown))?;
+        if let Some(time) = self.time() {
+            write!(f, " {}", format_millis(&time))?;
+        }
+        if let (Some(lat), Some(lon)) = (self.lat(), self.lon()) {
+            write!(f, " {:.6},{:.6}", lat, lon)?;
+        }
+        if let Some(alt) = self.alt() {
+            write!(f, " alt {:.1}m", alt)?;
+        }
+        if let Some(speed) = self.speed() {
+            write!(f, " speed {:.2}m/s", speed)?;
+        }
+        if let Some(track) = self.track() {
+            write!(f, " track {:.1}°", track)?;
+        }
+        Ok(())
+    }
+}
+/// `/dev/ttyUSB0 7/9 satellites used hdop 1.2`
+impl fmt::Display for SkyResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let used = self.satellites.iter().filter(|s| s.used).count();
+        write!(f, "{} {}/{} satellites used", self.device.as_deref().unwrap_or("-"), used, self.satellites.len())?;
+        if let Some(hdop) = self.hdop {
+            write!(f, " hdop {:.1}", hdop)?;
+        }
+        Ok(())
+    }
+}
+/// `/dev/ttyUSB0 u-blox 9600 bps active`
+impl fmt::Display for DeviceObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.path().unwrap_or("-"))?;
+        let bps = match *self {
+            DeviceObject::ActiveSeenPackets { ref driver, bps, .. } => {
+                write!(f, " {}", driver)?;
+                bps
+            },
+            DeviceObject::Active { bps, .. } => bps,
+            DeviceObject::Inactive { .. } => None
+        };
+        if let Some(bps) = bps {
+            write!(f, " {} bps", bps)?;
+        }
+        f.write_str(if self.is_active() { " active" } else { " inactive" })
+    }
+}
+/// TPV, SKY and DEVICE reports are shown as by their own `Display`; VERSION as
+/// `gpsd 3.17 (rev 3.17, protocol 3.12)`. Other classes get a short summary.
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Response::Tpv(ref tpv) => tpv.fmt(f),
+            Response::Sky(ref sky) => sky.fmt(f),
+            Response::Device(ref dev) => dev.fmt(f),
+            Response::Version { ref release, ref rev, proto_major, proto_minor, .. } =>
+                write!(f, "gpsd {} (rev {}, protocol {}.{})", release, rev, proto_major, proto_minor),
+            Response::Poll { ref time, active, .. } => write!(f, "POLL {} {} active", format_millis(time), active),
+            Response::Devices { ref devices, .. } => write!(f, "DEVICES {} known", devices.len()),
+            Response::Watch(ref w) => write!(f, "WATCH {}", if w.enable { "enabled" } else { "disabled" }),
+            Response::Error { ref message } => write!(f, "ERROR {}", message),
+            Response::Raw(ref line) => f.write_str(line),
+            Response::Unknown(ref data) => write!(f, "{} (unknown)", data["class"].as_str().unwrap_or("?"))
+        }
+    }
+}
+/// Settings to change on a device, with `Command::Device`. Fields left as
+/// `None` aren't changed.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct DeviceSettings {
+    /// The device to change. May be omitted only when gpsd has exactly one
+    /// device.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<DevicePath>,
+    /// Speed, in bits per second.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bps: Option<u32>,
+    /// Parity.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parity: Option<Parity>,
+    /// Stop bits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stopbits: Option<StopBits>,
+    /// NMEA mode, or the device's native binary mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub native: Option<DriverMode>,
+    /// Reporting cycle, in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cycle: Option<f64>
+}
+/// A command to send to gpsd.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Set watcher mode parameters (`?WATCH=...`).
+    Watch(WatchObject),
+    /// Request data from the last-seen fixes (`?POLL;`).
+    Poll,
+    /// Ask for the server's version (`?VERSION;`).
+    Version,
+    /// Ask for the server's devices (`?DEVICES;`).
+    Devices,
+    /// Change a device's settings (`?DEVICE=...`).
+    Device(DeviceSettings),
+    /// A command this crate has no variant for, sent as-is. The newline is
+    /// added if it's missing.
     Raw(String)
 }
+impl Command {
+    /// The command as sent to gpsd, including the newline.
+    pub fn to_line(&self) -> String {
+        match *self {
+            Command::Watch(ref w) => {
+                format!("?WATCH={}\n", serde_json::to_string(w).expect("WATCH objects always serialize"))
+            },
+            Command::Poll => "?POLL;\n".into(),
+            Command::Version => "?VERSION;\n".into(),
+            Command::Devices => "?DEVICES;\n".into(),
+            Command::Device(ref d) => {
+                format!("?DEVICE={}\n", serde_json::to_string(d).expect("DEVICE objects always serialize"))
+            },
+            Command::Raw(ref s) if s.ends_with('\n') => s.clone(),
+            Command::Raw(ref s) => format!("{}\n", s)
+        }
+    }
+    /// Whether gpsd replies to the command in a way we can recognise; `Raw`
+    /// commands don't count.
+    pub fn expects_reply(&self) -> bool {
+        !matches!(*self, Command::Raw(..))
+    }
+    /// Whether `resp` is gpsd's reply to the command: the response of the
+    /// corresponding class, or an ERROR.
+    ///
+    /// gpsd answers commands in the order it receives them, so the first
+    /// response for which this is true is the reply.
+    ///
+    /// While watching, gpsd also sends DEVICE reports unprompted, when a
+    /// device is activated or closed. A DEVICE command naming a device only
+    /// takes a report for that device as its reply, but one that gpsd sends
+    /// unprompted for the same device just before the reply is still taken
+    /// for it, as is any DEVICE report for a command that names no device.
+    pub fn is_reply(&self, resp: &Response) -> bool {
+        match (self, resp) {
+            (Command::Device(ref settings), Response::Device(ref dev)) => match settings.path {
+                Some(ref path) => dev.path().is_some_and(|p| path.matches(p)),
+                None => true
+            },
+            _ => matches!((self, resp),
+                (Command::Watch(..), Response::Watch(..)) |
+                (Command::Poll, Response::Poll { .. }) |
+                (Command::Version, Response::Version { .. }) |
+                (Command::Devices, Response::Devices { .. })) ||
+                (self.expects_reply() && matches!(*resp, Response::Error { .. }))
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Receiving relayed NMEA or gpsd JSON over UDP.
+//!
+//! Many marine and drone setups broadcast position on the LAN rather than
+//! exposing gpsd over TCP. A `UdpSource` listens for these datagrams (unicast,
+//! or by joining a multicast group) and parses them just like the lines read
+//! by a `GpsdConnection`, optionally decoding NMEA with an `NmeaDecoder`.
+
+use std::collections::VecDeque;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+use crate::nmea::NmeaDecoder;
+use crate::types::Response;
+use crate::{parse_line, GpsdResult};
+
+/// Maximum size of a datagram we're prepared to receive.
+const MAX_DATAGRAM: usize = 65536;
+
+/// A source of responses received as UDP datagrams.
+///
+/// Each datagram may contain one or more newline-separated lines. Lines that
+/// parse as gpsd JSON are returned as the corresponding `Response`; NMEA
+/// sentences (starting with `$` or `!`) are returned as `Response::Raw`,
+/// unless `set_decode_nmea` asks for the `$` ones to be decoded.
+pub struct UdpSource {
+    socket: UdpSocket,
+    nmea: Option<NmeaDecoder>,
+    pending: VecDeque<String>,
+    buf: Vec<u8>
+}
+impl UdpSource {
+    /// Listen for unicast (or broadcast) datagrams on the given address.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> GpsdResult<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        Ok(Self::from_socket(socket))
+    }
+    /// Join the multicast group `group`, listening on `port`.
+    ///
+    /// The socket is bound to the unspecified address of the group's family,
+    /// and the group is joined on the default interface.
+    pub fn multicast(group: IpAddr, port: u16) -> GpsdResult<Self> {
+        let src = match group {
+            IpAddr::V4(g) => {
+                let src = Self::bind((Ipv4Addr::UNSPECIFIED, port))?;
+                src.join_multicast_v4(&g, &Ipv4Addr::UNSPECIFIED)?;
+                src
+            },
+            IpAddr::V6(g) => {
+                let src = Self::bind((Ipv6Addr::UNSPECIFIED, port))?;
+                src.join_multicast_v6(&g, 0)?;
+                src
+            }
+        };
+        Ok(src)
+    }
+    /// Wrap an already configured socket.
+    pub fn from_socket(socket: UdpSocket) -> Self {
+        Self {
+            socket,
+            nmea: None,
+            pending: VecDeque::new(),
+            buf: vec![0; MAX_DATAGRAM]
+        }
+    }
+    /// Join an IPv4 multicast group on the interface with address `iface`
+    /// (or `0.0.0.0` for the default interface).
+    pub fn join_multicast_v4(&self, group: &Ipv4Addr, iface: &Ipv4Addr) -> GpsdResult<()> {
+        self.socket.join_multicast_v4(group, iface)?;
+        Ok(())
+    }
+    /// Join an IPv6 multicast group on the interface with index `iface`
+    /// (or 0 for the default interface).
+    pub fn join_multicast_v6(&self, group: &Ipv6Addr, iface: u32) -> GpsdResult<()> {
+        self.socket.join_multicast_v6(group, iface)?;
+        Ok(())
+    }
+    /// The local address the source is listening on.
+    pub fn local_addr(&self) -> GpsdResult<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+    /// Sets the read timeout for `get_response`.
+    ///
+    /// A value of `None` implies that the read will block indefinitely.
+    pub fn set_read_timeout(&mut self, dur: Option<Duration>) -> GpsdResult<()> {
+        self.socket.set_read_timeout(dur)?;
+        Ok(())
+    }
+    /// Whether NMEA sentences are decoded into the TPV and SKY reports they
+    /// make up, skipping those that can't be read, rather than returned as
+    /// `Response::Raw`. They aren't by default.
+    pub fn set_decode_nmea(&mut self, decode: bool) {
+        self.nmea = if decode { Some(NmeaDecoder::new()) } else { None };
+    }
+    /// Wait for the next response, blocking if necessary.
+    pub fn get_response(&mut self) -> GpsdResult<Response> {
+        loop {
+            if let Some(line) = self.pending.pop_front() {
+                if let (Some(dec), true) = (self.nmea.as_mut(), line.starts_with('$')) {
+                    match dec.decode_or_skip(&line) {
+                        Some(resp) => return Ok(resp),
+                        None => continue
+                    }
+                }
+                let raw = line.starts_with('$') || line.starts_with('!');
+                return parse_line(line, raw);
+            }
+            let (len, from) = self.socket.recv_from(&mut self.buf)?;
+            debug!("{} byte datagram from {}", len, from);
+            let data = String::from_utf8_lossy(&self.buf[..len]);
+            self.pending.extend(data.lines()
+                                .map(|l| l.trim())
+                                .filter(|l| !l.is_empty())
+                                .map(String::from));
+        }
+    }
+}
@@ -0,0 +1,270 @@
+//! Units for the quantities in reports.
+//!
+//! gpsd reports speeds in meters per second and heights in meters. `Speed`
+//! and `Length` hold a value in those units and convert it to whatever a
+//! user interface wants to show; get them from a TPV with `ground_speed`,
+//! `climb_rate` and `altitude`.
+//!
+//! Courses are degrees from north. A `Bearing` (from `course` or
+//! `magnetic_course`) is kept in [0, 360), and subtracting one from another
+//! gives the signed turn between them, so comparisons across north don't
+//! go wrong.
+//!
+//! gpsd's error estimates (the `ep*` fields) are at 95% confidence, in the
+//! unit of whatever they're estimating. `TpvResponse::errors` gathers them as
+//! `ErrorEstimate`s, which say so and can be rescaled.
+
+use std::fmt;
+use std::ops::{Add, Sub};
+use crate::types::TpvResponse;
+
+/// Meters per second in one knot.
+const MPS_PER_KNOT: f64 = 1852.0 / 3600.0;
+/// Meters per second in one mile per hour.
+const MPS_PER_MPH: f64 = 1609.344 / 3600.0;
+/// Meters in one foot.
+const METERS_PER_FOOT: f64 = 0.3048;
+
+/// A speed, stored in meters per second.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+#[serde(transparent)]
+pub struct Speed(f64);
+impl Speed {
+    pub fn from_mps(mps: f64) -> Self {
+        Speed(mps)
+    }
+    pub fn from_knots(knots: f64) -> Self {
+        Speed(knots * MPS_PER_KNOT)
+    }
+    pub fn from_kmh(kmh: f64) -> Self {
+        Speed(kmh / 3.6)
+    }
+    pub fn from_mph(mph: f64) -> Self {
+        Speed(mph * MPS_PER_MPH)
+    }
+    /// Meters per second, as gpsd reports it.
+    pub fn mps(self) -> f64 {
+        self.0
+    }
+    pub fn knots(self) -> f64 {
+        self.0 / MPS_PER_KNOT
+    }
+    /// Kilometers per hour.
+    pub fn kmh(self) -> f64 {
+        self.0 * 3.6
+    }
+    /// Miles per hour.
+    pub fn mph(self) -> f64 {
+        self.0 / MPS_PER_MPH
+    }
+}
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.2} m/s", self.0)
+    }
+}
+
+/// A height or distance, stored in meters.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+#[serde(transparent)]
+pub struct Length(f64);
+impl Length {
+    pub fn from_meters(meters: f64) -> Self {
+        Length(meters)
+    }
+    pub fn from_feet(feet: f64) -> Self {
+        Length(feet * METERS_PER_FOOT)
+    }
+    /// Meters, as gpsd reports it.
+    pub fn meters(self) -> f64 {
+        self.0
+    }
+    pub fn feet(self) -> f64 {
+        self.0 / METERS_PER_FOOT
+    }
+}
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.1} m", self.0)
+    }
+}
+
+/// A direction in degrees clockwise from north, in [0, 360).
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+#[serde(from = "f64", into = "f64")]
+pub struct Bearing(f64);
+impl Bearing {
+    /// `degrees`, wrapped into [0, 360).
+    pub fn new(degrees: f64) -> Self {
+        let d = degrees.rem_euclid(360.0);
+        // A tiny negative angle can round up to 360.
+        Bearing(if d >= 360.0 { 0.0 } else { d })
+    }
+    pub fn degrees(self) -> f64 {
+        self.0
+    }
+    /// The signed turn from `from` to this bearing, in (-180, 180];
+    /// positive is clockwise. Same as `self - from`.
+    pub fn difference(self, from: Bearing) -> f64 {
+        let d = (self.0 - from.0).rem_euclid(360.0);
+        if d > 180.0 { d - 360.0 } else { d }
+    }
+    /// The opposite direction.
+    pub fn reciprocal(self) -> Self {
+        self + 180.0
+    }
+}
+impl From<f64> for Bearing {
+    fn from(degrees: f64) -> Self {
+        Bearing::new(degrees)
+    }
+}
+impl From<Bearing> for f64 {
+    fn from(b: Bearing) -> f64 {
+        b.0
+    }
+}
+/// Turn clockwise by some degrees.
+impl Add<f64> for Bearing {
+    type Output = Bearing;
+    fn add(self, degrees: f64) -> Bearing {
+        Bearing::new(self.0 + degrees)
+    }
+}
+/// Turn anticlockwise by some degrees.
+impl Sub<f64> for Bearing {
+    type Output = Bearing;
+    fn sub(self, degrees: f64) -> Bearing {
+        Bearing::new(self.0 - degrees)
+    }
+}
+/// The signed turn between two bearings; see `difference`.
+impl Sub for Bearing {
+    type Output = f64;
+    fn sub(self, from: Bearing) -> f64 {
+        self.difference(from)
+    }
+}
+impl fmt::Display for Bearing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:.1}°", self.0)
+    }
+}
+
+/// The unit an `ErrorEstimate` is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorUnit {
+    Seconds,
+    Meters,
+    Degrees,
+    MetersPerSecond
+}
+/// An error estimate from gpsd: the true value is within `value` of the
+/// reported one with 95% confidence.
+///
+/// The rescaling helpers assume the error is normally distributed, as gpsd
+/// does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErrorEstimate {
+    pub value: f64,
+    pub unit: ErrorUnit
+}
+/// Standard deviations either side of the mean covering 95% of a normal
+/// distribution.
+const SIGMAS_95: f64 = 1.959_963_984_540_054;
+/// The same, for 99%.
+const SIGMAS_99: f64 = 2.575_829_303_548_901;
+impl ErrorEstimate {
+    /// The confidence gpsd's estimates are at.
+    pub const CONFIDENCE: f64 = 0.95;
+
+    pub fn new(value: f64, unit: ErrorUnit) -> Self {
+        Self { value, unit }
+    }
+    /// The one standard deviation (68%) error.
+    pub fn one_sigma(self) -> f64 {
+        self.value / SIGMAS_95
+    }
+    /// The error at 99% confidence.
+    pub fn at_99_percent(self) -> f64 {
+        self.value / SIGMAS_95 * SIGMAS_99
+    }
+}
+impl fmt::Display for ErrorEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let unit = match self.unit {
+            ErrorUnit::Seconds => "s",
+            ErrorUnit::Meters => "m",
+            ErrorUnit::Degrees => "°",
+            ErrorUnit::MetersPerSecond => "m/s"
+        };
+        write!(f, "±{} {} (95%)", self.value, unit)
+    }
+}
+/// The error estimates in a TPV report. Get them with
+/// `TpvResponse::errors`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ErrorEstimates {
+    /// Timestamp error (`ept`).
+    pub time: Option<ErrorEstimate>,
+    /// Latitude error (`epy`).
+    pub lat: Option<ErrorEstimate>,
+    /// Longitude error (`epx`).
+    pub lon: Option<ErrorEstimate>,
+    /// Altitude error (`epv`).
+    pub alt: Option<ErrorEstimate>,
+    /// Course error (`epd`).
+    pub track: Option<ErrorEstimate>,
+    /// Speed error (`eps`).
+    pub speed: Option<ErrorEstimate>,
+    /// Climb/sink error (`epc`).
+    pub climb: Option<ErrorEstimate>
+}
+
+impl TpvResponse {
+    /// The report's error estimates, with their units.
+    pub fn errors(&self) -> ErrorEstimates {
+        let (time, lat, lon, alt, track, speed, climb) = match *self {
+            TpvResponse::Fix3D { time_err, lat_err, lon_err, alt_err, track_err, speed_err, climb_err, .. } =>
+                (Some(time_err), lat_err, lon_err, alt_err, track_err, speed_err, climb_err),
+            TpvResponse::Fix2D { time_err, lat_err, lon_err, track_err, speed_err, .. } =>
+                (Some(time_err), lat_err, lon_err, None, track_err, speed_err, None),
+            TpvResponse::LatLonOnly { time_err, lat_err, lon_err, alt_err, track_err, speed_err, climb_err, .. } =>
+                (Some(time_err), lat_err, lon_err, alt_err, track_err, speed_err, climb_err),
+            TpvResponse::Dustbin { time_err, lat_err, lon_err, alt_err, track_err, speed_err, climb_err, .. } =>
+                (time_err, lat_err, lon_err, alt_err, track_err, speed_err, climb_err),
+            _ => return ErrorEstimates::default()
+        };
+        let est = |v: Option<f64>, unit| v.map(|v| ErrorEstimate::new(v, unit));
+        ErrorEstimates {
+            time: est(time, ErrorUnit::Seconds),
+            lat: est(lat, ErrorUnit::Meters),
+            lon: est(lon, ErrorUnit::Meters),
+            alt: est(alt, ErrorUnit::Meters),
+            track: est(track, ErrorUnit::Degrees),
+            speed: est(speed, ErrorUnit::MetersPerSecond),
+            climb: est(climb, ErrorUnit::MetersPerSecond)
+        }
+    }
+    /// Speed over ground, if reported.
+    pub fn ground_speed(&self) -> Option<Speed> {
+        self.speed().map(Speed)
+    }
+    /// Climb (positive) or sink (negative) rate, if reported.
+    pub fn climb_rate(&self) -> Option<Speed> {
+        self.climb().map(Speed)
+    }
+    /// Altitude, if reported.
+    pub fn altitude(&self) -> Option<Length> {
+        self.alt().map(Length)
+    }
+    /// Course over ground from true north, if reported.
+    pub fn course(&self) -> Option<Bearing> {
+        self.track().map(Bearing::new)
+    }
+    /// Course over ground from magnetic north, if reported (as `magtrack`,
+    /// which only some gpsd versions send).
+    pub fn magnetic_course(&self) -> Option<Bearing> {
+        self.extra().get("magtrack").and_then(|v| v.as_f64()).map(Bearing::new)
+    }
+}
@@ -0,0 +1,139 @@
+//! UTM and MGRS coordinates for fixes.
+//!
+//! Only available with the `utm` feature enabled. Positions are projected
+//! from WGS84 with the transverse Mercator series in Snyder's *Map
+//! Projections: A Working Manual*, which is good to a centimeter or so
+//! within a zone: plenty for MGRS, whose finest references are to the meter.
+//! Zones follow the standard grid, including the exceptions for southwest
+//! Norway and Svalbard.
+//!
+//! UTM only covers latitudes from 80°S to 84°N; the polar regions use UPS,
+//! which isn't supported, so positions there give `None`.
+
+use std::fmt;
+use crate::geodesy::{WGS84_A, WGS84_F};
+use crate::types::Position;
+
+/// Scale factor on the central meridian.
+const K0: f64 = 0.9996;
+/// Easting of the central meridian.
+const FALSE_EASTING: f64 = 500_000.0;
+/// Northing of the equator, in the southern hemisphere.
+const FALSE_NORTHING: f64 = 10_000_000.0;
+/// Latitude bands, 8° each from 80°S (X is 12°).
+const BANDS: &[u8] = b"CDEFGHJKLMNPQRSTUVWX";
+/// MGRS 100 km column letters, by zone number modulo 3.
+const COLUMNS: [&[u8]; 3] = [b"STUVWXYZ", b"ABCDEFGH", b"JKLMNPQR"];
+/// MGRS 100 km row letters.
+const ROWS: &[u8] = b"ABCDEFGHJKLMNPQRSTUV";
+
+/// A UTM coordinate.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Utm {
+    /// Zone number, 1 to 60.
+    pub zone: u8,
+    /// Latitude band letter, `C` to `X`; `N` and above are north of the
+    /// equator.
+    pub band: char,
+    /// Meters east, with the zone's central meridian at 500 km.
+    pub easting: f64,
+    /// Meters north of the equator, or of 10,000 km south of it in the
+    /// southern hemisphere.
+    pub northing: f64
+}
+/// The UTM zone for `lat` and `lon`.
+fn zone(lat: f64, lon: f64) -> u8 {
+    if (56.0..64.0).contains(&lat) && (3.0..12.0).contains(&lon) {
+        return 32;
+    }
+    if (72.0..=84.0).contains(&lat) && (0.0..42.0).contains(&lon) {
+        return match lon {
+            l if l < 9.0 => 31,
+            l if l < 21.0 => 33,
+            l if l < 33.0 => 35,
+            _ => 37
+        };
+    }
+    (((lon + 180.0) / 6.0).floor() as i32).rem_euclid(60) as u8 + 1
+}
+impl Utm {
+    /// The UTM coordinate of `lat` and `lon` degrees, or `None` if they're
+    /// outside UTM's coverage.
+    pub fn from_geodetic(lat: f64, lon: f64) -> Option<Self> {
+        if !(-80.0..=84.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+            return None;
+        }
+        let zone = zone(lat, lon);
+        let band = BANDS[(((lat + 80.0) / 8.0) as usize).min(BANDS.len() - 1)] as char;
+        let lon0 = (f64::from(zone) - 1.0) * 6.0 - 180.0 + 3.0;
+
+        let e2 = WGS84_F * (2.0 - WGS84_F);
+        let (e4, e6) = (e2 * e2, e2 * e2 * e2);
+        let ep2 = e2 / (1.0 - e2);
+        let phi = lat.to_radians();
+        let (sin, cos, tan) = (phi.sin(), phi.cos(), phi.tan());
+        let n = WGS84_A / (1.0 - e2 * sin * sin).sqrt();
+        let t = tan * tan;
+        let c = ep2 * cos * cos;
+        // The longitude difference, wrapped so zone 1 works across the
+        // antimeridian.
+        let dlon = (lon - lon0 + 180.0).rem_euclid(360.0) - 180.0;
+        let a = cos * dlon.to_radians();
+        // Meridian arc from the equator.
+        let m = WGS84_A * ((1.0 - e2 / 4.0 - 3.0 * e4 / 64.0 - 5.0 * e6 / 256.0) * phi
+            - (3.0 * e2 / 8.0 + 3.0 * e4 / 32.0 + 45.0 * e6 / 1024.0) * (2.0 * phi).sin()
+            + (15.0 * e4 / 256.0 + 45.0 * e6 / 1024.0) * (4.0 * phi).sin()
+            - (35.0 * e6 / 3072.0) * (6.0 * phi).sin());
+
+        let easting = FALSE_EASTING + K0 * n * (a
+            + (1.0 - t + c) * a.powi(3) / 6.0
+            + (5.0 - 18.0 * t + t * t + 72.0 * c - 58.0 * ep2) * a.powi(5) / 120.0);
+        let mut northing = K0 * (m + n * tan * (a * a / 2.0
+            + (5.0 - t + 9.0 * c + 4.0 * c * c) * a.powi(4) / 24.0
+            + (61.0 - 58.0 * t + t * t + 600.0 * c - 330.0 * ep2) * a.powi(6) / 720.0));
+        if lat < 0.0 {
+            northing += FALSE_NORTHING;
+        }
+        Some(Utm { zone, band, easting, northing })
+    }
+    /// Whether the coordinate is in the northern hemisphere.
+    pub fn is_north(&self) -> bool {
+        self.band >= 'N'
+    }
+    /// The coordinate as an MGRS reference, like `18SUJ2348706483`, with
+    /// `digits` (at most 5) digits each of easting and northing: 5 gives 1 m
+    /// precision, 4 gives 10 m and so on, and 0 just names the 100 km square.
+    /// As MGRS has it, the digits are truncated, not rounded.
+    pub fn to_mgrs(&self, digits: usize) -> String {
+        let digits = digits.min(5);
+        let (e100k, n100k) = ((self.easting / 100_000.0) as usize, (self.northing / 100_000.0) as usize);
+        let columns = COLUMNS[usize::from(self.zone) % 3];
+        let column = columns[e100k.saturating_sub(1).min(columns.len() - 1)] as char;
+        let offset = if self.zone % 2 == 1 { 0 } else { 5 };
+        let row = ROWS[(n100k + offset) % ROWS.len()] as char;
+        if digits == 0 {
+            return format!("{:02}{}{}{}", self.zone, self.band, column, row);
+        }
+        let scale = 10f64.powi(5 - digits as i32);
+        let e = ((self.easting % 100_000.0) / scale) as u32;
+        let n = ((self.northing % 100_000.0) / scale) as u32;
+        format!("{:02}{}{}{}{:0w$}{:0w$}", self.zone, self.band, column, row, e, n, w = digits)
+    }
+}
+/// Like `33V 347359 6580653`, to the meter.
+impl fmt::Display for Utm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{} {:.0} {:.0}", self.zone, self.band, self.easting.floor(), self.northing.floor())
+    }
+}
+impl Position {
+    /// The position as a UTM coordinate, if it's within UTM's coverage.
+    pub fn utm(&self) -> Option<Utm> {
+        Utm::from_geodetic(self.lat.degrees(), self.lon.degrees())
+    }
+    /// The position as an MGRS reference with `digits` digits of easting and
+    /// northing; see `Utm::to_mgrs`.
+    pub fn mgrs(&self, digits: usize) -> Option<String> {
+        self.utm().map(|u| u.to_mgrs(digits))
+    }
+}
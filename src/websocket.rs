@@ -0,0 +1,266 @@
+//! Talking to gpsd over WebSockets.
+//!
+//! Only available with the `websocket` feature enabled. gpsd doesn't speak
+//! WebSockets itself, but can be bridged with e.g.
+//! `websockify 8080 localhost:2947`, which is the only way to reach it from a
+//! browser.
+//!
+//! Natively, `WebSocketTransport` is a `Transport` like any other, so a
+//! `GpsdConnection` works over it unchanged. On wasm32, where nothing may
+//! block, `WebSocketSource` uses the browser's WebSocket and hands responses
+//! to a callback as they arrive; parsing is done by the same
+//! `nonblocking::ResponseParser` either way.
+//!
+//! Messages are sent as binary frames, using the `binary` subprotocol that
+//! websockify expects. Received frames may be text or binary, and needn't line
+//! up with gpsd's lines.
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::native::WebSocketTransport;
+#[cfg(target_arch = "wasm32")]
+pub use self::wasm::WebSocketSource;
+
+/// The subprotocol to ask for.
+const SUBPROTOCOL: &str = "binary";
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::io::{self, Read, Write};
+    use std::net::{TcpStream, ToSocketAddrs};
+    use std::time::Duration;
+    use tungstenite::client::IntoClientRequest;
+    use tungstenite::handshake::HandshakeError;
+    use tungstenite::http::HeaderValue;
+    use tungstenite::{Message, WebSocket};
+    use crate::transport::{ShutdownHandle, Transport};
+    use crate::{connect_addrs, GpsdConnection, GpsdResult};
+    use super::SUBPROTOCOL;
+
+    /// A WebSocket connection, carrying gpsd's protocol over `S`.
+    pub struct WebSocketTransport<S: Transport = TcpStream> {
+        ws: WebSocket<S>,
+        pending: Vec<u8>
+    }
+    impl WebSocketTransport<TcpStream> {
+        /// Connect to the WebSocket at `url` (of the form
+        /// `ws://host:port/path`).
+        ///
+        /// `wss://` URLs aren't supported here; make the TLS connection
+        /// yourself and use `WebSocketTransport::client`.
+        pub fn connect(url: &str) -> io::Result<Self> {
+            let req = url.into_client_request().map_err(to_io)?;
+            if req.uri().scheme_str() != Some("ws") {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "only ws:// URLs are supported"));
+            }
+            let host = req.uri().host()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "URL has no host"))?
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_owned();
+            let port = req.uri().port_u16().unwrap_or(80);
+            let addrs = (&host[..], port).to_socket_addrs()?.collect::<Vec<_>>();
+            let sock = connect_addrs(&addrs, None)?;
+            Self::client(url, sock)
+        }
+    }
+    impl<S: Transport> WebSocketTransport<S> {
+        /// Perform the WebSocket handshake for `url` over an already-open
+        /// `stream`.
+        pub fn client(url: &str, stream: S) -> io::Result<Self> {
+            let mut req = url.into_client_request().map_err(to_io)?;
+            req.headers_mut().insert("Sec-WebSocket-Protocol", HeaderValue::from_static(SUBPROTOCOL));
+            let (ws, _) = tungstenite::client(req, stream).map_err(|e| match e {
+                HandshakeError::Failure(e) => to_io(e),
+                HandshakeError::Interrupted(_) => io::Error::new(io::ErrorKind::WouldBlock, "WebSocket handshake interrupted")
+            })?;
+            Ok(Self { ws, pending: Vec::new() })
+        }
+        /// The stream the WebSocket runs over.
+        pub fn get_ref(&self) -> &S {
+            self.ws.get_ref()
+        }
+    }
+    /// Convert a tungstenite error, passing I/O errors (including timeouts)
+    /// through as they are.
+    fn to_io(e: tungstenite::Error) -> io::Error {
+        match e {
+            tungstenite::Error::Io(e) => e,
+            e => io::Error::other(e)
+        }
+    }
+    impl<S: Transport> Read for WebSocketTransport<S> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            while self.pending.is_empty() {
+                let data = match self.ws.read() {
+                    Ok(Message::Text(t)) => t.as_bytes().to_vec(),
+                    Ok(Message::Binary(b)) => b.to_vec(),
+                    Ok(Message::Close(_)) => {
+                        // Send the reply tungstenite queued, to finish closing.
+                        let _ = self.ws.flush();
+                        return Ok(0);
+                    },
+                    Ok(_) => continue,
+                    Err(tungstenite::Error::ConnectionClosed) |
+                    Err(tungstenite::Error::AlreadyClosed) => return Ok(0),
+                    Err(e) => return Err(to_io(e))
+                };
+                self.pending = data;
+            }
+            let n = buf.len().min(self.pending.len());
+            buf[..n].copy_from_slice(&self.pending[..n]);
+            self.pending.drain(..n);
+            Ok(n)
+        }
+    }
+    impl<S: Transport> Write for WebSocketTransport<S> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.ws.send(Message::binary(buf.to_vec())).map_err(to_io)?;
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.ws.flush().map_err(to_io)
+        }
+    }
+    impl<S: Transport> Transport for WebSocketTransport<S> {
+        fn set_read_timeout(&self, dur: Option<Duration>) -> io::Result<()> {
+            self.ws.get_ref().set_read_timeout(dur)
+        }
+        fn shutdown(&self) -> io::Result<()> {
+            self.ws.get_ref().shutdown()
+        }
+        fn shutdown_handle(&self) -> Option<ShutdownHandle> {
+            self.ws.get_ref().shutdown_handle()
+        }
+    }
+
+    impl GpsdConnection {
+        /// Make a new connection to gpsd through the WebSocket at `url`. See
+        /// `WebSocketTransport::connect`.
+        pub fn connect_websocket(url: &str) -> GpsdResult<Self> {
+            let url = url.to_owned();
+            Self::with_transport(move || WebSocketTransport::connect(&url))
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod wasm {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use js_sys::{ArrayBuffer, Uint8Array};
+    use wasm_bindgen::closure::Closure;
+    use wasm_bindgen::{JsCast, JsValue};
+    use web_sys::{BinaryType, Event, MessageEvent, WebSocket};
+    use crate::errors::GpsdError;
+    use crate::nonblocking::ResponseParser;
+    use crate::types::{RawLevel, Response};
+    use crate::{watch_command, GpsdResult};
+    use super::SUBPROTOCOL;
+
+    type Handler = Rc<RefCell<dyn FnMut(GpsdResult<Response>)>>;
+
+    fn js_error(e: JsValue) -> GpsdError {
+        GpsdError::Disconnected(format!("WebSocket error: {:?}", e))
+    }
+
+    /// A browser WebSocket connection to gpsd, calling a handler with each
+    /// response.
+    ///
+    /// Commands sent before the socket has opened are queued until it does.
+    /// When the socket closes, the handler gets a `GpsdError::Disconnected`
+    /// error. Dropping the source closes the socket.
+    pub struct WebSocketSource {
+        ws: WebSocket,
+        parser: Rc<RefCell<ResponseParser>>,
+        queue: Rc<RefCell<Option<Vec<String>>>>,
+        _onmessage: Closure<dyn FnMut(MessageEvent)>,
+        _onopen: Closure<dyn FnMut(Event)>,
+        _onclose: Closure<dyn FnMut(Event)>
+    }
+    impl WebSocketSource {
+        /// Connect to the WebSocket at `url`, passing each response to
+        /// `handler`.
+        pub fn connect<F>(url: &str, handler: F) -> GpsdResult<Self>
+            where F: FnMut(GpsdResult<Response>) + 'static {
+            let ws = WebSocket::new_with_str(url, SUBPROTOCOL).map_err(js_error)?;
+            ws.set_binary_type(BinaryType::Arraybuffer);
+            let handler: Handler = Rc::new(RefCell::new(handler));
+            let parser = Rc::new(RefCell::new(ResponseParser::new()));
+            let queue = Rc::new(RefCell::new(Some(Vec::<String>::new())));
+
+            let (p, h) = (parser.clone(), handler.clone());
+            let onmessage = Closure::<dyn FnMut(MessageEvent)>::new(move |e: MessageEvent| {
+                let data = e.data();
+                if let Some(text) = data.as_string() {
+                    p.borrow_mut().feed(text.as_bytes());
+                } else if let Some(buf) = data.dyn_ref::<ArrayBuffer>() {
+                    p.borrow_mut().feed(&Uint8Array::new(buf).to_vec());
+                }
+                loop {
+                    // Don't hold the parser across the handler, which may
+                    // send commands (and so touch the parser).
+                    let resp = p.borrow_mut().poll_response();
+                    match resp {
+                        Some(r) => (h.borrow_mut())(r),
+                        None => break
+                    }
+                }
+            });
+            ws.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+
+            let (q, w) = (queue.clone(), ws.clone());
+            let onopen = Closure::<dyn FnMut(Event)>::new(move |_| {
+                for cmd in q.borrow_mut().take().unwrap_or_default() {
+                    if let Err(e) = w.send_with_u8_array(cmd.as_bytes()) {
+                        warn!("sending queued command failed: {:?}", e);
+                    }
+                }
+            });
+            ws.set_onopen(Some(onopen.as_ref().unchecked_ref()));
+
+            let h = handler;
+            let onclose = Closure::<dyn FnMut(Event)>::new(move |_| {
+                (h.borrow_mut())(Err(GpsdError::closed()));
+            });
+            ws.set_onclose(Some(onclose.as_ref().unchecked_ref()));
+
+            Ok(Self { ws, parser, queue, _onmessage: onmessage, _onopen: onopen, _onclose: onclose })
+        }
+        fn send(&self, msg: &str) -> GpsdResult<()> {
+            if let Some(ref mut q) = *self.queue.borrow_mut() {
+                q.push(msg.to_owned());
+                return Ok(());
+            }
+            self.ws.send_with_u8_array(msg.as_bytes()).map_err(js_error)
+        }
+        /// Enable or disable watcher mode.
+        pub fn watch(&self, watch: bool) -> GpsdResult<()> {
+            self.watch_raw(watch, true, RawLevel::Off)
+        }
+        /// Enable RAW mode. See `GpsdConnection::watch_raw` for the meaning of `raw`.
+        pub fn watch_raw(&self, watch: bool, json: bool, raw: RawLevel) -> GpsdResult<()> {
+            self.parser.borrow_mut().set_raw(raw.is_on());
+            self.send(&watch_command(watch, json, raw))
+        }
+        /// Request data from the last-seen fixes on all active GPS devices.
+        pub fn poll(&self) -> GpsdResult<()> {
+            self.send("?POLL;\n")
+        }
+        /// Ask for the server's version (triggers a Response::Version).
+        pub fn version(&self) -> GpsdResult<()> {
+            self.send("?VERSION;\n")
+        }
+        /// Ask for the server's devices (triggers a Response::Devices)
+        pub fn devices(&self) -> GpsdResult<()> {
+            self.send("?DEVICES;\n")
+        }
+    }
+    impl Drop for WebSocketSource {
+        fn drop(&mut self) {
+            self.ws.set_onmessage(None);
+            self.ws.set_onopen(None);
+            self.ws.set_onclose(None);
+            let _ = self.ws.close();
+        }
+    }
+}